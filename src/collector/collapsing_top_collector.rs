@@ -0,0 +1,467 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+
+use columnar::{Column, ColumnType, MonotonicallyMappableToU64, StrColumn};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::collector::sort_key_top_collector::{SegmentSortFeature, SegmentSortKeys};
+use crate::collector::top_score_collector::TopNComputer;
+use crate::collector::{Collector, SegmentCollector, SortBy, SortKey, SortValue};
+use crate::schema::Type;
+use crate::{DocAddress, DocId, Order, Score, SegmentOrdinal, SegmentReader, TantivyError};
+
+/// The value shared by the documents of a [`CollapsedGroup`].
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupKey {
+    /// Documents without a value for the collapse field.
+    Null,
+    /// A `u64` value.
+    U64(u64),
+    /// An `i64` value.
+    I64(i64),
+    /// A string value.
+    Str(String),
+}
+
+/// A group of documents sharing the same value for the collapse field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollapsedGroup {
+    /// The value of the collapse field for this group.
+    pub key: GroupKey,
+    /// The best documents of the group, best first, along with their sort values.
+    ///
+    /// It contains at least the best document of the group, and at most
+    /// the number of inner hits requested.
+    pub hits: Vec<(Vec<SortValue>, DocAddress)>,
+}
+
+/// The `CollapsingTopDocs` collector keeps track of the top `N` groups of documents,
+/// where a group is made of the documents sharing the same value for a given fast field.
+///
+/// This is typically used to show only one hit per product family, per thread, etc.
+///
+/// The collapse field must be a `u64`, `i64` or `str` fast field. For multivalued fields,
+/// the first value of each document is used. All of the documents without a value are
+/// collapsed into a single [`GroupKey::Null`] group.
+///
+/// Documents are ranked by decreasing score by default, or by a [`SortBy`] using
+/// [`CollapsingTopDocs::order_by`]. Groups are ranked by their best document.
+///
+/// ```rust
+/// use tantivy::collector::{CollapsingTopDocs, GroupKey};
+/// use tantivy::query::QueryParser;
+/// use tantivy::schema::{Schema, FAST, STRING, TEXT};
+/// use tantivy::{doc, DocAddress, Index};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let title = schema_builder.add_text_field("title", TEXT);
+/// let family = schema_builder.add_text_field("family", STRING | FAST);
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema);
+///
+/// let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+/// index_writer.add_document(doc!(title => "red shoe", family => "shoe"))?;
+/// index_writer.add_document(doc!(title => "red red shoe", family => "shoe"))?;
+/// index_writer.add_document(doc!(title => "red hat", family => "hat"))?;
+/// index_writer.commit()?;
+///
+/// let searcher = index.reader()?.searcher();
+/// let query = QueryParser::for_index(&index, vec![title]).parse_query("red")?;
+/// let groups = searcher.search(&query, &CollapsingTopDocs::new("family", 10))?;
+///
+/// assert_eq!(groups.len(), 2);
+/// assert_eq!(groups[0].key, GroupKey::Str("shoe".to_string()));
+/// assert_eq!(groups[0].hits.len(), 1);
+/// assert_eq!(groups[0].hits[0].1, DocAddress::new(0, 1));
+/// assert_eq!(groups[1].key, GroupKey::Str("hat".to_string()));
+/// # Ok(())
+/// # }
+/// ```
+pub struct CollapsingTopDocs {
+    field: String,
+    sort_by: SortBy,
+    limit: usize,
+    offset: usize,
+    inner_hits: usize,
+}
+
+impl fmt::Debug for CollapsingTopDocs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CollapsingTopDocs(field={}, limit={}, offset={}, inner_hits={})",
+            self.field, self.limit, self.offset, self.inner_hits
+        )
+    }
+}
+
+impl CollapsingTopDocs {
+    /// Creates a collector returning the top `limit` groups, collapsing documents
+    /// on the fast field `field`.
+    ///
+    /// # Panics
+    /// The method panics if limit is 0
+    pub fn new(field: impl ToString, limit: usize) -> CollapsingTopDocs {
+        assert!(limit >= 1, "Limit must be strictly greater than 0.");
+        CollapsingTopDocs {
+            field: field.to_string(),
+            sort_by: SortKey::score(Order::Desc).into(),
+            limit,
+            offset: 0,
+            inner_hits: 1,
+        }
+    }
+
+    /// Skip the first "offset" groups when collecting.
+    #[must_use]
+    pub fn and_offset(mut self, offset: usize) -> CollapsingTopDocs {
+        self.offset = offset;
+        self
+    }
+
+    /// Ranks the documents, and therefore the groups, using the given sort
+    /// instead of the score.
+    #[must_use]
+    pub fn order_by(mut self, sort_by: impl Into<SortBy>) -> CollapsingTopDocs {
+        self.sort_by = sort_by.into();
+        self
+    }
+
+    /// Returns up to `num_hits` documents per group instead of only the best one.
+    ///
+    /// Note that in order to return exact inner hits, segments need to return all of
+    /// their groups rather than only their top groups, which is more expensive with
+    /// high cardinality collapse fields.
+    ///
+    /// # Panics
+    /// The method panics if num_hits is 0
+    #[must_use]
+    pub fn with_inner_hits(mut self, num_hits: usize) -> CollapsingTopDocs {
+        assert!(
+            num_hits >= 1,
+            "The number of inner hits must be strictly greater than 0."
+        );
+        self.inner_hits = num_hits;
+        self
+    }
+}
+
+impl Collector for CollapsingTopDocs {
+    type Fruit = Vec<CollapsedGroup>;
+
+    type Child = CollapsingTopSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        Ok(CollapsingTopSegmentCollector {
+            group_reader: GroupKeySegmentReader::open(&self.field, segment_reader)?,
+            sort_keys: SegmentSortKeys::open(&self.sort_by, segment_reader)?,
+            groups: FxHashMap::default(),
+            inner_hits: self.inner_hits,
+            num_groups: self.limit + self.offset,
+            segment_ord: segment_local_id,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.sort_by.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<crate::Result<Vec<CollapsedGroup>>>,
+    ) -> crate::Result<Vec<CollapsedGroup>> {
+        let mut groups: HashMap<GroupKey, Vec<(Vec<SortValue>, DocAddress)>> = HashMap::new();
+        for segment_fruit in segment_fruits {
+            for group in segment_fruit? {
+                match groups.entry(group.key) {
+                    Entry::Occupied(mut entry) => entry.get_mut().extend(group.hits),
+                    Entry::Vacant(entry) => {
+                        entry.insert(group.hits);
+                    }
+                }
+            }
+        }
+        let mut groups: Vec<CollapsedGroup> = groups
+            .into_iter()
+            .map(|(key, mut hits)| {
+                hits.sort_by(|left, right| self.sort_by.compare_docs(left, right));
+                hits.truncate(self.inner_hits);
+                CollapsedGroup { key, hits }
+            })
+            .collect();
+        // Segments never return empty groups.
+        groups.sort_by(|left, right| self.sort_by.compare_docs(&left.hits[0], &right.hits[0]));
+        Ok(groups
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .collect())
+    }
+}
+
+/// Segment local reader for the collapse field.
+enum GroupKeySegmentReader {
+    Numerical {
+        column: Column<u64>,
+        column_type: ColumnType,
+    },
+    /// Strings are grouped on their term ordinals, which are only valid within the segment.
+    Str(StrColumn),
+    /// The field has no column in this segment.
+    Empty,
+}
+
+impl GroupKeySegmentReader {
+    fn open(field_name: &str, segment_reader: &SegmentReader) -> crate::Result<Self> {
+        let schema = segment_reader.schema();
+        let field = schema.get_field(field_name)?;
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {:?} is not a fast field.",
+                field_entry.name()
+            )));
+        }
+        let fast_fields = segment_reader.fast_fields();
+        let reader_opt = match field_entry.field_type().value_type() {
+            Type::U64 | Type::I64 => fast_fields
+                .u64_lenient_for_type(Some(&[ColumnType::U64, ColumnType::I64]), field_name)?
+                .map(|(column, column_type)| GroupKeySegmentReader::Numerical {
+                    column,
+                    column_type,
+                }),
+            Type::Str => fast_fields.str(field_name)?.map(GroupKeySegmentReader::Str),
+            value_type => {
+                return Err(TantivyError::SchemaError(format!(
+                    "Cannot collapse on field {:?} of type {value_type:?}. Only u64, i64 and str \
+                     fields are supported.",
+                    field_entry.name()
+                )));
+            }
+        };
+        Ok(reader_opt.unwrap_or(GroupKeySegmentReader::Empty))
+    }
+
+    #[inline]
+    fn raw_key(&self, doc: DocId) -> Option<u64> {
+        match self {
+            GroupKeySegmentReader::Numerical { column, .. } => column.first(doc),
+            GroupKeySegmentReader::Str(column) => column.ords().first(doc),
+            GroupKeySegmentReader::Empty => None,
+        }
+    }
+
+    fn group_key(&self, raw_key: Option<u64>) -> crate::Result<GroupKey> {
+        let Some(raw_key) = raw_key else {
+            return Ok(GroupKey::Null);
+        };
+        let group_key = match self {
+            GroupKeySegmentReader::Numerical {
+                column_type: ColumnType::I64,
+                ..
+            } => GroupKey::I64(i64::from_u64(raw_key)),
+            GroupKeySegmentReader::Numerical { .. } => GroupKey::U64(raw_key),
+            GroupKeySegmentReader::Str(column) => {
+                let mut text = String::new();
+                if !column.ord_to_str(raw_key, &mut text)? {
+                    return Err(TantivyError::InternalError(format!(
+                        "Term ordinal {raw_key} is missing from the dictionary."
+                    )));
+                }
+                GroupKey::Str(text)
+            }
+            GroupKeySegmentReader::Empty => GroupKey::Null,
+        };
+        Ok(group_key)
+    }
+}
+
+/// Segment Collector associated with [`CollapsingTopDocs`].
+pub struct CollapsingTopSegmentCollector {
+    group_reader: GroupKeySegmentReader,
+    sort_keys: SegmentSortKeys,
+    groups: FxHashMap<Option<u64>, TopNComputer<SegmentSortFeature, DocId>>,
+    inner_hits: usize,
+    num_groups: usize,
+    segment_ord: SegmentOrdinal,
+}
+
+impl SegmentCollector for CollapsingTopSegmentCollector {
+    type Fruit = crate::Result<Vec<CollapsedGroup>>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let raw_key = self.group_reader.raw_key(doc);
+        let feature = self.sort_keys.feature(doc, score);
+        let inner_hits = self.inner_hits;
+        self.groups
+            .entry(raw_key)
+            .or_insert_with(|| TopNComputer::new(inner_hits))
+            .push(feature, doc);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        let mut groups: Vec<_> = self
+            .groups
+            .into_iter()
+            .map(|(raw_key, topn_computer)| (raw_key, topn_computer.into_sorted_vec()))
+            .collect();
+        // Ranking groups by their best document is exact across segments: a group that is
+        // not in the top groups of any segment cannot be in the top groups overall.
+        //
+        // However, the other hits of a top group may belong to segments where it is not
+        // a top group, so we can only prune when we only need the best document of each group.
+        if self.inner_hits == 1 && groups.len() > self.num_groups {
+            groups.sort_unstable_by(|left, right| left.1[0].cmp(&right.1[0]));
+            groups.truncate(self.num_groups);
+        }
+        let segment_ord = self.segment_ord;
+        groups
+            .into_iter()
+            .map(|(raw_key, hits)| {
+                let hits = hits
+                    .into_iter()
+                    .map(|hit| {
+                        let sort_values = self.sort_keys.sort_values(hit.doc, &hit.feature)?;
+                        Ok((sort_values, DocAddress::new(segment_ord, hit.doc)))
+                    })
+                    .collect::<crate::Result<Vec<_>>>()?;
+                Ok(CollapsedGroup {
+                    key: self.group_reader.group_key(raw_key)?,
+                    hits,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CollapsingTopDocs, GroupKey};
+    use crate::collector::{Count, SortKey, SortValue};
+    use crate::query::{AllQuery, QueryParser};
+    use crate::schema::{Schema, FAST, STRING, TEXT};
+    use crate::{Index, IndexWriter, Order};
+
+    fn create_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT);
+        let family = schema_builder.add_text_field("family", STRING | FAST);
+        let family_id = schema_builder.add_i64_field("family_id", FAST);
+        let price = schema_builder.add_u64_field("price", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(
+            title => "red shoe", family => "shoe", family_id => -1i64, price => 30u64))?;
+        index_writer.add_document(doc!(
+            title => "red hat", family => "hat", family_id => 2i64, price => 10u64))?;
+        index_writer.add_document(doc!(title => "red scarf", price => 5u64))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(
+            title => "red red shoe", family => "shoe", family_id => -1i64, price => 20u64))?;
+        index_writer.add_document(doc!(
+            title => "red shoe lace", family => "shoe", family_id => -1i64, price => 1u64))?;
+        index_writer.add_document(doc!(
+            title => "red glove", family => "glove", family_id => 3i64, price => 15u64))?;
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    #[test]
+    fn test_collapse_on_str_field_across_segments() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 2);
+        let collector =
+            CollapsingTopDocs::new("family", 10).order_by(SortKey::field("price", Order::Desc));
+        let groups = searcher.search(&AllQuery, &collector)?;
+        let summary: Vec<(GroupKey, Vec<SortValue>)> = groups
+            .into_iter()
+            .map(|mut group| {
+                assert_eq!(group.hits.len(), 1);
+                (group.key, group.hits.pop().unwrap().0)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (GroupKey::Str("shoe".to_string()), vec![SortValue::U64(30)]),
+                (GroupKey::Str("glove".to_string()), vec![SortValue::U64(15)]),
+                (GroupKey::Str("hat".to_string()), vec![SortValue::U64(10)]),
+                (GroupKey::Null, vec![SortValue::U64(5)]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_collapse_with_inner_hits() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let collector = CollapsingTopDocs::new("family_id", 1)
+            .order_by(SortKey::field("price", Order::Asc))
+            .with_inner_hits(3);
+        let groups = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key, GroupKey::I64(-1));
+        // The inner hits come from both segments.
+        let hit_values: Vec<Vec<SortValue>> = groups[0]
+            .hits
+            .iter()
+            .map(|(vals, _)| vals.clone())
+            .collect();
+        assert_eq!(
+            hit_values,
+            vec![
+                vec![SortValue::U64(1)],
+                vec![SortValue::U64(20)],
+                vec![SortValue::U64(30)],
+            ]
+        );
+
+        let collector = CollapsingTopDocs::new("family_id", 1)
+            .and_offset(1)
+            .order_by(SortKey::field("price", Order::Asc))
+            .with_inner_hits(3);
+        let groups = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key, GroupKey::Null);
+        assert_eq!(groups[0].hits.len(), 1);
+        assert_eq!(groups[0].hits[0].0, vec![SortValue::U64(5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collapse_by_score() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let title = index.schema().get_field("title").unwrap();
+        let query = QueryParser::for_index(&index, vec![title]).parse_query("shoe")?;
+        let (count, groups) =
+            searcher.search(&query, &(Count, CollapsingTopDocs::new("family", 3)))?;
+        assert_eq!(count, 3);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key, GroupKey::Str("shoe".to_string()));
+        assert!(matches!(groups[0].hits[0].0[..], [SortValue::Score(_)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_collapse_on_unsupported_field() -> crate::Result<()> {
+        let index = create_index()?;
+        let searcher = index.reader()?.searcher();
+        let err = searcher
+            .search(&AllQuery, &CollapsingTopDocs::new("title", 3))
+            .unwrap_err();
+        assert!(matches!(err, crate::TantivyError::SchemaError(_)));
+        Ok(())
+    }
+}
@@ -6,6 +6,9 @@
 //! Your fruit could for instance be :
 //! - [the count of matching documents](crate::collector::Count)
 //! - [the top 10 documents, by relevancy or by a fast field](crate::collector::TopDocs)
+//! - [the top 10 documents, sorted on several keys](crate::collector::TopDocs::order_by)
+//! - [the top 10 groups of documents, collapsed on a fast
+//!   field](crate::collector::CollapsingTopDocs)
 //! - [facet counts](FacetCollector)
 //!
 //! At some point in your code, you will trigger the actual search operation by calling
@@ -105,6 +108,12 @@ pub use self::custom_score_top_collector::{CustomScorer, CustomSegmentScorer};
 
 mod tweak_score_top_collector;
 pub use self::tweak_score_top_collector::{ScoreSegmentTweaker, ScoreTweaker};
+
+mod sort_key_top_collector;
+pub use self::sort_key_top_collector::{Missing, SortBy, SortKey, SortTarget, SortValue};
+
+mod collapsing_top_collector;
+pub use self::collapsing_top_collector::{CollapsedGroup, CollapsingTopDocs, GroupKey};
 mod facet_collector;
 pub use self::facet_collector::{FacetCollector, FacetCounts};
 use crate::query::Weight;
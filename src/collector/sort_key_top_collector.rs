@@ -0,0 +1,823 @@
+use std::cmp::Ordering;
+use std::net::Ipv6Addr;
+
+use columnar::{BytesColumn, Column, ColumnType, DynamicColumn, MonotonicallyMappableToU64};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use crate::collector::top_score_collector::TopNComputer;
+use crate::collector::{Collector, SegmentCollector};
+use crate::schema::Schema;
+use crate::{
+    DateTime, DocAddress, DocId, Order, Score, SegmentOrdinal, SegmentReader, TantivyError,
+};
+
+/// Defines where documents without a value for a sort key end up.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Missing {
+    /// Documents without a value are returned before all other documents.
+    First,
+    /// Documents without a value are returned after all other documents.
+    #[default]
+    Last,
+}
+
+/// What a [`SortKey`] sorts on.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortTarget {
+    /// A fast field, identified by its name.
+    ///
+    /// Any column type is supported. For multivalued fields, the first value
+    /// of the document is used. If the field has several columns (e.g. a JSON
+    /// field with values of different types), the first column is used.
+    FastField(String),
+    /// The relevance score of the document.
+    Score,
+    /// The address of the document.
+    DocId,
+}
+
+/// A single sort criterion of a [`SortBy`].
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SortKey {
+    /// The value to sort on.
+    pub target: SortTarget,
+    /// The sort order.
+    pub order: Order,
+    /// Where documents without a value end up.
+    ///
+    /// This is only relevant for fast fields, as the score and
+    /// the doc id are defined for all documents.
+    #[serde(default)]
+    pub missing: Missing,
+}
+
+impl SortKey {
+    /// Creates a sort key on the fast field `field_name`.
+    pub fn field(field_name: impl ToString, order: Order) -> SortKey {
+        SortKey {
+            target: SortTarget::FastField(field_name.to_string()),
+            order,
+            missing: Missing::default(),
+        }
+    }
+
+    /// Creates a sort key on the document score.
+    pub fn score(order: Order) -> SortKey {
+        SortKey {
+            target: SortTarget::Score,
+            order,
+            missing: Missing::default(),
+        }
+    }
+
+    /// Creates a sort key on the document address.
+    pub fn doc_id(order: Order) -> SortKey {
+        SortKey {
+            target: SortTarget::DocId,
+            order,
+            missing: Missing::default(),
+        }
+    }
+
+    /// Sets where documents without a value end up.
+    #[must_use]
+    pub fn missing(mut self, missing: Missing) -> SortKey {
+        self.missing = missing;
+        self
+    }
+
+    /// Compares two values of this key, `Less` meaning that `left` comes first.
+    fn compare(&self, left: (&SortValue, DocAddress), right: (&SortValue, DocAddress)) -> Ordering {
+        let ordering = match (left.0, right.0) {
+            (SortValue::Null, SortValue::Null) => return Ordering::Equal,
+            (SortValue::Null, _) => {
+                return match self.missing {
+                    Missing::First => Ordering::Less,
+                    Missing::Last => Ordering::Greater,
+                };
+            }
+            (_, SortValue::Null) => {
+                return match self.missing {
+                    Missing::First => Ordering::Greater,
+                    Missing::Last => Ordering::Less,
+                };
+            }
+            // Doc ids are only meaningful within a segment.
+            _ if self.target == SortTarget::DocId => left.1.cmp(&right.1),
+            (left_value, right_value) => left_value.compare(right_value),
+        };
+        if self.order.is_desc() {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// A multi-key sort specification for [`TopDocs::order_by`](crate::collector::TopDocs::order_by).
+///
+/// Documents are compared key by key. Ties on all keys are broken by
+/// ascending `DocAddress`, so that the sort is stable and suitable for pagination.
+///
+/// `SortBy` can be (de)serialized, e.g. the following JSON
+///
+/// ```json
+/// [
+///   {"target": {"fast_field": "brand"}, "order": "Asc", "missing": "first"},
+///   {"target": "score", "order": "Desc"}
+/// ]
+/// ```
+/// sorts by ascending brand, documents without a brand first, and then by decreasing score.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(transparent)]
+pub struct SortBy {
+    keys: Vec<SortKey>,
+}
+
+impl SortBy {
+    /// Creates a `SortBy` from a list of sort keys, by decreasing priority.
+    pub fn new(keys: impl IntoIterator<Item = SortKey>) -> SortBy {
+        SortBy {
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    /// Appends a sort key, with a lower priority than the existing ones.
+    #[must_use]
+    pub fn then(mut self, key: SortKey) -> SortBy {
+        self.keys.push(key);
+        self
+    }
+
+    /// Returns the sort keys, by decreasing priority.
+    pub fn keys(&self) -> &[SortKey] {
+        &self.keys
+    }
+
+    pub(crate) fn requires_scoring(&self) -> bool {
+        self.keys.iter().any(|key| key.target == SortTarget::Score)
+    }
+
+    fn check_schema(&self, schema: &Schema) -> crate::Result<()> {
+        for key in &self.keys {
+            if let SortTarget::FastField(field_name) = &key.target {
+                let (field, _) = schema
+                    .find_field(field_name)
+                    .ok_or_else(|| TantivyError::FieldNotFound(field_name.clone()))?;
+                let field_entry = schema.get_field_entry(field);
+                if !field_entry.is_fast() {
+                    return Err(TantivyError::SchemaError(format!(
+                        "Field {:?} is not a fast field.",
+                        field_entry.name()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares two documents given their sort values, `Less` meaning that `left` comes first.
+    pub(crate) fn compare_docs(
+        &self,
+        left: &(Vec<SortValue>, DocAddress),
+        right: &(Vec<SortValue>, DocAddress),
+    ) -> Ordering {
+        self.keys
+            .iter()
+            .zip(left.0.iter().zip(right.0.iter()))
+            .map(|(key, (left_value, right_value))| {
+                key.compare((left_value, left.1), (right_value, right.1))
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| left.1.cmp(&right.1))
+    }
+}
+
+impl From<Vec<SortKey>> for SortBy {
+    fn from(keys: Vec<SortKey>) -> SortBy {
+        SortBy { keys }
+    }
+}
+
+impl From<SortKey> for SortBy {
+    fn from(key: SortKey) -> SortBy {
+        SortBy { keys: vec![key] }
+    }
+}
+
+/// The value of a [`SortKey`] for a given document.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortValue {
+    /// The document has no value for this key.
+    Null,
+    /// The relevance score.
+    Score(Score),
+    /// A `u64` value. Doc ids are also reported as `U64`.
+    U64(u64),
+    /// An `i64` value.
+    I64(i64),
+    /// A `f64` value.
+    F64(f64),
+    /// A `bool` value.
+    Bool(bool),
+    /// A date value.
+    Date(DateTime),
+    /// An ip address.
+    IpAddr(Ipv6Addr),
+    /// A string value.
+    Str(String),
+    /// A bytes value.
+    Bytes(Vec<u8>),
+}
+
+impl SortValue {
+    fn type_rank(&self) -> u8 {
+        match self {
+            SortValue::Null => 0,
+            SortValue::Score(_) => 1,
+            SortValue::U64(_) => 2,
+            SortValue::I64(_) => 3,
+            SortValue::F64(_) => 4,
+            SortValue::Bool(_) => 5,
+            SortValue::Date(_) => 6,
+            SortValue::IpAddr(_) => 7,
+            SortValue::Str(_) => 8,
+            SortValue::Bytes(_) => 9,
+        }
+    }
+
+    /// Ascending comparison.
+    ///
+    /// Values of different types (which can only happen with JSON fields)
+    /// are ordered by type.
+    fn compare(&self, other: &SortValue) -> Ordering {
+        match (self, other) {
+            (SortValue::Score(left), SortValue::Score(right)) => left.total_cmp(right),
+            (SortValue::U64(left), SortValue::U64(right)) => left.cmp(right),
+            (SortValue::I64(left), SortValue::I64(right)) => left.cmp(right),
+            (SortValue::F64(left), SortValue::F64(right)) => left.total_cmp(right),
+            (SortValue::Bool(left), SortValue::Bool(right)) => left.cmp(right),
+            (SortValue::Date(left), SortValue::Date(right)) => left.cmp(right),
+            (SortValue::IpAddr(left), SortValue::IpAddr(right)) => left.cmp(right),
+            (SortValue::Str(left), SortValue::Str(right)) => left.cmp(right),
+            (SortValue::Bytes(left), SortValue::Bytes(right)) => left.cmp(right),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+/// Segment local reader for the value of a [`SortKey`].
+enum SortKeySegmentReader {
+    Score,
+    DocId,
+    /// Any column that can be read as u64 while preserving the order, except ip addresses, since
+    /// their u64 representation is not convertible back to the original value.
+    U64Lenient {
+        column: Column<u64>,
+        column_type: ColumnType,
+    },
+    IpAddr(Column<Ipv6Addr>),
+    /// Str and bytes columns are sorted on their term ordinals, which are only valid within
+    /// the segment. They are resolved to their actual value when harvesting.
+    Dictionary {
+        column: BytesColumn,
+        is_str: bool,
+    },
+    /// The field has no column in this segment.
+    Empty,
+}
+
+impl SortKeySegmentReader {
+    fn open(target: &SortTarget, segment_reader: &SegmentReader) -> crate::Result<Self> {
+        let field_name = match target {
+            SortTarget::Score => return Ok(SortKeySegmentReader::Score),
+            SortTarget::DocId => return Ok(SortKeySegmentReader::DocId),
+            SortTarget::FastField(field_name) => field_name,
+        };
+        let fast_fields = segment_reader.fast_fields();
+        let Some(column_handle) = fast_fields
+            .dynamic_column_handles(field_name)?
+            .into_iter()
+            .next()
+        else {
+            return Ok(SortKeySegmentReader::Empty);
+        };
+        let column_type = column_handle.column_type();
+        let reader = match column_handle.open()? {
+            DynamicColumn::IpAddr(column) => SortKeySegmentReader::IpAddr(column),
+            DynamicColumn::Str(column) => SortKeySegmentReader::Dictionary {
+                column: column.into(),
+                is_str: true,
+            },
+            DynamicColumn::Bytes(column) => SortKeySegmentReader::Dictionary {
+                column,
+                is_str: false,
+            },
+            _ => {
+                let column = column_handle.open_u64_lenient()?.ok_or_else(|| {
+                    TantivyError::InternalError(format!(
+                        "Column of type {column_type} cannot be read as u64."
+                    ))
+                })?;
+                SortKeySegmentReader::U64Lenient {
+                    column,
+                    column_type,
+                }
+            }
+        };
+        Ok(reader)
+    }
+
+    /// Returns a value whose order is the order of the key, within the segment.
+    #[inline]
+    fn raw_value(&self, doc: DocId, score: Score) -> Option<u128> {
+        match self {
+            SortKeySegmentReader::Score => Some(common::f64_to_u64(score as f64) as u128),
+            SortKeySegmentReader::DocId => Some(doc as u128),
+            SortKeySegmentReader::U64Lenient { column, .. } => {
+                column.first(doc).map(|val| val as u128)
+            }
+            SortKeySegmentReader::IpAddr(column) => column.first(doc).map(u128::from),
+            SortKeySegmentReader::Dictionary { column, .. } => {
+                column.ords().first(doc).map(|ord| ord as u128)
+            }
+            SortKeySegmentReader::Empty => None,
+        }
+    }
+
+    fn sort_value(&self, doc: DocId, score: Score) -> crate::Result<SortValue> {
+        let sort_value = match self {
+            SortKeySegmentReader::Score => SortValue::Score(score),
+            SortKeySegmentReader::DocId => SortValue::U64(doc as u64),
+            SortKeySegmentReader::U64Lenient {
+                column,
+                column_type,
+            } => match column.first(doc) {
+                Some(val) => match column_type {
+                    ColumnType::I64 => SortValue::I64(i64::from_u64(val)),
+                    ColumnType::F64 => SortValue::F64(f64::from_u64(val)),
+                    ColumnType::Bool => SortValue::Bool(bool::from_u64(val)),
+                    ColumnType::DateTime => SortValue::Date(DateTime::from_u64(val)),
+                    _ => SortValue::U64(val),
+                },
+                None => SortValue::Null,
+            },
+            SortKeySegmentReader::IpAddr(column) => column
+                .first(doc)
+                .map(SortValue::IpAddr)
+                .unwrap_or(SortValue::Null),
+            SortKeySegmentReader::Dictionary { column, is_str } => {
+                let Some(ord) = column.ords().first(doc) else {
+                    return Ok(SortValue::Null);
+                };
+                let mut bytes = Vec::new();
+                if !column.ord_to_bytes(ord, &mut bytes)? {
+                    return Ok(SortValue::Null);
+                }
+                if *is_str {
+                    let text = String::from_utf8(bytes).map_err(|_| {
+                        TantivyError::InternalError("Str column contains invalid utf-8".to_string())
+                    })?;
+                    SortValue::Str(text)
+                } else {
+                    SortValue::Bytes(bytes)
+                }
+            }
+            SortKeySegmentReader::Empty => SortValue::Null,
+        };
+        Ok(sort_value)
+    }
+}
+
+/// Segment local, order preserving representation of the sort keys of a document.
+///
+/// Each key is represented by a `(rank, value)` pair. The rank places missing
+/// values before or after the present ones, and the value is flipped so that
+/// in all cases, the greater the feature, the earlier the document comes.
+pub(crate) type SegmentSortFeature = SmallVec<[(u8, u128); 4]>;
+
+const MISSING_LAST_RANK: u8 = 0;
+const PRESENT_RANK: u8 = 1;
+const MISSING_FIRST_RANK: u8 = 2;
+
+/// Evaluates the keys of a [`SortBy`] on the documents of a given segment.
+pub(crate) struct SegmentSortKeys {
+    keys: Vec<SortKey>,
+    readers: Vec<SortKeySegmentReader>,
+}
+
+impl SegmentSortKeys {
+    pub(crate) fn open(
+        sort_by: &SortBy,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<SegmentSortKeys> {
+        sort_by.check_schema(segment_reader.schema())?;
+        let readers = sort_by
+            .keys
+            .iter()
+            .map(|key| SortKeySegmentReader::open(&key.target, segment_reader))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(SegmentSortKeys {
+            keys: sort_by.keys.clone(),
+            readers,
+        })
+    }
+
+    /// Computes the feature of a document. The greater the feature, the earlier
+    /// the document comes.
+    #[inline]
+    pub(crate) fn feature(&self, doc: DocId, score: Score) -> SegmentSortFeature {
+        self.keys
+            .iter()
+            .zip(self.readers.iter())
+            .map(|(key, reader)| match reader.raw_value(doc, score) {
+                Some(val) if key.order.is_desc() => (PRESENT_RANK, val),
+                Some(val) => (PRESENT_RANK, u128::MAX - val),
+                None if key.missing == Missing::First => (MISSING_FIRST_RANK, 0),
+                None => (MISSING_LAST_RANK, 0),
+            })
+            .collect()
+    }
+
+    /// Resolves the values of the sort keys of a document, given its feature.
+    pub(crate) fn sort_values(
+        &self,
+        doc: DocId,
+        feature: &SegmentSortFeature,
+    ) -> crate::Result<Vec<SortValue>> {
+        let score = self.score_from_feature(feature);
+        self.readers
+            .iter()
+            .map(|reader| reader.sort_value(doc, score))
+            .collect()
+    }
+
+    /// Recovers the score of a document from its feature.
+    ///
+    /// The score is encoded losslessly, so there is no need to keep it around.
+    /// If the sort does not involve the score, the returned value is irrelevant.
+    fn score_from_feature(&self, feature: &SegmentSortFeature) -> Score {
+        self.keys
+            .iter()
+            .zip(feature.iter())
+            .find(|(key, _)| key.target == SortTarget::Score)
+            .map(|(key, &(_, val))| {
+                let val = if key.order.is_desc() {
+                    val
+                } else {
+                    u128::MAX - val
+                };
+                common::u64_to_f64(val as u64) as Score
+            })
+            .unwrap_or(0.0)
+    }
+}
+
+pub(crate) struct SortKeyTopCollector {
+    sort_by: SortBy,
+    limit: usize,
+    offset: usize,
+}
+
+impl SortKeyTopCollector {
+    pub(crate) fn new(sort_by: SortBy, limit: usize, offset: usize) -> SortKeyTopCollector {
+        SortKeyTopCollector {
+            sort_by,
+            limit,
+            offset,
+        }
+    }
+}
+
+impl Collector for SortKeyTopCollector {
+    type Fruit = Vec<(Vec<SortValue>, DocAddress)>;
+
+    type Child = SortKeyTopSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        Ok(SortKeyTopSegmentCollector {
+            sort_keys: SegmentSortKeys::open(&self.sort_by, segment_reader)?,
+            topn_computer: TopNComputer::new(self.limit + self.offset),
+            segment_ord: segment_local_id,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.sort_by.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<crate::Result<Self::Fruit>>,
+    ) -> crate::Result<Self::Fruit> {
+        let mut all_docs: Vec<(Vec<SortValue>, DocAddress)> = Vec::new();
+        for segment_fruit in segment_fruits {
+            all_docs.extend(segment_fruit?);
+        }
+        all_docs.sort_by(|left, right| self.sort_by.compare_docs(left, right));
+        Ok(all_docs
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .collect())
+    }
+}
+
+/// Segment Collector associated with [`TopDocs::order_by`](crate::collector::TopDocs::order_by).
+pub struct SortKeyTopSegmentCollector {
+    sort_keys: SegmentSortKeys,
+    topn_computer: TopNComputer<SegmentSortFeature, DocId>,
+    segment_ord: SegmentOrdinal,
+}
+
+impl SegmentCollector for SortKeyTopSegmentCollector {
+    // Str and bytes values need to be resolved while we still have access to
+    // the segment dictionaries, hence the `Result`.
+    type Fruit = crate::Result<Vec<(Vec<SortValue>, DocAddress)>>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let feature = self.sort_keys.feature(doc, score);
+        self.topn_computer.push(feature, doc);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        let segment_ord = self.segment_ord;
+        let sort_keys = self.sort_keys;
+        self.topn_computer
+            .into_sorted_vec()
+            .into_iter()
+            .map(|comparable_doc| {
+                let doc = comparable_doc.doc;
+                let sort_values = sort_keys.sort_values(doc, &comparable_doc.feature)?;
+                Ok((sort_values, DocAddress::new(segment_ord, doc)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::{Missing, SortBy, SortKey, SortTarget, SortValue};
+    use crate::collector::TopDocs;
+    use crate::query::{AllQuery, QueryParser};
+    use crate::schema::{Schema, FAST, STORED, STRING, TEXT};
+    use crate::{DocAddress, Index, IndexWriter, Order, Score};
+
+    fn str_value(text: &str) -> SortValue {
+        SortValue::Str(text.to_string())
+    }
+
+    fn create_multi_segment_index() -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        let brand = schema_builder.add_text_field("brand", STRING | FAST);
+        let price = schema_builder.add_i64_field("price", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        // The term ordinals of "beta" differ from one segment to the other.
+        index_writer.add_document(doc!(brand => "delta", price => 3i64))?;
+        index_writer.add_document(doc!(brand => "beta", price => -1i64))?;
+        index_writer.add_document(doc!(price => 7i64))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(brand => "alpha", price => 5i64))?;
+        index_writer.add_document(doc!(brand => "beta", price => 2i64))?;
+        index_writer.add_document(doc!(brand => "charlie"))?;
+        index_writer.commit()?;
+        Ok(index)
+    }
+
+    #[test]
+    fn test_sort_by_str_across_segments() -> crate::Result<()> {
+        let index = create_multi_segment_index()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 2);
+        let collector = TopDocs::with_limit(10).order_by(SortBy::new([
+            SortKey::field("brand", Order::Asc),
+            SortKey::field("price", Order::Desc),
+        ]));
+        let top_docs = searcher.search(&AllQuery, &collector)?;
+        let values: Vec<Vec<SortValue>> = top_docs.into_iter().map(|(vals, _)| vals).collect();
+        assert_eq!(
+            values,
+            vec![
+                vec![str_value("alpha"), SortValue::I64(5)],
+                vec![str_value("beta"), SortValue::I64(2)],
+                vec![str_value("beta"), SortValue::I64(-1)],
+                vec![str_value("charlie"), SortValue::Null],
+                vec![str_value("delta"), SortValue::I64(3)],
+                vec![SortValue::Null, SortValue::I64(7)],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_missing_first_and_last() -> crate::Result<()> {
+        let index = create_multi_segment_index()?;
+        let searcher = index.reader()?.searcher();
+        let brands = |key: SortKey| -> crate::Result<Vec<SortValue>> {
+            let top_docs = searcher.search(&AllQuery, &TopDocs::with_limit(2).order_by(key))?;
+            Ok(top_docs
+                .into_iter()
+                .map(|(mut vals, _)| vals.pop().unwrap())
+                .collect())
+        };
+        assert_eq!(
+            brands(SortKey::field("brand", Order::Desc))?,
+            vec![str_value("delta"), str_value("charlie")]
+        );
+        assert_eq!(
+            brands(SortKey::field("brand", Order::Desc).missing(Missing::First))?,
+            vec![SortValue::Null, str_value("delta")]
+        );
+        assert_eq!(
+            brands(SortKey::field("brand", Order::Asc).missing(Missing::First))?,
+            vec![SortValue::Null, str_value("alpha")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_with_offset() -> crate::Result<()> {
+        let index = create_multi_segment_index()?;
+        let searcher = index.reader()?.searcher();
+        // Both segments hold 3 documents, so their ordinals do not follow the commit order.
+        // Segment ids are generated incrementally in tests.
+        let second_segment_ord = (0..2)
+            .max_by_key(|&segment_ord| searcher.segment_reader(segment_ord).segment_id())
+            .unwrap();
+        let first_segment_ord = 1 - second_segment_ord;
+        let collector = TopDocs::with_limit(2)
+            .and_offset(1)
+            .order_by(SortKey::field("price", Order::Asc));
+        let top_docs = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(
+            top_docs,
+            vec![
+                (
+                    vec![SortValue::I64(2)],
+                    DocAddress::new(second_segment_ord, 1)
+                ),
+                (
+                    vec![SortValue::I64(3)],
+                    DocAddress::new(first_segment_ord, 0)
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_doc_id() -> crate::Result<()> {
+        let index = create_multi_segment_index()?;
+        let searcher = index.reader()?.searcher();
+        let collector = TopDocs::with_limit(4).order_by(SortKey::doc_id(Order::Desc));
+        let doc_addresses: Vec<DocAddress> = searcher
+            .search(&AllQuery, &collector)?
+            .into_iter()
+            .map(|(_, doc_address)| doc_address)
+            .collect();
+        assert_eq!(
+            doc_addresses,
+            vec![
+                DocAddress::new(1, 2),
+                DocAddress::new(1, 1),
+                DocAddress::new(1, 0),
+                DocAddress::new(0, 2),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_score_then_field() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT);
+        let rating = schema_builder.add_f64_field("rating", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(title => "beer", rating => 1.5f64))?;
+        index_writer.add_document(doc!(title => "beer beer beer", rating => 0.5f64))?;
+        index_writer.add_document(doc!(title => "beer", rating => 4.5f64))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let query = QueryParser::for_index(&index, vec![title]).parse_query("beer")?;
+        let scores: Vec<(Score, DocAddress)> = searcher.search(&query, &TopDocs::with_limit(3))?;
+
+        let collector = TopDocs::with_limit(3).order_by(SortBy::new([
+            SortKey::score(Order::Asc),
+            SortKey::field("rating", Order::Desc),
+        ]));
+        let top_docs = searcher.search(&query, &collector)?;
+        let doc_addresses: Vec<DocAddress> = top_docs.iter().map(|(_, addr)| *addr).collect();
+        assert_eq!(
+            doc_addresses,
+            vec![
+                DocAddress::new(0, 2),
+                DocAddress::new(0, 0),
+                DocAddress::new(0, 1)
+            ]
+        );
+        // The score is reported exactly.
+        let (best_score, best_doc) = scores[0];
+        assert_eq!(best_doc, DocAddress::new(0, 1));
+        assert_eq!(
+            top_docs[2].0,
+            vec![SortValue::Score(best_score), SortValue::F64(0.5)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_ip_and_bytes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let ip = schema_builder.add_ip_addr_field("ip", FAST);
+        let payload = schema_builder.add_bytes_field("payload", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let ip_low = Ipv6Addr::from(10u128);
+        let ip_high = Ipv6Addr::from(1_000_000u128);
+        index_writer.add_document(doc!(ip => ip_low, payload => vec![2u8]))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(ip => ip_high, payload => vec![1u8, 5u8]))?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let collector = TopDocs::with_limit(2).order_by(SortBy::new([
+            SortKey::field("ip", Order::Desc),
+            SortKey::field("payload", Order::Asc),
+        ]));
+        let values: Vec<Vec<SortValue>> = searcher
+            .search(&AllQuery, &collector)?
+            .into_iter()
+            .map(|(vals, _)| vals)
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                vec![SortValue::IpAddr(ip_high), SortValue::Bytes(vec![1, 5])],
+                vec![SortValue::IpAddr(ip_low), SortValue::Bytes(vec![2])],
+            ]
+        );
+        let collector = TopDocs::with_limit(1).order_by(SortKey::field("payload", Order::Asc));
+        let top_docs = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(top_docs[0].0, vec![SortValue::Bytes(vec![1, 5])]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_not_fast_field() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("size", STORED);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!())?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let collector = TopDocs::with_limit(1).order_by(SortKey::field("size", Order::Asc));
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(crate::TantivyError::SchemaError(_))
+        ));
+        let collector = TopDocs::with_limit(1).order_by(SortKey::field("unknown", Order::Asc));
+        assert!(matches!(
+            searcher.search(&AllQuery, &collector),
+            Err(crate::TantivyError::FieldNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_serde() {
+        let sort_by: SortBy = serde_json::from_str(
+            r#"[
+                {"target": {"fast_field": "brand"}, "order": "Asc", "missing": "first"},
+                {"target": "score", "order": "Desc"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sort_by.keys(),
+            &[
+                SortKey::field("brand", Order::Asc).missing(Missing::First),
+                SortKey {
+                    target: SortTarget::Score,
+                    order: Order::Desc,
+                    missing: Missing::Last,
+                },
+            ]
+        );
+    }
+}
@@ -8,11 +8,14 @@ use serde::{Deserialize, Serialize};
 
 use super::Collector;
 use crate::collector::custom_score_top_collector::CustomScoreTopCollector;
+use crate::collector::sort_key_top_collector::SortKeyTopCollector;
 use crate::collector::top_collector::{ComparableDoc, TopCollector, TopSegmentCollector};
 use crate::collector::tweak_score_top_collector::TweakedScoreTopCollector;
 use crate::collector::{
-    CustomScorer, CustomSegmentScorer, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector,
+    CustomScorer, CustomSegmentScorer, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector, SortBy,
+    SortValue,
 };
+use crate::docset::{DocSet, TERMINATED};
 use crate::fastfield::{FastFieldNotAvailableError, FastValue};
 use crate::query::Weight;
 use crate::{DocAddress, DocId, Order, Score, SegmentOrdinal, SegmentReader, TantivyError};
@@ -186,6 +189,76 @@ impl CustomScorer<u64> for ScorerByField {
     }
 }
 
+/// Top-K by a fast field, which stops collecting a segment early when the segment is
+/// sorted by that same field and order.
+///
+/// In such a segment, documents come in the order of the ranking, so the first
+/// `limit + offset` matching documents are the top documents of the segment.
+struct FastFieldTopCollector {
+    collector: CustomScoreTopCollector<ScorerByField, u64>,
+    field: String,
+    order: Order,
+    num_docs_to_collect: usize,
+}
+
+impl FastFieldTopCollector {
+    fn is_segment_sorted_by_field(&self, segment_reader: &SegmentReader) -> bool {
+        segment_reader
+            .sort_by_fields()
+            .first()
+            .map(|sort_by_field| {
+                sort_by_field.field == self.field && sort_by_field.order == self.order
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl Collector for FastFieldTopCollector {
+    type Fruit = Vec<(u64, DocAddress)>;
+
+    type Child = <CustomScoreTopCollector<ScorerByField, u64> as Collector>::Child;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> crate::Result<Self::Child> {
+        self.collector.for_segment(segment_local_id, segment_reader)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<Self::Fruit>) -> crate::Result<Self::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> crate::Result<Self::Fruit> {
+        if !self.is_segment_sorted_by_field(reader) {
+            return self.collector.collect_segment(weight, segment_ord, reader);
+        }
+        let mut segment_collector = self.for_segment(segment_ord, reader)?;
+        let mut scorer = weight.scorer(reader, 1.0)?;
+        let alive_bitset_opt = reader.alive_bitset();
+        let mut num_collected = 0;
+        let mut doc = scorer.doc();
+        while doc != TERMINATED && num_collected < self.num_docs_to_collect {
+            if alive_bitset_opt.map_or(true, |alive_bitset| alive_bitset.is_alive(doc)) {
+                segment_collector.collect(doc, 0.0);
+                num_collected += 1;
+            }
+            doc = scorer.advance();
+        }
+        Ok(segment_collector.harvest())
+    }
+}
+
 impl TopDocs {
     /// Creates a top score collector, with a number of documents equal to "limit".
     ///
@@ -311,18 +384,28 @@ impl TopDocs {
     ///
     /// To comfortably work with `u64`s, `i64`s, `f64`s, or `date`s, please refer to
     /// the [.order_by_fast_field(...)](TopDocs::order_by_fast_field) method.
+    ///
+    /// If the index is sorted by the same field and order (see
+    /// [`IndexSettings::sort_by_fields`](crate::IndexSettings::sort_by_fields)), the collection
+    /// of each segment stops as soon as `limit + offset` documents have been collected.
     pub fn order_by_u64_field(
         self,
         field: impl ToString,
         order: Order,
     ) -> impl Collector<Fruit = Vec<(u64, DocAddress)>> {
-        CustomScoreTopCollector::new(
-            ScorerByField {
-                field: field.to_string(),
-                order,
-            },
-            self.0.into_tscore(),
-        )
+        let num_docs_to_collect = self.0.limit + self.0.offset;
+        FastFieldTopCollector {
+            collector: CustomScoreTopCollector::new(
+                ScorerByField {
+                    field: field.to_string(),
+                    order: order.clone(),
+                },
+                self.0.into_tscore(),
+            ),
+            field: field.to_string(),
+            order,
+            num_docs_to_collect,
+        }
     }
 
     /// Set top-K to rank documents by a given fast field.
@@ -411,6 +494,64 @@ impl TopDocs {
         }
     }
 
+    /// Ranks the documents on several sort keys.
+    ///
+    /// Each key is either a fast field (of any type, including `str` and `bytes`),
+    /// the score, or the doc id, with its own order and its own
+    /// [`Missing`](crate::collector::Missing) policy for documents without a value.
+    /// Ties on all keys are broken by ascending `DocAddress`.
+    ///
+    /// The fruit contains, for each document, the value of every sort key, in the order of
+    /// the keys. `str` and `bytes` fast fields are sorted on their term ordinals within a segment
+    /// and are only resolved to their actual value for the top documents of each segment.
+    ///
+    /// If a field is not a fast field or does not exist, an error is returned at the moment of
+    /// the search.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tantivy::schema::{Schema, FAST, STRING};
+    /// # use tantivy::{doc, Index, DocAddress, Order};
+    /// # use tantivy::query::AllQuery;
+    /// use tantivy::collector::{Missing, SortBy, SortKey, SortValue, TopDocs};
+    ///
+    /// # fn main() -> tantivy::Result<()> {
+    /// #   let mut schema_builder = Schema::builder();
+    /// #   let brand = schema_builder.add_text_field("brand", STRING | FAST);
+    /// #   let price = schema_builder.add_u64_field("price", FAST);
+    /// #   let schema = schema_builder.build();
+    /// #   let index = Index::create_in_ram(schema);
+    /// #   let mut index_writer = index.writer_with_num_threads(1, 20_000_000)?;
+    /// #   index_writer.add_document(doc!(brand => "zeta", price => 10u64))?;
+    /// #   index_writer.add_document(doc!(brand => "alpha", price => 30u64))?;
+    /// #   index_writer.add_document(doc!(price => 20u64))?;
+    /// #   index_writer.add_document(doc!(brand => "alpha", price => 40u64))?;
+    /// #   index_writer.commit()?;
+    /// #   let searcher = index.reader()?.searcher();
+    /// let sort_by = SortBy::new([
+    ///     SortKey::field("brand", Order::Asc).missing(Missing::First),
+    ///     SortKey::field("price", Order::Desc),
+    /// ]);
+    /// let top_docs = searcher.search(&AllQuery, &TopDocs::with_limit(3).order_by(sort_by))?;
+    /// assert_eq!(
+    ///     top_docs,
+    ///     vec![
+    ///         (vec![SortValue::Null, SortValue::U64(20)], DocAddress::new(0, 2)),
+    ///         (vec![SortValue::Str("alpha".to_string()), SortValue::U64(40)], DocAddress::new(0, 3)),
+    ///         (vec![SortValue::Str("alpha".to_string()), SortValue::U64(30)], DocAddress::new(0, 1)),
+    ///     ]
+    /// );
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn order_by(
+        self,
+        sort_by: impl Into<SortBy>,
+    ) -> impl Collector<Fruit = Vec<(Vec<SortValue>, DocAddress)>> {
+        SortKeyTopCollector::new(sort_by.into(), self.0.limit, self.0.offset)
+    }
+
     /// Ranks the documents using a custom score.
     ///
     /// This method offers a convenient way to tweak or replace
@@ -872,8 +1013,8 @@ mod tests {
     use crate::time::format_description::well_known::Rfc3339;
     use crate::time::OffsetDateTime;
     use crate::{
-        assert_nearly_equals, DateTime, DocAddress, DocId, Index, IndexWriter, Order, Score,
-        SegmentReader,
+        assert_nearly_equals, DateTime, DocAddress, DocId, Index, IndexSettings, IndexSortByField,
+        IndexWriter, Order, Score, SegmentReader,
     };
 
     fn make_index() -> crate::Result<Index> {
@@ -1314,6 +1455,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_top_field_collector_sorted_index() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field(TITLE, TEXT);
+        let size = schema_builder.add_i64_field(SIZE, FAST);
+        let schema = schema_builder.build();
+        let sorted_index = Index::builder()
+            .schema(schema.clone())
+            .settings(IndexSettings {
+                sort_by_fields: vec![IndexSortByField {
+                    field: SIZE.to_string(),
+                    order: Order::Desc,
+                }],
+                ..Default::default()
+            })
+            .create_in_ram()?;
+        let unsorted_index = Index::create_in_ram(schema);
+        for index in [&sorted_index, &unsorted_index] {
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            for batch in 0..3i64 {
+                for i in 0..20i64 {
+                    let size_val = (i * 7 + batch * 3) % 25 - 5;
+                    let title_val = if i % 3 == 0 {
+                        "stout beer"
+                    } else {
+                        "lager beer"
+                    };
+                    index_writer.add_document(doc!(title => title_val, size => size_val))?;
+                }
+                index_writer.add_document(doc!(title => "mystery beer"))?;
+                index_writer.commit()?;
+            }
+        }
+        for (query_str, order, offset) in [
+            ("beer", Order::Desc, 0),
+            ("stout", Order::Desc, 2),
+            ("beer", Order::Asc, 1),
+        ] {
+            let top_values = |index: &Index| -> crate::Result<Vec<i64>> {
+                let query_parser = QueryParser::for_index(index, vec![title]);
+                let query = query_parser.parse_query(query_str)?;
+                let collector = TopDocs::with_limit(4)
+                    .and_offset(offset)
+                    .order_by_fast_field::<i64>(SIZE, order.clone());
+                let top_docs = index.reader()?.searcher().search(&query, &collector)?;
+                Ok(top_docs.into_iter().map(|(value, _)| value).collect())
+            };
+            let sorted_top_values = top_values(&sorted_index)?;
+            assert_eq!(sorted_top_values.len(), 4);
+            assert_eq!(sorted_top_values, top_values(&unsorted_index)?);
+        }
+        Ok(())
+    }
+
     fn index(
         query: &str,
         query_field: Field,
@@ -1,16 +1,20 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fmt, io};
 
 use crate::collector::Collector;
 use crate::core::Executor;
 use crate::index::{SegmentId, SegmentReader};
-use crate::query::{Bm25StatisticsProvider, EnableScoring, Query};
+use crate::query::profile::{self, Profiler};
+use crate::query::{
+    Bm25StatisticsProvider, EnableScoring, Query, QueryCache, QueryProfile, Weight,
+};
 use crate::schema::document::DocumentDeserialize;
 use crate::schema::{Schema, Term};
 use crate::space_usage::SearcherSpaceUsage;
 use crate::store::{CacheStats, StoreReader};
-use crate::{DocAddress, Index, Opstamp, TrackedObject};
+use crate::{DocAddress, Index, Opstamp, TantivyError, TrackedObject};
 
 /// Identifies the searcher generation accessed by a [`Searcher`].
 ///
@@ -90,6 +94,34 @@ impl Searcher {
         store_reader.get(doc_address.doc_id)
     }
 
+    /// Returns the address of the document with the given unique key, if any.
+    ///
+    /// `key` must be a term of the unique key field of the schema.
+    /// See [`SchemaBuilder::set_unique_key_field`](crate::schema::SchemaBuilder::set_unique_key_field).
+    pub fn doc_address_by_key(&self, key: &Term) -> crate::Result<Option<DocAddress>> {
+        if self.schema().unique_key_field() != Some(key.field()) {
+            return Err(TantivyError::InvalidArgument(format!(
+                "{:?} is not a term of the unique key field.",
+                key
+            )));
+        }
+        for (segment_ord, segment_reader) in self.inner.segment_readers.iter().enumerate() {
+            if let Some(doc_id) = segment_reader.doc_id_by_key(key)? {
+                return Ok(Some(DocAddress::new(segment_ord as u32, doc_id)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fetches the document with the given unique key, if any.
+    ///
+    /// See [`Searcher::doc_address_by_key`].
+    pub fn doc_by_key<D: DocumentDeserialize>(&self, key: &Term) -> crate::Result<Option<D>> {
+        self.doc_address_by_key(key)?
+            .map(|doc_address| self.doc(doc_address))
+            .transpose()
+    }
+
     /// The cache stats for the underlying store reader.
     ///
     /// Aggregates the sum for each segment store reader.
@@ -114,11 +146,24 @@ impl Searcher {
         store_reader.get_async(doc_address.doc_id, executor).await
     }
 
+    /// Returns the [`QueryCache`] of the [`IndexReader`](crate::IndexReader) this searcher
+    /// was obtained from, if any.
+    pub fn query_cache(&self) -> Option<&QueryCache> {
+        self.inner.query_cache.as_ref()
+    }
+
     /// Access the schema associated with the index of this searcher.
     pub fn schema(&self) -> &Schema {
         &self.inner.schema
     }
 
+    /// Returns the version of the schema of this searcher.
+    ///
+    /// See [`IndexWriter::update_schema`](crate::IndexWriter::update_schema).
+    pub(crate) fn schema_version(&self) -> u32 {
+        self.inner.schema_version
+    }
+
     /// Returns the overall number of documents in the index.
     pub fn num_docs(&self) -> u64 {
         self.inner
@@ -225,16 +270,111 @@ impl Searcher {
         enabled_scoring: EnableScoring,
     ) -> crate::Result<C::Fruit> {
         let weight = query.weight(enabled_scoring)?;
+        self.collect_with_weight(weight.as_ref(), collector, executor)
+    }
+
+    fn collect_with_weight<C: Collector>(
+        &self,
+        weight: &dyn Weight,
+        collector: &C,
+        executor: &Executor,
+    ) -> crate::Result<C::Fruit> {
         let segment_readers = self.segment_readers();
         let fruits = executor.map(
             |(segment_ord, segment_reader)| {
-                collector.collect_segment(weight.as_ref(), segment_ord as u32, segment_reader)
+                collector.collect_segment(weight, segment_ord as u32, segment_reader)
             },
             segment_readers.iter().enumerate(),
         )?;
         collector.merge_fruits(fruits)
     }
 
+    /// Same as [`search(...)`](Searcher::search), but also returns a [`QueryProfile`]
+    /// detailing where time was spent.
+    ///
+    /// The profile mirrors the structure of the query. For each query and each segment, it
+    /// reports the time spent creating the scorer, and in `advance`, `seek` and `score`. It also
+    /// reports the time spent collecting each segment, as well as the term dictionary lookups
+    /// and doc store fetches performed during the search.
+    ///
+    /// Profiling has an overhead and disables some optimizations: it should only be used to
+    /// investigate slow queries.
+    pub fn search_with_profile<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+    ) -> crate::Result<(C::Fruit, QueryProfile)> {
+        let profiler = Arc::new(Profiler::default());
+        let enabled_scoring = if collector.requires_scoring() {
+            EnableScoring::enabled_from_searcher(self)
+        } else {
+            EnableScoring::disabled_from_searcher(self)
+        };
+        let weight = profiler.install(|| profile::weight(query, enabled_scoring))?;
+        let executor = self.inner.index.search_executor();
+        let segment_readers = self.segment_readers();
+        let fruits = executor.map(
+            |(segment_ord, segment_reader)| {
+                profiler.install(|| {
+                    let start = Instant::now();
+                    let fruit = collector.collect_segment(
+                        weight.as_ref(),
+                        segment_ord as u32,
+                        segment_reader,
+                    );
+                    profiler.record_collection(
+                        segment_ord as u32,
+                        segment_reader.segment_id(),
+                        start,
+                    );
+                    fruit
+                })
+            },
+            segment_readers.iter().enumerate(),
+        )?;
+        let start = Instant::now();
+        let fruit = collector.merge_fruits(fruits)?;
+        profiler.record_merge_fruits(start);
+        Ok((fruit, profiler.profile()))
+    }
+
+    /// Asynchronous version of [`search(...)`](Searcher::search).
+    ///
+    /// The data required by the query is first prefetched asynchronously:
+    /// - the term dictionary blocks of the query terms, used to compute the BM25 statistics,
+    /// - for each segment, the data listed by [`Weight::warmup`] (postings, positions, fieldnorms,
+    ///   fast field columns...).
+    ///
+    /// The collector then runs synchronously, on the search executor of the index.
+    ///
+    /// Prefetching only prevents the collection from blocking on I/O if the
+    /// [`Directory`](crate::Directory) caches the data it reads asynchronously.
+    #[cfg(feature = "quickwit")]
+    pub async fn search_async<C: Collector>(
+        &self,
+        query: &dyn Query,
+        collector: &C,
+    ) -> crate::Result<C::Fruit> {
+        let enabled_scoring = if collector.requires_scoring() {
+            let mut terms: Vec<Term> = Vec::new();
+            query.query_terms(&mut |term, _| terms.push(term.clone()));
+            futures_util::future::try_join_all(terms.iter().map(|term| self.doc_freq_async(term)))
+                .await?;
+            EnableScoring::enabled_from_searcher(self)
+        } else {
+            EnableScoring::disabled_from_searcher(self)
+        };
+        let weight = query.weight(enabled_scoring)?;
+        futures_util::future::try_join_all(
+            self.segment_readers()
+                .iter()
+                .map(|segment_reader| weight.warmup(segment_reader)),
+        )
+        .await?;
+        let executor = self.inner.index.search_executor();
+        self.collect_with_weight(weight.as_ref(), collector, executor)
+    }
+
     /// Summarize total space usage of this searcher.
     pub fn space_usage(&self) -> io::Result<SearcherSpaceUsage> {
         let mut space_usage = SearcherSpaceUsage::new();
@@ -257,20 +397,24 @@ impl From<Arc<SearcherInner>> for Searcher {
 /// the destruction of the `Searcher`.
 pub(crate) struct SearcherInner {
     schema: Schema,
+    schema_version: u32,
     index: Index,
     segment_readers: Vec<SegmentReader>,
     store_readers: Vec<StoreReader>,
-    generation: TrackedObject<SearcherGeneration>,
+    pub(crate) generation: TrackedObject<SearcherGeneration>,
+    query_cache: Option<QueryCache>,
 }
 
 impl SearcherInner {
     /// Creates a new `Searcher`
     pub(crate) fn new(
         schema: Schema,
+        schema_version: u32,
         index: Index,
         segment_readers: Vec<SegmentReader>,
         generation: TrackedObject<SearcherGeneration>,
         doc_store_cache_num_blocks: usize,
+        query_cache: Option<QueryCache>,
     ) -> io::Result<SearcherInner> {
         assert_eq!(
             &segment_readers
@@ -287,10 +431,12 @@ impl SearcherInner {
 
         Ok(SearcherInner {
             schema,
+            schema_version,
             index,
             segment_readers,
             store_readers,
             generation,
+            query_cache,
         })
     }
 }
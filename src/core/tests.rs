@@ -466,3 +466,69 @@ fn test_non_text_json_term_freq_bitpacked() {
         assert_eq!(postings.term_freq(), 1u32);
     }
 }
+
+#[cfg(feature = "quickwit")]
+#[test]
+fn test_search_async() -> crate::Result<()> {
+    use futures::executor::block_on;
+
+    use crate::collector::TopDocs;
+    use crate::query::{BooleanQuery, Occur, PhraseQuery, Query, RangeQuery, RegexQuery};
+    use crate::schema::FAST;
+
+    let mut schema_builder = Schema::builder();
+    let text = schema_builder.add_text_field("text", TEXT);
+    let num = schema_builder.add_u64_field("num", FAST);
+    let index = Index::create_in_ram(schema_builder.build());
+    let mut index_writer: IndexWriter = index.writer_for_tests()?;
+    for i in 0..20u64 {
+        let body = if i % 2 == 0 {
+            "hello happy world"
+        } else {
+            "happy days"
+        };
+        index_writer.add_document(doc!(text => body, num => i))?;
+        if i == 10 {
+            index_writer.commit()?;
+        }
+    }
+    index_writer.commit()?;
+    let searcher = index.reader()?.searcher();
+    let queries: Vec<Box<dyn Query>> = vec![
+        Box::new(TermQuery::new(
+            Term::from_field_text(text, "happy"),
+            IndexRecordOption::WithFreqs,
+        )),
+        Box::new(PhraseQuery::new(vec![
+            Term::from_field_text(text, "happy"),
+            Term::from_field_text(text, "world"),
+        ])),
+        Box::new(RegexQuery::from_pattern("h.*o", text)?),
+        Box::new(BooleanQuery::new(vec![
+            (
+                Occur::Must,
+                Box::new(RangeQuery::new_u64("num".to_string(), 5..15)) as Box<dyn Query>,
+            ),
+            (
+                Occur::Should,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(text, "days"),
+                    IndexRecordOption::WithFreqs,
+                )),
+            ),
+        ])),
+    ];
+    for query in &queries {
+        assert_eq!(
+            block_on(searcher.search_async(query.as_ref(), &Count))?,
+            searcher.search(query.as_ref(), &Count)?
+        );
+        let top_docs = TopDocs::with_limit(20);
+        assert_eq!(
+            block_on(searcher.search_async(query.as_ref(), &top_docs))?,
+            searcher.search(query.as_ref(), &top_docs)?
+        );
+    }
+    assert_eq!(block_on(searcher.search_async(&queries[1], &Count))?, 10);
+    Ok(())
+}
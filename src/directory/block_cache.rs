@@ -0,0 +1,809 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fmt, io};
+
+use common::HasLen;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+use crate::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use crate::directory::{
+    Directory, DirectoryLock, FileHandle, Lock, OwnedBytes, WatchCallback, WatchHandle, WritePtr,
+};
+
+/// Maximum number of files whose counters are kept by a [`BlockCache`].
+const MAX_NUM_FILE_COUNTERS: usize = 1_000;
+
+/// Identifier of a directory registered with a [`BlockCache`], namespacing the blocks of
+/// its files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlockCacheDirectoryId(u64);
+
+type BlockKey = (BlockCacheDirectoryId, PathBuf, usize);
+
+/// Eviction policy of a [`BlockCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvictionPolicy {
+    /// Evicts the least recently used block.
+    #[default]
+    Lru,
+    /// Evicts the first block not used since the clock hand last went over it.
+    ///
+    /// A cheaper approximation of LRU.
+    Clock,
+}
+
+/// Settings of a [`BlockCache`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockCacheSettings {
+    /// Maximum number of bytes of the blocks held by the cache.
+    pub capacity_num_bytes: usize,
+    /// Size of the blocks read from the underlying files and kept in the cache.
+    pub block_num_bytes: usize,
+    /// Policy used to pick the blocks to evict.
+    pub eviction_policy: EvictionPolicy,
+}
+
+impl Default for BlockCacheSettings {
+    fn default() -> BlockCacheSettings {
+        BlockCacheSettings {
+            capacity_num_bytes: 64 << 20,
+            block_num_bytes: 64 << 10,
+            eviction_policy: EvictionPolicy::default(),
+        }
+    }
+}
+
+/// Hits and misses of a [`BlockCache`].
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockCacheCounters {
+    /// Number of blocks read from the cache.
+    pub hit: usize,
+    /// Number of blocks read from the underlying files.
+    pub miss: usize,
+}
+
+/// Statistics of a [`BlockCache`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlockCacheInfo {
+    /// Counters of all of the files.
+    pub counters: BlockCacheCounters,
+    /// Counters of the most recently read files, at most 1,000 of them.
+    ///
+    /// The counters of files with the same path in several directories are summed.
+    pub files: HashMap<PathBuf, BlockCacheCounters>,
+    /// Number of blocks held by the cache.
+    pub num_blocks: usize,
+    /// Number of bytes of the blocks held by the cache.
+    pub num_bytes: usize,
+}
+
+struct ClockSlot {
+    key: BlockKey,
+    block: OwnedBytes,
+    referenced: bool,
+}
+
+/// Blocks evicted following the CLOCK algorithm.
+#[derive(Default)]
+struct ClockBlocks {
+    slots: Vec<Option<ClockSlot>>,
+    index: HashMap<BlockKey, usize>,
+    free_slots: Vec<usize>,
+    hand: usize,
+}
+
+impl ClockBlocks {
+    fn get(&mut self, key: &BlockKey) -> Option<OwnedBytes> {
+        let slot = self.slots[*self.index.get(key)?].as_mut()?;
+        slot.referenced = true;
+        Some(slot.block.clone())
+    }
+
+    fn insert(&mut self, key: BlockKey, block: OwnedBytes) -> Option<OwnedBytes> {
+        let replaced = self.remove(&key);
+        let slot = Some(ClockSlot {
+            key: key.clone(),
+            block,
+            referenced: true,
+        });
+        let slot_ord = match self.free_slots.pop() {
+            Some(slot_ord) => {
+                self.slots[slot_ord] = slot;
+                slot_ord
+            }
+            None => {
+                self.slots.push(slot);
+                self.slots.len() - 1
+            }
+        };
+        self.index.insert(key, slot_ord);
+        replaced
+    }
+
+    fn remove(&mut self, key: &BlockKey) -> Option<OwnedBytes> {
+        let slot_ord = self.index.remove(key)?;
+        self.free_slots.push(slot_ord);
+        self.slots[slot_ord].take().map(|slot| slot.block)
+    }
+
+    fn evict(&mut self) -> Option<(BlockKey, OwnedBytes)> {
+        if self.index.is_empty() {
+            return None;
+        }
+        loop {
+            self.hand = (self.hand + 1) % self.slots.len();
+            match &mut self.slots[self.hand] {
+                Some(slot) if slot.referenced => slot.referenced = false,
+                Some(slot) => {
+                    let key = slot.key.clone();
+                    let block = self.remove(&key)?;
+                    return Some((key, block));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+enum Blocks {
+    Lru(LruCache<BlockKey, OwnedBytes>),
+    Clock(ClockBlocks),
+}
+
+impl Blocks {
+    fn get(&mut self, key: &BlockKey) -> Option<OwnedBytes> {
+        match self {
+            Blocks::Lru(lru) => lru.get(key).cloned(),
+            Blocks::Clock(clock) => clock.get(key),
+        }
+    }
+
+    fn insert(&mut self, key: BlockKey, block: OwnedBytes) -> Option<OwnedBytes> {
+        match self {
+            Blocks::Lru(lru) => lru.put(key, block),
+            Blocks::Clock(clock) => clock.insert(key, block),
+        }
+    }
+
+    fn remove(&mut self, key: &BlockKey) -> Option<OwnedBytes> {
+        match self {
+            Blocks::Lru(lru) => lru.pop(key),
+            Blocks::Clock(clock) => clock.remove(key),
+        }
+    }
+
+    fn evict(&mut self) -> Option<(BlockKey, OwnedBytes)> {
+        match self {
+            Blocks::Lru(lru) => lru.pop_lru(),
+            Blocks::Clock(clock) => clock.evict(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Blocks::Lru(lru) => lru.len(),
+            Blocks::Clock(clock) => clock.index.len(),
+        }
+    }
+}
+
+struct InnerBlockCache {
+    blocks: Blocks,
+    // Ordinals of the blocks held by the cache, by file.
+    file_blocks: HashMap<(BlockCacheDirectoryId, PathBuf), HashSet<usize>>,
+    num_bytes: usize,
+    counters: BlockCacheCounters,
+    files: LruCache<PathBuf, BlockCacheCounters>,
+    next_directory_id: u64,
+}
+
+impl InnerBlockCache {
+    fn insert_block(&mut self, key: BlockKey, block: OwnedBytes) {
+        let (directory_id, path, block_ord) = &key;
+        self.file_blocks
+            .entry((*directory_id, path.clone()))
+            .or_default()
+            .insert(*block_ord);
+        self.num_bytes += block.len();
+        if let Some(replaced) = self.blocks.insert(key, block) {
+            self.num_bytes -= replaced.len();
+        }
+    }
+
+    fn evict_block(&mut self) -> bool {
+        let Some(((directory_id, path, block_ord), evicted)) = self.blocks.evict() else {
+            return false;
+        };
+        self.num_bytes -= evicted.len();
+        let file_key = (directory_id, path);
+        if let Some(block_ords) = self.file_blocks.get_mut(&file_key) {
+            block_ords.remove(&block_ord);
+            if block_ords.is_empty() {
+                self.file_blocks.remove(&file_key);
+            }
+        }
+        true
+    }
+
+    fn remove_file_blocks(&mut self, directory_id: BlockCacheDirectoryId, path: &Path) {
+        let file_key = (directory_id, path.to_path_buf());
+        let Some(block_ords) = self.file_blocks.remove(&file_key) else {
+            return;
+        };
+        let (directory_id, path) = file_key;
+        let mut key = (directory_id, path, 0);
+        for block_ord in block_ords {
+            key.2 = block_ord;
+            if let Some(block) = self.blocks.remove(&key) {
+                self.num_bytes -= block.len();
+            }
+        }
+    }
+
+    fn record(&mut self, path: &Path, num_hits: usize, num_misses: usize) {
+        self.counters.hit += num_hits;
+        self.counters.miss += num_misses;
+        if let Some(file_counters) = self.files.get_mut(path) {
+            file_counters.hit += num_hits;
+            file_counters.miss += num_misses;
+        } else {
+            self.files.put(
+                path.to_path_buf(),
+                BlockCacheCounters {
+                    hit: num_hits,
+                    miss: num_misses,
+                },
+            );
+        }
+    }
+}
+
+/// A memory-bounded cache of blocks of files, that can be shared by several directories.
+///
+/// [`MmapDirectory`](crate::directory::MmapDirectory) relies on the page cache of the OS.
+/// Other [`Directory`] implementations can be wrapped in a [`BlockCachedDirectory`], or opt
+/// into a `BlockCache` by wrapping the file handles they return from
+/// [`get_file_handle()`](crate::Directory::get_file_handle) with
+/// [`BlockCache::wrap_file_handle()`]. The files are then read by blocks of
+/// [`block_num_bytes`](BlockCacheSettings::block_num_bytes), which are kept in memory until
+/// they are evicted.
+///
+/// Blocks are identified by the id returned by [`BlockCache::register_directory()`] and the
+/// path of their file: a directory replacing the content of a file that may have been read
+/// through the cache must call [`BlockCache::invalidate()`].
+#[derive(Clone)]
+pub struct BlockCache {
+    settings: Arc<BlockCacheSettings>,
+    inner: Arc<Mutex<InnerBlockCache>>,
+}
+
+impl fmt::Debug for BlockCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockCache")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+impl BlockCache {
+    /// Creates an empty cache.
+    ///
+    /// # Panics
+    /// Panics if `settings.block_num_bytes` is 0.
+    pub fn new(settings: BlockCacheSettings) -> BlockCache {
+        assert!(settings.block_num_bytes > 0, "Blocks cannot be empty");
+        let blocks = match settings.eviction_policy {
+            EvictionPolicy::Lru => Blocks::Lru(LruCache::unbounded()),
+            EvictionPolicy::Clock => Blocks::Clock(ClockBlocks::default()),
+        };
+        BlockCache {
+            settings: Arc::new(settings),
+            inner: Arc::new(Mutex::new(InnerBlockCache {
+                blocks,
+                file_blocks: HashMap::new(),
+                num_bytes: 0,
+                counters: BlockCacheCounters::default(),
+                files: LruCache::new(
+                    NonZeroUsize::new(MAX_NUM_FILE_COUNTERS).expect("non-zero capacity"),
+                ),
+                next_directory_id: 0,
+            })),
+        }
+    }
+
+    /// Returns a new id, namespacing the blocks of the files of a directory.
+    ///
+    /// Directories sharing the cache must each register, so that their files with the same
+    /// path do not share blocks.
+    pub fn register_directory(&self) -> BlockCacheDirectoryId {
+        let mut inner = self.inner.lock().unwrap();
+        let directory_id = BlockCacheDirectoryId(inner.next_directory_id);
+        inner.next_directory_id += 1;
+        directory_id
+    }
+
+    /// Returns a file handle reading `file_handle`, the file at `path` of the directory
+    /// `directory_id`, through the cache.
+    pub fn wrap_file_handle(
+        &self,
+        directory_id: BlockCacheDirectoryId,
+        path: &Path,
+        file_handle: Arc<dyn FileHandle>,
+    ) -> Arc<dyn FileHandle> {
+        Arc::new(BlockCachedFileHandle {
+            directory_id,
+            path: path.to_path_buf(),
+            underlying: file_handle,
+            cache: self.clone(),
+        })
+    }
+
+    /// Removes the blocks and the counters of a file of the directory `directory_id`.
+    pub fn invalidate(&self, directory_id: BlockCacheDirectoryId, path: &Path) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_file_blocks(directory_id, path);
+        inner.files.pop(path);
+    }
+
+    /// Returns the statistics of the cache.
+    pub fn get_cache_info(&self) -> BlockCacheInfo {
+        let inner = self.inner.lock().unwrap();
+        BlockCacheInfo {
+            counters: inner.counters.clone(),
+            files: inner
+                .files
+                .iter()
+                .map(|(path, counters)| (path.clone(), counters.clone()))
+                .collect(),
+            num_blocks: inner.blocks.len(),
+            num_bytes: inner.num_bytes,
+        }
+    }
+
+    fn insert(&self, inner: &mut InnerBlockCache, key: BlockKey, block: OwnedBytes) {
+        if block.len() > self.settings.capacity_num_bytes {
+            return;
+        }
+        inner.insert_block(key, block);
+        while inner.num_bytes > self.settings.capacity_num_bytes && inner.evict_block() {}
+    }
+}
+
+/// File handle reading an underlying file handle by blocks, through a [`BlockCache`].
+struct BlockCachedFileHandle {
+    directory_id: BlockCacheDirectoryId,
+    path: PathBuf,
+    underlying: Arc<dyn FileHandle>,
+    cache: BlockCache,
+}
+
+impl fmt::Debug for BlockCachedFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BlockCachedFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for BlockCachedFileHandle {
+    fn len(&self) -> usize {
+        self.underlying.len()
+    }
+}
+
+impl FileHandle for BlockCachedFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.is_empty() {
+            return Ok(OwnedBytes::empty());
+        }
+        let block_num_bytes = self.cache.settings.block_num_bytes;
+        let first_block = range.start / block_num_bytes;
+        let last_block = (range.end - 1) / block_num_bytes;
+        let mut blocks: Vec<Option<OwnedBytes>> = {
+            let mut inner = self.cache.inner.lock().unwrap();
+            (first_block..=last_block)
+                .map(|block_ord| {
+                    inner
+                        .blocks
+                        .get(&(self.directory_id, self.path.clone(), block_ord))
+                })
+                .collect()
+        };
+        let num_misses = blocks.iter().filter(|block| block.is_none()).count();
+        if num_misses > 0 {
+            // The missing blocks are read at once, outside of the lock.
+            let first_missing = first_block + blocks.iter().position(Option::is_none).unwrap();
+            let last_missing = first_block + blocks.iter().rposition(Option::is_none).unwrap();
+            let missing_start = first_missing * block_num_bytes;
+            let missing_end = ((last_missing + 1) * block_num_bytes).min(self.underlying.len());
+            let mut missing_data = self.underlying.read_bytes(missing_start..missing_end)?;
+            let mut inner = self.cache.inner.lock().unwrap();
+            for block_ord in first_missing..=last_missing {
+                let block_len = block_num_bytes.min(missing_data.len());
+                let (block, remaining) = missing_data.split(block_len);
+                missing_data = remaining;
+                let block_opt = &mut blocks[block_ord - first_block];
+                if block_opt.is_none() {
+                    // Each block gets its own allocation, so that evicting it frees its memory
+                    // even if other blocks of the same read remain cached.
+                    let block = OwnedBytes::new(block.as_slice().to_vec());
+                    let key = (self.directory_id, self.path.clone(), block_ord);
+                    self.cache.insert(&mut inner, key, block.clone());
+                    *block_opt = Some(block);
+                }
+            }
+        }
+        self.cache
+            .inner
+            .lock()
+            .unwrap()
+            .record(&self.path, blocks.len() - num_misses, num_misses);
+
+        let first_block_start = first_block * block_num_bytes;
+        if let [Some(block)] = &blocks[..] {
+            return Ok(block.slice(range.start - first_block_start..range.end - first_block_start));
+        }
+        let mut data = Vec::with_capacity(range.len());
+        for (block_ord, block) in (first_block..).zip(blocks) {
+            let block = block.expect("all blocks were fetched");
+            let block_start = block_ord * block_num_bytes;
+            let start = range.start.max(block_start) - block_start;
+            let end = range.end.min(block_start + block.len()) - block_start;
+            data.extend_from_slice(&block.as_slice()[start..end]);
+        }
+        Ok(OwnedBytes::new(data))
+    }
+}
+
+/// A [`Directory`] reading the files of an underlying directory through a [`BlockCache`].
+///
+/// The blocks of a file are invalidated when it is deleted, opened for writing or atomically
+/// written through this directory. The cache may be shared with other directories.
+#[derive(Clone)]
+pub struct BlockCachedDirectory {
+    underlying: Box<dyn Directory>,
+    cache: BlockCache,
+    directory_id: BlockCacheDirectoryId,
+}
+
+impl fmt::Debug for BlockCachedDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BlockCachedDirectory({:?})", self.underlying)
+    }
+}
+
+impl BlockCachedDirectory {
+    /// Wraps `underlying`, reading its files through `cache`.
+    pub fn wrap<T: Into<Box<dyn Directory>>>(
+        underlying: T,
+        cache: BlockCache,
+    ) -> BlockCachedDirectory {
+        let directory_id = cache.register_directory();
+        BlockCachedDirectory {
+            underlying: underlying.into(),
+            cache,
+            directory_id,
+        }
+    }
+
+    /// Returns the cache used by this directory.
+    pub fn cache(&self) -> &BlockCache {
+        &self.cache
+    }
+}
+
+impl Directory for BlockCachedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let file_handle = self.underlying.get_file_handle(path)?;
+        Ok(self
+            .cache
+            .wrap_file_handle(self.directory_id, path, file_handle))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.underlying.delete(path)?;
+        self.cache.invalidate(self.directory_id, path);
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.underlying.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let write_ptr = self.underlying.open_write(path)?;
+        self.cache.invalidate(self.directory_id, path);
+        Ok(write_ptr)
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        self.underlying.atomic_read(path)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.underlying.atomic_write(path, data)?;
+        self.cache.invalidate(self.directory_id, path);
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.underlying.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.underlying.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        self.underlying.watch(watch_callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::ops::Range;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use common::HasLen;
+
+    use super::{
+        BlockCache, BlockCacheCounters, BlockCacheSettings, BlockCachedDirectory, EvictionPolicy,
+        MAX_NUM_FILE_COUNTERS,
+    };
+    use crate::collector::Count;
+    use crate::directory::{
+        Directory, FileHandle, FileSlice, OwnedBytes, RamDirectory, TerminatingWrite,
+    };
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    /// Counts the reads of the underlying file.
+    #[derive(Debug)]
+    struct CountingFileHandle {
+        file_slice: FileSlice,
+        num_reads: Arc<AtomicUsize>,
+    }
+
+    impl HasLen for CountingFileHandle {
+        fn len(&self) -> usize {
+            self.file_slice.len()
+        }
+    }
+
+    impl FileHandle for CountingFileHandle {
+        fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+            self.num_reads.fetch_add(1, Ordering::SeqCst);
+            self.file_slice.read_bytes_slice(range)
+        }
+    }
+
+    fn counting_file(data: Vec<u8>) -> (Arc<dyn FileHandle>, Arc<AtomicUsize>) {
+        let num_reads = Arc::new(AtomicUsize::default());
+        let file_handle = CountingFileHandle {
+            file_slice: FileSlice::from(data),
+            num_reads: num_reads.clone(),
+        };
+        (Arc::new(file_handle), num_reads)
+    }
+
+    fn test_block_cache_aux(eviction_policy: EvictionPolicy) -> io::Result<()> {
+        let cache = BlockCache::new(BlockCacheSettings {
+            capacity_num_bytes: 30,
+            block_num_bytes: 10,
+            eviction_policy,
+        });
+        let data: Vec<u8> = (0..95).collect();
+        let (underlying, num_reads) = counting_file(data.clone());
+        let directory_id = cache.register_directory();
+        let file = cache.wrap_file_handle(directory_id, Path::new("file"), underlying);
+        assert_eq!(file.len(), 95);
+        for start in 0..95 {
+            for end in start..=95 {
+                assert_eq!(file.read_bytes(start..end)?.as_slice(), &data[start..end]);
+            }
+        }
+        let info = cache.get_cache_info();
+        assert!(info.num_bytes <= 30);
+        assert_eq!(info.num_blocks, 3);
+        assert!(info.counters.hit > 0);
+        assert_eq!(info.files[Path::new("file")], info.counters);
+
+        // Blocks held in the cache are not read again.
+        let num_reads_before = num_reads.load(Ordering::SeqCst);
+        assert_eq!(file.read_bytes(5..15)?.as_slice(), &data[5..15]);
+        assert_eq!(num_reads.load(Ordering::SeqCst), num_reads_before + 1);
+        assert_eq!(file.read_bytes(5..15)?.as_slice(), &data[5..15]);
+        assert_eq!(num_reads.load(Ordering::SeqCst), num_reads_before + 1);
+
+        cache.invalidate(directory_id, Path::new("file"));
+        let info = cache.get_cache_info();
+        assert_eq!(info.num_bytes, 0);
+        assert_eq!(info.num_blocks, 0);
+        assert!(info.files.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cache_lru() -> io::Result<()> {
+        test_block_cache_aux(EvictionPolicy::Lru)
+    }
+
+    #[test]
+    fn test_block_cache_clock() -> io::Result<()> {
+        test_block_cache_aux(EvictionPolicy::Clock)
+    }
+
+    #[test]
+    fn test_block_cache_shared_between_files() -> io::Result<()> {
+        let cache = BlockCache::new(BlockCacheSettings {
+            capacity_num_bytes: 100,
+            block_num_bytes: 10,
+            eviction_policy: EvictionPolicy::Lru,
+        });
+        let (underlying_a, _) = counting_file(vec![1u8; 20]);
+        let (underlying_b, _) = counting_file(vec![2u8; 20]);
+        let directory_id = cache.register_directory();
+        let file_a = cache.wrap_file_handle(directory_id, Path::new("a"), underlying_a);
+        let file_b = cache.wrap_file_handle(directory_id, Path::new("b"), underlying_b);
+        assert_eq!(file_a.read_bytes(0..20)?.as_slice(), &[1u8; 20]);
+        assert_eq!(file_b.read_bytes(0..5)?.as_slice(), &[2u8; 5]);
+        assert_eq!(file_b.read_bytes(5..10)?.as_slice(), &[2u8; 5]);
+        let info = cache.get_cache_info();
+        assert_eq!(info.num_bytes, 30);
+        assert_eq!(
+            info.files[Path::new("a")],
+            BlockCacheCounters { hit: 0, miss: 2 }
+        );
+        assert_eq!(
+            info.files[Path::new("b")],
+            BlockCacheCounters { hit: 1, miss: 1 }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cache_blocks_do_not_share_memory() -> io::Result<()> {
+        let cache = BlockCache::new(BlockCacheSettings {
+            capacity_num_bytes: 100,
+            block_num_bytes: 16,
+            eviction_policy: EvictionPolicy::Lru,
+        });
+        let data: Vec<u8> = (0..32).collect();
+        let (underlying, num_reads) = counting_file(data.clone());
+        let directory_id = cache.register_directory();
+        let file = cache.wrap_file_handle(directory_id, Path::new("file"), underlying);
+        // Both blocks are read from the underlying file at once.
+        assert_eq!(file.read_bytes(0..32)?.as_slice(), &data[..]);
+        assert_eq!(num_reads.load(Ordering::SeqCst), 1);
+        let first_block = file.read_bytes(0..16)?;
+        let second_block = file.read_bytes(16..32)?;
+        assert_eq!(num_reads.load(Ordering::SeqCst), 1);
+        // The blocks are not slices of the same buffer.
+        assert_ne!(
+            first_block.as_slice().as_ptr_range().end,
+            second_block.as_slice().as_ptr()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cache_namespaces_directories() -> io::Result<()> {
+        let cache = BlockCache::new(BlockCacheSettings {
+            capacity_num_bytes: 100,
+            block_num_bytes: 10,
+            eviction_policy: EvictionPolicy::Lru,
+        });
+        let (underlying_a, _) = counting_file(vec![1u8; 20]);
+        let (underlying_b, _) = counting_file(vec![2u8; 20]);
+        let directory_a = cache.register_directory();
+        let directory_b = cache.register_directory();
+        let path = Path::new("meta.json");
+        let file_a = cache.wrap_file_handle(directory_a, path, underlying_a);
+        let file_b = cache.wrap_file_handle(directory_b, path, underlying_b);
+        assert_eq!(file_a.read_bytes(0..20)?.as_slice(), &[1u8; 20]);
+        assert_eq!(file_b.read_bytes(0..20)?.as_slice(), &[2u8; 20]);
+        assert_eq!(cache.get_cache_info().num_blocks, 4);
+
+        // Invalidating the file of a directory keeps the blocks of the other directory.
+        cache.invalidate(directory_a, path);
+        assert_eq!(cache.get_cache_info().num_blocks, 2);
+        assert_eq!(file_b.read_bytes(0..20)?.as_slice(), &[2u8; 20]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cache_file_counters_are_bounded() -> io::Result<()> {
+        let cache = BlockCache::new(BlockCacheSettings::default());
+        let directory_id = cache.register_directory();
+        for file_ord in 0..MAX_NUM_FILE_COUNTERS + 10 {
+            let (underlying, _) = counting_file(vec![1u8; 10]);
+            let path = format!("file_{file_ord}");
+            let file = cache.wrap_file_handle(directory_id, Path::new(&path), underlying);
+            file.read_bytes(0..10)?;
+        }
+        let info = cache.get_cache_info();
+        assert_eq!(info.files.len(), MAX_NUM_FILE_COUNTERS);
+        assert!(!info.files.contains_key(Path::new("file_0")));
+        assert_eq!(info.counters.miss, MAX_NUM_FILE_COUNTERS + 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cached_directory() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let cache = BlockCache::new(BlockCacheSettings {
+            block_num_bytes: 256,
+            ..Default::default()
+        });
+        let directory = BlockCachedDirectory::wrap(RamDirectory::create(), cache.clone());
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..100 {
+            index_writer.add_document(doc!(text_field=>format!("term{}", i % 10)))?;
+        }
+        index_writer.commit()?;
+
+        let index = Index::open(directory.clone())?;
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "term3"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 10);
+        let info = cache.get_cache_info();
+        assert!(info.num_blocks > 0);
+        assert!(info.counters.miss > 0);
+        // Searching again is served by the cache.
+        assert_eq!(searcher.search(&query, &Count)?, 10);
+        let misses = info.counters.miss;
+        let info = cache.get_cache_info();
+        assert_eq!(info.counters.miss, misses);
+        assert!(info.counters.hit > 0);
+
+        // Replacing a file through the directory invalidates its blocks.
+        let path = Path::new("file");
+        directory.atomic_write(path, &[1u8; 10])?;
+        assert_eq!(
+            directory.open_read(path)?.read_bytes()?.as_slice(),
+            &[1u8; 10]
+        );
+        directory.atomic_write(path, &[2u8; 10])?;
+        assert_eq!(
+            directory.open_read(path)?.read_bytes()?.as_slice(),
+            &[2u8; 10]
+        );
+        assert!(cache.get_cache_info().files[path].miss >= 1);
+        assert!(directory.delete(path).is_ok());
+        assert!(!cache.get_cache_info().files.contains_key(path));
+
+        // Writing a file through the directory invalidates its blocks, even if the previous
+        // version was deleted behind its back.
+        let underlying_directory = RamDirectory::create();
+        let directory = BlockCachedDirectory::wrap(underlying_directory.clone(), cache.clone());
+        underlying_directory.atomic_write(path, &[1u8; 10])?;
+        assert_eq!(
+            directory.open_read(path)?.read_bytes()?.as_slice(),
+            &[1u8; 10]
+        );
+        assert!(underlying_directory.delete(path).is_ok());
+        let mut write = directory.open_write(path)?;
+        write.write_all(&[2u8; 10])?;
+        write.terminate()?;
+        assert_eq!(
+            directory.open_read(path)?.read_bytes()?.as_slice(),
+            &[2u8; 10]
+        );
+        Ok(())
+    }
+}
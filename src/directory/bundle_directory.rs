@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, mem};
+
+use common::{BinarySerializable, HasLen, OwnedBytes, VInt};
+
+use crate::core::META_FILEPATH;
+use crate::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use crate::directory::{
+    CompositeFile, CompositeWrite, Directory, DirectoryLock, FileHandle, FileSlice, Lock,
+    TerminatingWrite, WatchCallback, WatchHandle, WritePtr, INDEX_WRITER_LOCK,
+};
+use crate::schema::Field;
+use crate::store::{Compressor, Decompressor};
+use crate::Index;
+
+/// Slot of the composite file holding the paths of the bundled files.
+///
+/// The bundled file `i` is stored in the slot `i + 1`.
+const FILE_TABLE_SLOT: u32 = 0;
+
+/// Number of uncompressed bytes of the blocks of a compressed bundle.
+const BLOCK_NUM_BYTES: usize = 1 << 16;
+
+/// Number of bytes copied at once into an uncompressed bundle.
+const COPY_CHUNK_NUM_BYTES: usize = 1 << 20;
+
+/// Length of the trailer of a compressed file: its number of blocks and its uncompressed
+/// length.
+const COMPRESSED_TRAILER_NUM_BYTES: usize = 2 * mem::size_of::<u64>();
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_only_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "A BundleDirectory is read-only",
+    )
+}
+
+/// Packs the last commit of the index stored in `directory` into a single bundle file,
+/// that can then be opened with a [`BundleDirectory`].
+///
+/// The bundle holds the `meta.json` file and all of the files of the searchable segments,
+/// as they are, in a composite file whose first slot is the table of the bundled paths.
+pub fn write_bundle<T: Into<Box<dyn Directory>>, W: TerminatingWrite + Write>(
+    directory: T,
+    output: W,
+) -> crate::Result<()> {
+    write_bundle_with_compressor(directory, output, Compressor::None)
+}
+
+/// Same as [`write_bundle()`], compressing the bundled files with `compressor`.
+///
+/// Each file is split into blocks of 64KB which are compressed independently, followed by
+/// the table of the offsets of the blocks: reading a byte range of a file only decompresses
+/// the blocks overlapping it.
+pub fn write_bundle_with_compressor<T: Into<Box<dyn Directory>>, W: TerminatingWrite + Write>(
+    directory: T,
+    output: W,
+    compressor: Compressor,
+) -> crate::Result<()> {
+    let directory: Box<dyn Directory> = directory.into();
+    let index = Index::open(directory.clone())?;
+    let mut paths: Vec<PathBuf> = vec![META_FILEPATH.to_path_buf()];
+    for segment_meta in index.searchable_segment_metas()? {
+        let mut segment_paths: Vec<PathBuf> = segment_meta.list_files().into_iter().collect();
+        segment_paths.sort();
+        for path in segment_paths {
+            if directory.exists(&path)? {
+                paths.push(path);
+            }
+        }
+    }
+
+    let mut composite_write = CompositeWrite::wrap(output);
+    let file_table = composite_write.for_field(Field::from_field_id(FILE_TABLE_SLOT));
+    VInt(paths.len() as u64).serialize(file_table)?;
+    for path in &paths {
+        let path_str = path.to_str().ok_or_else(|| {
+            crate::TantivyError::InvalidArgument(format!("Path {path:?} is not valid UTF-8"))
+        })?;
+        path_str.to_string().serialize(file_table)?;
+    }
+    // Uncompressed bundles have nothing after the paths.
+    let decompressor = Decompressor::from(compressor);
+    if decompressor != Decompressor::None {
+        decompressor.get_id().serialize(file_table)?;
+        VInt(BLOCK_NUM_BYTES as u64).serialize(file_table)?;
+    }
+    for (file_ord, path) in paths.iter().enumerate() {
+        let output =
+            composite_write.for_field(Field::from_field_id(FILE_TABLE_SLOT + 1 + file_ord as u32));
+        // Atomically written files have no footer: they are read as they are.
+        let file_slice = if *path == *META_FILEPATH {
+            FileSlice::from(directory.atomic_read(path)?)
+        } else {
+            directory.open_read(path)?
+        };
+        if decompressor == Decompressor::None {
+            copy_file(&file_slice, output)?;
+        } else {
+            compress_file(&file_slice, compressor, output)?;
+        }
+    }
+    composite_write.close()?;
+    Ok(())
+}
+
+fn copy_file(file_slice: &FileSlice, output: &mut impl Write) -> io::Result<()> {
+    for start in (0..file_slice.len()).step_by(COPY_CHUNK_NUM_BYTES) {
+        let end = (start + COPY_CHUNK_NUM_BYTES).min(file_slice.len());
+        output.write_all(file_slice.read_bytes_slice(start..end)?.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Writes the compressed blocks of `file_slice`, followed by the end offsets of the
+/// blocks, the number of blocks and the uncompressed length of the file.
+fn compress_file(
+    file_slice: &FileSlice,
+    compressor: Compressor,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let mut compressed = Vec::new();
+    let mut block_ends: Vec<u64> = Vec::new();
+    let mut num_bytes_written = 0u64;
+    for start in (0..file_slice.len()).step_by(BLOCK_NUM_BYTES) {
+        let end = (start + BLOCK_NUM_BYTES).min(file_slice.len());
+        let block = file_slice.read_bytes_slice(start..end)?;
+        compressor.compress_into(block.as_slice(), &mut compressed)?;
+        output.write_all(&compressed)?;
+        num_bytes_written += compressed.len() as u64;
+        block_ends.push(num_bytes_written);
+    }
+    for block_end in &block_ends {
+        block_end.serialize(output)?;
+    }
+    (block_ends.len() as u64).serialize(output)?;
+    (file_slice.len() as u64).serialize(output)?;
+    Ok(())
+}
+
+/// A read-only [`Directory`] over a bundle file, written by [`write_bundle()`].
+///
+/// Reading a file of an uncompressed bundle is a slice of the bundle's [`FileSlice`]: opening
+/// a `BundleDirectory` only reads the footer and the table of the bundled paths, and the
+/// tables of the block offsets if the bundle is compressed.
+/// Writing or deleting files fails with an error, and no `IndexWriter` can be created on it.
+#[derive(Clone)]
+pub struct BundleDirectory {
+    files: Arc<HashMap<PathBuf, FileSlice>>,
+}
+
+impl std::fmt::Debug for BundleDirectory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BundleDirectory({} files)", self.files.len())
+    }
+}
+
+impl BundleDirectory {
+    /// Opens the bundle stored in `data`.
+    pub fn open(data: FileSlice) -> io::Result<BundleDirectory> {
+        let composite_file = CompositeFile::open(&data)?;
+        let file_table = composite_file
+            .open_read(Field::from_field_id(FILE_TABLE_SLOT))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bundle has no file table"))?
+            .read_bytes()?;
+        let mut cursor = file_table.as_slice();
+        let num_files = VInt::deserialize(&mut cursor)?.0 as usize;
+        let mut paths = Vec::with_capacity(num_files);
+        for _ in 0..num_files {
+            paths.push(PathBuf::from(String::deserialize(&mut cursor)?));
+        }
+        let compression = if cursor.is_empty() {
+            None
+        } else {
+            let decompressor_id = u8::deserialize(&mut cursor)?;
+            let decompressor = Decompressor::try_from_id(decompressor_id).ok_or_else(|| {
+                invalid_data(format!(
+                    "Bundle is compressed with the unknown or disabled compressor \
+                     {decompressor_id}"
+                ))
+            })?;
+            let block_num_bytes = VInt::deserialize(&mut cursor)?.0 as usize;
+            if block_num_bytes == 0 {
+                return Err(invalid_data("Bundle has empty blocks".to_string()));
+            }
+            Some((decompressor, block_num_bytes))
+        };
+        let mut files = HashMap::with_capacity(num_files);
+        for (file_ord, path) in paths.into_iter().enumerate() {
+            let file_slice = composite_file
+                .open_read(Field::from_field_id(FILE_TABLE_SLOT + 1 + file_ord as u32))
+                .ok_or_else(|| invalid_data(format!("Bundle is missing the file {path:?}")))?;
+            let file_slice = match compression {
+                None => file_slice,
+                Some((decompressor, block_num_bytes)) => {
+                    let file_handle = CompressedFileHandle::open(
+                        path.clone(),
+                        file_slice,
+                        decompressor,
+                        block_num_bytes,
+                    )?;
+                    FileSlice::new(Arc::new(file_handle))
+                }
+            };
+            files.insert(path, file_slice);
+        }
+        Ok(BundleDirectory {
+            files: Arc::new(files),
+        })
+    }
+}
+
+impl Directory for BundleDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let file_slice = self.open_read(path)?;
+        Ok(Arc::new(file_slice))
+    }
+
+    fn open_read(&self, path: &Path) -> Result<FileSlice, OpenReadError> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_path_buf()))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        Err(DeleteError::IoError {
+            io_error: Arc::new(read_only_error()),
+            filepath: path.to_path_buf(),
+        })
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        Ok(self.files.contains_key(path))
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        Err(OpenWriteError::wrap_io_error(
+            read_only_error(),
+            path.to_path_buf(),
+        ))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let bytes = self
+            .open_read(path)?
+            .read_bytes()
+            .map_err(|io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf()))?;
+        Ok(bytes.as_slice().to_vec())
+    }
+
+    fn atomic_write(&self, _path: &Path, _data: &[u8]) -> io::Result<()> {
+        Err(read_only_error())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        if lock.filepath == INDEX_WRITER_LOCK.filepath {
+            return Err(LockError::IoError(Arc::new(read_only_error())));
+        }
+        // Readers take the meta lock: as the bundle never changes, it is a no-op.
+        Ok(DirectoryLock::from(Box::new(())))
+    }
+
+    fn watch(&self, _watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        // The bundle never changes.
+        Ok(WatchHandle::empty())
+    }
+}
+
+/// File handle decompressing the blocks overlapping the byte ranges read.
+struct CompressedFileHandle {
+    path: PathBuf,
+    // Compressed blocks, without the table of their offsets.
+    blocks: FileSlice,
+    block_ends: Vec<usize>,
+    decompressor: Decompressor,
+    block_num_bytes: usize,
+    // Number of uncompressed bytes.
+    len: usize,
+}
+
+impl CompressedFileHandle {
+    fn open(
+        path: PathBuf,
+        file_slice: FileSlice,
+        decompressor: Decompressor,
+        block_num_bytes: usize,
+    ) -> io::Result<CompressedFileHandle> {
+        let truncated = || invalid_data(format!("Bundled file {path:?} is truncated"));
+        let (rest, trailer) = file_slice
+            .len()
+            .checked_sub(COMPRESSED_TRAILER_NUM_BYTES)
+            .map(|split| file_slice.split(split))
+            .ok_or_else(truncated)?;
+        let trailer = trailer.read_bytes()?;
+        let mut trailer_cursor = trailer.as_slice();
+        let num_blocks = u64::deserialize(&mut trailer_cursor)? as usize;
+        let len = u64::deserialize(&mut trailer_cursor)? as usize;
+        let offsets_num_bytes = num_blocks
+            .checked_mul(mem::size_of::<u64>())
+            .filter(|&num_bytes| num_bytes <= rest.len())
+            .ok_or_else(truncated)?;
+        let blocks_num_bytes = rest.len() - offsets_num_bytes;
+        let (blocks, offsets) = rest.split(blocks_num_bytes);
+        let offsets = offsets.read_bytes()?;
+        let mut offsets_cursor = offsets.as_slice();
+        let mut block_ends = Vec::with_capacity(num_blocks);
+        for _ in 0..num_blocks {
+            let block_end = u64::deserialize(&mut offsets_cursor)? as usize;
+            if block_end < block_ends.last().copied().unwrap_or(0) || block_end > blocks.len() {
+                return Err(invalid_data(format!(
+                    "Bundled file {path:?} has invalid block offsets"
+                )));
+            }
+            block_ends.push(block_end);
+        }
+        if len / block_num_bytes + usize::from(len % block_num_bytes != 0) != num_blocks {
+            return Err(invalid_data(format!(
+                "Bundled file {path:?} has {num_blocks} blocks for {len} bytes"
+            )));
+        }
+        Ok(CompressedFileHandle {
+            path,
+            blocks,
+            block_ends,
+            decompressor,
+            block_num_bytes,
+            len,
+        })
+    }
+
+    fn block_start(&self, block_ord: usize) -> usize {
+        if block_ord == 0 {
+            0
+        } else {
+            self.block_ends[block_ord - 1]
+        }
+    }
+}
+
+impl fmt::Debug for CompressedFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CompressedFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for CompressedFileHandle {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl FileHandle for CompressedFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.is_empty() {
+            return Ok(OwnedBytes::empty());
+        }
+        if range.end > self.len {
+            return Err(invalid_data(format!(
+                "Range {range:?} is out of the bounds of {:?}",
+                self.path
+            )));
+        }
+        let first_block = range.start / self.block_num_bytes;
+        let last_block = (range.end - 1) / self.block_num_bytes;
+        let compressed_start = self.block_start(first_block);
+        let compressed = self
+            .blocks
+            .read_bytes_slice(compressed_start..self.block_ends[last_block])?;
+        let mut data = Vec::with_capacity(range.len());
+        let mut block = Vec::with_capacity(self.block_num_bytes);
+        for block_ord in first_block..=last_block {
+            let compressed_block = &compressed.as_slice()[self.block_start(block_ord)
+                - compressed_start
+                ..self.block_ends[block_ord] - compressed_start];
+            self.decompressor
+                .decompress_into(compressed_block, &mut block)?;
+            let block_start = block_ord * self.block_num_bytes;
+            let start = range.start.max(block_start) - block_start;
+            let end = range.end.min(block_start + block.len()) - block_start;
+            if start > end || end > block.len() {
+                return Err(invalid_data(format!(
+                    "Compressed block {block_ord} of {:?} is truncated",
+                    self.path
+                )));
+            }
+            data.extend_from_slice(&block[start..end]);
+        }
+        Ok(OwnedBytes::new(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use common::HasLen;
+
+    use super::{write_bundle, write_bundle_with_compressor, BundleDirectory};
+    use crate::collector::Count;
+    use crate::directory::{Directory, FileSlice, RamDirectory};
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, Value, STORED, STRING};
+    use crate::store::Compressor;
+    use crate::{Index, IndexWriter, TantivyDocument, Term};
+
+    #[test]
+    fn test_bundle_directory() -> crate::Result<()> {
+        test_bundle_directory_aux(Compressor::None)
+    }
+
+    #[cfg(feature = "lz4-compression")]
+    #[test]
+    fn test_bundle_directory_lz4() -> crate::Result<()> {
+        test_bundle_directory_aux(Compressor::Lz4)
+    }
+
+    #[cfg(feature = "zstd-compression")]
+    #[test]
+    fn test_bundle_directory_zstd() -> crate::Result<()> {
+        test_bundle_directory_aux(Compressor::Zstd(Default::default()))
+    }
+
+    fn test_bundle_directory_aux(compressor: Compressor) -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING | STORED);
+        let directory = RamDirectory::create();
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for text in ["a", "b", "c"] {
+            index_writer.add_document(doc!(text_field=>text))?;
+            index_writer.add_document(doc!(text_field=>"hello"))?;
+            index_writer.commit()?;
+        }
+        index_writer.delete_term(Term::from_field_text(text_field, "b"));
+        index_writer.commit()?;
+        // Uncommitted documents are not part of the bundle.
+        index_writer.add_document(doc!(text_field=>"d"))?;
+        index_writer.prepare_commit()?;
+
+        let mut bundle = Vec::new();
+        if compressor == Compressor::None {
+            write_bundle(directory, &mut bundle)?;
+        } else {
+            write_bundle_with_compressor(directory, &mut bundle, compressor)?;
+        }
+        let bundle_directory = BundleDirectory::open(FileSlice::from(bundle))?;
+        let index = Index::open(bundle_directory.clone())?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 3);
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 5);
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "hello"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 3);
+        let doc: TantivyDocument = searcher.doc(crate::DocAddress::new(0, 0))?;
+        assert!(doc.get_first(text_field).unwrap().as_str().is_some());
+
+        // The bundle is read-only.
+        assert!(bundle_directory.open_write(Path::new("foo")).is_err());
+        assert!(bundle_directory
+            .atomic_write(Path::new("meta.json"), b"")
+            .is_err());
+        assert!(bundle_directory.delete(Path::new("meta.json")).is_err());
+        assert!(index.writer_for_tests::<TantivyDocument>().is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "lz4-compression")]
+    #[test]
+    fn test_compressed_file_ranges() -> crate::Result<()> {
+        let data: Vec<u8> = (0..3 * super::BLOCK_NUM_BYTES + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut compressed = Vec::new();
+        super::compress_file(
+            &FileSlice::from(data.clone()),
+            Compressor::Lz4,
+            &mut compressed,
+        )?;
+        let file_handle = super::CompressedFileHandle::open(
+            "file".into(),
+            FileSlice::from(compressed),
+            crate::store::Decompressor::Lz4,
+            super::BLOCK_NUM_BYTES,
+        )?;
+        let file_slice = FileSlice::new(std::sync::Arc::new(file_handle));
+        assert_eq!(file_slice.len(), data.len());
+        for range in [
+            0..0,
+            0..1,
+            10..super::BLOCK_NUM_BYTES,
+            super::BLOCK_NUM_BYTES - 1..super::BLOCK_NUM_BYTES + 1,
+            100..2 * super::BLOCK_NUM_BYTES + 100,
+            0..data.len(),
+            data.len() - 3..data.len(),
+        ] {
+            assert_eq!(
+                file_slice.read_bytes_slice(range.clone())?.as_slice(),
+                &data[range]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_directory_invalid_bundle() {
+        let mut bundle = Vec::new();
+        bundle.extend_from_slice(&[0u8; 3]);
+        assert!(BundleDirectory::open(FileSlice::from(bundle)).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use std::ops::Range;
 use common::{BinarySerializable, CountingWriter, HasLen, VInt};
 
 use crate::directory::{FileSlice, TerminatingWrite, WritePtr};
+use crate::schema::field_mapping::FieldMapping;
 use crate::schema::Field;
 use crate::space_usage::{FieldUsage, PerFieldSpaceUsage};
 
@@ -109,10 +110,14 @@ impl CompositeFile {
     /// Opens a composite file stored in a given
     /// `FileSlice`.
     pub fn open(data: &FileSlice) -> io::Result<CompositeFile> {
+        let invalid_data = || io::Error::new(io::ErrorKind::InvalidData, "Invalid composite file");
         let end = data.len();
-        let footer_len_data = data.slice_from(end - 4).read_bytes()?;
+        let footer_len_start = end.checked_sub(4).ok_or_else(invalid_data)?;
+        let footer_len_data = data.slice_from(footer_len_start).read_bytes()?;
         let footer_len = u32::deserialize(&mut footer_len_data.as_slice())? as usize;
-        let footer_start = end - 4 - footer_len;
+        let footer_start = footer_len_start
+            .checked_sub(footer_len)
+            .ok_or_else(invalid_data)?;
         let footer_data = data
             .slice(footer_start..footer_start + footer_len)
             .read_bytes()?;
@@ -167,6 +172,23 @@ impl CompositeFile {
             .map(|byte_range| self.data.slice(byte_range.clone()))
     }
 
+    /// Returns a composite file in which the fields are renumbered according to
+    /// `field_mapping`. Fields that are not mapped are dropped.
+    pub(crate) fn remap_fields(&self, field_mapping: &FieldMapping) -> CompositeFile {
+        let offsets_index = self
+            .offsets_index
+            .iter()
+            .filter_map(|(file_addr, byte_range)| {
+                let field = field_mapping.map(file_addr.field)?;
+                Some((FileAddr::new(field, file_addr.idx), byte_range.clone()))
+            })
+            .collect();
+        CompositeFile {
+            data: self.data.clone(),
+            offsets_index,
+        }
+    }
+
     pub fn space_usage(&self) -> PerFieldSpaceUsage {
         let mut fields = Vec::new();
         for (&field_addr, byte_range) in &self.offsets_index {
@@ -0,0 +1,578 @@
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, result};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use common::HasLen;
+
+use crate::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use crate::directory::{
+    AntiCallToken, Directory, DirectoryLock, FileHandle, FileSlice, Lock, OwnedBytes,
+    TerminatingWrite, WatchCallback, WatchHandle, WritePtr,
+};
+
+/// Default number of plaintext bytes of the blocks encrypted independently.
+pub const DEFAULT_ENCRYPTION_BLOCK_NUM_BYTES: usize = 64 * 1024;
+
+/// Magic number starting the encrypted files.
+const MAGIC: [u8; 4] = *b"TENC";
+/// Magic number followed by the number of plaintext bytes of the blocks, as a `u32`.
+const HEADER_NUM_BYTES: usize = 8;
+const NONCE_NUM_BYTES: usize = 12;
+const TAG_NUM_BYTES: usize = 16;
+/// Number of bytes added to each block by the encryption.
+const BLOCK_OVERHEAD_NUM_BYTES: usize = NONCE_NUM_BYTES + TAG_NUM_BYTES;
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Encrypts and decrypts the blocks of the files of an [`EncryptedDirectory`].
+///
+/// Each block is encrypted with a random nonce, stored before its ciphertext. The path of
+/// the file, the ordinal of the block and whether it is the last block of the file are
+/// authenticated alongside, so that blocks cannot be moved, reordered or truncated
+/// without the decryption failing.
+#[derive(Clone)]
+struct BlockCipher {
+    aes: Aes256Gcm,
+}
+
+impl BlockCipher {
+    fn associated_data(path: &Path, block_ord: u64, is_last: bool) -> Vec<u8> {
+        let mut aad = path.to_string_lossy().as_bytes().to_vec();
+        aad.extend_from_slice(&block_ord.to_le_bytes());
+        aad.push(is_last as u8);
+        aad
+    }
+
+    fn encrypt_block(
+        &self,
+        path: &Path,
+        block_ord: u64,
+        is_last: bool,
+        plaintext: &[u8],
+        output: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = Self::associated_data(path, block_ord, is_last);
+        let ciphertext = self
+            .aes
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to encrypt block"))?;
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&ciphertext);
+        Ok(())
+    }
+
+    fn decrypt_block(
+        &self,
+        path: &Path,
+        block_ord: u64,
+        is_last: bool,
+        encrypted: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        if encrypted.len() < BLOCK_OVERHEAD_NUM_BYTES {
+            return Err(invalid_data(format!(
+                "Encrypted block {block_ord} of {path:?} is truncated"
+            )));
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_NUM_BYTES);
+        let aad = Self::associated_data(path, block_ord, is_last);
+        self.aes
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| {
+                invalid_data(format!(
+                    "Failed to decrypt block {block_ord} of {path:?}: wrong key or corrupted data"
+                ))
+            })
+    }
+}
+
+/// A [`Directory`] wrapper encrypting the files of the underlying directory with AES-256-GCM.
+///
+/// Files are split into blocks of a fixed number of bytes, which are encrypted independently,
+/// so that reading a byte range only reads and decrypts the blocks overlapping it. Decrypting a
+/// block also verifies its integrity: reading a corrupted block, or reading a file with the
+/// wrong key, returns an error. This comes on top of the checksum of tantivy's footers,
+/// which is computed on the plaintext.
+///
+/// Flushing a writer only writes the complete blocks: the content of a file can only be read
+/// once its writer is terminated. For this reason, an `IndexWriter` with the write-ahead log
+/// enabled cannot be created on an `EncryptedDirectory`.
+///
+/// The key is provided by the caller, and is never stored.
+#[derive(Clone)]
+pub struct EncryptedDirectory {
+    underlying: Box<dyn Directory>,
+    cipher: BlockCipher,
+    block_num_bytes: usize,
+}
+
+impl fmt::Debug for EncryptedDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptedDirectory({:?})", self.underlying)
+    }
+}
+
+impl EncryptedDirectory {
+    /// Wraps `underlying`, encrypting its files with the given 256-bit key.
+    pub fn wrap<T: Into<Box<dyn Directory>>>(underlying: T, key: &[u8; 32]) -> EncryptedDirectory {
+        EncryptedDirectory {
+            underlying: underlying.into(),
+            cipher: BlockCipher {
+                aes: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            },
+            block_num_bytes: DEFAULT_ENCRYPTION_BLOCK_NUM_BYTES,
+        }
+    }
+
+    /// Sets the number of plaintext bytes of the blocks of the files written from now on.
+    ///
+    /// Files store the size of their blocks: files written with a different block size
+    /// can still be read.
+    ///
+    /// # Panics
+    /// Panics if `block_num_bytes` is 0 or does not fit in a `u32`.
+    pub fn with_block_num_bytes(mut self, block_num_bytes: usize) -> EncryptedDirectory {
+        assert!(
+            block_num_bytes > 0 && block_num_bytes <= u32::MAX as usize,
+            "Invalid encryption block size {block_num_bytes}"
+        );
+        self.block_num_bytes = block_num_bytes;
+        self
+    }
+
+    fn header(&self) -> [u8; HEADER_NUM_BYTES] {
+        let mut header = [0u8; HEADER_NUM_BYTES];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..].copy_from_slice(&(self.block_num_bytes as u32).to_le_bytes());
+        header
+    }
+
+    fn open_encrypted(
+        &self,
+        path: &Path,
+        underlying: Arc<dyn FileHandle>,
+    ) -> io::Result<EncryptedFileHandle> {
+        let encrypted_len = underlying.len();
+        if encrypted_len < HEADER_NUM_BYTES {
+            return Err(invalid_data(format!("{path:?} is not an encrypted file")));
+        }
+        let header = underlying.read_bytes(0..HEADER_NUM_BYTES)?;
+        if header.as_slice()[..4] != MAGIC {
+            return Err(invalid_data(format!("{path:?} is not an encrypted file")));
+        }
+        let mut block_num_bytes_data = [0u8; 4];
+        block_num_bytes_data.copy_from_slice(&header.as_slice()[4..]);
+        let block_num_bytes = u32::from_le_bytes(block_num_bytes_data) as usize;
+        if block_num_bytes == 0 {
+            return Err(invalid_data(format!("Invalid block size in {path:?}")));
+        }
+        // The last block holds `len % block_num_bytes` bytes, and may be empty.
+        let encrypted_block_num_bytes = block_num_bytes + BLOCK_OVERHEAD_NUM_BYTES;
+        let body_num_bytes = encrypted_len - HEADER_NUM_BYTES;
+        let num_full_blocks = body_num_bytes / encrypted_block_num_bytes;
+        let last_block_num_bytes = (body_num_bytes % encrypted_block_num_bytes)
+            .checked_sub(BLOCK_OVERHEAD_NUM_BYTES)
+            .ok_or_else(|| invalid_data(format!("Encrypted file {path:?} is truncated")))?;
+        Ok(EncryptedFileHandle {
+            path: path.to_path_buf(),
+            underlying,
+            cipher: self.cipher.clone(),
+            block_num_bytes,
+            num_blocks: num_full_blocks + 1,
+            len: num_full_blocks * block_num_bytes + last_block_num_bytes,
+        })
+    }
+}
+
+impl Directory for EncryptedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let underlying = self.underlying.get_file_handle(path)?;
+        let file_handle = self
+            .open_encrypted(path, underlying)
+            .map_err(|io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf()))?;
+        Ok(Arc::new(file_handle))
+    }
+
+    fn delete(&self, path: &Path) -> result::Result<(), DeleteError> {
+        self.underlying.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.underlying.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let mut underlying = self.underlying.open_write(path)?;
+        underlying
+            .write_all(&self.header())
+            .map_err(|io_error| OpenWriteError::wrap_io_error(io_error, path.to_path_buf()))?;
+        Ok(BufWriter::new(Box::new(EncryptedWriter {
+            path: path.to_path_buf(),
+            underlying,
+            cipher: self.cipher.clone(),
+            block_num_bytes: self.block_num_bytes,
+            buffer: Vec::with_capacity(self.block_num_bytes),
+            block_ord: 0,
+        })))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let wrap_io_error = |io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf());
+        let encrypted = self.underlying.atomic_read(path)?;
+        let file_handle = self
+            .open_encrypted(path, Arc::new(FileSlice::from(encrypted)))
+            .map_err(wrap_io_error)?;
+        let data = file_handle
+            .read_bytes(0..file_handle.len())
+            .map_err(wrap_io_error)?;
+        Ok(data.as_slice().to_vec())
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut encrypted = self.header().to_vec();
+        let mut blocks = data.chunks(self.block_num_bytes).peekable();
+        let mut block_ord = 0;
+        while let Some(block) = blocks.next() {
+            // A full last block is followed by an empty one.
+            let is_last = blocks.peek().is_none() && block.len() < self.block_num_bytes;
+            self.cipher
+                .encrypt_block(path, block_ord, is_last, block, &mut encrypted)?;
+            block_ord += 1;
+        }
+        if data.len() % self.block_num_bytes == 0 {
+            self.cipher
+                .encrypt_block(path, block_ord, true, &[], &mut encrypted)?;
+        }
+        self.underlying.atomic_write(path, &encrypted)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.underlying.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.underlying.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        self.underlying.watch(watch_callback)
+    }
+}
+
+/// File handle decrypting the blocks overlapping the byte ranges read.
+struct EncryptedFileHandle {
+    path: PathBuf,
+    underlying: Arc<dyn FileHandle>,
+    cipher: BlockCipher,
+    block_num_bytes: usize,
+    num_blocks: usize,
+    // Number of plaintext bytes.
+    len: usize,
+}
+
+impl fmt::Debug for EncryptedFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptedFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for EncryptedFileHandle {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl FileHandle for EncryptedFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.is_empty() {
+            return Ok(OwnedBytes::empty());
+        }
+        let encrypted_block_num_bytes = self.block_num_bytes + BLOCK_OVERHEAD_NUM_BYTES;
+        let first_block = range.start / self.block_num_bytes;
+        let last_block = (range.end - 1) / self.block_num_bytes;
+        let encrypted_start = HEADER_NUM_BYTES + first_block * encrypted_block_num_bytes;
+        let encrypted_end = (HEADER_NUM_BYTES + (last_block + 1) * encrypted_block_num_bytes)
+            .min(self.underlying.len());
+        let encrypted = self.underlying.read_bytes(encrypted_start..encrypted_end)?;
+        let mut data = Vec::with_capacity(range.len());
+        for (block_ord, encrypted_block) in
+            (first_block..).zip(encrypted.as_slice().chunks(encrypted_block_num_bytes))
+        {
+            let block = self.cipher.decrypt_block(
+                &self.path,
+                block_ord as u64,
+                block_ord + 1 == self.num_blocks,
+                encrypted_block,
+            )?;
+            let block_start = block_ord * self.block_num_bytes;
+            let start = range.start.max(block_start) - block_start;
+            let end = range.end.min(block_start + block.len()) - block_start;
+            if start > end || end > block.len() {
+                return Err(invalid_data(format!(
+                    "Encrypted block {block_ord} of {:?} is truncated",
+                    self.path
+                )));
+            }
+            data.extend_from_slice(&block[start..end]);
+        }
+        Ok(OwnedBytes::new(data))
+    }
+}
+
+/// Writer associated with the [`EncryptedDirectory`].
+///
+/// Full blocks are encrypted as they are written. The last block, which may be empty,
+/// is encrypted when the writer is terminated.
+struct EncryptedWriter {
+    path: PathBuf,
+    underlying: WritePtr,
+    cipher: BlockCipher,
+    block_num_bytes: usize,
+    buffer: Vec<u8>,
+    block_ord: u64,
+}
+
+impl EncryptedWriter {
+    fn write_block(&mut self, num_bytes: usize, is_last: bool) -> io::Result<()> {
+        let mut encrypted = Vec::with_capacity(num_bytes + BLOCK_OVERHEAD_NUM_BYTES);
+        self.cipher.encrypt_block(
+            &self.path,
+            self.block_ord,
+            is_last,
+            &self.buffer[..num_bytes],
+            &mut encrypted,
+        )?;
+        self.underlying.write_all(&encrypted)?;
+        self.buffer.drain(..num_bytes);
+        self.block_ord += 1;
+        Ok(())
+    }
+}
+
+impl Write for EncryptedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= self.block_num_bytes {
+            self.write_block(self.block_num_bytes, false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.underlying.flush()
+    }
+}
+
+impl TerminatingWrite for EncryptedWriter {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.write_block(self.buffer.len(), true)?;
+        self.underlying.terminate_ref(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+
+    use common::HasLen;
+
+    use super::EncryptedDirectory;
+    use crate::collector::Count;
+    use crate::directory::{Directory, RamDirectory, TerminatingWrite};
+    use crate::indexer::write_ahead_log::write_ahead_log_path;
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, STORED, STRING};
+    use crate::{Index, IndexSettings, IndexWriter, TantivyDocument, TantivyError, Term};
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    fn write_file(directory: &dyn Directory, path: &Path, data: &[u8]) -> crate::Result<()> {
+        let mut write = directory.open_write(path)?;
+        write.write_all(data)?;
+        write.terminate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_directory_read_ranges() -> crate::Result<()> {
+        let underlying = RamDirectory::create();
+        let directory = EncryptedDirectory::wrap(underlying.clone(), &KEY).with_block_num_bytes(10);
+        for len in [0, 1, 9, 10, 11, 20, 95] {
+            let path = format!("file_{len}");
+            let path = Path::new(&path);
+            let data: Vec<u8> = (0..len as u8).collect();
+            write_file(&directory, path, &data)?;
+            let file = directory.open_read(path)?;
+            assert_eq!(file.len(), len);
+            for start in 0..len {
+                for end in start..=len {
+                    assert_eq!(
+                        file.read_bytes_slice(start..end)?.as_slice(),
+                        &data[start..end]
+                    );
+                }
+            }
+            // The underlying file does not hold the plaintext.
+            let encrypted = underlying.open_read(path)?.read_bytes()?;
+            assert!(len < 4 || !encrypted.as_slice().windows(4).any(|w| w == &data[..4]));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_directory_atomic_write() -> crate::Result<()> {
+        let underlying = RamDirectory::create();
+        let directory = EncryptedDirectory::wrap(underlying.clone(), &KEY).with_block_num_bytes(4);
+        for data in [&b""[..], b"abc", b"abcd", b"happy tax payer"] {
+            directory.atomic_write(Path::new("meta.json"), data)?;
+            assert_eq!(directory.atomic_read(Path::new("meta.json"))?, data);
+            assert_ne!(underlying.atomic_read(Path::new("meta.json"))?, data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_directory_integrity() -> crate::Result<()> {
+        let underlying = RamDirectory::create();
+        let directory = EncryptedDirectory::wrap(underlying.clone(), &KEY).with_block_num_bytes(10);
+        let path = Path::new("file");
+        let data: Vec<u8> = (0..50).collect();
+        write_file(&directory, path, &data)?;
+
+        // Wrong key.
+        let other_directory = EncryptedDirectory::wrap(underlying.clone(), &[8u8; 32]);
+        assert!(other_directory
+            .open_read(path)?
+            .read_bytes_slice(0..10)
+            .is_err());
+
+        // Corrupted block.
+        let mut encrypted = underlying
+            .open_read(path)?
+            .read_bytes()?
+            .as_slice()
+            .to_vec();
+        // Flips a byte of the ciphertext of the first block.
+        encrypted[20] ^= 1;
+        assert!(underlying.delete(path).is_ok());
+        write_file(&underlying, path, &encrypted)?;
+        let file = directory.open_read(path)?;
+        assert!(file.read_bytes_slice(0..10).is_err());
+        assert_eq!(file.read_bytes_slice(10..50)?.as_slice(), &data[10..50]);
+
+        // Truncated file.
+        let encrypted = &encrypted[..encrypted.len() - 5];
+        assert!(underlying.delete(path).is_ok());
+        write_file(&underlying, path, encrypted)?;
+        assert!(directory.open_read(path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_directory_index() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING | STORED);
+        let directory = EncryptedDirectory::wrap(RamDirectory::create(), &KEY);
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..100 {
+            index_writer.add_document(doc!(text_field=>format!("term{}", i % 10)))?;
+        }
+        index_writer.commit()?;
+
+        let index = Index::open(directory)?;
+        assert!(index.validate_checksum()?.is_empty());
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "term3"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_directory_rejects_write_ahead_log() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("text", STRING);
+        let underlying = RamDirectory::create();
+        let directory = EncryptedDirectory::wrap(underlying.clone(), &KEY);
+        let settings = IndexSettings {
+            write_ahead_log: true,
+            ..Default::default()
+        };
+        let index = Index::create(directory, schema_builder.build(), settings)?;
+        // The flushed records of the log could not be read back after a crash.
+        for _ in 0..2 {
+            assert!(matches!(
+                index.writer_for_tests::<TantivyDocument>(),
+                Err(TantivyError::InvalidArgument(_))
+            ));
+            assert!(!underlying.exists(&write_ahead_log_path(0))?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_directory_crash_recovery() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let underlying = RamDirectory::create();
+        let directory = EncryptedDirectory::wrap(underlying.clone(), &KEY);
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for _ in 0..10 {
+            index_writer.add_document(doc!(text_field=>"committed"))?;
+        }
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text_field=>"uncommitted"))?;
+        // A crash leaves the files being written flushed, but without their last block.
+        let crashed_path = Path::new("crashed.idx");
+        let mut write = index.directory().open_write(crashed_path)?;
+        write.write_all(&[1u8; 100])?;
+        write.flush()?;
+        drop(write);
+        assert!(directory.open_read(crashed_path).is_err());
+        drop(index_writer);
+
+        let index = Index::open(directory)?;
+        let index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.garbage_collect_files().wait()?;
+        assert!(!underlying.exists(crashed_path)?);
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 10);
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "committed"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 10);
+        Ok(())
+    }
+}
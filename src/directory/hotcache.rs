@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::{fmt, mem};
+
+use common::{BinarySerializable, HasLen, VInt};
+use once_cell::sync::OnceCell;
+
+use crate::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use crate::directory::{
+    Directory, DirectoryLock, FileHandle, Lock, OwnedBytes, WatchCallback, WatchHandle, WritePtr,
+};
+use crate::error::DataCorruption;
+use crate::index::Index;
+use crate::ReloadPolicy;
+
+/// Version of the format of the hotcache.
+const HOTCACHE_FORMAT_VERSION: u32 = 2;
+
+/// Length and ranges read of a file.
+#[derive(Debug, Default)]
+struct RecordedFile {
+    len: usize,
+    ranges: Vec<Range<usize>>,
+}
+
+type RecordedFiles = Arc<Mutex<HashMap<PathBuf, RecordedFile>>>;
+
+/// Directory recording the byte ranges read in its files, and the content of the files
+/// read with [`Directory::atomic_read()`].
+#[derive(Clone, Debug)]
+struct RecordingDirectory {
+    underlying: Box<dyn Directory>,
+    files: RecordedFiles,
+    atomic_files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl RecordingDirectory {
+    fn wrap(underlying: Box<dyn Directory>) -> RecordingDirectory {
+        RecordingDirectory {
+            underlying,
+            files: RecordedFiles::default(),
+            atomic_files: Arc::default(),
+        }
+    }
+
+    /// Returns the files read with [`Directory::atomic_read()`] so far, sorted by path, and
+    /// resets the recording.
+    fn take_atomic_files(&self) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut atomic_files: Vec<(PathBuf, Vec<u8>)> =
+            mem::take(&mut *self.atomic_files.lock().unwrap())
+                .into_iter()
+                .collect();
+        atomic_files.sort_by(|(left, _), (right, _)| left.cmp(right));
+        atomic_files
+    }
+
+    /// Returns the files recorded so far, with their sorted and coalesced ranges, and
+    /// resets the recording.
+    fn take_recorded_files(&self) -> Vec<(PathBuf, RecordedFile)> {
+        let mut files: Vec<(PathBuf, RecordedFile)> = mem::take(&mut *self.files.lock().unwrap())
+            .into_iter()
+            .collect();
+        files.sort_by(|(left, _), (right, _)| left.cmp(right));
+        for (_, file) in &mut files {
+            file.ranges.sort_by_key(|range| range.start);
+            let mut coalesced_ranges: Vec<Range<usize>> = Vec::with_capacity(file.ranges.len());
+            for range in file.ranges.drain(..) {
+                match coalesced_ranges.last_mut() {
+                    Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                    _ => coalesced_ranges.push(range),
+                }
+            }
+            file.ranges = coalesced_ranges;
+        }
+        files
+    }
+}
+
+impl Directory for RecordingDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let underlying = self.underlying.get_file_handle(path)?;
+        self.files
+            .lock()
+            .unwrap()
+            .entry(path.to_path_buf())
+            .or_default()
+            .len = underlying.len();
+        Ok(Arc::new(RecordingFileHandle {
+            path: path.to_path_buf(),
+            underlying,
+            files: self.files.clone(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.underlying.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.underlying.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        self.underlying.open_write(path)
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let data = self.underlying.atomic_read(path)?;
+        self.atomic_files
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), data.clone());
+        Ok(data)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.underlying.atomic_write(path, data)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.underlying.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.underlying.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        self.underlying.watch(watch_callback)
+    }
+}
+
+struct RecordingFileHandle {
+    path: PathBuf,
+    underlying: Arc<dyn FileHandle>,
+    files: RecordedFiles,
+}
+
+impl fmt::Debug for RecordingFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RecordingFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for RecordingFileHandle {
+    fn len(&self) -> usize {
+        self.underlying.len()
+    }
+}
+
+impl FileHandle for RecordingFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if !range.is_empty() {
+            self.files
+                .lock()
+                .unwrap()
+                .entry(self.path.clone())
+                .or_default()
+                .ranges
+                .push(range.clone());
+        }
+        self.underlying.read_bytes(range)
+    }
+}
+
+/// Writes the hotcache of the index stored in `directory` into `output`.
+///
+/// The hotcache holds the files read with [`Directory::atomic_read()`] (`meta.json`,
+/// `.managed.json`), the length of the files of the index, and the byte ranges read while
+/// opening the index and a searcher on it: footers, term dictionary indexes, fast field
+/// headers, doc store indexes... A [`CachingDirectory`] serves these from the hotcache,
+/// so that an index on a high-latency storage can be opened without reading its files.
+///
+/// The hotcache is a snapshot: it has to be rebuilt whenever the index changes.
+pub fn write_hotcache<T: Into<Box<dyn Directory>>>(
+    directory: T,
+    output: &mut dyn Write,
+) -> crate::Result<()> {
+    let recording_directory = RecordingDirectory::wrap(directory.into());
+    let index = Index::open(recording_directory.clone())?;
+    // Opening a searcher opens the segment readers and their doc stores.
+    index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()?
+        .searcher();
+    let atomic_files = recording_directory.take_atomic_files();
+    let files = recording_directory.take_recorded_files();
+
+    HOTCACHE_FORMAT_VERSION.serialize(output)?;
+    VInt(atomic_files.len() as u64).serialize(output)?;
+    for (path, data) in &atomic_files {
+        serialize_path(path, output)?;
+        VInt(data.len() as u64).serialize(output)?;
+        output.write_all(data)?;
+    }
+    VInt(files.len() as u64).serialize(output)?;
+    for (path, file) in &files {
+        serialize_path(path, output)?;
+        VInt(file.len as u64).serialize(output)?;
+        VInt(file.ranges.len() as u64).serialize(output)?;
+        for range in &file.ranges {
+            VInt(range.start as u64).serialize(output)?;
+            VInt(range.len() as u64).serialize(output)?;
+        }
+    }
+    for (path, file) in &files {
+        // The ranges are relative to the files including their footer.
+        let file_slice = recording_directory.underlying.open_read(path)?;
+        for range in &file.ranges {
+            output.write_all(file_slice.read_bytes_slice(range.clone())?.as_slice())?;
+        }
+    }
+    Ok(())
+}
+
+fn serialize_path(path: &Path, output: &mut dyn Write) -> crate::Result<()> {
+    let path = path.to_str().ok_or_else(|| {
+        crate::TantivyError::InvalidArgument(format!("Path {path:?} is not valid UTF-8"))
+    })?;
+    path.to_string().serialize(output)?;
+    Ok(())
+}
+
+/// Cached length and byte ranges of a file.
+#[derive(Clone)]
+struct CachedFile {
+    len: usize,
+    // Sorted, non-overlapping byte ranges and their bytes.
+    slices: Vec<(Range<usize>, OwnedBytes)>,
+}
+
+impl CachedFile {
+    fn get(&self, range: &Range<usize>) -> Option<OwnedBytes> {
+        let slice_ord = self
+            .slices
+            .partition_point(|(slice_range, _)| slice_range.end <= range.start);
+        let (slice_range, bytes) = self.slices.get(slice_ord)?;
+        if slice_range.start > range.start || slice_range.end < range.end {
+            return None;
+        }
+        Some(bytes.slice(range.start - slice_range.start..range.end - slice_range.start))
+    }
+}
+
+/// Content of a hotcache: the files read with [`Directory::atomic_read()`], and the
+/// cached byte ranges of the other files.
+struct Hotcache {
+    atomic_files: HashMap<PathBuf, Vec<u8>>,
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+fn parse_hotcache(hotcache: OwnedBytes) -> io::Result<Hotcache> {
+    let mut cursor: &[u8] = hotcache.as_slice();
+    let version = u32::deserialize(&mut cursor)?;
+    if version != HOTCACHE_FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported hotcache format version {version}"),
+        ));
+    }
+    let num_atomic_files = VInt::deserialize(&mut cursor)?.0 as usize;
+    let mut atomic_files = HashMap::with_capacity(num_atomic_files);
+    for _ in 0..num_atomic_files {
+        let path = PathBuf::from(String::deserialize(&mut cursor)?);
+        let num_bytes = VInt::deserialize(&mut cursor)?.0 as usize;
+        if num_bytes > cursor.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Hotcache content of {path:?} is truncated"),
+            ));
+        }
+        let (data, remaining) = cursor.split_at(num_bytes);
+        cursor = remaining;
+        atomic_files.insert(path, data.to_vec());
+    }
+    let num_files = VInt::deserialize(&mut cursor)?.0 as usize;
+    let mut files: Vec<(PathBuf, usize, Vec<Range<usize>>)> = Vec::with_capacity(num_files);
+    for _ in 0..num_files {
+        let path = PathBuf::from(String::deserialize(&mut cursor)?);
+        let len = VInt::deserialize(&mut cursor)?.0 as usize;
+        let num_ranges = VInt::deserialize(&mut cursor)?.0 as usize;
+        let mut ranges = Vec::with_capacity(num_ranges);
+        for _ in 0..num_ranges {
+            let start = VInt::deserialize(&mut cursor)?.0 as usize;
+            let num_bytes = VInt::deserialize(&mut cursor)?.0 as usize;
+            ranges.push(start..start + num_bytes);
+        }
+        files.push((path, len, ranges));
+    }
+    let mut data = hotcache.slice(hotcache.len() - cursor.len()..hotcache.len());
+    let mut cached_files = HashMap::with_capacity(num_files);
+    for (path, len, ranges) in files {
+        let mut slices = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            if range.end > len || range.len() > data.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Hotcache range {range:?} of {path:?} is out of bounds"),
+                ));
+            }
+            let (bytes, remaining) = data.split(range.len());
+            data = remaining;
+            slices.push((range, bytes));
+        }
+        cached_files.insert(path, CachedFile { len, slices });
+    }
+    Ok(Hotcache {
+        atomic_files,
+        files: cached_files,
+    })
+}
+
+/// A [`Directory`] serving the files lengths and byte ranges of a hotcache, written
+/// by [`write_hotcache()`], and delegating everything else to an underlying directory.
+///
+/// The files of the hotcache are only opened in the underlying directory when a byte
+/// range which is not in the hotcache is read. The files read with
+/// [`Directory::atomic_read()`] are served from the hotcache until they are written or
+/// deleted through this directory.
+#[derive(Clone)]
+pub struct CachingDirectory {
+    underlying: Box<dyn Directory>,
+    atomic_files: Arc<RwLock<HashMap<PathBuf, Vec<u8>>>>,
+    files: Arc<HashMap<PathBuf, CachedFile>>,
+}
+
+impl fmt::Debug for CachingDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CachingDirectory({:?})", self.underlying)
+    }
+}
+
+impl CachingDirectory {
+    /// Wraps `underlying`, serving the content of `hotcache` from memory.
+    pub fn new<T: Into<Box<dyn Directory>>>(
+        underlying: T,
+        hotcache: OwnedBytes,
+    ) -> crate::Result<CachingDirectory> {
+        let hotcache = parse_hotcache(hotcache).map_err(|io_error| {
+            DataCorruption::comment_only(format!("Failed to read the hotcache: {io_error}"))
+        })?;
+        Ok(CachingDirectory {
+            underlying: underlying.into(),
+            atomic_files: Arc::new(RwLock::new(hotcache.atomic_files)),
+            files: Arc::new(hotcache.files),
+        })
+    }
+}
+
+impl Directory for CachingDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let Some(cached_file) = self.files.get(path) else {
+            return self.underlying.get_file_handle(path);
+        };
+        Ok(Arc::new(CachingFileHandle {
+            path: path.to_path_buf(),
+            cached_file: cached_file.clone(),
+            underlying_directory: self.underlying.clone(),
+            underlying: OnceCell::new(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.atomic_files.write().unwrap().remove(path);
+        self.underlying.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.underlying.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        self.underlying.open_write(path)
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        if let Some(data) = self.atomic_files.read().unwrap().get(path) {
+            return Ok(data.clone());
+        }
+        self.underlying.atomic_read(path)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        // Holding the lock while writing keeps the cached content in sync with the
+        // underlying directory.
+        let mut atomic_files = self.atomic_files.write().unwrap();
+        atomic_files.remove(path);
+        self.underlying.atomic_write(path, data)?;
+        atomic_files.insert(path.to_path_buf(), data.to_vec());
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.underlying.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.underlying.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        self.underlying.watch(watch_callback)
+    }
+}
+
+struct CachingFileHandle {
+    path: PathBuf,
+    cached_file: CachedFile,
+    underlying_directory: Box<dyn Directory>,
+    // Opened on the first read missing the hotcache.
+    underlying: OnceCell<Arc<dyn FileHandle>>,
+}
+
+impl fmt::Debug for CachingFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CachingFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for CachingFileHandle {
+    fn len(&self) -> usize {
+        self.cached_file.len
+    }
+}
+
+impl FileHandle for CachingFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if let Some(bytes) = self.cached_file.get(&range) {
+            return Ok(bytes);
+        }
+        let underlying = self.underlying.get_or_try_init(|| {
+            self.underlying_directory
+                .get_file_handle(&self.path)
+                .map_err(|open_read_error| io::Error::new(io::ErrorKind::Other, open_read_error))
+        })?;
+        underlying.read_bytes(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_hotcache, CachingDirectory, RecordingDirectory};
+    use crate::collector::Count;
+    use crate::core::{MANAGED_FILEPATH, META_FILEPATH};
+    use crate::directory::{Directory, OwnedBytes, RamDirectory};
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, FAST, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    #[test]
+    fn test_caching_directory() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let num_field = schema_builder.add_u64_field("num", FAST);
+        let directory = RamDirectory::create();
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..3u64 {
+            index_writer.add_document(doc!(text_field=>"hello", num_field=>i))?;
+            index_writer.commit()?;
+        }
+        index_writer.add_document(doc!(text_field=>"happy", num_field=>3u64))?;
+        index_writer.commit()?;
+
+        let mut hotcache = Vec::new();
+        write_hotcache(directory.clone(), &mut hotcache)?;
+
+        // Opening the index through the hotcache reads none of its files, including
+        // meta.json and .managed.json.
+        let recording_directory = RecordingDirectory::wrap(Box::new(directory));
+        let caching_directory =
+            CachingDirectory::new(recording_directory.clone(), OwnedBytes::new(hotcache))?;
+        let index = Index::open(caching_directory)?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.segment_readers().len(), 4);
+        assert!(recording_directory.take_atomic_files().is_empty());
+        assert!(recording_directory
+            .take_recorded_files()
+            .iter()
+            .all(|(_, file)| file.ranges.is_empty()));
+
+        // Other ranges are read from the underlying directory.
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "hello"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 3);
+        assert!(recording_directory
+            .take_recorded_files()
+            .iter()
+            .any(|(_, file)| !file.ranges.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_caching_directory_atomic_files() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING);
+        let directory = RamDirectory::create();
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text_field=>"hello"))?;
+        index_writer.commit()?;
+
+        let mut hotcache = Vec::new();
+        write_hotcache(directory.clone(), &mut hotcache)?;
+
+        let recording_directory = RecordingDirectory::wrap(Box::new(directory.clone()));
+        let caching_directory =
+            CachingDirectory::new(recording_directory.clone(), OwnedBytes::new(hotcache))?;
+        for path in [&*META_FILEPATH, &*MANAGED_FILEPATH] {
+            assert_eq!(
+                caching_directory.atomic_read(path)?,
+                directory.atomic_read(path)?
+            );
+        }
+        assert!(recording_directory.take_atomic_files().is_empty());
+
+        // Writing through the caching directory updates the cached content.
+        caching_directory.atomic_write(&META_FILEPATH, b"updated")?;
+        assert_eq!(caching_directory.atomic_read(&META_FILEPATH)?, b"updated");
+        assert_eq!(directory.atomic_read(&META_FILEPATH)?, b"updated");
+        Ok(())
+    }
+
+    #[test]
+    fn test_caching_directory_invalid_hotcache() {
+        let directory = RamDirectory::create();
+        assert!(CachingDirectory::new(directory, OwnedBytes::new(vec![1, 2])).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, result};
+
+use common::HasLen;
+
+use crate::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use crate::directory::{
+    AntiCallToken, Directory, DirectoryLock, FileHandle, Lock, OwnedBytes, TerminatingWrite,
+    WatchCallback, WatchHandle, WritePtr,
+};
+use crate::index::{SegmentComponent, SegmentId};
+
+/// Kind of an IO operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoKind {
+    /// Bytes read from a file.
+    Read,
+    /// Bytes written to a file.
+    Write,
+}
+
+/// An IO operation on a file of an [`InstrumentedDirectory`].
+#[derive(Clone, Debug)]
+pub struct IoEvent<'a> {
+    /// Path of the file.
+    pub path: &'a Path,
+    /// Segment the file belongs to, if it is a segment component.
+    pub segment_id: Option<SegmentId>,
+    /// Component of the file, if it is a segment component.
+    pub component: Option<SegmentComponent>,
+    /// Kind of the operation.
+    pub kind: IoKind,
+    /// Number of bytes read or written.
+    pub num_bytes: usize,
+    /// Time spent in the underlying directory.
+    pub duration: Duration,
+}
+
+/// IO statistics of a set of files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoStats {
+    /// Number of reads.
+    pub num_reads: u64,
+    /// Number of bytes read.
+    pub num_bytes_read: u64,
+    /// Total time spent reading.
+    pub read_duration: Duration,
+    /// Number of writes.
+    pub num_writes: u64,
+    /// Number of bytes written.
+    pub num_bytes_written: u64,
+    /// Total time spent writing.
+    pub write_duration: Duration,
+}
+
+impl IoStats {
+    fn record(&mut self, event: &IoEvent) {
+        match event.kind {
+            IoKind::Read => {
+                self.num_reads += 1;
+                self.num_bytes_read += event.num_bytes as u64;
+                self.read_duration += event.duration;
+            }
+            IoKind::Write => {
+                self.num_writes += 1;
+                self.num_bytes_written += event.num_bytes as u64;
+                self.write_duration += event.duration;
+            }
+        }
+    }
+
+    fn add(&mut self, other: &IoStats) {
+        self.num_reads += other.num_reads;
+        self.num_bytes_read += other.num_bytes_read;
+        self.read_duration += other.read_duration;
+        self.num_writes += other.num_writes;
+        self.num_bytes_written += other.num_bytes_written;
+        self.write_duration += other.write_duration;
+    }
+}
+
+/// IO statistics of an [`InstrumentedDirectory`], per [`SegmentComponent`].
+#[derive(Clone, Debug, Default)]
+pub struct DirectoryStats {
+    components: HashMap<SegmentComponent, IoStats>,
+    other: IoStats,
+}
+
+impl DirectoryStats {
+    /// Returns the statistics of the files of the given component, all segments combined.
+    pub fn component(&self, component: SegmentComponent) -> IoStats {
+        self.components.get(&component).copied().unwrap_or_default()
+    }
+
+    /// Returns the statistics of the files which are not segment components,
+    /// like `meta.json`.
+    pub fn other(&self) -> IoStats {
+        self.other
+    }
+
+    /// Returns the statistics of all of the files.
+    pub fn total(&self) -> IoStats {
+        let mut total = self.other;
+        for stats in self.components.values() {
+            total.add(stats);
+        }
+        total
+    }
+
+    fn record(&mut self, event: &IoEvent) {
+        match event.component {
+            Some(component) => self.components.entry(component).or_default(),
+            None => &mut self.other,
+        }
+        .record(event);
+    }
+}
+
+/// Callback called on every IO operation of an [`InstrumentedDirectory`].
+pub type IoCallback = Arc<dyn Fn(&IoEvent) + Send + Sync>;
+
+#[derive(Clone)]
+struct Recorder {
+    stats: Arc<Mutex<DirectoryStats>>,
+    callbacks: Vec<IoCallback>,
+}
+
+impl Recorder {
+    fn record(&self, path: &Path, kind: IoKind, num_bytes: usize, duration: Duration) {
+        let (segment_id, component) = match SegmentComponent::parse_path(path) {
+            Some((segment_id, component)) => (Some(segment_id), Some(component)),
+            None => (None, None),
+        };
+        let event = IoEvent {
+            path,
+            segment_id,
+            component,
+            kind,
+            num_bytes,
+            duration,
+        };
+        self.stats.lock().unwrap().record(&event);
+        for callback in &self.callbacks {
+            callback(&event);
+        }
+    }
+}
+
+/// A [`Directory`] wrapper recording the bytes read and written in the files of the
+/// underlying directory, the number of operations and their latency.
+///
+/// The operations are attributed to the [`SegmentComponent`] of the file, and aggregated
+/// in [`DirectoryStats`]. Callbacks can also be registered to trace every operation, for
+/// instance to attribute them to segments, or to [`log`](InstrumentedDirectory::with_logging)
+/// them.
+///
+/// Writes are recorded as the underlying writer is written to, that is after the buffering
+/// of the [`WritePtr`].
+#[derive(Clone)]
+pub struct InstrumentedDirectory {
+    underlying: Box<dyn Directory>,
+    recorder: Recorder,
+}
+
+impl fmt::Debug for InstrumentedDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InstrumentedDirectory({:?})", self.underlying)
+    }
+}
+
+impl InstrumentedDirectory {
+    /// Wraps `underlying`.
+    pub fn wrap<T: Into<Box<dyn Directory>>>(underlying: T) -> InstrumentedDirectory {
+        InstrumentedDirectory {
+            underlying: underlying.into(),
+            recorder: Recorder {
+                stats: Arc::default(),
+                callbacks: Vec::new(),
+            },
+        }
+    }
+
+    /// Registers a callback called on every IO operation.
+    pub fn with_callback(
+        mut self,
+        callback: impl Fn(&IoEvent) + Send + Sync + 'static,
+    ) -> InstrumentedDirectory {
+        self.recorder.callbacks.push(Arc::new(callback));
+        self
+    }
+
+    /// Logs every IO operation with the given level.
+    pub fn with_logging(self, level: log::Level) -> InstrumentedDirectory {
+        self.with_callback(move |event| {
+            log!(
+                level,
+                "{:?} {} bytes of {:?} in {:?}",
+                event.kind,
+                event.num_bytes,
+                event.path,
+                event.duration
+            );
+        })
+    }
+
+    /// Returns the statistics recorded since the directory was created or
+    /// the statistics were last reset.
+    ///
+    /// The statistics are shared by the clones of the directory.
+    pub fn stats(&self) -> DirectoryStats {
+        self.recorder.stats.lock().unwrap().clone()
+    }
+
+    /// Resets the statistics.
+    pub fn reset_stats(&self) {
+        *self.recorder.stats.lock().unwrap() = DirectoryStats::default();
+    }
+}
+
+impl Directory for InstrumentedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let underlying = self.underlying.get_file_handle(path)?;
+        Ok(Arc::new(InstrumentedFileHandle {
+            path: path.to_path_buf(),
+            underlying,
+            recorder: self.recorder.clone(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> result::Result<(), DeleteError> {
+        self.underlying.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.underlying.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let underlying = self.underlying.open_write(path)?;
+        Ok(BufWriter::new(Box::new(InstrumentedWriter {
+            path: path.to_path_buf(),
+            underlying,
+            recorder: self.recorder.clone(),
+        })))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let start = Instant::now();
+        let data = self.underlying.atomic_read(path)?;
+        self.recorder
+            .record(path, IoKind::Read, data.len(), start.elapsed());
+        Ok(data)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let start = Instant::now();
+        self.underlying.atomic_write(path, data)?;
+        self.recorder
+            .record(path, IoKind::Write, data.len(), start.elapsed());
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.underlying.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.underlying.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        self.underlying.watch(watch_callback)
+    }
+}
+
+struct InstrumentedFileHandle {
+    path: PathBuf,
+    underlying: Arc<dyn FileHandle>,
+    recorder: Recorder,
+}
+
+impl fmt::Debug for InstrumentedFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InstrumentedFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for InstrumentedFileHandle {
+    fn len(&self) -> usize {
+        self.underlying.len()
+    }
+}
+
+impl FileHandle for InstrumentedFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        let start = Instant::now();
+        let bytes = self.underlying.read_bytes(range)?;
+        self.recorder
+            .record(&self.path, IoKind::Read, bytes.len(), start.elapsed());
+        Ok(bytes)
+    }
+}
+
+struct InstrumentedWriter {
+    path: PathBuf,
+    underlying: WritePtr,
+    recorder: Recorder,
+}
+
+impl Write for InstrumentedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let num_bytes = self.underlying.write(buf)?;
+        self.recorder
+            .record(&self.path, IoKind::Write, num_bytes, start.elapsed());
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.underlying.flush()
+    }
+}
+
+impl TerminatingWrite for InstrumentedWriter {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.underlying.terminate_ref(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{InstrumentedDirectory, IoKind};
+    use crate::collector::Count;
+    use crate::directory::RamDirectory;
+    use crate::index::{SegmentComponent, SegmentId};
+    use crate::query::TermQuery;
+    use crate::schema::{IndexRecordOption, Schema, STORED, STRING};
+    use crate::{Index, IndexWriter, Term};
+
+    #[test]
+    fn test_instrumented_directory() -> crate::Result<()> {
+        let read_segment_ids: Arc<Mutex<Vec<SegmentId>>> = Arc::default();
+        let read_segment_ids_clone = read_segment_ids.clone();
+        let directory =
+            InstrumentedDirectory::wrap(RamDirectory::create()).with_callback(move |event| {
+                if event.kind == IoKind::Read {
+                    if let Some(segment_id) = event.segment_id {
+                        read_segment_ids_clone.lock().unwrap().push(segment_id);
+                    }
+                }
+            });
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING | STORED);
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..100 {
+            index_writer.add_document(doc!(text_field=>format!("term{}", i % 10)))?;
+        }
+        index_writer.commit()?;
+
+        let stats = directory.stats();
+        assert!(stats.other().num_bytes_written > 0);
+        for component in [
+            SegmentComponent::Postings,
+            SegmentComponent::Terms,
+            SegmentComponent::Store,
+            SegmentComponent::FastFields,
+            SegmentComponent::FieldNorms,
+        ] {
+            assert!(stats.component(component).num_bytes_written > 0);
+        }
+        assert_eq!(
+            stats.component(SegmentComponent::Delete),
+            Default::default()
+        );
+        assert_eq!(
+            stats.total().num_bytes_written,
+            stats.other().num_bytes_written
+                + SegmentComponent::iterator()
+                    .map(|component| stats.component(*component).num_bytes_written)
+                    .sum::<u64>()
+        );
+
+        let searcher = index.reader()?.searcher();
+        directory.reset_stats();
+        read_segment_ids.lock().unwrap().clear();
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "term3"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 10);
+        let stats = directory.stats();
+        assert_eq!(stats.total().num_bytes_written, 0);
+        assert!(stats.component(SegmentComponent::Postings).num_reads > 0);
+        assert!(stats.component(SegmentComponent::Postings).num_bytes_read > 0);
+        assert_eq!(stats.component(SegmentComponent::Store).num_reads, 0);
+        let segment_id = searcher.segment_reader(0).segment_id();
+        assert!(read_segment_ids
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|read_segment_id| *read_segment_id == segment_id));
+        Ok(())
+    }
+}
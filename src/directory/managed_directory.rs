@@ -4,18 +4,22 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock, RwLockWriteGuard};
 use std::{io, result};
 
+use common::HasLen;
 use crc32fast::Hasher;
 
 use crate::core::MANAGED_FILEPATH;
 use crate::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
 use crate::directory::footer::{Footer, FooterProxy};
 use crate::directory::{
-    DirectoryLock, FileHandle, FileSlice, GarbageCollectionResult, Lock, WatchCallback,
-    WatchHandle, WritePtr, META_LOCK,
+    DirectoryLock, FileHandle, FileSlice, GarbageCollectionResult, Lock, TerminatingWrite,
+    WatchCallback, WatchHandle, WritePtr, META_LOCK,
 };
 use crate::error::DataCorruption;
 use crate::Directory;
 
+/// Size of the chunks in which [`ManagedDirectory::copy_file`] copies a file.
+const COPY_CHUNK_LEN: usize = 1 << 20;
+
 /// Returns true if the file is "managed".
 /// Non-managed file are not subject to garbage collection.
 ///
@@ -256,6 +260,33 @@ impl ManagedDirectory {
         Ok(footer.crc() == crc)
     }
 
+    /// Copies a file to another directory, as is.
+    ///
+    /// Contrary to [`Directory::open_read`], the footer of the file is copied as well,
+    /// so that the copy can be read by a `ManagedDirectory` wrapping `target`.
+    ///
+    /// The file is streamed to `target` in chunks of `COPY_CHUNK_LEN` bytes, so that copying
+    /// a large file does not load it entirely in memory. A file left at `path` in `target`,
+    /// by an interrupted copy for instance, is replaced.
+    pub fn copy_file(&self, path: &Path, target: &dyn Directory) -> crate::Result<()> {
+        let file_slice = self.directory.open_read(path)?;
+        if target.exists(path)? {
+            target
+                .delete(path)
+                .map_err(|delete_error| io::Error::new(io::ErrorKind::Other, delete_error))?;
+        }
+        let mut writer = target.open_write(path)?;
+        let mut offset = 0;
+        while offset < file_slice.len() {
+            let chunk_end = (offset + COPY_CHUNK_LEN).min(file_slice.len());
+            let chunk = file_slice.read_bytes_slice(offset..chunk_end)?;
+            writer.write_all(chunk.as_slice())?;
+            offset = chunk_end;
+        }
+        writer.terminate()?;
+        Ok(())
+    }
+
     /// List all managed files
     pub fn list_managed_files(&self) -> HashSet<PathBuf> {
         let managed_paths = self
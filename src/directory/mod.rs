@@ -3,11 +3,18 @@
 #[cfg(feature = "mmap")]
 mod mmap_directory;
 
+mod block_cache;
+mod bundle_directory;
 mod directory;
 mod directory_lock;
+#[cfg(feature = "encryption")]
+mod encrypted_directory;
 mod file_watcher;
 mod footer;
+mod hotcache;
+mod instrumented_directory;
 mod managed_directory;
+mod object_store_directory;
 mod ram_directory;
 mod watch_event_router;
 
@@ -22,9 +29,23 @@ use std::path::PathBuf;
 pub use common::file_slice::{FileHandle, FileSlice};
 pub use common::{AntiCallToken, OwnedBytes, TerminatingWrite};
 
+pub use self::block_cache::{
+    BlockCache, BlockCacheCounters, BlockCacheDirectoryId, BlockCacheInfo, BlockCacheSettings,
+    BlockCachedDirectory, EvictionPolicy,
+};
+pub use self::bundle_directory::{write_bundle, write_bundle_with_compressor, BundleDirectory};
 pub(crate) use self::composite_file::{CompositeFile, CompositeWrite};
 pub use self::directory::{Directory, DirectoryClone, DirectoryLock};
 pub use self::directory_lock::{Lock, INDEX_WRITER_LOCK, META_LOCK};
+#[cfg(feature = "encryption")]
+pub use self::encrypted_directory::{EncryptedDirectory, DEFAULT_ENCRYPTION_BLOCK_NUM_BYTES};
+pub use self::hotcache::{write_hotcache, CachingDirectory};
+pub use self::instrumented_directory::{
+    DirectoryStats, InstrumentedDirectory, IoCallback, IoEvent, IoKind, IoStats,
+};
+pub use self::object_store_directory::{
+    LocalObjectStore, ObjectStore, ObjectStoreDirectory, ObjectStoreDirectorySettings,
+};
 pub use self::ram_directory::RamDirectory;
 pub use self::watch_event_router::{WatchCallback, WatchCallbackList, WatchHandle};
 
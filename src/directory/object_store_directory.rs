@@ -0,0 +1,841 @@
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fmt, fs};
+
+use common::HasLen;
+use lru::LruCache;
+use uuid::Uuid;
+
+use crate::core::META_FILEPATH;
+use crate::directory::error::{DeleteError, OpenDirectoryError, OpenReadError, OpenWriteError};
+use crate::directory::{
+    AntiCallToken, Directory, FileHandle, OwnedBytes, TerminatingWrite, WatchCallback,
+    WatchCallbackList, WatchHandle, WritePtr,
+};
+
+/// Extension of the files of the block cache.
+const BLOCK_FILE_EXTENSION: &str = "block";
+
+/// Prefix of the subdirectory of the cache directory holding the blocks of a block cache.
+const BLOCK_CACHE_DIRECTORY_PREFIX: &str = "tantivy-block-cache-";
+
+/// Storage backend of an [`ObjectStoreDirectory`].
+///
+/// An object store holds objects addressed by a path, that are written at once and
+/// read by byte ranges, like S3-compatible storages do.
+pub trait ObjectStore: fmt::Debug + Send + Sync + 'static {
+    /// Returns the length of the object, or `None` if it does not exist.
+    fn object_len(&self, path: &Path) -> io::Result<Option<usize>>;
+
+    /// Reads the given byte range of an object.
+    fn get_range(&self, path: &Path, range: Range<usize>) -> io::Result<Vec<u8>>;
+
+    /// Creates or replaces an object.
+    ///
+    /// Readers should never observe a partially written object.
+    fn put(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Deletes an object.
+    ///
+    /// Deleting an object that does not exist returns an error of kind
+    /// [`io::ErrorKind::NotFound`].
+    fn delete(&self, path: &Path) -> io::Result<()>;
+}
+
+/// An [`ObjectStore`] storing its objects as files of a local directory.
+///
+/// It is mainly meant as a stand-in for a remote object store in tests.
+#[derive(Clone, Debug)]
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    /// Creates an object store storing its objects in `root`.
+    ///
+    /// The directory is created if it does not exist.
+    pub fn open(root: impl Into<PathBuf>) -> io::Result<LocalObjectStore> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(LocalObjectStore { root })
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn object_len(&self, path: &Path) -> io::Result<Option<usize>> {
+        match fs::metadata(self.root.join(path)) {
+            Ok(metadata) => Ok(Some(metadata.len() as usize)),
+            Err(io_error) if io_error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(io_error) => Err(io_error),
+        }
+    }
+
+    fn get_range(&self, path: &Path, range: Range<usize>) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(self.root.join(path))?;
+        file.seek(SeekFrom::Start(range.start as u64))?;
+        let mut data = vec![0u8; range.len()];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn put(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let object_path = self.root.join(path);
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Written in a temporary file, then renamed, so that the object is replaced atomically.
+        let mut tmp_path = object_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &object_path)
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(self.root.join(path))
+    }
+}
+
+/// Settings of an [`ObjectStoreDirectory`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectStoreDirectorySettings {
+    /// Size of the blocks fetched from the object store and kept in the block cache.
+    pub block_num_bytes: usize,
+    /// Maximum number of bytes of the blocks kept in the block cache.
+    pub cache_capacity_num_bytes: u64,
+    /// Number of bytes at the end of each file kept in memory once the file is opened.
+    ///
+    /// This is where tantivy stores the footers and the indexes of its files.
+    pub footer_num_bytes: usize,
+    /// Maximum number of files whose end is kept in memory.
+    ///
+    /// The least recently opened files are evicted first. If set to 0, the end of a file
+    /// is fetched every time the file is opened.
+    pub footer_cache_capacity: usize,
+}
+
+impl Default for ObjectStoreDirectorySettings {
+    fn default() -> ObjectStoreDirectorySettings {
+        ObjectStoreDirectorySettings {
+            block_num_bytes: 1 << 20,
+            cache_capacity_num_bytes: 1 << 30,
+            footer_num_bytes: 16 << 10,
+            footer_cache_capacity: 1_000,
+        }
+    }
+}
+
+type BlockKey = (PathBuf, usize);
+
+struct CachedBlock {
+    file_id: u64,
+    num_bytes: u64,
+}
+
+struct BlockCacheState {
+    lru: LruCache<BlockKey, CachedBlock>,
+    num_bytes: u64,
+    next_file_id: u64,
+}
+
+/// LRU cache of blocks of the objects, stored as files of a local directory.
+///
+/// The blocks are stored in a subdirectory of the cache directory with a random name, which
+/// the block cache creates when it is opened and removes when it is dropped.
+struct BlockCache {
+    root: PathBuf,
+    capacity_num_bytes: u64,
+    state: Mutex<BlockCacheState>,
+}
+
+impl BlockCache {
+    /// Opens an empty block cache in a new subdirectory of `cache_directory`.
+    fn open(
+        cache_directory: &Path,
+        capacity_num_bytes: u64,
+    ) -> Result<BlockCache, OpenDirectoryError> {
+        if !cache_directory.is_dir() {
+            return Err(OpenDirectoryError::DoesNotExist(
+                cache_directory.to_path_buf(),
+            ));
+        }
+        let root = cache_directory.join(format!(
+            "{BLOCK_CACHE_DIRECTORY_PREFIX}{}",
+            Uuid::new_v4().as_simple()
+        ));
+        // `create_dir` fails if the subdirectory already exists, so that it is never shared.
+        fs::create_dir(&root)
+            .map_err(|io_error| OpenDirectoryError::wrap_io_error(io_error, root.clone()))?;
+        Ok(BlockCache {
+            root,
+            capacity_num_bytes,
+            state: Mutex::new(BlockCacheState {
+                lru: LruCache::unbounded(),
+                num_bytes: 0,
+                next_file_id: 0,
+            }),
+        })
+    }
+
+    fn block_path(&self, file_id: u64) -> PathBuf {
+        self.root.join(format!("{file_id}.{BLOCK_FILE_EXTENSION}"))
+    }
+
+    fn get(&self, key: &BlockKey) -> Option<Vec<u8>> {
+        let file_id = self.state.lock().unwrap().lru.get(key)?.file_id;
+        // The block may have been evicted in the meantime, which is just a cache miss.
+        fs::read(self.block_path(file_id)).ok()
+    }
+
+    fn insert(&self, key: BlockKey, data: &[u8]) {
+        let num_bytes = data.len() as u64;
+        if num_bytes > self.capacity_num_bytes {
+            return;
+        }
+        let file_id = {
+            let mut state = self.state.lock().unwrap();
+            state.next_file_id += 1;
+            state.next_file_id
+        };
+        let block_path = self.block_path(file_id);
+        if let Err(io_error) = fs::write(&block_path, data) {
+            warn!("Failed to write block cache file {block_path:?}: {io_error:?}");
+            return;
+        }
+        let mut evicted_blocks = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some((_, evicted)) = state.lru.push(key, CachedBlock { file_id, num_bytes }) {
+                state.num_bytes -= evicted.num_bytes;
+                evicted_blocks.push(evicted);
+            }
+            state.num_bytes += num_bytes;
+            while state.num_bytes > self.capacity_num_bytes {
+                let Some((_, evicted)) = state.lru.pop_lru() else {
+                    break;
+                };
+                state.num_bytes -= evicted.num_bytes;
+                evicted_blocks.push(evicted);
+            }
+        }
+        self.remove_block_files(evicted_blocks);
+    }
+
+    /// Removes all of the blocks of an object.
+    fn invalidate(&self, path: &Path) {
+        let mut evicted_blocks = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            let keys: Vec<BlockKey> = state
+                .lru
+                .iter()
+                .filter(|((block_path, _), _)| block_path == path)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in keys {
+                if let Some(evicted) = state.lru.pop(&key) {
+                    state.num_bytes -= evicted.num_bytes;
+                    evicted_blocks.push(evicted);
+                }
+            }
+        }
+        self.remove_block_files(evicted_blocks);
+    }
+
+    fn remove_block_files(&self, blocks: Vec<CachedBlock>) {
+        for block in blocks {
+            let block_path = self.block_path(block.file_id);
+            if let Err(io_error) = fs::remove_file(&block_path) {
+                warn!("Failed to remove block cache file {block_path:?}: {io_error:?}");
+            }
+        }
+    }
+
+    fn num_bytes(&self) -> u64 {
+        self.state.lock().unwrap().num_bytes
+    }
+}
+
+impl Drop for BlockCache {
+    fn drop(&mut self) {
+        if let Err(io_error) = fs::remove_dir_all(&self.root) {
+            warn!(
+                "Failed to remove block cache directory {:?}: {io_error:?}",
+                self.root
+            );
+        }
+    }
+}
+
+/// End of a file, kept in memory once the file is opened.
+#[derive(Clone)]
+struct Footer {
+    file_len: usize,
+    bytes: OwnedBytes,
+}
+
+impl Footer {
+    fn start(&self) -> usize {
+        self.file_len - self.bytes.len()
+    }
+}
+
+struct InnerDirectory {
+    store: Box<dyn ObjectStore>,
+    settings: ObjectStoreDirectorySettings,
+    block_cache: BlockCache,
+    footers: Option<Mutex<LruCache<PathBuf, Footer>>>,
+    watch_router: WatchCallbackList,
+}
+
+impl InnerDirectory {
+    fn invalidate(&self, path: &Path) {
+        if let Some(footers) = &self.footers {
+            footers.lock().unwrap().pop(path);
+        }
+        self.block_cache.invalidate(path);
+    }
+
+    fn footer(&self, path: &Path) -> Result<Footer, OpenReadError> {
+        if let Some(footers) = &self.footers {
+            if let Some(footer) = footers.lock().unwrap().get(path) {
+                return Ok(footer.clone());
+            }
+        }
+        let wrap_io_error = |io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf());
+        let file_len = self
+            .store
+            .object_len(path)
+            .map_err(wrap_io_error)?
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_path_buf()))?;
+        let footer_start = file_len.saturating_sub(self.settings.footer_num_bytes);
+        let bytes = self
+            .store
+            .get_range(path, footer_start..file_len)
+            .map_err(wrap_io_error)?;
+        let footer = Footer {
+            file_len,
+            bytes: OwnedBytes::new(bytes),
+        };
+        if let Some(footers) = &self.footers {
+            footers
+                .lock()
+                .unwrap()
+                .put(path.to_path_buf(), footer.clone());
+        }
+        Ok(footer)
+    }
+
+    fn read_block(&self, path: &Path, file_len: usize, block_ord: usize) -> io::Result<Vec<u8>> {
+        let key = (path.to_path_buf(), block_ord);
+        if let Some(block) = self.block_cache.get(&key) {
+            return Ok(block);
+        }
+        let block_num_bytes = self.settings.block_num_bytes;
+        let block_start = block_ord * block_num_bytes;
+        let block_end = (block_start + block_num_bytes).min(file_len);
+        let block = self.store.get_range(path, block_start..block_end)?;
+        self.block_cache.insert(key, &block);
+        Ok(block)
+    }
+}
+
+/// A [`Directory`] storing its files in an [`ObjectStore`].
+///
+/// Files are fetched from the object store by blocks of
+/// [`block_num_bytes`](ObjectStoreDirectorySettings::block_num_bytes), which are kept in an
+/// LRU cache stored in a local directory. The end of the most recently opened files, where
+/// tantivy stores its footers, is kept in memory.
+///
+/// Files written with [`Directory::open_write()`] are buffered in memory and uploaded at once
+/// when their writer is terminated: as objects cannot be appended to, flushing a writer does
+/// not upload anything. For this reason, an `IndexWriter` with the write-ahead log enabled
+/// cannot be created on an `ObjectStoreDirectory`.
+/// Locks and [`Directory::watch()`] only account for the writes made through this
+/// `ObjectStoreDirectory` and its clones: concurrent writers on the same object store must
+/// be coordinated by the caller.
+#[derive(Clone)]
+pub struct ObjectStoreDirectory {
+    inner: Arc<InnerDirectory>,
+}
+
+impl fmt::Debug for ObjectStoreDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectStoreDirectory({:?})", self.inner.store)
+    }
+}
+
+impl ObjectStoreDirectory {
+    /// Opens a directory over `store`, caching the blocks of its files in `cache_directory`.
+    ///
+    /// The blocks are stored in a subdirectory of `cache_directory` named
+    /// `tantivy-block-cache-<uuid>`, which the directory creates with a random name and
+    /// removes once it and all of its clones are dropped. Nothing else must write to this
+    /// subdirectory: it is owned by the directory and is never shared, even with another
+    /// `ObjectStoreDirectory` opened on the same `cache_directory`. The rest of
+    /// `cache_directory` is left untouched, including the subdirectories left behind by a
+    /// process that did not exit cleanly.
+    pub fn open(
+        store: impl ObjectStore,
+        cache_directory: &Path,
+    ) -> Result<ObjectStoreDirectory, OpenDirectoryError> {
+        Self::open_with_settings(
+            store,
+            cache_directory,
+            ObjectStoreDirectorySettings::default(),
+        )
+    }
+
+    /// Opens a directory over `store` with the given settings.
+    pub fn open_with_settings(
+        store: impl ObjectStore,
+        cache_directory: &Path,
+        settings: ObjectStoreDirectorySettings,
+    ) -> Result<ObjectStoreDirectory, OpenDirectoryError> {
+        assert!(settings.block_num_bytes > 0, "Blocks cannot be empty");
+        let block_cache = BlockCache::open(cache_directory, settings.cache_capacity_num_bytes)?;
+        let footers = NonZeroUsize::new(settings.footer_cache_capacity)
+            .map(|footer_cache_capacity| Mutex::new(LruCache::new(footer_cache_capacity)));
+        Ok(ObjectStoreDirectory {
+            inner: Arc::new(InnerDirectory {
+                store: Box::new(store),
+                settings,
+                block_cache,
+                footers,
+                watch_router: WatchCallbackList::default(),
+            }),
+        })
+    }
+
+    /// Returns the number of bytes of the blocks held by the block cache.
+    pub fn cache_num_bytes(&self) -> u64 {
+        self.inner.block_cache.num_bytes()
+    }
+}
+
+impl Directory for ObjectStoreDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let footer = self.inner.footer(path)?;
+        Ok(Arc::new(ObjectFileHandle {
+            path: path.to_path_buf(),
+            footer,
+            directory: self.inner.clone(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.inner.invalidate(path);
+        self.inner.store.delete(path).map_err(|io_error| {
+            if io_error.kind() == io::ErrorKind::NotFound {
+                DeleteError::FileDoesNotExist(path.to_path_buf())
+            } else {
+                DeleteError::IoError {
+                    io_error: Arc::new(io_error),
+                    filepath: path.to_path_buf(),
+                }
+            }
+        })
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        let object_len = self
+            .inner
+            .store
+            .object_len(path)
+            .map_err(|io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf()))?;
+        Ok(object_len.is_some())
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let wrap_io_error = |io_error| OpenWriteError::wrap_io_error(io_error, path.to_path_buf());
+        if self
+            .inner
+            .store
+            .object_len(path)
+            .map_err(wrap_io_error)?
+            .is_some()
+        {
+            return Err(OpenWriteError::FileAlreadyExists(path.to_path_buf()));
+        }
+        // Creates the object right away, to mimic the other directories.
+        self.inner.store.put(path, &[]).map_err(wrap_io_error)?;
+        self.inner.invalidate(path);
+        Ok(BufWriter::new(Box::new(ObjectWriter {
+            path: path.to_path_buf(),
+            directory: self.inner.clone(),
+            data: Vec::new(),
+            is_terminated: false,
+        })))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        // Atomically written files may be replaced: they are never cached.
+        let wrap_io_error = |io_error| OpenReadError::wrap_io_error(io_error, path.to_path_buf());
+        let file_len = self
+            .inner
+            .store
+            .object_len(path)
+            .map_err(wrap_io_error)?
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_path_buf()))?;
+        self.inner
+            .store
+            .get_range(path, 0..file_len)
+            .map_err(wrap_io_error)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.store.put(path, data)?;
+        self.inner.invalidate(path);
+        if path == *META_FILEPATH {
+            drop(self.inner.watch_router.broadcast());
+        }
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> crate::Result<WatchHandle> {
+        Ok(self.inner.watch_router.subscribe(watch_callback))
+    }
+}
+
+/// File handle over an object, reading through the footer and the block cache.
+struct ObjectFileHandle {
+    path: PathBuf,
+    footer: Footer,
+    directory: Arc<InnerDirectory>,
+}
+
+impl fmt::Debug for ObjectFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectFileHandle({:?})", self.path)
+    }
+}
+
+impl HasLen for ObjectFileHandle {
+    fn len(&self) -> usize {
+        self.footer.file_len
+    }
+}
+
+impl FileHandle for ObjectFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        let footer_start = self.footer.start();
+        if range.start >= footer_start {
+            return Ok(self
+                .footer
+                .bytes
+                .slice(range.start - footer_start..range.end - footer_start));
+        }
+        let block_num_bytes = self.directory.settings.block_num_bytes;
+        let mut data = Vec::with_capacity(range.len());
+        let mut block_ord = range.start / block_num_bytes;
+        while block_ord * block_num_bytes < range.end {
+            let block_start = block_ord * block_num_bytes;
+            let block = self
+                .directory
+                .read_block(&self.path, self.footer.file_len, block_ord)?;
+            let start = range.start.max(block_start) - block_start;
+            let end = range.end.min(block_start + block.len()) - block_start;
+            data.extend_from_slice(&block[start..end]);
+            block_ord += 1;
+        }
+        Ok(OwnedBytes::new(data))
+    }
+}
+
+/// Writer associated with the [`ObjectStoreDirectory`].
+///
+/// The data is buffered in memory and uploaded once the writer is terminated.
+struct ObjectWriter {
+    path: PathBuf,
+    directory: Arc<InnerDirectory>,
+    data: Vec<u8>,
+    is_terminated: bool,
+}
+
+impl Drop for ObjectWriter {
+    fn drop(&mut self) {
+        if !self.is_terminated && !self.data.is_empty() {
+            warn!(
+                "You forgot to terminate {:?} before its writer got Drop. Its data is lost.",
+                self.path
+            )
+        }
+    }
+}
+
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Uploading the data on every flush would upload it over and over again.
+        Ok(())
+    }
+}
+
+impl TerminatingWrite for ObjectWriter {
+    fn terminate_ref(&mut self, _: AntiCallToken) -> io::Result<()> {
+        self.directory.store.put(&self.path, &self.data)?;
+        self.directory.invalidate(&self.path);
+        self.is_terminated = true;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use std::io::{self, Write};
+    use std::ops::Range;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::{
+        LocalObjectStore, ObjectStore, ObjectStoreDirectory, ObjectStoreDirectorySettings,
+    };
+    use crate::collector::Count;
+    use crate::directory::{Directory, TerminatingWrite};
+    use crate::query::AllQuery;
+    use crate::schema::{Schema, TEXT};
+    use crate::{Index, IndexSettings, IndexWriter, TantivyDocument, TantivyError};
+
+    /// Counts the ranges read from the underlying store.
+    #[derive(Debug)]
+    struct CountingStore {
+        store: LocalObjectStore,
+        num_range_reads: Arc<AtomicUsize>,
+    }
+
+    impl ObjectStore for CountingStore {
+        fn object_len(&self, path: &Path) -> io::Result<Option<usize>> {
+            self.store.object_len(path)
+        }
+
+        fn get_range(&self, path: &Path, range: Range<usize>) -> io::Result<Vec<u8>> {
+            self.num_range_reads.fetch_add(1, Ordering::SeqCst);
+            self.store.get_range(path, range)
+        }
+
+        fn put(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+            self.store.put(path, data)
+        }
+
+        fn delete(&self, path: &Path) -> io::Result<()> {
+            self.store.delete(path)
+        }
+    }
+
+    fn counting_directory(
+        settings: ObjectStoreDirectorySettings,
+    ) -> (ObjectStoreDirectory, Arc<AtomicUsize>, tempfile::TempDir) {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("cache")).unwrap();
+        let num_range_reads = Arc::new(AtomicUsize::default());
+        let store = CountingStore {
+            store: LocalObjectStore::open(tempdir.path().join("store")).unwrap(),
+            num_range_reads: num_range_reads.clone(),
+        };
+        let directory = ObjectStoreDirectory::open_with_settings(
+            store,
+            &tempdir.path().join("cache"),
+            settings,
+        )
+        .unwrap();
+        (directory, num_range_reads, tempdir)
+    }
+
+    fn write_file(directory: &dyn Directory, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut write = directory.open_write(path).unwrap();
+        write.write_all(data)?;
+        write.terminate()
+    }
+
+    #[test]
+    fn test_object_store_directory_block_cache() -> crate::Result<()> {
+        let (directory, num_range_reads, _tempdir) =
+            counting_directory(ObjectStoreDirectorySettings {
+                block_num_bytes: 10,
+                cache_capacity_num_bytes: 30,
+                footer_num_bytes: 5,
+                ..Default::default()
+            });
+        let path = Path::new("file");
+        let data: Vec<u8> = (0..100).collect();
+        write_file(&directory, path, &data)?;
+
+        let file = directory.open_read(path)?;
+        // The footer is fetched when the file is opened.
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 1);
+        assert_eq!(file.read_bytes_slice(96..100)?.as_slice(), &data[96..100]);
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 1);
+
+        assert_eq!(file.read_bytes_slice(5..25)?.as_slice(), &data[5..25]);
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 4);
+        assert_eq!(file.read_bytes_slice(12..18)?.as_slice(), &data[12..18]);
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 4);
+        assert_eq!(directory.cache_num_bytes(), 30);
+
+        // Reading another block evicts the least recently used one.
+        assert_eq!(file.read_bytes_slice(30..40)?.as_slice(), &data[30..40]);
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 5);
+        assert_eq!(directory.cache_num_bytes(), 30);
+        assert_eq!(file.read_bytes_slice(10..30)?.as_slice(), &data[10..30]);
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 5);
+        assert_eq!(file.read_bytes_slice(0..10)?.as_slice(), &data[0..10]);
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 6);
+
+        // Deleting the file drops its blocks.
+        assert!(directory.delete(path).is_ok());
+        assert_eq!(directory.cache_num_bytes(), 0);
+        assert!(directory.open_read(path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_directory_uploads_on_terminate() -> crate::Result<()> {
+        let (directory, _num_range_reads, tempdir) =
+            counting_directory(ObjectStoreDirectorySettings::default());
+        let store = LocalObjectStore::open(tempdir.path().join("store"))?;
+        let path = Path::new("file");
+        let mut write = directory.open_write(path)?;
+        write.write_all(b"hello")?;
+        write.flush()?;
+        assert_eq!(store.object_len(path)?, Some(0));
+        write.terminate()?;
+        assert_eq!(store.object_len(path)?, Some(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_directory_footer_cache_is_bounded() -> crate::Result<()> {
+        let (directory, num_range_reads, _tempdir) =
+            counting_directory(ObjectStoreDirectorySettings {
+                footer_cache_capacity: 1,
+                ..Default::default()
+            });
+        for path in ["first", "second"] {
+            write_file(&directory, Path::new(path), b"hello")?;
+        }
+        directory.open_read(Path::new("first"))?;
+        directory.open_read(Path::new("first"))?;
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 1);
+        // Opening another file evicts the footer of the first one.
+        directory.open_read(Path::new("second"))?;
+        directory.open_read(Path::new("first"))?;
+        assert_eq!(num_range_reads.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_directory_atomic_read_is_not_cached() -> crate::Result<()> {
+        let (directory, _num_range_reads, _tempdir) =
+            counting_directory(ObjectStoreDirectorySettings::default());
+        let path = Path::new("meta.json");
+        directory.atomic_write(path, b"hello")?;
+        assert_eq!(directory.atomic_read(path)?, b"hello");
+        directory.atomic_write(path, b"happy tax payer")?;
+        assert_eq!(directory.atomic_read(path)?, b"happy tax payer");
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_directory_rejects_write_ahead_log() -> crate::Result<()> {
+        let (directory, _num_range_reads, _tempdir) =
+            counting_directory(ObjectStoreDirectorySettings::default());
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("text", TEXT);
+        let settings = IndexSettings {
+            write_ahead_log: true,
+            ..Default::default()
+        };
+        let index = Index::create(directory, schema_builder.build(), settings)?;
+        assert!(matches!(
+            index.writer_for_tests::<TantivyDocument>(),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_directory_shared_cache_directory() -> crate::Result<()> {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_directory = tempdir.path().join("cache");
+        std::fs::create_dir(&cache_directory)?;
+        std::fs::write(cache_directory.join("1.block"), b"not a block")?;
+        let store = LocalObjectStore::open(tempdir.path().join("store"))?;
+        let path = Path::new("file");
+        let settings = ObjectStoreDirectorySettings {
+            block_num_bytes: 4,
+            footer_num_bytes: 0,
+            ..Default::default()
+        };
+        let first_directory = ObjectStoreDirectory::open_with_settings(
+            store.clone(),
+            &cache_directory,
+            settings.clone(),
+        )
+        .unwrap();
+        write_file(&first_directory, path, b"hello")?;
+        let first_file = first_directory.open_read(path)?;
+        assert_eq!(first_file.read_bytes()?.as_slice(), b"hello");
+
+        // Another directory on the same cache directory does not overwrite the blocks of the
+        // first one, even when its object store holds different data.
+        let other_store = LocalObjectStore::open(tempdir.path().join("other_store"))?;
+        let second_directory =
+            ObjectStoreDirectory::open_with_settings(other_store, &cache_directory, settings)
+                .unwrap();
+        write_file(&second_directory, path, b"happy")?;
+        let second_file = second_directory.open_read(path)?;
+        assert_eq!(second_file.read_bytes()?.as_slice(), b"happy");
+        assert_eq!(first_file.read_bytes()?.as_slice(), b"hello");
+        assert_eq!(std::fs::read_dir(&cache_directory)?.count(), 3);
+
+        // Dropping a directory only removes its own subdirectory.
+        drop((first_file, first_directory));
+        drop((second_file, second_directory));
+        let remaining_files: Vec<_> = std::fs::read_dir(&cache_directory)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<_>>()?;
+        assert_eq!(remaining_files, vec!["1.block"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_directory_index() -> crate::Result<()> {
+        let (directory, _num_range_reads, _tempdir) =
+            counting_directory(ObjectStoreDirectorySettings {
+                block_num_bytes: 64,
+                cache_capacity_num_bytes: 1_000,
+                footer_num_bytes: 32,
+                ..Default::default()
+            });
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create(
+            directory.clone(),
+            schema_builder.build(),
+            Default::default(),
+        )?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..100 {
+            index_writer.add_document(doc!(text_field=>format!("hello {i}")))?;
+        }
+        index_writer.commit()?;
+        index_writer.wait_merging_threads()?;
+
+        let index = Index::open(directory.clone())?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 100);
+        assert!(directory.cache_num_bytes() <= 1_000);
+        Ok(())
+    }
+}
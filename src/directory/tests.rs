@@ -63,6 +63,91 @@ mod mmap_directory_tests {
     }
 }
 
+#[cfg(feature = "mmap")]
+mod object_store_directory_tests {
+    use crate::directory::{LocalObjectStore, ObjectStoreDirectory};
+
+    type DirectoryImpl = ObjectStoreDirectory;
+
+    // Objects are only uploaded on terminate: the tests reading flushed but
+    // unterminated files do not apply.
+    fn make_directory() -> (DirectoryImpl, tempfile::TempDir) {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = LocalObjectStore::open(tempdir.path().join("store")).unwrap();
+        let directory = ObjectStoreDirectory::open(store, tempdir.path()).unwrap();
+        (directory, tempdir)
+    }
+
+    #[test]
+    fn test_write_create_the_file() {
+        let (directory, _tempdir) = make_directory();
+        super::test_write_create_the_file(&directory);
+    }
+
+    #[test]
+    fn test_rewrite_forbidden() -> crate::Result<()> {
+        let (directory, _tempdir) = make_directory();
+        super::test_rewrite_forbidden(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_non_blocking() {
+        let (directory, _tempdir) = make_directory();
+        super::test_lock_non_blocking(&directory);
+    }
+
+    #[test]
+    fn test_lock_blocking() {
+        let (directory, _tempdir) = make_directory();
+        super::test_lock_blocking(&directory);
+    }
+
+    #[test]
+    fn test_watch() {
+        let (directory, _tempdir) = make_directory();
+        super::test_watch(&directory);
+    }
+}
+
+#[cfg(feature = "encryption")]
+mod encrypted_directory_tests {
+    use crate::directory::{EncryptedDirectory, RamDirectory};
+
+    type DirectoryImpl = EncryptedDirectory;
+
+    // Flushing only writes the complete blocks: the tests reading flushed but
+    // unterminated files do not apply.
+    fn make_directory() -> DirectoryImpl {
+        EncryptedDirectory::wrap(RamDirectory::create(), &[1u8; 32])
+    }
+
+    #[test]
+    fn test_rewrite_forbidden() -> crate::Result<()> {
+        let directory = make_directory();
+        super::test_rewrite_forbidden(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_non_blocking() {
+        let directory = make_directory();
+        super::test_lock_non_blocking(&directory);
+    }
+
+    #[test]
+    fn test_lock_blocking() {
+        let directory = make_directory();
+        super::test_lock_blocking(&directory);
+    }
+
+    #[test]
+    fn test_watch() {
+        let directory = make_directory();
+        super::test_watch(&directory);
+    }
+}
+
 mod ram_directory_tests {
     use crate::directory::RamDirectory;
 
@@ -11,6 +11,7 @@ use crate::directory::error::{
     Incompatibility, LockError, OpenDirectoryError, OpenReadError, OpenWriteError,
 };
 use crate::fastfield::FastFieldNotAvailableError;
+use crate::indexer::LimitExceeded;
 use crate::schema::document::DeserializeError;
 use crate::{query, schema};
 
@@ -110,6 +111,9 @@ pub enum TantivyError {
     #[error("Deserialize error: {0}")]
     /// An error occurred while attempting to deserialize a document.
     DeserializeError(DeserializeError),
+    /// An operation was rejected by the limits of the `IndexWriter`.
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitExceeded),
 }
 
 impl From<io::Error> for TantivyError {
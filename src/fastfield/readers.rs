@@ -21,13 +21,63 @@ use crate::TantivyError;
 #[derive(Clone)]
 pub struct FastFieldReaders {
     columnar: Arc<ColumnarReader>,
+    // Columns rewritten by numeric updates, taking precedence over the columns of `columnar`.
+    numeric_updates: Option<Arc<ColumnarReader>>,
     schema: Schema,
 }
 
 impl FastFieldReaders {
     pub(crate) fn open(fast_field_file: FileSlice, schema: Schema) -> io::Result<FastFieldReaders> {
         let columnar = Arc::new(ColumnarReader::open(fast_field_file)?);
-        Ok(FastFieldReaders { columnar, schema })
+        Ok(FastFieldReaders {
+            columnar,
+            numeric_updates: None,
+            schema,
+        })
+    }
+
+    /// Overlays the columns rewritten by numeric updates on top of the fast fields.
+    ///
+    /// See [`apply_numeric_updates`](crate::indexer::numeric_updates::apply_numeric_updates).
+    pub(crate) fn with_numeric_updates(
+        self,
+        numeric_updates: Arc<ColumnarReader>,
+    ) -> FastFieldReaders {
+        FastFieldReaders {
+            numeric_updates: Some(numeric_updates),
+            ..self
+        }
+    }
+
+    /// Returns the columns rewritten by numeric updates, if any.
+    pub(crate) fn numeric_updates(&self) -> Option<&ColumnarReader> {
+        self.numeric_updates.as_deref()
+    }
+
+    /// Returns the columns associated with `column_name`, taking numeric updates into account.
+    fn read_columns(&self, column_name: &str) -> io::Result<Vec<DynamicColumnHandle>> {
+        if let Some(numeric_updates) = &self.numeric_updates {
+            let updated_columns = numeric_updates.read_columns(column_name)?;
+            if !updated_columns.is_empty() {
+                return Ok(updated_columns);
+            }
+        }
+        self.columnar.read_columns(column_name)
+    }
+
+    /// Lists all of the columns, taking numeric updates into account.
+    pub(crate) fn list_columns(&self) -> io::Result<Vec<(String, DynamicColumnHandle)>> {
+        let mut columns = self.columnar.list_columns()?;
+        if let Some(numeric_updates) = &self.numeric_updates {
+            for (column_name, updated_column) in numeric_updates.list_columns()? {
+                if let Some((_, column)) = columns.iter_mut().find(|(name, column)| {
+                    *name == column_name && column.column_type() == updated_column.column_type()
+                }) {
+                    *column = updated_column;
+                }
+            }
+        }
+        Ok(columns)
     }
 
     fn resolve_field(&self, column_name: &str) -> crate::Result<Option<String>> {
@@ -42,7 +92,7 @@ impl FastFieldReaders {
     pub(crate) fn space_usage(&self, schema: &Schema) -> io::Result<PerFieldSpaceUsage> {
         let mut per_field_usages: Vec<FieldUsage> = Default::default();
         for (field, field_entry) in schema.fields() {
-            let column_handles = self.columnar.read_columns(field_entry.name())?;
+            let column_handles = self.read_columns(field_entry.name())?;
             let num_bytes: ByteCount = column_handles
                 .iter()
                 .map(|column_handle| column_handle.num_bytes())
@@ -137,7 +187,6 @@ impl FastFieldReaders {
             return Ok(0u64.into());
         };
         Ok(self
-            .columnar
             .read_columns(&resolved_field_name)?
             .into_iter()
             .map(|column_handle| column_handle.num_bytes())
@@ -227,7 +276,6 @@ impl FastFieldReaders {
             return Ok(None);
         };
         let dynamic_column_handle_opt = self
-            .columnar
             .read_columns(&resolved_field_name)?
             .into_iter()
             .find(|column| column.column_type() == column_type);
@@ -243,7 +291,6 @@ impl FastFieldReaders {
             return Ok(Vec::new());
         };
         let dynamic_column_handles = self
-            .columnar
             .read_columns(&resolved_field_name)?
             .into_iter()
             .collect();
@@ -258,6 +305,12 @@ impl FastFieldReaders {
         let Some(resolved_field_name) = self.resolve_field(field_name)? else {
             return Ok(Vec::new());
         };
+        if let Some(numeric_updates) = &self.numeric_updates {
+            let updated_columns = numeric_updates.read_columns(&resolved_field_name)?;
+            if !updated_columns.is_empty() {
+                return Ok(updated_columns);
+            }
+        }
         let columns = self
             .columnar
             .read_columns_async(&resolved_field_name)
@@ -278,7 +331,7 @@ impl FastFieldReaders {
         let Some(resolved_field_name) = self.resolve_field(field_name)? else {
             return Ok(None);
         };
-        for col in self.columnar.read_columns(&resolved_field_name)? {
+        for col in self.read_columns(&resolved_field_name)? {
             if let Some(type_white_list) = type_white_list_opt {
                 if !type_white_list.contains(&col.column_type()) {
                     continue;
@@ -306,7 +359,7 @@ impl FastFieldReaders {
         let Some(resolved_field_name) = self.resolve_field(field_name)? else {
             return Ok(columns_and_types);
         };
-        for col in self.columnar.read_columns(&resolved_field_name)? {
+        for col in self.read_columns(&resolved_field_name)? {
             if let Some(type_white_list) = type_white_list_opt {
                 if !type_white_list.contains(&col.column_type()) {
                     continue;
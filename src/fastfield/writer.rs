@@ -1,6 +1,6 @@
 use std::io;
 
-use columnar::{ColumnarWriter, NumericalValue};
+use columnar::{ColumnarWriter, DynamicColumn, NumericalValue, RowId};
 use common::{DateTimePrecision, JsonPathWriter};
 use tokenizer_api::Token;
 
@@ -220,6 +220,71 @@ impl FastFieldsWriter {
         Ok(())
     }
 
+    /// Copies all of the values of `column`, an existing column of a segment with `num_rows`
+    /// rows, into the column `column_name`.
+    ///
+    /// Documents are then expected to be added for each of the `num_rows` rows.
+    pub(crate) fn record_column(
+        &mut self,
+        column_name: &str,
+        column: &DynamicColumn,
+        num_rows: RowId,
+    ) -> io::Result<()> {
+        let columnar_writer = &mut self.columnar_writer;
+        let mut buffer = Vec::new();
+        for row_id in 0..num_rows {
+            match column {
+                DynamicColumn::Bool(column) => {
+                    for val in column.values_for_doc(row_id) {
+                        columnar_writer.record_bool(row_id, column_name, val);
+                    }
+                }
+                DynamicColumn::I64(column) => {
+                    for val in column.values_for_doc(row_id) {
+                        columnar_writer.record_numerical(row_id, column_name, val);
+                    }
+                }
+                DynamicColumn::U64(column) => {
+                    for val in column.values_for_doc(row_id) {
+                        columnar_writer.record_numerical(row_id, column_name, val);
+                    }
+                }
+                DynamicColumn::F64(column) => {
+                    for val in column.values_for_doc(row_id) {
+                        columnar_writer.record_numerical(row_id, column_name, val);
+                    }
+                }
+                DynamicColumn::IpAddr(column) => {
+                    for val in column.values_for_doc(row_id) {
+                        columnar_writer.record_ip_addr(row_id, column_name, val);
+                    }
+                }
+                DynamicColumn::DateTime(column) => {
+                    for val in column.values_for_doc(row_id) {
+                        columnar_writer.record_datetime(row_id, column_name, val);
+                    }
+                }
+                DynamicColumn::Bytes(column) => {
+                    for term_ord in column.term_ords(row_id) {
+                        buffer.clear();
+                        column.ord_to_bytes(term_ord, &mut buffer)?;
+                        columnar_writer.record_bytes(row_id, column_name, &buffer);
+                    }
+                }
+                DynamicColumn::Str(column) => {
+                    for term_ord in column.term_ords(row_id) {
+                        buffer.clear();
+                        column.ord_to_bytes(term_ord, &mut buffer)?;
+                        let text = std::str::from_utf8(&buffer)
+                            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                        columnar_writer.record_str(row_id, column_name, text);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Serializes all of the `FastFieldWriter`s by pushing them in
     /// order to the fast field serializer.
     pub fn serialize(mut self, wrt: &mut dyn io::Write) -> io::Result<()> {
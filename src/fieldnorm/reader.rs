@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use super::{fieldnorm_to_id, id_to_fieldnorm};
 use crate::directory::{CompositeFile, FileSlice, OwnedBytes};
+use crate::schema::field_mapping::FieldMapping;
 use crate::schema::Field;
 use crate::space_usage::PerFieldSpaceUsage;
 use crate::DocId;
@@ -36,6 +37,13 @@ impl FieldNormReaders {
         }
     }
 
+    /// Returns the field norm readers with fields renumbered according to `field_mapping`.
+    pub(crate) fn remap_fields(&self, field_mapping: &FieldMapping) -> FieldNormReaders {
+        FieldNormReaders {
+            data: Arc::new(self.data.remap_fields(field_mapping)),
+        }
+    }
+
     /// Return a break down of the space usage per field.
     pub fn space_usage(&self) -> PerFieldSpaceUsage {
         self.data.space_usage()
@@ -2,6 +2,7 @@ use std::cmp::Ordering;
 use std::{io, iter};
 
 use super::{fieldnorm_to_id, FieldNormsSerializer};
+use crate::indexer::doc_id_mapping::DocIdMapping;
 use crate::schema::{Field, Schema};
 use crate::DocId;
 
@@ -91,7 +92,18 @@ impl FieldNormsWriter {
     }
 
     /// Serialize the seen fieldnorm values to the serializer for all fields.
-    pub fn serialize(&self, mut fieldnorms_serializer: FieldNormsSerializer) -> io::Result<()> {
+    pub fn serialize(&self, fieldnorms_serializer: FieldNormsSerializer) -> io::Result<()> {
+        self.serialize_with_doc_id_map(fieldnorms_serializer, None)
+    }
+
+    /// Serialize the seen fieldnorm values to the serializer for all fields.
+    ///
+    /// If a `doc_id_map` is given, the fieldnorms are written in the order of the new doc ids.
+    pub(crate) fn serialize_with_doc_id_map(
+        &self,
+        mut fieldnorms_serializer: FieldNormsSerializer,
+        doc_id_map: Option<&DocIdMapping>,
+    ) -> io::Result<()> {
         for (field, fieldnorms_buffer) in self.fieldnorms_buffers.iter().enumerate().filter_map(
             |(field_id, fieldnorms_buffer_opt)| {
                 fieldnorms_buffer_opt.as_ref().map(|fieldnorms_buffer| {
@@ -99,7 +111,15 @@ impl FieldNormsWriter {
                 })
             },
         ) {
-            fieldnorms_serializer.serialize_field(field, fieldnorms_buffer)?;
+            if let Some(doc_id_map) = doc_id_map {
+                let remapped_fieldnorms_buffer: Vec<u8> = doc_id_map
+                    .iter_old_doc_ids()
+                    .map(|old_doc_id| fieldnorms_buffer[old_doc_id as usize])
+                    .collect();
+                fieldnorms_serializer.serialize_field(field, &remapped_fieldnorms_buffer)?;
+            } else {
+                fieldnorms_serializer.serialize_field(field, fieldnorms_buffer)?;
+            }
         }
         fieldnorms_serializer.close()?;
         Ok(())
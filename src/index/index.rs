@@ -1,8 +1,7 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-#[cfg(feature = "mmap")]
-use std::path::Path;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use std::thread::available_parallelism;
 
 use super::segment::Segment;
@@ -14,32 +13,46 @@ use crate::directory::error::OpenReadError;
 use crate::directory::MmapDirectory;
 use crate::directory::{Directory, ManagedDirectory, RamDirectory, INDEX_WRITER_LOCK};
 use crate::error::{DataCorruption, TantivyError};
-use crate::index::{IndexMeta, SegmentId, SegmentMeta, SegmentMetaInventory};
+use crate::index::{
+    commit_opstamp, IndexCommit, IndexMeta, SegmentId, SegmentMeta, SegmentMetaInventory,
+    VersionedSchema,
+};
 use crate::indexer::index_writer::{MAX_NUM_THREAD, MEMORY_BUDGET_NUM_BYTES_MIN};
+use crate::indexer::numeric_updates::NumericUpdatesCache;
 use crate::indexer::segment_updater::save_metas;
 use crate::indexer::{IndexWriter, SingleSegmentIndexWriter};
-use crate::reader::{IndexReader, IndexReaderBuilder};
+use crate::reader::{IndexReader, IndexReaderBuilder, NrtSegments, ReloadPolicy};
 use crate::schema::document::Document;
-use crate::schema::{Field, FieldType, Schema};
+use crate::schema::{Field, FieldType, Schema, Type};
 use crate::tokenizer::{TextAnalyzer, TokenizerManager};
-use crate::SegmentReader;
+use crate::{Opstamp, SegmentReader};
 
 fn load_metas(
     directory: &dyn Directory,
     inventory: &SegmentMetaInventory,
 ) -> crate::Result<IndexMeta> {
-    let meta_data = directory.atomic_read(&META_FILEPATH)?;
+    load_metas_at(directory, &META_FILEPATH, inventory)
+}
+
+/// Reads the index meta file stored at `path`, which is either `meta.json`
+/// or the meta file of a historical commit.
+fn load_metas_at(
+    directory: &dyn Directory,
+    path: &Path,
+    inventory: &SegmentMetaInventory,
+) -> crate::Result<IndexMeta> {
+    let meta_data = directory.atomic_read(path)?;
     let meta_string = String::from_utf8(meta_data).map_err(|_utf8_err| {
         error!("Meta data is not valid utf8.");
         DataCorruption::new(
-            META_FILEPATH.to_path_buf(),
+            path.to_path_buf(),
             "Meta file does not contain valid utf8 file.".to_string(),
         )
     })?;
     IndexMeta::deserialize(&meta_string, inventory)
         .map_err(|e| {
             DataCorruption::new(
-                META_FILEPATH.to_path_buf(),
+                path.to_path_buf(),
                 format!("Meta file cannot be deserialized. {e:?}. Content: {meta_string:?}"),
             )
         })
@@ -65,8 +78,11 @@ fn save_new_metas(
             index_settings,
             segments: Vec::new(),
             schema,
+            schema_version: 0,
+            previous_schemas: Vec::new(),
             opstamp: 0u64,
             payload: None,
+            commit_timestamp: None,
         },
         directory,
     )?;
@@ -232,7 +248,27 @@ impl IndexBuilder {
     }
 
     fn validate(&self) -> crate::Result<()> {
-        if let Some(_schema) = self.schema.as_ref() {
+        if let Some(schema) = self.schema.as_ref() {
+            for sort_by_field in &self.index_settings.sort_by_fields {
+                let schema_field = schema.get_field(&sort_by_field.field).map_err(|_| {
+                    TantivyError::InvalidArgument(format!(
+                        "Field to sort index {} not found in schema",
+                        sort_by_field.field
+                    ))
+                })?;
+                let entry = schema.get_field_entry(schema_field);
+                let is_sortable_type = matches!(
+                    entry.field_type().value_type(),
+                    Type::U64 | Type::I64 | Type::F64 | Type::Bool | Type::Date
+                );
+                if !entry.is_fast() || !is_sortable_type {
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "Field {} is not a numeric fast field. Only u64, i64, f64, bool and date \
+                         fast fields can be used to sort an index",
+                        sort_by_field.field
+                    )));
+                }
+            }
             Ok(())
         } else {
             Err(TantivyError::InvalidArgument(
@@ -262,16 +298,64 @@ impl IndexBuilder {
     }
 }
 
+/// The current schema of an index, along with the previous versions of the schema
+/// some segments may have been written with.
+struct SchemaVersions {
+    version: u32,
+    schema: Schema,
+    previous: HashMap<u32, Schema>,
+}
+
+impl SchemaVersions {
+    fn from_metas(metas: &IndexMeta) -> SchemaVersions {
+        let mut schema_versions = SchemaVersions {
+            version: metas.schema_version,
+            schema: metas.schema.clone(),
+            previous: HashMap::new(),
+        };
+        schema_versions.register(metas);
+        schema_versions
+    }
+
+    /// Registers the schemas listed in `metas`.
+    ///
+    /// The current schema is only replaced if `metas` holds a more recent version.
+    fn register(&mut self, metas: &IndexMeta) {
+        for versioned_schema in &metas.previous_schemas {
+            if versioned_schema.version != self.version {
+                self.previous
+                    .entry(versioned_schema.version)
+                    .or_insert_with(|| versioned_schema.schema.clone());
+            }
+        }
+        if metas.schema_version > self.version {
+            self.set_schema(metas.schema_version, metas.schema.clone());
+        } else if metas.schema_version < self.version {
+            self.previous
+                .entry(metas.schema_version)
+                .or_insert_with(|| metas.schema.clone());
+        }
+    }
+
+    fn set_schema(&mut self, version: u32, schema: Schema) {
+        let previous_schema = std::mem::replace(&mut self.schema, schema);
+        self.previous.insert(self.version, previous_schema);
+        self.previous.remove(&version);
+        self.version = version;
+    }
+}
+
 /// Search Index
 #[derive(Clone)]
 pub struct Index {
     directory: ManagedDirectory,
-    schema: Schema,
+    schema_versions: Arc<RwLock<SchemaVersions>>,
     settings: IndexSettings,
     executor: Executor,
     tokenizers: TokenizerManager,
     fast_field_tokenizers: TokenizerManager,
     inventory: SegmentMetaInventory,
+    numeric_updates_cache: NumericUpdatesCache,
 }
 
 impl Index {
@@ -382,15 +466,15 @@ impl Index {
         metas: &IndexMeta,
         inventory: SegmentMetaInventory,
     ) -> Index {
-        let schema = metas.schema.clone();
         Index {
             settings: metas.index_settings.clone(),
             directory,
-            schema,
+            schema_versions: Arc::new(RwLock::new(SchemaVersions::from_metas(metas))),
             tokenizers: TokenizerManager::default(),
             fast_field_tokenizers: TokenizerManager::default(),
             executor: Executor::single_thread(),
             inventory,
+            numeric_updates_cache: NumericUpdatesCache::default(),
         }
     }
 
@@ -416,7 +500,8 @@ impl Index {
 
     /// Get the tokenizer associated with a specific field.
     pub fn tokenizer_for_field(&self, field: Field) -> crate::Result<TextAnalyzer> {
-        let field_entry = self.schema.get_field_entry(field);
+        let schema = self.schema();
+        let field_entry = schema.get_field_entry(field);
         let field_type = field_entry.field_type();
         let tokenizer_manager: &TokenizerManager = self.tokenizers();
         let indexing_options_opt = match field_type {
@@ -503,7 +588,18 @@ impl Index {
     /// `SegmentMeta` are guaranteed to not be garbage collected, regardless of
     /// whether the segment is recorded as part of the index or not.
     pub fn new_segment_meta(&self, segment_id: SegmentId, max_doc: u32) -> SegmentMeta {
-        self.inventory.new_segment_meta(segment_id, max_doc)
+        self.new_segment_meta_with_schema_version(segment_id, max_doc, self.schema_version())
+    }
+
+    /// Creates a new segment_meta for a segment written with the given version of the schema.
+    pub(crate) fn new_segment_meta_with_schema_version(
+        &self,
+        segment_id: SegmentId,
+        max_doc: u32,
+        schema_version: u32,
+    ) -> SegmentMeta {
+        self.inventory
+            .new_segment_meta(segment_id, max_doc, schema_version)
     }
 
     /// Open the index using the provided directory
@@ -518,7 +614,63 @@ impl Index {
 
     /// Reads the index meta file from the directory.
     pub fn load_metas(&self) -> crate::Result<IndexMeta> {
-        load_metas(self.directory(), &self.inventory)
+        let metas = load_metas(self.directory(), &self.inventory)?;
+        self.schema_versions
+            .write()
+            .expect("schema lock poisoned")
+            .register(&metas);
+        Ok(metas)
+    }
+
+    /// Lists the commits of the index, from the oldest to the last one.
+    ///
+    /// Besides the last commit, the list contains the historical commits kept by the
+    /// [`CommitDeletionPolicy`](crate::indexer::CommitDeletionPolicy) of the `IndexWriter`.
+    pub fn list_commits(&self) -> crate::Result<Vec<IndexCommit>> {
+        let mut commits = self.list_historical_commits()?;
+        commits.push(IndexCommit::new(
+            META_FILEPATH.to_path_buf(),
+            self.load_metas()?,
+        ));
+        Ok(commits)
+    }
+
+    /// Lists the historical commits of the index, ordered by opstamp.
+    pub(crate) fn list_historical_commits(&self) -> crate::Result<Vec<IndexCommit>> {
+        let mut commit_paths: Vec<(Opstamp, PathBuf)> = self
+            .directory
+            .list_managed_files()
+            .into_iter()
+            .filter_map(|path| Some((commit_opstamp(&path)?, path)))
+            .collect();
+        commit_paths.sort();
+        let mut commits = Vec::with_capacity(commit_paths.len());
+        for (_, path) in commit_paths {
+            match load_metas_at(&self.directory, &path, &self.inventory) {
+                Ok(metas) => commits.push(IndexCommit::new(path, metas)),
+                // The commit may have been deleted in the meantime.
+                Err(TantivyError::OpenReadError(OpenReadError::FileDoesNotExist(_))) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(commits)
+    }
+
+    /// Creates an [`IndexReader`] on the given commit of the index.
+    ///
+    /// The reader is not reloaded: it keeps searching the segments of the commit,
+    /// whose files are not garbage collected until the reader is dropped.
+    pub fn open_commit(&self, commit: &IndexCommit) -> crate::Result<IndexReader> {
+        self.schema_versions
+            .write()
+            .expect("schema lock poisoned")
+            .register(commit.meta());
+        let commit_segments = NrtSegments::default();
+        commit_segments.publish(commit.opstamp(), commit.meta().segments.clone());
+        self.reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .nrt_segments(commit_segments)
+            .try_into()
     }
 
     /// Open a new index writer. Attempts to acquire a lockfile.
@@ -614,7 +766,69 @@ impl Index {
     ///
     /// The schema is actually cloned.
     pub fn schema(&self) -> Schema {
-        self.schema.clone()
+        self.schema_versions
+            .read()
+            .expect("schema lock poisoned")
+            .schema
+            .clone()
+    }
+
+    /// Returns the version of the index schema.
+    ///
+    /// See [`IndexWriter::update_schema`].
+    pub fn schema_version(&self) -> u32 {
+        self.schema_versions
+            .read()
+            .expect("schema lock poisoned")
+            .version
+    }
+
+    /// Returns the cache of the fast field columns rewritten by numeric updates.
+    pub(crate) fn numeric_updates_cache(&self) -> &NumericUpdatesCache {
+        &self.numeric_updates_cache
+    }
+
+    /// Returns the current schema along with its version.
+    pub(crate) fn versioned_schema(&self) -> (u32, Schema) {
+        let schema_versions = self.schema_versions.read().expect("schema lock poisoned");
+        (schema_versions.version, schema_versions.schema.clone())
+    }
+
+    /// Returns the schema associated with the given version, if it is known.
+    pub(crate) fn schema_for_version(&self, version: u32) -> Option<Schema> {
+        let schema_versions = self.schema_versions.read().expect("schema lock poisoned");
+        if schema_versions.version == version {
+            Some(schema_versions.schema.clone())
+        } else {
+            schema_versions.previous.get(&version).cloned()
+        }
+    }
+
+    /// Replaces the schema of the index by a new version of the schema.
+    pub(crate) fn set_schema(&self, version: u32, schema: Schema) {
+        self.schema_versions
+            .write()
+            .expect("schema lock poisoned")
+            .set_schema(version, schema);
+    }
+
+    /// Returns the previous versions of the schema used by some of the given segments.
+    pub(crate) fn schemas_in_use(&self, segment_metas: &[SegmentMeta]) -> Vec<VersionedSchema> {
+        let schema_versions = self.schema_versions.read().expect("schema lock poisoned");
+        let mut versions: Vec<u32> = segment_metas
+            .iter()
+            .map(SegmentMeta::schema_version)
+            .filter(|&version| version != schema_versions.version)
+            .collect();
+        versions.sort_unstable();
+        versions.dedup();
+        versions
+            .into_iter()
+            .filter_map(|version| {
+                let schema = schema_versions.previous.get(&version)?.clone();
+                Some(VersionedSchema { version, schema })
+            })
+            .collect()
     }
 
     /// Returns the list of segments that are searchable
@@ -633,9 +847,9 @@ impl Index {
 
     /// Creates a new segment.
     pub fn new_segment(&self) -> Segment {
-        let segment_meta = self
-            .inventory
-            .new_segment_meta(SegmentId::generate_random(), 0);
+        let segment_meta =
+            self.inventory
+                .new_segment_meta(SegmentId::generate_random(), 0, self.schema_version());
         self.segment(segment_meta)
     }
 
@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+
+use crate::core::META_FILEPATH;
+use crate::index::IndexMeta;
+use crate::Opstamp;
+
+/// Returns the path of the file holding the metas of the historical commit `opstamp`.
+pub(crate) fn commit_path(opstamp: Opstamp) -> PathBuf {
+    PathBuf::from(format!("meta.{opstamp}.json"))
+}
+
+/// Returns the opstamp of the historical commit stored at `path`,
+/// if `path` is the path of a historical commit.
+pub(crate) fn commit_opstamp(path: &Path) -> Option<Opstamp> {
+    path.to_str()?
+        .strip_prefix("meta.")?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()
+}
+
+/// A commit point of an index.
+///
+/// Besides the last commit, whose metas are stored in `meta.json`, the
+/// [`CommitDeletionPolicy`](crate::indexer::CommitDeletionPolicy) of the `IndexWriter` may keep
+/// some historical commits, which can be listed with
+/// [`Index::list_commits`](crate::Index::list_commits) and searched with
+/// [`Index::open_commit`](crate::Index::open_commit).
+#[derive(Clone, Debug)]
+pub struct IndexCommit {
+    path: PathBuf,
+    meta: IndexMeta,
+}
+
+impl IndexCommit {
+    pub(crate) fn new(path: PathBuf, meta: IndexMeta) -> IndexCommit {
+        IndexCommit { path, meta }
+    }
+
+    /// Returns the opstamp of the commit.
+    pub fn opstamp(&self) -> Opstamp {
+        self.meta.opstamp
+    }
+
+    /// Returns the time of the commit, in seconds since the UNIX epoch.
+    ///
+    /// Commits made by versions of tantivy that did not record it have no timestamp.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.meta.commit_timestamp
+    }
+
+    /// Returns the payload of the commit.
+    pub fn payload(&self) -> Option<&str> {
+        self.meta.payload.as_deref()
+    }
+
+    /// Returns the metas of the commit.
+    pub fn meta(&self) -> &IndexMeta {
+        &self.meta
+    }
+
+    /// Returns the path of the file holding the metas of the commit.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the commit is the last commit of the index.
+    pub fn is_last(&self) -> bool {
+        self.path.as_path() == *META_FILEPATH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{commit_opstamp, commit_path};
+
+    #[test]
+    fn test_commit_path() {
+        assert_eq!(commit_opstamp(&commit_path(17)), Some(17));
+        assert_eq!(commit_opstamp(Path::new("meta.json")), None);
+        assert_eq!(commit_opstamp(Path::new("meta.x.json")), None);
+    }
+}
@@ -33,12 +33,21 @@ impl SegmentMetaInventory {
             .collect::<Vec<_>>()
     }
 
-    pub fn new_segment_meta(&self, segment_id: SegmentId, max_doc: u32) -> SegmentMeta {
+    pub fn new_segment_meta(
+        &self,
+        segment_id: SegmentId,
+        max_doc: u32,
+        schema_version: u32,
+    ) -> SegmentMeta {
         let inner = InnerSegmentMeta {
             segment_id,
             max_doc,
+            schema_version,
             include_temp_doc_store: Arc::new(AtomicBool::new(true)),
             deletes: None,
+            numeric_updates_opstamp: None,
+            num_bytes: None,
+            creation_timestamp: None,
         };
         SegmentMeta::from(self.inventory.track(inner))
     }
@@ -94,6 +103,13 @@ impl SegmentMeta {
             .store(false, std::sync::atomic::Ordering::Relaxed);
     }
 
+    /// Returns the version of the schema the segment was written with.
+    ///
+    /// See [`IndexMeta::schema_version`].
+    pub fn schema_version(&self) -> u32 {
+        self.tracked.schema_version
+    }
+
     /// Returns the number of deleted documents.
     pub fn num_deleted_docs(&self) -> u32 {
         self.tracked
@@ -142,6 +158,9 @@ impl SegmentMeta {
             SegmentComponent::FastFields => ".fast".to_string(),
             SegmentComponent::FieldNorms => ".fieldnorm".to_string(),
             SegmentComponent::Delete => format!(".{}.del", self.delete_opstamp().unwrap_or(0)),
+            SegmentComponent::NumericUpdates => {
+                format!(".{}.upd", self.numeric_updates_opstamp().unwrap_or(0))
+            }
         });
         PathBuf::from(path)
     }
@@ -169,6 +188,29 @@ impl SegmentMeta {
             .map(|delete_meta| delete_meta.opstamp)
     }
 
+    /// Returns the `Opstamp` of the last numeric update
+    /// taken in account in this segment.
+    pub fn numeric_updates_opstamp(&self) -> Option<Opstamp> {
+        self.tracked.numeric_updates_opstamp
+    }
+
+    /// Returns the size in bytes of the files of the segment on disk, if known.
+    ///
+    /// The delete and numeric update files are not accounted for.
+    /// The size is unknown for segments written by versions of tantivy
+    /// that did not record it.
+    pub fn num_bytes(&self) -> Option<u64> {
+        self.tracked.num_bytes
+    }
+
+    /// Returns the time the segment was written, in seconds since the UNIX epoch.
+    ///
+    /// The time is unknown for segments written by versions of tantivy
+    /// that did not record it.
+    pub fn creation_timestamp(&self) -> Option<u64> {
+        self.tracked.creation_timestamp
+    }
+
     /// Returns true iff the segment meta contains
     /// delete information.
     pub fn has_deletes(&self) -> bool {
@@ -185,8 +227,44 @@ impl SegmentMeta {
         let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
             segment_id: inner_meta.segment_id,
             max_doc,
+            schema_version: inner_meta.schema_version,
             deletes: None,
             include_temp_doc_store: Arc::new(AtomicBool::new(true)),
+            numeric_updates_opstamp: None,
+            num_bytes: None,
+            creation_timestamp: None,
+        });
+        SegmentMeta { tracked }
+    }
+
+    /// Records the size of the files of a freshly written segment.
+    #[must_use]
+    pub(crate) fn with_num_bytes(self, num_bytes: u64) -> SegmentMeta {
+        let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
+            segment_id: inner_meta.segment_id,
+            max_doc: inner_meta.max_doc,
+            schema_version: inner_meta.schema_version,
+            include_temp_doc_store: inner_meta.include_temp_doc_store.clone(),
+            deletes: inner_meta.deletes.clone(),
+            numeric_updates_opstamp: inner_meta.numeric_updates_opstamp,
+            num_bytes: Some(num_bytes),
+            creation_timestamp: inner_meta.creation_timestamp,
+        });
+        SegmentMeta { tracked }
+    }
+
+    /// Records the time a freshly written segment was created.
+    #[must_use]
+    pub(crate) fn with_creation_timestamp(self, creation_timestamp: u64) -> SegmentMeta {
+        let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
+            segment_id: inner_meta.segment_id,
+            max_doc: inner_meta.max_doc,
+            schema_version: inner_meta.schema_version,
+            include_temp_doc_store: inner_meta.include_temp_doc_store.clone(),
+            deletes: inner_meta.deletes.clone(),
+            numeric_updates_opstamp: inner_meta.numeric_updates_opstamp,
+            num_bytes: inner_meta.num_bytes,
+            creation_timestamp: Some(creation_timestamp),
         });
         SegmentMeta { tracked }
     }
@@ -205,8 +283,27 @@ impl SegmentMeta {
         let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
             segment_id: inner_meta.segment_id,
             max_doc: inner_meta.max_doc,
+            schema_version: inner_meta.schema_version,
             include_temp_doc_store: Arc::new(AtomicBool::new(true)),
             deletes: Some(delete_meta),
+            numeric_updates_opstamp: inner_meta.numeric_updates_opstamp,
+            num_bytes: inner_meta.num_bytes,
+            creation_timestamp: inner_meta.creation_timestamp,
+        });
+        SegmentMeta { tracked }
+    }
+
+    #[must_use]
+    pub(crate) fn with_numeric_updates_opstamp(self, opstamp: Opstamp) -> SegmentMeta {
+        let tracked = self.tracked.map(move |inner_meta| InnerSegmentMeta {
+            segment_id: inner_meta.segment_id,
+            max_doc: inner_meta.max_doc,
+            schema_version: inner_meta.schema_version,
+            include_temp_doc_store: Arc::new(AtomicBool::new(true)),
+            deletes: inner_meta.deletes.clone(),
+            numeric_updates_opstamp: Some(opstamp),
+            num_bytes: inner_meta.num_bytes,
+            creation_timestamp: inner_meta.creation_timestamp,
         });
         SegmentMeta { tracked }
     }
@@ -216,7 +313,15 @@ impl SegmentMeta {
 struct InnerSegmentMeta {
     segment_id: SegmentId,
     max_doc: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    schema_version: u32,
     deletes: Option<DeleteMeta>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    numeric_updates_opstamp: Option<Opstamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    num_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    creation_timestamp: Option<u64>,
     /// If you want to avoid the SegmentComponent::TempStore file to be covered by
     /// garbage collection and deleted, set this to true. This is used during merge.
     #[serde(skip)]
@@ -235,6 +340,10 @@ impl InnerSegmentMeta {
     }
 }
 
+fn is_zero(val: &u32) -> bool {
+    *val == 0
+}
+
 fn return_true() -> bool {
     true
 }
@@ -243,12 +352,25 @@ fn is_true(val: &bool) -> bool {
     *val
 }
 
+fn is_false(val: &bool) -> bool {
+    !*val
+}
+
 /// Search Index Settings.
 ///
 /// Contains settings which are applied on the whole
 /// index, like presort documents.
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct IndexSettings {
+    /// Sorts the documents of every segment by the given fast fields.
+    ///
+    /// The first field is the primary sort key, the following ones are used to break ties.
+    /// Documents without a value for a sort field are placed last. Ties on all fields keep
+    /// the insertion order of the documents.
+    ///
+    /// Only `u64`, `i64`, `f64`, `bool` and `date` fast fields are supported.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sort_by_fields: Vec<IndexSortByField>,
     /// The `Compressor` used to compress the doc store.
     #[serde(default)]
     pub docstore_compression: Compressor,
@@ -261,6 +383,17 @@ pub struct IndexSettings {
     #[serde(default = "default_docstore_blocksize")]
     /// The size of each block that will be compressed and written to disk
     pub docstore_blocksize: usize,
+    /// If set to true, the operations of the `IndexWriter` are recorded in a write-ahead log
+    /// until they are committed, and replayed when a new `IndexWriter` is opened.
+    ///
+    /// `IndexWriter::delete_query` is not available when the write-ahead log is enabled, as
+    /// arbitrary queries cannot be logged.
+    ///
+    /// The log is only flushed as records are appended: creating an `IndexWriter` fails on
+    /// directories where the content of a flushed file is not readable until its writer is
+    /// terminated, such as the `EncryptedDirectory` and the `ObjectStoreDirectory`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub write_ahead_log: bool,
 }
 
 /// Must be a function to be compatible with serde defaults
@@ -271,13 +404,28 @@ fn default_docstore_blocksize() -> usize {
 impl Default for IndexSettings {
     fn default() -> Self {
         Self {
+            sort_by_fields: Vec::new(),
             docstore_compression: Compressor::default(),
             docstore_blocksize: default_docstore_blocksize(),
             docstore_compress_dedicated_thread: true,
+            write_ahead_log: false,
         }
     }
 }
 
+/// Settings to presort the documents in an index
+///
+/// Presorting documents can greatly improve performance
+/// in some scenarios, by applying top n
+/// optimizations.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct IndexSortByField {
+    /// The field to sort the documents by
+    pub field: String,
+    /// The order to sort the documents by
+    pub order: Order,
+}
+
 /// The order to sort by
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum Order {
@@ -297,6 +445,15 @@ impl Order {
     }
 }
 
+/// A previous version of the schema of an index.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct VersionedSchema {
+    /// Version of the schema.
+    pub version: u32,
+    /// The schema itself.
+    pub schema: Schema,
+}
+
 /// Meta information about the `Index`.
 ///
 /// This object is serialized on disk in the `meta.json` file.
@@ -313,6 +470,15 @@ pub struct IndexMeta {
     pub segments: Vec<SegmentMeta>,
     /// Index `Schema`
     pub schema: Schema,
+    /// Version of the index `Schema`.
+    ///
+    /// It starts at 0 and is incremented by every
+    /// [`IndexWriter::update_schema`](crate::IndexWriter::update_schema).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub schema_version: u32,
+    /// Previous versions of the `Schema` that some of the segments were written with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_schemas: Vec<VersionedSchema>,
     /// Opstamp associated with the last `commit` operation.
     pub opstamp: Opstamp,
     /// Payload associated with the last commit.
@@ -322,6 +488,9 @@ pub struct IndexMeta {
     /// This payload is entirely unused by tantivy.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payload: Option<String>,
+    /// Time of the last commit, in seconds since the UNIX epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_timestamp: Option<u64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -330,9 +499,15 @@ struct UntrackedIndexMeta {
     #[serde(default)]
     pub index_settings: IndexSettings,
     pub schema: Schema,
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub previous_schemas: Vec<VersionedSchema>,
     pub opstamp: Opstamp,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payload: Option<String>,
+    #[serde(default)]
+    pub commit_timestamp: Option<u64>,
 }
 
 impl UntrackedIndexMeta {
@@ -345,8 +520,11 @@ impl UntrackedIndexMeta {
                 .map(|inner_seg_meta| inner_seg_meta.track(inventory))
                 .collect::<Vec<SegmentMeta>>(),
             schema: self.schema,
+            schema_version: self.schema_version,
+            previous_schemas: self.previous_schemas,
             opstamp: self.opstamp,
             payload: self.payload,
+            commit_timestamp: self.commit_timestamp,
         }
     }
 }
@@ -362,8 +540,11 @@ impl IndexMeta {
             index_settings: IndexSettings::default(),
             segments: vec![],
             schema,
+            schema_version: 0,
+            previous_schemas: Vec::new(),
             opstamp: 0u64,
             payload: None,
+            commit_timestamp: None,
         }
     }
 
@@ -390,13 +571,13 @@ impl fmt::Debug for IndexMeta {
 #[cfg(test)]
 mod tests {
 
-    use super::IndexMeta;
+    use super::{IndexMeta, IndexSortByField, VersionedSchema};
     use crate::index::index_meta::UntrackedIndexMeta;
-    use crate::schema::{Schema, TEXT};
+    use crate::schema::{Schema, FAST, TEXT};
     use crate::store::Compressor;
     #[cfg(feature = "zstd-compression")]
     use crate::store::ZstdCompressor;
-    use crate::IndexSettings;
+    use crate::{IndexSettings, Order};
 
     #[test]
     fn test_serialize_metas() {
@@ -409,8 +590,11 @@ mod tests {
             index_settings: IndexSettings::default(),
             segments: Vec::new(),
             schema,
+            schema_version: 0,
+            previous_schemas: Vec::new(),
             opstamp: 0u64,
             payload: None,
+            commit_timestamp: None,
         };
         let json = serde_json::ser::to_string(&index_metas).expect("serialization failed");
         assert_eq!(
@@ -424,6 +608,71 @@ mod tests {
         assert_eq!(index_metas.opstamp, deser_meta.opstamp);
     }
 
+    #[test]
+    fn test_serialize_metas_sort_by_fields() {
+        let schema = {
+            let mut schema_builder = Schema::builder();
+            schema_builder.add_u64_field("rank", FAST);
+            schema_builder.build()
+        };
+        let index_metas = IndexMeta {
+            index_settings: IndexSettings {
+                sort_by_fields: vec![IndexSortByField {
+                    field: "rank".to_string(),
+                    order: Order::Desc,
+                }],
+                ..Default::default()
+            },
+            segments: Vec::new(),
+            schema,
+            schema_version: 0,
+            previous_schemas: Vec::new(),
+            opstamp: 0u64,
+            payload: None,
+            commit_timestamp: None,
+        };
+        let json = serde_json::ser::to_string(&index_metas).expect("serialization failed");
+        assert!(json.starts_with(
+            r#"{"index_settings":{"sort_by_fields":[{"field":"rank","order":"Desc"}],"#
+        ));
+        let deser_meta: UntrackedIndexMeta = serde_json::from_str(&json).unwrap();
+        assert_eq!(index_metas.index_settings, deser_meta.index_settings);
+    }
+
+    #[test]
+    fn test_serialize_metas_schema_versions() {
+        let previous_schema = {
+            let mut schema_builder = Schema::builder();
+            schema_builder.add_text_field("text", TEXT);
+            schema_builder.build()
+        };
+        let schema = {
+            let mut schema_builder = Schema::builder();
+            schema_builder.add_text_field("text", TEXT);
+            schema_builder.add_u64_field("rank", FAST);
+            schema_builder.build()
+        };
+        let index_metas = IndexMeta {
+            index_settings: IndexSettings::default(),
+            segments: Vec::new(),
+            schema,
+            schema_version: 1,
+            previous_schemas: vec![VersionedSchema {
+                version: 0,
+                schema: previous_schema,
+            }],
+            opstamp: 0u64,
+            payload: None,
+            commit_timestamp: None,
+        };
+        let json = serde_json::ser::to_string(&index_metas).expect("serialization failed");
+        assert!(json.contains(r#""schema_version":1,"previous_schemas":[{"version":0,"schema":"#));
+        let deser_meta: UntrackedIndexMeta = serde_json::from_str(&json).unwrap();
+        assert_eq!(deser_meta.schema_version, 1);
+        assert_eq!(deser_meta.previous_schemas, index_metas.previous_schemas);
+        assert_eq!(deser_meta.schema, index_metas.schema);
+    }
+
     #[test]
     #[cfg(feature = "zstd-compression")]
     fn test_serialize_metas_zstd_compressor() {
@@ -434,16 +683,21 @@ mod tests {
         };
         let index_metas = IndexMeta {
             index_settings: IndexSettings {
+                sort_by_fields: Vec::new(),
                 docstore_compression: crate::store::Compressor::Zstd(ZstdCompressor {
                     compression_level: Some(4),
                 }),
                 docstore_blocksize: 1_000_000,
                 docstore_compress_dedicated_thread: true,
+                write_ahead_log: false,
             },
             segments: Vec::new(),
             schema,
+            schema_version: 0,
+            previous_schemas: Vec::new(),
             opstamp: 0u64,
             payload: None,
+            commit_timestamp: None,
         };
         let json = serde_json::ser::to_string(&index_metas).expect("serialization failed");
         assert_eq!(
@@ -500,9 +754,11 @@ mod tests {
         assert_eq!(
             index_settings,
             IndexSettings {
+                sort_by_fields: Vec::new(),
                 docstore_compression: Compressor::default(),
                 docstore_compress_dedicated_thread: true,
-                docstore_blocksize: 16_384
+                docstore_blocksize: 16_384,
+                write_ahead_log: false,
             }
         );
         {
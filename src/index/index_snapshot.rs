@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::core::{MANAGED_FILEPATH, META_FILEPATH};
+use crate::directory::error::OpenReadError;
+use crate::directory::Directory;
+use crate::error::DataCorruption;
+use crate::index::{Index, IndexMeta};
+use crate::Opstamp;
+
+/// A snapshot of a commit of an index, obtained with
+/// [`IndexWriter::snapshot`](crate::IndexWriter::snapshot).
+///
+/// As long as the snapshot is alive, the files of its commit are not garbage collected,
+/// even if the commit is superseded by later commits or merges. The files get released
+/// when the snapshot is dropped.
+///
+/// A snapshot can be exported to another [`Directory`], in order to back up the index
+/// without pausing the writer. The files of an index are never modified once written,
+/// with the exception of `meta.json`, so that an incremental backup only needs to copy
+/// the files that were not part of the previous snapshot.
+pub struct IndexSnapshot {
+    index: Index,
+    meta: Arc<IndexMeta>,
+    files: HashSet<PathBuf>,
+}
+
+impl fmt::Debug for IndexSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndexSnapshot")
+            .field("opstamp", &self.meta.opstamp)
+            .field("files", &self.files)
+            .finish()
+    }
+}
+
+impl IndexSnapshot {
+    /// Creates a snapshot of the commit described by `meta`.
+    ///
+    /// The segment metas of `meta` have to be tracked by the inventory of `index`.
+    pub(crate) fn new(index: Index, meta: Arc<IndexMeta>) -> crate::Result<IndexSnapshot> {
+        // Not all of the files listed by the segment metas exist, depending on the schema and
+        // the deletes. The ones that do are managed by the directory.
+        let managed_files = index.directory().list_managed_files();
+        let mut files: HashSet<PathBuf> = meta
+            .segments
+            .iter()
+            .flat_map(|segment_meta| segment_meta.list_files())
+            .filter(|path| managed_files.contains(path))
+            .collect();
+        files.insert(META_FILEPATH.to_path_buf());
+        Ok(IndexSnapshot { index, meta, files })
+    }
+
+    /// Returns the opstamp of the commit.
+    pub fn opstamp(&self) -> Opstamp {
+        self.meta.opstamp
+    }
+
+    /// Returns the metas of the commit.
+    pub fn meta(&self) -> &IndexMeta {
+        &self.meta
+    }
+
+    /// Returns the files of the commit, including `meta.json`.
+    pub fn files(&self) -> &HashSet<PathBuf> {
+        &self.files
+    }
+
+    /// Exports all of the files of the snapshot to `target`.
+    ///
+    /// Once the export is done, `target` contains an index that can be opened with
+    /// [`Index::open`]. The `meta.json` file is written last, so that an interrupted
+    /// export does not leave an index in an inconsistent state.
+    ///
+    /// Returns the list of the exported files.
+    pub fn export(&self, target: &dyn Directory) -> crate::Result<Vec<PathBuf>> {
+        self.export_files(&HashSet::new(), target)
+    }
+
+    /// Exports the files of the snapshot that are not in `previous_files` to `target`.
+    ///
+    /// `previous_files` are typically the [`IndexSnapshot::files`] of the snapshot
+    /// previously exported to `target`. `meta.json` is always exported.
+    ///
+    /// Returns the list of the exported files.
+    pub fn export_incremental(
+        &self,
+        previous_files: &HashSet<PathBuf>,
+        target: &dyn Directory,
+    ) -> crate::Result<Vec<PathBuf>> {
+        self.export_files(previous_files, target)
+    }
+
+    fn export_files(
+        &self,
+        previous_files: &HashSet<PathBuf>,
+        target: &dyn Directory,
+    ) -> crate::Result<Vec<PathBuf>> {
+        let directory = self.index.directory();
+        let mut exported_files: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|path| path.as_path() != *META_FILEPATH && !previous_files.contains(*path))
+            .cloned()
+            .collect();
+        exported_files.sort();
+        for path in &exported_files {
+            directory.copy_file(path, target)?;
+        }
+        // The exported files are registered as managed, so that they get garbage collected
+        // once they are not used by the exported index anymore.
+        let mut managed_files: HashSet<PathBuf> = match target.atomic_read(&MANAGED_FILEPATH) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|err| {
+                DataCorruption::new(
+                    MANAGED_FILEPATH.to_path_buf(),
+                    format!("Managed file cannot be deserialized: {err:?}."),
+                )
+            })?,
+            Err(OpenReadError::FileDoesNotExist(_)) => HashSet::new(),
+            Err(err) => return Err(err.into()),
+        };
+        managed_files.extend(self.files.iter().cloned());
+        let mut managed_files_json = serde_json::to_vec(&managed_files)?;
+        writeln!(&mut managed_files_json)?;
+        target.atomic_write(&MANAGED_FILEPATH, &managed_files_json)?;
+
+        let mut meta_json = serde_json::to_vec_pretty(self.meta.as_ref())?;
+        writeln!(&mut meta_json)?;
+        target.sync_directory()?;
+        target.atomic_write(&META_FILEPATH, &meta_json)?;
+        exported_files.push(META_FILEPATH.to_path_buf());
+        Ok(exported_files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    use crate::directory::{Directory, RamDirectory};
+    use crate::index::SegmentId;
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, INDEXED};
+    use crate::{Index, IndexWriter};
+
+    fn num_docs(directory: &RamDirectory) -> crate::Result<u64> {
+        let index = Index::open(directory.clone())?;
+        Ok(index.reader()?.searcher().num_docs())
+    }
+
+    #[test]
+    fn test_index_snapshot() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(id_field=>1u64))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(id_field=>2u64))?;
+        index_writer.commit()?;
+        let first_snapshot = index_writer.snapshot()?;
+        assert!(first_snapshot.files().contains(&PathBuf::from("meta.json")));
+
+        let backup_directory = RamDirectory::create();
+        let exported_files = first_snapshot.export(&backup_directory)?;
+        assert_eq!(
+            exported_files.iter().collect::<HashSet<_>>(),
+            first_snapshot.files().iter().collect::<HashSet<_>>()
+        );
+        assert_eq!(num_docs(&backup_directory)?, 2);
+
+        // The files of the snapshot survive the merge of its segments.
+        index_writer.delete_term(crate::Term::from_field_u64(id_field, 1));
+        index_writer.add_document(doc!(id_field=>3u64))?;
+        index_writer.commit()?;
+        let segment_ids: Vec<SegmentId> = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.garbage_collect_files().wait()?;
+        for path in first_snapshot.files() {
+            assert!(index.directory().exists(path)?);
+        }
+
+        let second_snapshot = index_writer.snapshot()?;
+        let exported_files =
+            second_snapshot.export_incremental(first_snapshot.files(), &backup_directory)?;
+        assert!(exported_files
+            .iter()
+            .all(|path| !first_snapshot.files().contains(path)
+                || path == &PathBuf::from("meta.json")));
+        assert_eq!(num_docs(&backup_directory)?, 2);
+        let backup_index = Index::open(backup_directory.clone())?;
+        assert_eq!(backup_index.searchable_segment_ids()?.len(), 1);
+
+        // Dropping the snapshot releases its files.
+        let released_files: Vec<PathBuf> = first_snapshot
+            .files()
+            .difference(second_snapshot.files())
+            .cloned()
+            .collect();
+        assert!(!released_files.is_empty());
+        drop(first_snapshot);
+        index_writer.garbage_collect_files().wait()?;
+        for path in &released_files {
+            assert!(!index.directory().exists(path)?);
+        }
+        Ok(())
+    }
+}
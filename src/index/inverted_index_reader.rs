@@ -7,6 +7,7 @@ use fnv::FnvHashSet;
 use crate::directory::FileSlice;
 use crate::positions::PositionReader;
 use crate::postings::{BlockSegmentPostings, SegmentPostings, TermInfo};
+use crate::query::profile;
 use crate::schema::{IndexRecordOption, Term, Type};
 use crate::termdict::TermDictionary;
 
@@ -63,7 +64,7 @@ impl InvertedIndexReader {
 
     /// Returns the term info associated with the term.
     pub fn get_term_info(&self, term: &Term) -> io::Result<Option<TermInfo>> {
-        self.termdict.get(term.serialized_value_bytes())
+        profile::time_term_dictionary_lookup(|| self.termdict.get(term.serialized_value_bytes()))
     }
 
     /// Return the term dictionary datastructure.
@@ -232,19 +233,20 @@ impl InvertedIndexReader {
 
     async fn get_term_range_async(
         &self,
-        terms: impl std::ops::RangeBounds<Term>,
+        lower_bound: std::ops::Bound<&[u8]>,
+        upper_bound: std::ops::Bound<&[u8]>,
         limit: Option<u64>,
     ) -> io::Result<impl Iterator<Item = TermInfo> + '_> {
         use std::ops::Bound;
         let range_builder = self.termdict.range();
-        let range_builder = match terms.start_bound() {
-            Bound::Included(bound) => range_builder.ge(bound.serialized_value_bytes()),
-            Bound::Excluded(bound) => range_builder.gt(bound.serialized_value_bytes()),
+        let range_builder = match lower_bound {
+            Bound::Included(bound) => range_builder.ge(bound),
+            Bound::Excluded(bound) => range_builder.gt(bound),
             Bound::Unbounded => range_builder,
         };
-        let range_builder = match terms.end_bound() {
-            Bound::Included(bound) => range_builder.le(bound.serialized_value_bytes()),
-            Bound::Excluded(bound) => range_builder.lt(bound.serialized_value_bytes()),
+        let range_builder = match upper_bound {
+            Bound::Included(bound) => range_builder.le(bound),
+            Bound::Excluded(bound) => range_builder.lt(bound),
             Bound::Unbounded => range_builder,
         };
         let range_builder = if let Some(limit) = limit {
@@ -299,7 +301,35 @@ impl InvertedIndexReader {
         limit: Option<u64>,
         with_positions: bool,
     ) -> io::Result<bool> {
-        let mut term_info = self.get_term_range_async(terms, limit).await?;
+        use std::ops::Bound;
+        fn serialized_bound(bound: Bound<&Term>) -> Bound<&[u8]> {
+            match bound {
+                Bound::Included(term) => Bound::Included(term.serialized_value_bytes()),
+                Bound::Excluded(term) => Bound::Excluded(term.serialized_value_bytes()),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+        self.warm_postings_range_bytes(
+            serialized_bound(terms.start_bound()),
+            serialized_bound(terms.end_bound()),
+            limit,
+            with_positions,
+        )
+        .await
+    }
+
+    /// Same as [`Self::warm_postings_range`], with the bounds expressed as
+    /// serialized term values.
+    pub(crate) async fn warm_postings_range_bytes(
+        &self,
+        lower_bound: std::ops::Bound<&[u8]>,
+        upper_bound: std::ops::Bound<&[u8]>,
+        limit: Option<u64>,
+        with_positions: bool,
+    ) -> io::Result<bool> {
+        let mut term_info = self
+            .get_term_range_async(lower_bound, upper_bound, limit)
+            .await?;
 
         let Some(first_terminfo) = term_info.next() else {
             // no key matches, nothing more to load
@@ -308,6 +338,19 @@ impl InvertedIndexReader {
 
         let last_terminfo = term_info.last().unwrap_or_else(|| first_terminfo.clone());
 
+        self.warm_postings_between(&first_terminfo, &last_terminfo, with_positions)
+            .await?;
+        Ok(true)
+    }
+
+    /// Warmup the block postings of all of the terms between `first_terminfo` and
+    /// `last_terminfo`, both included.
+    pub(crate) async fn warm_postings_between(
+        &self,
+        first_terminfo: &TermInfo,
+        last_terminfo: &TermInfo,
+        with_positions: bool,
+    ) -> io::Result<()> {
         let postings_range = first_terminfo.postings_range.start..last_terminfo.postings_range.end;
         let positions_range =
             first_terminfo.positions_range.start..last_terminfo.positions_range.end;
@@ -323,7 +366,7 @@ impl InvertedIndexReader {
         } else {
             postings.await?;
         }
-        Ok(true)
+        Ok(())
     }
 
     /// Warmup the block postings for all terms.
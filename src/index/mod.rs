@@ -3,7 +3,9 @@
 //! It contains `Index` and `Segment`, where a `Index` consists of one or more `Segment`s.
 
 mod index;
+mod index_commit;
 mod index_meta;
+mod index_snapshot;
 mod inverted_index_reader;
 mod segment;
 mod segment_component;
@@ -11,8 +13,13 @@ mod segment_id;
 mod segment_reader;
 
 pub use self::index::{Index, IndexBuilder};
+pub use self::index_commit::IndexCommit;
+pub(crate) use self::index_commit::{commit_opstamp, commit_path};
 pub(crate) use self::index_meta::SegmentMetaInventory;
-pub use self::index_meta::{IndexMeta, IndexSettings, Order, SegmentMeta};
+pub use self::index_meta::{
+    IndexMeta, IndexSettings, IndexSortByField, Order, SegmentMeta, VersionedSchema,
+};
+pub use self::index_snapshot::IndexSnapshot;
 pub use self::inverted_index_reader::InvertedIndexReader;
 pub use self::segment::Segment;
 pub use self::segment_component::SegmentComponent;
@@ -1,10 +1,16 @@
 use std::fmt;
+use std::io::BufWriter;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::HasLen;
 
 use super::SegmentComponent;
 use crate::directory::error::{OpenReadError, OpenWriteError};
 use crate::directory::{Directory, FileSlice, WritePtr};
 use crate::index::{Index, SegmentId, SegmentMeta};
+use crate::indexer::merge_scheduler::{IoThrottle, ThrottledWrite};
 use crate::schema::Schema;
 use crate::Opstamp;
 
@@ -13,6 +19,8 @@ use crate::Opstamp;
 pub struct Segment {
     index: Index,
     meta: SegmentMeta,
+    // Throttles the writes of the segment files, for merges.
+    io_throttle: Option<Arc<IoThrottle>>,
 }
 
 impl fmt::Debug for Segment {
@@ -24,7 +32,11 @@ impl fmt::Debug for Segment {
 impl Segment {
     /// Creates a new segment given an `Index` and a `SegmentId`
     pub(crate) fn for_index(index: Index, meta: SegmentMeta) -> Segment {
-        Segment { index, meta }
+        Segment {
+            index,
+            meta,
+            io_throttle: None,
+        }
     }
 
     /// Returns the index the segment belongs to.
@@ -37,6 +49,16 @@ impl Segment {
         self.index.schema()
     }
 
+    /// Returns the schema the segment is written with.
+    ///
+    /// It may be a previous version of the index schema if the schema has been updated since
+    /// the segment was created.
+    pub(crate) fn segment_schema(&self) -> Schema {
+        self.index
+            .schema_for_version(self.meta.schema_version())
+            .unwrap_or_else(|| self.index.schema())
+    }
+
     /// Returns the segment meta-information
     pub fn meta(&self) -> &SegmentMeta {
         &self.meta
@@ -49,6 +71,7 @@ impl Segment {
     pub(crate) fn with_max_doc(self, max_doc: u32) -> Segment {
         Segment {
             index: self.index,
+            io_throttle: self.io_throttle,
             meta: self.meta.with_max_doc(max_doc),
         }
     }
@@ -58,10 +81,64 @@ impl Segment {
     pub fn with_delete_meta(self, num_deleted_docs: u32, opstamp: Opstamp) -> Segment {
         Segment {
             index: self.index,
+            io_throttle: self.io_throttle,
             meta: self.meta.with_delete_meta(num_deleted_docs, opstamp),
         }
     }
 
+    #[must_use]
+    pub(crate) fn with_numeric_updates_opstamp(self, opstamp: Opstamp) -> Segment {
+        Segment {
+            index: self.index,
+            io_throttle: self.io_throttle,
+            meta: self.meta.with_numeric_updates_opstamp(opstamp),
+        }
+    }
+
+    /// Records the size on disk of the files of a freshly written segment, as well as
+    /// its creation time.
+    ///
+    /// The temporary store, delete and numeric update files are not accounted for.
+    pub(crate) fn with_file_stats(self) -> crate::Result<Segment> {
+        let directory = self.index.directory();
+        let mut num_bytes = 0u64;
+        for component in SegmentComponent::iterator() {
+            if matches!(
+                component,
+                SegmentComponent::TempStore
+                    | SegmentComponent::Delete
+                    | SegmentComponent::NumericUpdates
+            ) {
+                continue;
+            }
+            // Not all of the components exist, depending on the schema.
+            let path = self.relative_path(*component);
+            if directory.exists(&path)? {
+                num_bytes += directory.open_read(&path)?.len() as u64;
+            }
+        }
+        let creation_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        Ok(Segment {
+            meta: self
+                .meta
+                .with_num_bytes(num_bytes)
+                .with_creation_timestamp(creation_timestamp),
+            index: self.index,
+            io_throttle: self.io_throttle,
+        })
+    }
+
+    /// Throttles the writes of the files of the segment.
+    pub(crate) fn with_io_throttle(self, io_throttle: Arc<IoThrottle>) -> Segment {
+        Segment {
+            io_throttle: Some(io_throttle),
+            ..self
+        }
+    }
+
     /// Returns the segment's id.
     pub fn id(&self) -> SegmentId {
         self.meta.id()
@@ -85,6 +162,13 @@ impl Segment {
     pub fn open_write(&mut self, component: SegmentComponent) -> Result<WritePtr, OpenWriteError> {
         let path = self.relative_path(component);
         let write = self.index.directory_mut().open_write(&path)?;
-        Ok(write)
+        let Some(io_throttle) = &self.io_throttle else {
+            return Ok(write);
+        };
+        let (underlying, _) = write.into_parts();
+        Ok(BufWriter::new(Box::new(ThrottledWrite::wrap(
+            underlying,
+            io_throttle.clone(),
+        ))))
     }
 }
@@ -1,10 +1,15 @@
+use std::path::Path;
 use std::slice;
 
+use crate::index::SegmentId;
+
 /// Enum describing each component of a tantivy segment.
 /// Each component is stored in its own file,
 /// using the pattern `segment_uuid`.`component_extension`,
 /// except the delete component that takes an `segment_uuid`.`delete_opstamp`.`component_extension`
-#[derive(Copy, Clone, Eq, PartialEq)]
+/// and the numeric updates component that takes an
+/// `segment_uuid`.`numeric_updates_opstamp`.`component_extension`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum SegmentComponent {
     /// Postings (or inverted list). Sorted lists of document ids, associated with terms
     Postings,
@@ -27,12 +32,16 @@ pub enum SegmentComponent {
     /// Bitset describing which document of the segment is alive.
     /// (It was representing deleted docs but changed to represent alive docs from v0.17)
     Delete,
+    /// Columns of the fast fields updated with
+    /// [`IndexWriter::update_numeric`](crate::IndexWriter::update_numeric),
+    /// overlaid on the `FastFields` columns.
+    NumericUpdates,
 }
 
 impl SegmentComponent {
     /// Iterates through the components.
     pub fn iterator() -> slice::Iter<'static, SegmentComponent> {
-        static SEGMENT_COMPONENTS: [SegmentComponent; 8] = [
+        static SEGMENT_COMPONENTS: [SegmentComponent; 9] = [
             SegmentComponent::Postings,
             SegmentComponent::Positions,
             SegmentComponent::FastFields,
@@ -41,7 +50,69 @@ impl SegmentComponent {
             SegmentComponent::Store,
             SegmentComponent::TempStore,
             SegmentComponent::Delete,
+            SegmentComponent::NumericUpdates,
         ];
         SEGMENT_COMPONENTS.iter()
     }
+
+    /// Returns the segment and the component of a segment file, given its path.
+    ///
+    /// Returns `None` for the files which are not segment components, like `meta.json`.
+    pub(crate) fn parse_path(path: &Path) -> Option<(SegmentId, SegmentComponent)> {
+        let file_name = path.file_name()?.to_str()?;
+        let (uuid_string, extension) = file_name.split_once('.')?;
+        let segment_id = SegmentId::from_uuid_string(uuid_string).ok()?;
+        let component = match extension {
+            "idx" => SegmentComponent::Postings,
+            "pos" => SegmentComponent::Positions,
+            "fast" => SegmentComponent::FastFields,
+            "fieldnorm" => SegmentComponent::FieldNorms,
+            "term" => SegmentComponent::Terms,
+            "store" => SegmentComponent::Store,
+            "store.temp" => SegmentComponent::TempStore,
+            _ => {
+                let (opstamp, extension) = extension.split_once('.')?;
+                opstamp.parse::<u64>().ok()?;
+                match extension {
+                    "del" => SegmentComponent::Delete,
+                    "upd" => SegmentComponent::NumericUpdates,
+                    _ => return None,
+                }
+            }
+        };
+        Some((segment_id, component))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::SegmentComponent;
+    use crate::index::{SegmentId, SegmentMetaInventory};
+
+    #[test]
+    fn test_parse_path() {
+        let segment_id = SegmentId::generate_random();
+        let segment_meta = SegmentMetaInventory::default().new_segment_meta(segment_id, 10, 0);
+        for component in SegmentComponent::iterator() {
+            let path = segment_meta.relative_path(*component);
+            assert_eq!(
+                SegmentComponent::parse_path(&path),
+                Some((segment_id, *component))
+            );
+        }
+        assert_eq!(SegmentComponent::parse_path(Path::new("meta.json")), None);
+        assert_eq!(
+            SegmentComponent::parse_path(Path::new(".managed.json")),
+            None
+        );
+        assert_eq!(
+            SegmentComponent::parse_path(Path::new(&format!(
+                "{}.unknown",
+                segment_id.uuid_string()
+            ))),
+            None
+        );
+    }
 }
@@ -3,6 +3,7 @@ use std::ops::BitOrAssign;
 use std::sync::{Arc, RwLock};
 use std::{fmt, io};
 
+use columnar::ColumnarReader;
 use fnv::FnvHashMap;
 use itertools::Itertools;
 
@@ -10,13 +11,16 @@ use crate::directory::{CompositeFile, FileSlice};
 use crate::error::DataCorruption;
 use crate::fastfield::{intersect_alive_bitsets, AliveBitSet, FacetReader, FastFieldReaders};
 use crate::fieldnorm::{FieldNormReader, FieldNormReaders};
-use crate::index::{InvertedIndexReader, Segment, SegmentComponent, SegmentId};
+use crate::index::{IndexSortByField, InvertedIndexReader, Segment, SegmentComponent, SegmentId};
+use crate::indexer::numeric_updates::apply_numeric_updates;
+use crate::indexer::schema_update::is_fast_column;
 use crate::json_utils::json_path_sep_to_dot;
-use crate::schema::{Field, IndexRecordOption, Schema, Type};
+use crate::schema::field_mapping::FieldMapping;
+use crate::schema::{Field, IndexRecordOption, Schema, Term, Type};
 use crate::space_usage::SegmentSpaceUsage;
 use crate::store::StoreReader;
 use crate::termdict::TermDictionary;
-use crate::{DocId, Opstamp};
+use crate::{DocId, DocSet, Opstamp, TERMINATED};
 
 /// Entry point to access all of the datastructures of the `Segment`
 ///
@@ -34,6 +38,7 @@ pub struct SegmentReader {
 
     segment_id: SegmentId,
     delete_opstamp: Option<Opstamp>,
+    numeric_updates_opstamp: Option<Opstamp>,
 
     max_doc: DocId,
     num_docs: DocId,
@@ -47,6 +52,9 @@ pub struct SegmentReader {
     store_file: FileSlice,
     alive_bitset_opt: Option<AliveBitSet>,
     schema: Schema,
+    segment_schema: Schema,
+    field_mapping: Option<FieldMapping>,
+    sort_by_fields: Arc<[IndexSortByField]>,
 }
 
 impl SegmentReader {
@@ -67,6 +75,27 @@ impl SegmentReader {
         &self.schema
     }
 
+    /// Returns the schema the segment was written with.
+    ///
+    /// It differs from [`SegmentReader::schema`] if the schema was updated after the segment
+    /// was written. See [`IndexWriter::update_schema`](crate::IndexWriter::update_schema).
+    pub(crate) fn segment_schema(&self) -> &Schema {
+        &self.segment_schema
+    }
+
+    /// Returns the mapping from the fields of the schema the segment was written with, to the
+    /// fields of the schema of the reader, or `None` if they are the same.
+    pub(crate) fn field_mapping(&self) -> Option<&FieldMapping> {
+        self.field_mapping.as_ref()
+    }
+
+    /// Returns the fields the documents of this segment are sorted by.
+    ///
+    /// See [`IndexSettings::sort_by_fields`](crate::IndexSettings::sort_by_fields).
+    pub fn sort_by_fields(&self) -> &[IndexSortByField] {
+        &self.sort_by_fields
+    }
+
     /// Return the number of documents that have been
     /// deleted in the segment.
     pub fn num_deleted_docs(&self) -> DocId {
@@ -116,7 +145,12 @@ impl SegmentReader {
     /// They are simply stored as a fast field, serialized in
     /// the `.fieldnorm` file of the segment.
     pub fn get_fieldnorms_reader(&self, field: Field) -> crate::Result<FieldNormReader> {
-        self.fieldnorm_readers.get_field(field)?.ok_or_else(|| {
+        let fieldnorm_reader_opt = self.fieldnorm_readers.get_field(field)?;
+        if fieldnorm_reader_opt.is_none() && self.field_mapping.is_some() {
+            // The field was added to the schema after the segment was written.
+            return Ok(FieldNormReader::constant(self.max_doc, 0));
+        }
+        fieldnorm_reader_opt.ok_or_else(|| {
             let field_name = self.schema.get_field_name(field);
             let err_msg = format!(
                 "Field norm not found for field {field_name:?}. Was the field set to record norm \
@@ -136,7 +170,10 @@ impl SegmentReader {
     /// `cache_num_blocks` sets the number of decompressed blocks to be cached in an LRU.
     /// The size of blocks is configurable, this should be reflexted in the
     pub fn get_store_reader(&self, cache_num_blocks: usize) -> io::Result<StoreReader> {
-        StoreReader::open(self.store_file.clone(), cache_num_blocks)
+        Ok(
+            StoreReader::open(self.store_file.clone(), cache_num_blocks)?
+                .with_field_mapping(self.field_mapping.clone()),
+        )
     }
 
     /// Open a new segment for reading.
@@ -149,17 +186,40 @@ impl SegmentReader {
         segment: &Segment,
         custom_bitset: Option<AliveBitSet>,
     ) -> crate::Result<SegmentReader> {
+        Self::open_with_schema(segment, segment.schema(), custom_bitset)
+    }
+
+    /// Open a new segment for reading, exposing its data through `schema`.
+    ///
+    /// `schema` may be a more recent version of the schema the segment was written with.
+    pub(crate) fn open_with_schema(
+        segment: &Segment,
+        schema: Schema,
+        custom_bitset: Option<AliveBitSet>,
+    ) -> crate::Result<SegmentReader> {
+        let schema_version = segment.meta().schema_version();
+        let segment_schema = segment
+            .index()
+            .schema_for_version(schema_version)
+            .ok_or_else(|| {
+                DataCorruption::comment_only(format!(
+                    "Segment {} was written with an unknown schema version {schema_version}.",
+                    segment.id().uuid_string()
+                ))
+            })?;
+        let field_mapping = FieldMapping::between(&segment_schema, &schema);
+
         let termdict_file = segment.open_read(SegmentComponent::Terms)?;
-        let termdict_composite = CompositeFile::open(&termdict_file)?;
+        let mut termdict_composite = CompositeFile::open(&termdict_file)?;
 
         let store_file = segment.open_read(SegmentComponent::Store)?;
 
         crate::fail_point!("SegmentReader::open#middle");
 
         let postings_file = segment.open_read(SegmentComponent::Postings)?;
-        let postings_composite = CompositeFile::open(&postings_file)?;
+        let mut postings_composite = CompositeFile::open(&postings_file)?;
 
-        let positions_composite = {
+        let mut positions_composite = {
             if let Ok(positions_file) = segment.open_read(SegmentComponent::Positions) {
                 CompositeFile::open(&positions_file)?
             } else {
@@ -167,12 +227,32 @@ impl SegmentReader {
             }
         };
 
-        let schema = segment.schema();
-
         let fast_fields_data = segment.open_read(SegmentComponent::FastFields)?;
-        let fast_fields_readers = FastFieldReaders::open(fast_fields_data, schema.clone())?;
+        let mut fast_fields_readers = FastFieldReaders::open(fast_fields_data, schema.clone())?;
+        if let Some(numeric_updates_opstamp) = segment.meta().numeric_updates_opstamp() {
+            let numeric_updates = segment.index().numeric_updates_cache().get_or_apply(
+                segment.id(),
+                numeric_updates_opstamp,
+                || {
+                    let numeric_updates_data =
+                        segment.open_read(SegmentComponent::NumericUpdates)?;
+                    Ok(apply_numeric_updates(
+                        fast_fields_readers.columnar(),
+                        &ColumnarReader::open(numeric_updates_data)?,
+                    )?)
+                },
+            )?;
+            fast_fields_readers = fast_fields_readers.with_numeric_updates(numeric_updates);
+        }
         let fieldnorm_data = segment.open_read(SegmentComponent::FieldNorms)?;
-        let fieldnorm_readers = FieldNormReaders::open(fieldnorm_data)?;
+        let mut fieldnorm_readers = FieldNormReaders::open(fieldnorm_data)?;
+
+        if let Some(field_mapping) = &field_mapping {
+            termdict_composite = termdict_composite.remap_fields(field_mapping);
+            postings_composite = postings_composite.remap_fields(field_mapping);
+            positions_composite = positions_composite.remap_fields(field_mapping);
+            fieldnorm_readers = fieldnorm_readers.remap_fields(field_mapping);
+        }
 
         let original_bitset = if segment.meta().has_deletes() {
             let alive_doc_file_slice = segment.open_read(SegmentComponent::Delete)?;
@@ -200,10 +280,14 @@ impl SegmentReader {
             fieldnorm_readers,
             segment_id: segment.id(),
             delete_opstamp: segment.meta().delete_opstamp(),
+            numeric_updates_opstamp: segment.meta().numeric_updates_opstamp(),
             store_file,
             alive_bitset_opt,
             positions_composite,
             schema,
+            segment_schema,
+            field_mapping,
+            sort_by_fields: segment.index().settings().sort_by_fields.clone().into(),
         })
     }
 
@@ -355,6 +439,10 @@ impl SegmentReader {
             .fast_fields()
             .columnar()
             .iter_columns()?
+            // Columns of dropped fields are hidden until the segment gets merged.
+            .filter(|(column_name, _)| {
+                self.field_mapping.is_none() || is_fast_column(&self.schema, column_name)
+            })
             .map(|(mut field_name, handle)| {
                 json_path_sep_to_dot(&mut field_name);
                 // map to canonical path, to avoid similar but different entries.
@@ -392,6 +480,13 @@ impl SegmentReader {
         self.delete_opstamp
     }
 
+    /// Returns the opstamp of the numeric updates of the segment, if any.
+    ///
+    /// See [`IndexWriter::update_numeric`](crate::IndexWriter::update_numeric).
+    pub fn numeric_updates_opstamp(&self) -> Option<Opstamp> {
+        self.numeric_updates_opstamp
+    }
+
     /// Returns the bitset representing the alive `DocId`s.
     pub fn alive_bitset(&self) -> Option<&AliveBitSet> {
         self.alive_bitset_opt.as_ref()
@@ -405,6 +500,26 @@ impl SegmentReader {
             .unwrap_or(false)
     }
 
+    /// Returns the alive document with the given unique key, if any.
+    ///
+    /// The key is looked up directly in the postings of the unique key field.
+    /// See [`SchemaBuilder::set_unique_key_field`](crate::schema::SchemaBuilder::set_unique_key_field).
+    pub fn doc_id_by_key(&self, key: &Term) -> crate::Result<Option<DocId>> {
+        let inverted_index = self.inverted_index(key.field())?;
+        let Some(mut postings) = inverted_index.read_postings(key, IndexRecordOption::Basic)?
+        else {
+            return Ok(None);
+        };
+        let mut doc = postings.doc();
+        while doc != TERMINATED {
+            if !self.is_deleted(doc) {
+                return Ok(Some(doc));
+            }
+            doc = postings.advance();
+        }
+        Ok(None)
+    }
+
     /// Returns an iterator that will iterate over the alive document ids
     pub fn doc_ids_alive(&self) -> Box<dyn Iterator<Item = DocId> + Send + '_> {
         if let Some(alive_bitset) = &self.alive_bitset_opt {
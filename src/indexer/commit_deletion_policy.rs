@@ -0,0 +1,145 @@
+use std::fmt::Debug;
+use std::marker;
+use std::time::Duration;
+
+use crate::index::IndexCommit;
+use crate::Opstamp;
+
+/// The `CommitDeletionPolicy` defines which of the previous commits of an index are kept.
+///
+/// Every time a commit is made, the segment updater asks the policy which of the previous
+/// commits should be kept. The files of the other previous commits are garbage collected.
+/// The last commit is always kept.
+pub trait CommitDeletionPolicy: marker::Send + marker::Sync + Debug {
+    /// Given the previous commits, ordered by opstamp, and the last commit, returns the
+    /// opstamps of the previous commits to keep.
+    fn commits_to_keep(
+        &self,
+        previous_commits: &[IndexCommit],
+        last_commit: &IndexCommit,
+    ) -> Vec<Opstamp>;
+}
+
+/// Only keeps the last commit.
+///
+/// This is the default policy.
+#[derive(Debug, Clone, Default)]
+pub struct KeepLastCommit;
+
+impl CommitDeletionPolicy for KeepLastCommit {
+    fn commits_to_keep(
+        &self,
+        _previous_commits: &[IndexCommit],
+        _last_commit: &IndexCommit,
+    ) -> Vec<Opstamp> {
+        Vec::new()
+    }
+}
+
+/// Keeps the last `num_commits` commits, including the last one.
+#[derive(Debug, Clone)]
+pub struct KeepLastCommits {
+    num_commits: usize,
+}
+
+impl KeepLastCommits {
+    /// Creates a policy keeping the last `num_commits` commits.
+    pub fn new(num_commits: usize) -> KeepLastCommits {
+        KeepLastCommits { num_commits }
+    }
+}
+
+impl CommitDeletionPolicy for KeepLastCommits {
+    fn commits_to_keep(
+        &self,
+        previous_commits: &[IndexCommit],
+        _last_commit: &IndexCommit,
+    ) -> Vec<Opstamp> {
+        let num_previous_commits = self.num_commits.saturating_sub(1);
+        previous_commits
+            .iter()
+            .rev()
+            .take(num_previous_commits)
+            .map(IndexCommit::opstamp)
+            .collect()
+    }
+}
+
+/// Keeps the commits made less than `duration` before the last commit.
+///
+/// Commits without a timestamp are not kept.
+#[derive(Debug, Clone)]
+pub struct KeepCommitsForDuration {
+    duration: Duration,
+}
+
+impl KeepCommitsForDuration {
+    /// Creates a policy keeping the commits made less than `duration` before the last commit.
+    pub fn new(duration: Duration) -> KeepCommitsForDuration {
+        KeepCommitsForDuration { duration }
+    }
+}
+
+impl CommitDeletionPolicy for KeepCommitsForDuration {
+    fn commits_to_keep(
+        &self,
+        previous_commits: &[IndexCommit],
+        last_commit: &IndexCommit,
+    ) -> Vec<Opstamp> {
+        let Some(last_timestamp) = last_commit.timestamp() else {
+            return Vec::new();
+        };
+        let min_timestamp = last_timestamp.saturating_sub(self.duration.as_secs());
+        previous_commits
+            .iter()
+            .filter(|commit| {
+                commit
+                    .timestamp()
+                    .map(|timestamp| timestamp >= min_timestamp)
+                    .unwrap_or(false)
+            })
+            .map(IndexCommit::opstamp)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::index::{commit_path, IndexMeta};
+    use crate::schema::Schema;
+
+    fn commit(opstamp: Opstamp, commit_timestamp: Option<u64>) -> IndexCommit {
+        let mut meta = IndexMeta::with_schema(Schema::builder().build());
+        meta.opstamp = opstamp;
+        meta.commit_timestamp = commit_timestamp;
+        IndexCommit::new(commit_path(opstamp), meta)
+    }
+
+    #[test]
+    fn test_commit_deletion_policies() {
+        let previous_commits = vec![
+            commit(1, None),
+            commit(2, Some(1_000)),
+            commit(3, Some(2_000)),
+        ];
+        let last_commit = commit(4, Some(2_500));
+        assert!(KeepLastCommit
+            .commits_to_keep(&previous_commits, &last_commit)
+            .is_empty());
+        assert_eq!(
+            KeepLastCommits::new(3).commits_to_keep(&previous_commits, &last_commit),
+            vec![3, 2]
+        );
+        assert!(KeepLastCommits::new(1)
+            .commits_to_keep(&previous_commits, &last_commit)
+            .is_empty());
+        assert_eq!(
+            KeepCommitsForDuration::new(Duration::from_secs(1_500))
+                .commits_to_keep(&previous_commits, &last_commit),
+            vec![2, 3]
+        );
+    }
+}
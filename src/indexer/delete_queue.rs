@@ -188,6 +188,19 @@ impl DeleteCursor {
         }
     }
 
+    /// Returns true if the cursor points to an operation that `other` has already consumed.
+    pub fn is_behind(&self, other: &DeleteCursor) -> bool {
+        // The position of `self` is read first: operations pushed in the meantime
+        // can only make `other` look further away.
+        let opstamp = self.clone().get().map(|operation| operation.opstamp);
+        let other_opstamp = other.clone().get().map(|operation| operation.opstamp);
+        match (opstamp, other_opstamp) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(opstamp), Some(other_opstamp)) => opstamp < other_opstamp,
+        }
+    }
+
     #[allow(clippy::wrong_self_convention)]
     fn is_behind_opstamp(&mut self, target_opstamp: Opstamp) -> bool {
         self.get()
@@ -247,6 +260,7 @@ mod tests {
 
     use super::{DeleteOperation, DeleteQueue};
     use crate::index::SegmentReader;
+    use crate::indexer::operation::DeleteTarget;
     use crate::query::{Explanation, Scorer, Weight};
     use crate::{DocId, Score};
 
@@ -267,7 +281,7 @@ mod tests {
 
         let make_op = |i: usize| DeleteOperation {
             opstamp: i as u64,
-            target: Box::new(DummyWeight),
+            target: DeleteTarget::Query(Box::new(DummyWeight)),
         };
 
         delete_queue.push(make_op(1));
@@ -304,4 +318,25 @@ mod tests {
             assert!(operations_it.get().is_none());
         }
     }
+
+    #[test]
+    fn test_delete_cursor_is_behind() {
+        let delete_queue = DeleteQueue::new();
+        delete_queue.push(DeleteOperation {
+            opstamp: 1,
+            target: DeleteTarget::Query(Box::new(DummyWeight)),
+        });
+        let first_cursor = delete_queue.cursor();
+        let mut last_cursor = first_cursor.clone();
+        assert!(!first_cursor.is_behind(&last_cursor));
+        last_cursor.advance();
+        assert!(first_cursor.is_behind(&last_cursor));
+        assert!(!last_cursor.is_behind(&first_cursor));
+        delete_queue.push(DeleteOperation {
+            opstamp: 2,
+            target: DeleteTarget::Query(Box::new(DummyWeight)),
+        });
+        assert!(first_cursor.is_behind(&last_cursor));
+        assert!(!last_cursor.is_behind(&last_cursor.clone()));
+    }
 }
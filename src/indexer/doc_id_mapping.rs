@@ -1,14 +1,30 @@
 //! This module is used when sorting the index by a property, e.g.
 //! to get mappings from old doc_id to new doc_id and vice versa, after sorting
 
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use columnar::{ColumnType, ColumnValues};
 use common::ReadOnlyBitSet;
 
-use crate::DocAddress;
+use crate::fastfield::FastFieldReaders;
+use crate::index::IndexSortByField;
+use crate::{DocAddress, DocId, SegmentReader};
+
+/// Column types that can be used to sort an index.
+const SORTABLE_COLUMN_TYPES: [ColumnType; 5] = [
+    ColumnType::U64,
+    ColumnType::I64,
+    ColumnType::F64,
+    ColumnType::Bool,
+    ColumnType::DateTime,
+];
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum MappingType {
     Stacked,
     StackedWithDeletes,
+    Shuffled,
 }
 
 /// Struct to provide mapping from new doc_id to old doc_id and segment.
@@ -43,4 +59,365 @@ impl SegmentDocIdMapping {
     pub(crate) fn iter_old_doc_addrs(&self) -> impl Iterator<Item = DocAddress> + '_ {
         self.new_doc_id_to_old_doc_addr.iter().copied()
     }
+
+    /// Reorders the documents by the index sort.
+    ///
+    /// The sort is stable: documents that are equal on all of the sort fields keep their
+    /// current relative order.
+    pub(crate) fn sort_by_fields(
+        self,
+        readers: &[SegmentReader],
+        sort_by_fields: &[IndexSortByField],
+    ) -> crate::Result<SegmentDocIdMapping> {
+        if sort_by_fields.is_empty() {
+            return Ok(self);
+        }
+        let num_docs = self.new_doc_id_to_old_doc_addr.len();
+        let mut sort_keys: Vec<Vec<u64>> = Vec::with_capacity(sort_by_fields.len());
+        for sort_by_field in sort_by_fields {
+            let sort_key_columns: Vec<SortKeyColumn> = readers
+                .iter()
+                .map(|reader| SortKeyColumn::open(reader.fast_fields(), sort_by_field))
+                .collect::<crate::Result<_>>()?;
+            let field_sort_keys: Vec<u64> = self
+                .iter_old_doc_addrs()
+                .map(|doc_addr| {
+                    sort_key_columns[doc_addr.segment_ord as usize].sort_key(doc_addr.doc_id)
+                })
+                .collect();
+            sort_keys.push(field_sort_keys);
+        }
+        let new_doc_id_to_old_doc_addr = sort_permutation(&sort_keys, num_docs)
+            .into_iter()
+            .map(|doc_id| self.new_doc_id_to_old_doc_addr[doc_id])
+            .collect();
+        Ok(SegmentDocIdMapping::new(
+            new_doc_id_to_old_doc_addr,
+            MappingType::Shuffled,
+            self.alive_bitsets,
+        ))
+    }
+}
+
+/// Struct to provide mapping from old doc_id to new doc_id and vice versa within a segment.
+///
+/// It is used by the `SegmentWriter` to write the documents of a new segment in the order
+/// of the index sort.
+pub(crate) struct DocIdMapping {
+    new_doc_id_to_old: Vec<DocId>,
+    old_doc_id_to_new: Vec<DocId>,
+}
+
+impl DocIdMapping {
+    /// Creates the mapping sorting the `num_docs` documents of a segment by the index sort.
+    ///
+    /// The sort is stable: documents that are equal on all of the sort fields keep their
+    /// insertion order.
+    pub(crate) fn sort_by_fields(
+        fast_field_readers: &FastFieldReaders,
+        num_docs: DocId,
+        sort_by_fields: &[IndexSortByField],
+    ) -> crate::Result<DocIdMapping> {
+        let mut sort_keys: Vec<Vec<u64>> = Vec::with_capacity(sort_by_fields.len());
+        for sort_by_field in sort_by_fields {
+            let sort_key_column = SortKeyColumn::open(fast_field_readers, sort_by_field)?;
+            sort_keys.push(
+                (0..num_docs)
+                    .map(|doc_id| sort_key_column.sort_key(doc_id))
+                    .collect(),
+            );
+        }
+        let new_doc_id_to_old = sort_permutation(&sort_keys, num_docs as usize)
+            .into_iter()
+            .map(|doc_id| doc_id as DocId)
+            .collect();
+        Ok(DocIdMapping::from_new_id_to_old_id(new_doc_id_to_old))
+    }
+
+    pub(crate) fn from_new_id_to_old_id(new_doc_id_to_old: Vec<DocId>) -> Self {
+        let mut old_doc_id_to_new = vec![0; new_doc_id_to_old.len()];
+        for (new_doc_id, &old_doc_id) in new_doc_id_to_old.iter().enumerate() {
+            old_doc_id_to_new[old_doc_id as usize] = new_doc_id as DocId;
+        }
+        DocIdMapping {
+            new_doc_id_to_old,
+            old_doc_id_to_new,
+        }
+    }
+
+    /// Returns the new doc id of the document with the given old doc id.
+    pub(crate) fn get_new_doc_id(&self, old_doc_id: DocId) -> DocId {
+        self.old_doc_id_to_new[old_doc_id as usize]
+    }
+
+    /// Returns the old doc ids, ordered by the new doc ids.
+    pub(crate) fn iter_old_doc_ids(&self) -> impl Iterator<Item = DocId> + Clone + '_ {
+        self.new_doc_id_to_old.iter().copied()
+    }
+}
+
+/// Returns the permutation of `0..num_docs` sorting the documents by their sort keys.
+///
+/// `sort_keys` holds one key per document for each of the sort fields, in priority order.
+fn sort_permutation(sort_keys: &[Vec<u64>], num_docs: usize) -> Vec<usize> {
+    let mut doc_ids: Vec<usize> = (0..num_docs).collect();
+    doc_ids.sort_by(|&left, &right| {
+        sort_keys
+            .iter()
+            .map(|field_sort_keys| field_sort_keys[left].cmp(&field_sort_keys[right]))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    doc_ids
+}
+
+/// Sort key of the documents of a segment for one of the index sort fields.
+///
+/// Keys are such that sorting them in ascending order yields the index sort order.
+/// Documents without a value get the largest key, so that they end up last regardless
+/// of the order.
+struct SortKeyColumn {
+    column_opt: Option<Arc<dyn ColumnValues<u64>>>,
+    is_asc: bool,
+}
+
+impl SortKeyColumn {
+    fn open(
+        fast_field_readers: &FastFieldReaders,
+        sort_by_field: &IndexSortByField,
+    ) -> crate::Result<Self> {
+        let is_asc = sort_by_field.order.is_asc();
+        let default_value = if is_asc { u64::MAX } else { 0u64 };
+        let column_opt = fast_field_readers
+            .u64_lenient_for_type(Some(&SORTABLE_COLUMN_TYPES), &sort_by_field.field)?
+            .map(|(column, _column_type)| column.first_or_default_col(default_value));
+        Ok(SortKeyColumn { column_opt, is_asc })
+    }
+
+    fn sort_key(&self, doc_id: DocId) -> u64 {
+        let Some(column) = self.column_opt.as_ref() else {
+            return u64::MAX;
+        };
+        let value = column.get_val(doc_id);
+        if self.is_asc {
+            value
+        } else {
+            u64::MAX - value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_indexsorting {
+    use crate::collector::TopDocs;
+    use crate::index::IndexSortByField;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{PhraseQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, TEXT};
+    use crate::{Index, IndexSettings, IndexWriter, Order, TantivyDocument, Term};
+
+    fn create_sorted_index(sort_by_fields: Vec<IndexSortByField>) -> crate::Result<Index> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("rank", FAST | INDEXED | STORED);
+        schema_builder.add_i64_field("group", FAST | STORED);
+        schema_builder.add_text_field("text", TEXT | STORED);
+        let schema = schema_builder.build();
+        Index::builder()
+            .schema(schema)
+            .settings(IndexSettings {
+                sort_by_fields,
+                ..Default::default()
+            })
+            .create_in_ram()
+    }
+
+    fn sort_by(field: &str, order: Order) -> IndexSortByField {
+        IndexSortByField {
+            field: field.to_string(),
+            order,
+        }
+    }
+
+    fn add_docs(index_writer: &mut IndexWriter, ranks: &[Option<u64>]) -> crate::Result<()> {
+        let schema = index_writer.index().schema();
+        let rank = schema.get_field("rank").unwrap();
+        let group = schema.get_field("group").unwrap();
+        let text = schema.get_field("text").unwrap();
+        for rank_opt in ranks {
+            let mut doc = TantivyDocument::default();
+            let label = if let Some(rank_val) = *rank_opt {
+                doc.add_u64(rank, rank_val);
+                doc.add_i64(group, (rank_val % 2) as i64);
+                format!("rank{rank_val} hello world")
+            } else {
+                "norank hello world".to_string()
+            };
+            doc.add_text(text, label);
+            index_writer.add_document(doc)?;
+        }
+        Ok(())
+    }
+
+    /// Returns, for each segment, the stored rank of its documents, in doc id order.
+    fn stored_ranks_per_segment(index: &Index) -> crate::Result<Vec<Vec<Option<u64>>>> {
+        let rank = index.schema().get_field("rank").unwrap();
+        let searcher = index.reader()?.searcher();
+        let mut ranks_per_segment = Vec::new();
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(1)?;
+            let mut ranks = Vec::new();
+            for doc_id in segment_reader.doc_ids_alive() {
+                let doc: TantivyDocument = store_reader.get(doc_id)?;
+                ranks.push(doc.get_first(rank).and_then(|value| value.as_u64()));
+            }
+            let rank_column = segment_reader.fast_fields().u64("rank")?;
+            let fast_field_ranks: Vec<Option<u64>> = segment_reader
+                .doc_ids_alive()
+                .map(|doc_id| rank_column.first(doc_id))
+                .collect();
+            assert_eq!(ranks, fast_field_ranks);
+            ranks_per_segment.push(ranks);
+        }
+        Ok(ranks_per_segment)
+    }
+
+    #[test]
+    fn test_index_sorting_new_segment() -> crate::Result<()> {
+        let index = create_sorted_index(vec![sort_by("rank", Order::Desc)])?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        add_docs(
+            &mut index_writer,
+            &[Some(3), None, Some(7), Some(1), Some(5)],
+        )?;
+        index_writer.commit()?;
+        assert_eq!(
+            stored_ranks_per_segment(&index)?,
+            vec![vec![Some(7), Some(5), Some(3), Some(1), None]]
+        );
+
+        let searcher = index.reader()?.searcher();
+        let segment_reader = searcher.segment_reader(0);
+        assert_eq!(
+            segment_reader.sort_by_fields(),
+            &[sort_by("rank", Order::Desc)]
+        );
+        let text = index.schema().get_field("text").unwrap();
+        let term_query = TermQuery::new(
+            Term::from_field_text(text, "rank5"),
+            IndexRecordOption::Basic,
+        );
+        let top_docs = searcher.search(&term_query, &TopDocs::with_limit(3))?;
+        assert_eq!(top_docs.len(), 1);
+        assert_eq!(top_docs[0].1.doc_id, 1);
+        let phrase_query = PhraseQuery::new(vec![
+            Term::from_field_text(text, "norank"),
+            Term::from_field_text(text, "hello"),
+        ]);
+        let top_docs = searcher.search(&phrase_query, &TopDocs::with_limit(3))?;
+        assert_eq!(top_docs.len(), 1);
+        assert_eq!(top_docs[0].1.doc_id, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_sorting_new_segment_fast_fields() -> crate::Result<()> {
+        let index = create_sorted_index(vec![sort_by("rank", Order::Asc)])?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        add_docs(&mut index_writer, &[Some(3), None, Some(7), Some(1)])?;
+        index_writer.commit()?;
+        index_writer.garbage_collect_files().wait()?;
+
+        let searcher = index.reader()?.searcher();
+        let segment_reader = searcher.segment_reader(0);
+        let rank_column = segment_reader.fast_fields().u64("rank")?;
+        let ranks: Vec<Option<u64>> = (0..segment_reader.max_doc())
+            .map(|doc_id| rank_column.first(doc_id))
+            .collect();
+        assert_eq!(ranks, vec![Some(1), Some(3), Some(7), None]);
+        let group_column = segment_reader.fast_fields().i64("group")?;
+        let groups: Vec<Option<i64>> = (0..segment_reader.max_doc())
+            .map(|doc_id| group_column.first(doc_id))
+            .collect();
+        assert_eq!(groups, vec![Some(1), Some(1), Some(1), None]);
+        // The temp docstore the documents were written to is not tracked anymore.
+        let temp_store_files: Vec<_> = index
+            .directory()
+            .list_managed_files()
+            .into_iter()
+            .filter(|path| path.to_string_lossy().ends_with(".store.temp"))
+            .collect();
+        assert!(temp_store_files.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_sorting_applies_deletes() -> crate::Result<()> {
+        let index = create_sorted_index(vec![sort_by("rank", Order::Asc)])?;
+        let rank = index.schema().get_field("rank").unwrap();
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        add_docs(&mut index_writer, &[Some(3), Some(7)])?;
+        index_writer.delete_term(Term::from_field_u64(rank, 3));
+        add_docs(&mut index_writer, &[Some(3), Some(1)])?;
+        index_writer.commit()?;
+        assert_eq!(
+            stored_ranks_per_segment(&index)?,
+            vec![vec![Some(1), Some(3), Some(7)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_sorting_merge() -> crate::Result<()> {
+        let index = create_sorted_index(vec![
+            sort_by("group", Order::Asc),
+            sort_by("rank", Order::Desc),
+        ])?;
+        let rank = index.schema().get_field("rank").unwrap();
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        add_docs(&mut index_writer, &[Some(4), Some(1), None])?;
+        index_writer.commit()?;
+        add_docs(&mut index_writer, &[Some(2), Some(3), Some(6)])?;
+        index_writer.commit()?;
+        add_docs(&mut index_writer, &[Some(5), Some(8)])?;
+        index_writer.commit()?;
+        index_writer.delete_term(Term::from_field_u64(rank, 8));
+        index_writer.commit()?;
+        let mut segment_ids = index.searchable_segment_ids()?;
+        segment_ids.sort();
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+
+        assert_eq!(
+            stored_ranks_per_segment(&index)?,
+            vec![vec![
+                Some(6),
+                Some(4),
+                Some(2),
+                Some(5),
+                Some(3),
+                Some(1),
+                None
+            ]]
+        );
+        let searcher = index.reader()?.searcher();
+        let text = index.schema().get_field("text").unwrap();
+        let term_query = TermQuery::new(
+            Term::from_field_text(text, "hello"),
+            IndexRecordOption::WithFreqsAndPositions,
+        );
+        let top_docs = searcher.search(&term_query, &TopDocs::with_limit(10))?;
+        let mut doc_ids: Vec<u32> = top_docs.iter().map(|(_, doc)| doc.doc_id).collect();
+        doc_ids.sort();
+        assert_eq!(doc_ids, vec![0, 1, 2, 3, 4, 5, 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_sorting_invalid_field() {
+        for sort_field in ["text", "missing_field"] {
+            let err = create_sorted_index(vec![sort_by(sort_field, Order::Asc)]).unwrap_err();
+            assert!(matches!(err, crate::TantivyError::InvalidArgument(_)));
+        }
+    }
 }
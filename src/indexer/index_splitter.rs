@@ -0,0 +1,412 @@
+use std::hash::Hasher;
+
+use columnar::{ColumnType, MonotonicallyMappableToU64};
+use common::BitSet;
+use fnv::FnvHasher;
+
+use crate::directory::Directory;
+use crate::index::{Index, IndexMeta, SegmentReader};
+use crate::indexer::merger::IndexMerger;
+use crate::indexer::segment_updater::save_metas;
+use crate::indexer::SegmentSerializer;
+use crate::schema::{Field, IndexRecordOption};
+use crate::{DocId, DocSet, TantivyError, TERMINATED};
+
+/// The key used by an [`IndexSplitter`] to route the documents to the output indexes.
+///
+/// Documents without any value for the key are routed to the first output index.
+#[derive(Clone, Debug)]
+pub enum SplitKey {
+    /// Routes a document to the output index `value % num_outputs`, where `value` is the
+    /// first value of the document for the given `u64` or `i64` fast field.
+    FastField(String),
+    /// Routes a document to the output index `hash % num_outputs`, where `hash` is the FNV
+    /// hash of the bytes of the term of the document for the given indexed field.
+    ///
+    /// The field has to be single-valued: splitting fails if a document has several distinct
+    /// terms for it, as is the case for tokenized text fields.
+    TermHash(Field),
+}
+
+/// Splits an index into several indexes, routing each document according to a [`SplitKey`].
+///
+/// The documents are routed in a single pass over the segments of the index, using their
+/// fast fields or inverted index. The output indexes are then all written in a second pass,
+/// which merges the segments of the index into one segment per output index: each term, stored
+/// document and fast field value is read once and written to the output index of its
+/// document, without deserializing the documents.
+///
+/// Deleted documents are not part of any output index. Each output index holds a single
+/// segment, unless it has no document at all.
+///
+/// # Warning
+/// The index is not meant to be split while an `IndexWriter` is running on it or on the
+/// output indexes.
+#[derive(Clone, Debug)]
+pub struct IndexSplitter {
+    split_key: SplitKey,
+}
+
+impl IndexSplitter {
+    /// Creates an `IndexSplitter` routing the documents according to `split_key`.
+    pub fn new(split_key: SplitKey) -> IndexSplitter {
+        IndexSplitter { split_key }
+    }
+
+    /// Splits `index` into as many indexes as `output_directories`.
+    ///
+    /// The output directories are assumed to be empty.
+    pub fn split<T: Into<Box<dyn Directory>>>(
+        &self,
+        index: &Index,
+        output_directories: Vec<T>,
+    ) -> crate::Result<Vec<Index>> {
+        let num_outputs = output_directories.len();
+        if num_outputs == 0 {
+            return Err(TantivyError::InvalidArgument(
+                "No output directories given to split the index".to_string(),
+            ));
+        }
+        let segments = index.searchable_segments()?;
+        let merger = IndexMerger::open(index.schema(), index.settings().clone(), &segments)?;
+        // For every output index, the documents of each segment routed to it.
+        let mut output_docs: Vec<Vec<BitSet>> =
+            vec![Vec::with_capacity(merger.readers.len()); num_outputs];
+        for segment_reader in &merger.readers {
+            let segment_outputs = self.route_segment(segment_reader, num_outputs)?;
+            for (docs, segment_docs) in output_docs.iter_mut().zip(segment_outputs) {
+                docs.push(segment_docs);
+            }
+        }
+
+        let mut output_indexes = Vec::with_capacity(num_outputs);
+        let mut output_segments = Vec::new();
+        let mut serializers = Vec::new();
+        let mut split_docs = Vec::new();
+        for (output_directory, docs) in output_directories.into_iter().zip(output_docs) {
+            let output_index =
+                Index::create(output_directory, index.schema(), index.settings().clone())?;
+            if docs.iter().any(|segment_docs| segment_docs.len() > 0) {
+                let segment = output_index.new_segment();
+                output_segments.push((output_indexes.len(), segment.id()));
+                serializers.push(SegmentSerializer::for_segment(segment, true)?);
+                split_docs.push(docs);
+            }
+            output_indexes.push(output_index);
+        }
+        let num_docs_per_segment = merger.write_split(serializers, &split_docs)?;
+
+        for ((output_ord, segment_id), num_docs) in
+            output_segments.into_iter().zip(num_docs_per_segment)
+        {
+            let output_index = &output_indexes[output_ord];
+            let segment_meta = output_index
+                .segment(output_index.new_segment_meta(segment_id, num_docs))
+                .with_file_stats()?
+                .meta()
+                .clone();
+            let index_meta = IndexMeta {
+                index_settings: index.settings().clone(),
+                segments: vec![segment_meta],
+                schema: index.schema(),
+                schema_version: 0,
+                previous_schemas: Vec::new(),
+                opstamp: 0u64,
+                payload: None,
+                commit_timestamp: None,
+            };
+            save_metas(&index_meta, output_index.directory())?;
+        }
+        Ok(output_indexes)
+    }
+
+    /// Returns the alive documents of the segment routed to each output index.
+    fn route_segment(
+        &self,
+        segment_reader: &SegmentReader,
+        num_outputs: usize,
+    ) -> crate::Result<Vec<BitSet>> {
+        let max_doc = segment_reader.max_doc();
+        let mut output_ords: Vec<Option<usize>> = vec![None; max_doc as usize];
+        match &self.split_key {
+            SplitKey::FastField(field_name) => {
+                let Some((column, column_type)) =
+                    segment_reader.fast_fields().u64_lenient(field_name)?
+                else {
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "Field {field_name:?} is not a fast field"
+                    )));
+                };
+                let route: fn(u64, usize) -> usize = match column_type {
+                    ColumnType::U64 => |value, num_outputs| (value % num_outputs as u64) as usize,
+                    ColumnType::I64 => |value, num_outputs| {
+                        i64::from_u64(value).rem_euclid(num_outputs as i64) as usize
+                    },
+                    _ => {
+                        return Err(TantivyError::InvalidArgument(format!(
+                            "Cannot split an index by the {column_type:?} fast field \
+                             {field_name:?}, expected a u64 or i64 fast field"
+                        )));
+                    }
+                };
+                for (doc, output_ord) in output_ords.iter_mut().enumerate() {
+                    *output_ord = column
+                        .first(doc as DocId)
+                        .map(|value| route(value, num_outputs));
+                }
+            }
+            SplitKey::TermHash(field) => {
+                // Documents with several terms cannot be routed by a single hash.
+                let mut multi_valued_docs = BitSet::with_max_value(max_doc);
+                let inverted_index = segment_reader.inverted_index(*field)?;
+                let mut term_stream = inverted_index.terms().stream()?;
+                while term_stream.advance() {
+                    let mut hasher = FnvHasher::default();
+                    hasher.write(term_stream.key());
+                    let term_output_ord = (hasher.finish() % num_outputs as u64) as usize;
+                    let mut postings = inverted_index.read_postings_from_terminfo(
+                        term_stream.value(),
+                        IndexRecordOption::Basic,
+                    )?;
+                    let mut doc = postings.doc();
+                    while doc != TERMINATED {
+                        if output_ords[doc as usize].is_some() {
+                            multi_valued_docs.insert(doc);
+                        } else {
+                            output_ords[doc as usize] = Some(term_output_ord);
+                        }
+                        doc = postings.advance();
+                    }
+                }
+                // Deleted documents are not routed, whatever their terms.
+                if segment_reader
+                    .doc_ids_alive()
+                    .any(|doc| multi_valued_docs.contains(doc))
+                {
+                    let field_name = segment_reader.schema().get_field_name(*field);
+                    return Err(TantivyError::InvalidArgument(format!(
+                        "Cannot split an index by the hash of the terms of {field_name:?}: some \
+                         documents have several terms for it"
+                    )));
+                }
+            }
+        }
+        let mut outputs: Vec<BitSet> = (0..num_outputs)
+            .map(|_| BitSet::with_max_value(max_doc))
+            .collect();
+        for doc in segment_reader.doc_ids_alive() {
+            outputs[output_ords[doc as usize].unwrap_or(0)].insert(doc);
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IndexSplitter, SplitKey};
+    use crate::collector::{Count, TopDocs};
+    use crate::directory::RamDirectory;
+    use crate::index::IndexSortByField;
+    use crate::query::{PhraseQuery, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT};
+    use crate::{
+        DocAddress, Index, IndexSettings, IndexWriter, Order, TantivyDocument, TantivyError, Term,
+    };
+
+    #[test]
+    fn test_split_by_fast_field() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let tenant_field = schema_builder.add_u64_field("tenant", FAST | INDEXED);
+        let text_field = schema_builder.add_text_field("text", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for tenant in 0..10u64 {
+            index_writer.add_document(doc!(tenant_field=>tenant, text_field=>"hello"))?;
+            index_writer.commit()?;
+        }
+        index_writer.delete_term(Term::from_field_u64(tenant_field, 4));
+        index_writer.commit()?;
+
+        let directories: Vec<RamDirectory> = (0..3).map(|_| RamDirectory::create()).collect();
+        let output_indexes = IndexSplitter::new(SplitKey::FastField("tenant".to_string()))
+            .split(&index, directories)?;
+        assert_eq!(output_indexes.len(), 3);
+        for (output_ord, output_index) in output_indexes.iter().enumerate() {
+            let searcher = output_index.reader()?.searcher();
+            let expected_tenants: Vec<u64> = (0..10u64)
+                .filter(|tenant| *tenant != 4 && *tenant as usize % 3 == output_ord)
+                .collect();
+            assert_eq!(searcher.num_docs(), expected_tenants.len() as u64);
+            assert_eq!(searcher.segment_readers().len(), 1);
+            for tenant in expected_tenants {
+                let query = TermQuery::new(
+                    Term::from_field_u64(tenant_field, tenant),
+                    IndexRecordOption::Basic,
+                );
+                assert_eq!(searcher.search(&query, &Count)?, 1);
+            }
+            let query = TermQuery::new(
+                Term::from_field_text(text_field, "hello"),
+                IndexRecordOption::WithFreqs,
+            );
+            let doc_address = searcher.search(&query, &TopDocs::with_limit(1))?[0].1;
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            assert_eq!(doc.get_first(text_field).unwrap().as_str(), Some("hello"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_sorted_index() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let rank_field = schema_builder.add_u64_field("rank", FAST | STORED);
+        let text_field = schema_builder.add_text_field("text", TEXT | STORED);
+        let index = Index::builder()
+            .schema(schema_builder.build())
+            .settings(IndexSettings {
+                sort_by_fields: vec![IndexSortByField {
+                    field: "rank".to_string(),
+                    order: Order::Desc,
+                }],
+                ..Default::default()
+            })
+            .create_in_ram()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for ranks in [[3u64, 8, 4, 1], [6, 2, 7, 5]] {
+            for rank in ranks {
+                index_writer.add_document(doc!(
+                    rank_field=>rank,
+                    text_field=>format!("happy tax payer {rank}")
+                ))?;
+            }
+            index_writer.commit()?;
+        }
+
+        let directories: Vec<RamDirectory> = (0..2).map(|_| RamDirectory::create()).collect();
+        let output_indexes = IndexSplitter::new(SplitKey::FastField("rank".to_string()))
+            .split(&index, directories)?;
+        for (output_ord, output_index) in output_indexes.iter().enumerate() {
+            let searcher = output_index.reader()?.searcher();
+            assert_eq!(searcher.segment_readers().len(), 1);
+            let stored_ranks: Vec<u64> = (0..searcher.segment_reader(0).max_doc())
+                .map(|doc_id| {
+                    let doc: TantivyDocument = searcher.doc(DocAddress::new(0, doc_id))?;
+                    Ok(doc.get_first(rank_field).unwrap().as_u64().unwrap())
+                })
+                .collect::<crate::Result<_>>()?;
+            let expected_ranks: Vec<u64> = (1..=8u64)
+                .rev()
+                .filter(|rank| *rank as usize % 2 == output_ord)
+                .collect();
+            assert_eq!(stored_ranks, expected_ranks);
+            let fast_field_ranks: Vec<u64> = {
+                let column = searcher.segment_reader(0).fast_fields().u64("rank")?;
+                (0..searcher.segment_reader(0).max_doc())
+                    .map(|doc_id| column.first(doc_id).unwrap())
+                    .collect()
+            };
+            assert_eq!(fast_field_ranks, expected_ranks);
+            // Positions are written in the order of the new doc ids.
+            let query = PhraseQuery::new(vec![
+                Term::from_field_text(text_field, "tax"),
+                Term::from_field_text(text_field, "payer"),
+            ]);
+            assert_eq!(searcher.search(&query, &Count)?, 4);
+            let query = TermQuery::new(
+                Term::from_field_text(text_field, &expected_ranks[0].to_string()),
+                IndexRecordOption::Basic,
+            );
+            let top_docs = searcher.search(&query, &TopDocs::with_limit(1))?;
+            assert_eq!(top_docs[0].1.doc_id, 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_term_hash() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let tenant_field = schema_builder.add_text_field("tenant", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for tenant in ["a", "b", "c", "d", "e", "f"] {
+            for _ in 0..3 {
+                index_writer.add_document(doc!(tenant_field=>tenant))?;
+            }
+        }
+        index_writer.add_document(doc!())?;
+        index_writer.commit()?;
+
+        let directories: Vec<RamDirectory> = (0..2).map(|_| RamDirectory::create()).collect();
+        let output_indexes =
+            IndexSplitter::new(SplitKey::TermHash(tenant_field)).split(&index, directories)?;
+        let searchers: Vec<_> = output_indexes
+            .iter()
+            .map(|output_index| output_index.reader().map(|reader| reader.searcher()))
+            .collect::<crate::Result<_>>()?;
+        assert_eq!(
+            searchers
+                .iter()
+                .map(|searcher| searcher.num_docs())
+                .sum::<u64>(),
+            19
+        );
+        // All of the documents of a tenant end up in the same index.
+        for tenant in ["a", "b", "c", "d", "e", "f"] {
+            let query = TermQuery::new(
+                Term::from_field_text(tenant_field, tenant),
+                IndexRecordOption::Basic,
+            );
+            let counts: Vec<usize> = searchers
+                .iter()
+                .map(|searcher| searcher.search(&query, &Count))
+                .collect::<crate::Result<_>>()?;
+            assert!(counts == vec![3, 0] || counts == vec![0, 3]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_term_hash_rejects_multi_valued_field() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let tenant_field = schema_builder.add_text_field("tenant", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(tenant_field=>"a"))?;
+        index_writer.add_document(doc!(tenant_field=>"b", tenant_field=>"c"))?;
+        index_writer.commit()?;
+        let splitter = IndexSplitter::new(SplitKey::TermHash(tenant_field));
+        let directories: Vec<RamDirectory> = (0..2).map(|_| RamDirectory::create()).collect();
+        assert!(matches!(
+            splitter.split(&index, directories),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+
+        // Once the document is deleted, the index can be split.
+        index_writer.delete_term(Term::from_field_text(tenant_field, "b"));
+        index_writer.commit()?;
+        let directories: Vec<RamDirectory> = (0..2).map(|_| RamDirectory::create()).collect();
+        let output_indexes = splitter.split(&index, directories)?;
+        let num_docs: u64 = output_indexes
+            .iter()
+            .map(|output_index| Ok(output_index.reader()?.searcher().num_docs()))
+            .sum::<crate::Result<u64>>()?;
+        assert_eq!(num_docs, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_invalid_arguments() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", STRING | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text_field=>"hello"))?;
+        index_writer.commit()?;
+        let splitter = IndexSplitter::new(SplitKey::FastField("text".to_string()));
+        assert!(splitter.split(&index, Vec::<RamDirectory>::new()).is_err());
+        assert!(splitter
+            .split(&index, vec![RamDirectory::create()])
+            .is_err());
+        Ok(())
+    }
+}
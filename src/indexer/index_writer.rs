@@ -1,28 +1,49 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::ops::Range;
-use std::sync::Arc;
-use std::thread;
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
+use std::{io, thread};
 
+use columnar::NumericalValue;
 use common::BitSet;
+use crossbeam_channel::TrySendError;
 use smallvec::smallvec;
 
 use super::operation::{AddOperation, UserOperation};
 use super::segment_updater::SegmentUpdater;
 use super::{AddBatch, AddBatchReceiver, AddBatchSender, PreparedCommit};
-use crate::directory::{DirectoryLock, GarbageCollectionResult, TerminatingWrite};
+use crate::directory::{DirectoryClone, DirectoryLock, GarbageCollectionResult, TerminatingWrite};
 use crate::error::TantivyError;
 use crate::fastfield::write_alive_bitset;
-use crate::index::{Index, Segment, SegmentComponent, SegmentId, SegmentMeta, SegmentReader};
+use crate::index::{
+    Index, IndexMeta, IndexSnapshot, Segment, SegmentComponent, SegmentId, SegmentMeta,
+    SegmentReader,
+};
 use crate::indexer::delete_queue::{DeleteCursor, DeleteQueue};
 use crate::indexer::doc_opstamp_mapping::DocToOpstampMapping;
+use crate::indexer::index_writer_limits::{DocsReservation, LimitTracker};
 use crate::indexer::index_writer_status::IndexWriterStatus;
-use crate::indexer::operation::DeleteOperation;
+use crate::indexer::key_locks::KeyLocks;
+use crate::indexer::numeric_updates::{to_field_value, NumericUpdates};
+use crate::indexer::operation::{DeleteOperation, DeleteTarget};
 use crate::indexer::stamper::Stamper;
-use crate::indexer::{MergePolicy, SegmentEntry, SegmentWriter};
+use crate::indexer::write_ahead_log::{
+    LoggedOperation, RecoveredOperations, WalRecord, WriteAheadLog,
+};
+use crate::indexer::{
+    CommitDeletionPolicy, IndexWriterLimits, LimitExceeded, MergePolicy, MergePriority,
+    MergeSchedulerSettings, SegmentEntry, SegmentWriter,
+};
 use crate::query::{EnableScoring, Query, TermQuery};
-use crate::schema::document::Document;
-use crate::schema::{IndexRecordOption, TantivyDocument, Term};
-use crate::{FutureResult, Opstamp};
+use crate::reader::NrtSegments;
+use crate::schema::document::{Document, Value};
+use crate::schema::field_mapping::validate_schema_update;
+use crate::schema::{Field, FieldType, IndexRecordOption, Schema, TantivyDocument, Term};
+use crate::{
+    DocSet, FutureResult, IndexReader, IndexReaderBuilder, Opstamp, ReloadPolicy, Searcher,
+    TERMINATED,
+};
 
 // Size of the margin for the `memory_arena`. A segment is closed when the remaining memory
 // in the `memory_arena` goes below MARGIN_IN_BYTES.
@@ -76,12 +97,40 @@ pub struct IndexWriter<D: Document = TantivyDocument> {
 
     stamper: Stamper,
     committed_opstamp: Opstamp,
+
+    write_ahead_log: Option<Arc<WriteAheadLog>>,
+
+    nrt_segments: NrtSegments,
+
+    limits: Arc<LimitTracker>,
+
+    // Reader of the last commit used by `update_fields`, along with the meta it was
+    // loaded from.
+    committed_reader: Mutex<Option<(Arc<IndexMeta>, IndexReader)>>,
+    // Last operation on the documents with a unique key since the last commit, by unique key,
+    // along with its opstamp.
+    pending_updates: Mutex<HashMap<Term, (Opstamp, PendingUpdate)>>,
+    // Keys of the documents being updated by `update_fields`.
+    update_key_locks: KeyLocks,
+}
+
+/// Last uncommitted operation on the document with a given unique key, as seen by
+/// [`IndexWriter::update_fields`].
+#[derive(Clone)]
+enum PendingUpdate {
+    /// The document was written by `update_fields`.
+    Updated(TantivyDocument),
+    /// The document was deleted.
+    Deleted,
+    /// The document was replaced by an upsert, so that its last committed version is read.
+    Upserted,
 }
 
 fn compute_deleted_bitset(
     alive_bitset: &mut BitSet,
     segment_reader: &SegmentReader,
     delete_cursor: &mut DeleteCursor,
+    numeric_updates: &mut NumericUpdates,
     doc_opstamps: &DocToOpstampMapping,
     target_opstamp: Opstamp,
 ) -> crate::Result<bool> {
@@ -93,16 +142,49 @@ fn compute_deleted_bitset(
 
         // A delete operation should only affect
         // document that were inserted before it.
-        delete_op
-            .target
-            .for_each_no_score(segment_reader, &mut |docs_matching_delete_query| {
-                for doc_matching_delete_query in docs_matching_delete_query.iter().cloned() {
-                    if doc_opstamps.is_deleted(doc_matching_delete_query, delete_op.opstamp) {
-                        alive_bitset.remove(doc_matching_delete_query);
-                        might_have_changed = true;
+        match &delete_op.target {
+            DeleteTarget::Query(weight) => {
+                weight.for_each_no_score(segment_reader, &mut |docs_matching_delete_query| {
+                    for doc_matching_delete_query in docs_matching_delete_query.iter().cloned() {
+                        if doc_opstamps.is_deleted(doc_matching_delete_query, delete_op.opstamp) {
+                            alive_bitset.remove(doc_matching_delete_query);
+                            might_have_changed = true;
+                        }
+                    }
+                })?;
+            }
+            DeleteTarget::Key(key) => {
+                // Unique keys are looked up directly in the postings,
+                // there is no need to go through a query.
+                let inverted_index = segment_reader.inverted_index(key.field())?;
+                if let Some(mut postings) =
+                    inverted_index.read_postings(key, IndexRecordOption::Basic)?
+                {
+                    let mut doc = postings.doc();
+                    while doc != TERMINATED {
+                        if doc_opstamps.is_deleted(doc, delete_op.opstamp) {
+                            alive_bitset.remove(doc);
+                            might_have_changed = true;
+                        }
+                        doc = postings.advance();
                     }
                 }
-            })?;
+            }
+            DeleteTarget::NumericUpdate { key, field, value } => {
+                let inverted_index = segment_reader.inverted_index(key.field())?;
+                if let Some(mut postings) =
+                    inverted_index.read_postings(key, IndexRecordOption::Basic)?
+                {
+                    let mut doc = postings.doc();
+                    while doc != TERMINATED {
+                        if doc_opstamps.is_deleted(doc, delete_op.opstamp) {
+                            numeric_updates.record(*field, doc, *value);
+                        }
+                        doc = postings.advance();
+                    }
+                }
+            }
+        }
         delete_cursor.advance();
     }
     Ok(might_have_changed)
@@ -139,14 +221,20 @@ pub(crate) fn advance_deletes(
 
     let num_deleted_docs_before = segment.meta().num_deleted_docs();
 
+    let mut numeric_updates = NumericUpdates::default();
     compute_deleted_bitset(
         &mut alive_bitset,
         &segment_reader,
         segment_entry.delete_cursor(),
+        &mut numeric_updates,
         &DocToOpstampMapping::None,
         target_opstamp,
     )?;
 
+    if !numeric_updates.is_empty() {
+        segment = numeric_updates.write(&segment_reader, segment, target_opstamp)?;
+    }
+
     if let Some(seg_alive_bitset) = segment_reader.alive_bitset() {
         alive_bitset.intersect_update(seg_alive_bitset.bitset());
     }
@@ -199,10 +287,12 @@ fn index_documents<D: Document>(
 
     let doc_opstamps: Vec<Opstamp> = segment_writer.finalize()?;
 
-    let segment_with_max_doc = segment.with_max_doc(max_doc);
+    let mut segment_with_max_doc = segment.with_max_doc(max_doc);
 
-    let alive_bitset_opt = apply_deletes(&segment_with_max_doc, &mut delete_cursor, &doc_opstamps)?;
+    let alive_bitset_opt =
+        apply_deletes(&mut segment_with_max_doc, &mut delete_cursor, &doc_opstamps)?;
 
+    let segment_with_max_doc = segment_with_max_doc.with_file_stats()?;
     let meta = segment_with_max_doc.meta().clone();
     meta.untrack_temp_docstore();
     // update segment_updater inventory to remove tempstore
@@ -212,8 +302,10 @@ fn index_documents<D: Document>(
 }
 
 /// `doc_opstamps` is required to be non-empty.
+///
+/// Numeric updates are written right away, updating the meta of `segment`.
 fn apply_deletes(
-    segment: &Segment,
+    segment: &mut Segment,
     delete_cursor: &mut DeleteCursor,
     doc_opstamps: &[Opstamp],
 ) -> crate::Result<Option<BitSet>> {
@@ -234,13 +326,18 @@ fn apply_deletes(
 
     let max_doc = segment.meta().max_doc();
     let mut deleted_bitset = BitSet::with_max_value_and_full(max_doc);
+    let mut numeric_updates = NumericUpdates::default();
     let may_have_deletes = compute_deleted_bitset(
         &mut deleted_bitset,
         &segment_reader,
         delete_cursor,
+        &mut numeric_updates,
         &doc_to_opstamps,
         max_doc_opstamp,
     )?;
+    if !numeric_updates.is_empty() {
+        *segment = numeric_updates.write(&segment_reader, segment.clone(), max_doc_opstamp)?;
+    }
     Ok(if may_have_deletes {
         Some(deleted_bitset)
     } else {
@@ -268,6 +365,24 @@ impl<D: Document> IndexWriter<D> {
         num_threads: usize,
         memory_budget_in_bytes_per_thread: usize,
         directory_lock: DirectoryLock,
+    ) -> crate::Result<Self> {
+        Self::with_nrt_segments(
+            index,
+            num_threads,
+            memory_budget_in_bytes_per_thread,
+            directory_lock,
+            NrtSegments::default(),
+        )
+    }
+
+    /// Creates a new index writer publishing its segments for near-real-time search
+    /// in `nrt_segments`.
+    fn with_nrt_segments(
+        index: &Index,
+        num_threads: usize,
+        memory_budget_in_bytes_per_thread: usize,
+        directory_lock: DirectoryLock,
+        nrt_segments: NrtSegments,
     ) -> crate::Result<Self> {
         if memory_budget_in_bytes_per_thread < MEMORY_BUDGET_NUM_BYTES_MIN {
             let err_msg = format!(
@@ -287,12 +402,26 @@ impl<D: Document> IndexWriter<D> {
 
         let delete_queue = DeleteQueue::new();
 
-        let current_opstamp = index.load_metas()?.opstamp;
+        let index_meta = index.load_metas()?;
+        let current_opstamp = index_meta.opstamp;
 
         let stamper = Stamper::new(current_opstamp);
 
-        let segment_updater =
-            SegmentUpdater::create(index.clone(), stamper.clone(), &delete_queue.cursor())?;
+        let (write_ahead_log, recovered_operations) = if index.settings().write_ahead_log {
+            let (write_ahead_log, recovered_operations) =
+                WriteAheadLog::open(index.directory().box_clone(), current_opstamp)?;
+            (Some(Arc::new(write_ahead_log)), recovered_operations)
+        } else {
+            (None, RecoveredOperations::default())
+        };
+
+        let segment_updater = SegmentUpdater::create(
+            index.clone(),
+            stamper.clone(),
+            &delete_queue.cursor(),
+            write_ahead_log.clone(),
+            nrt_segments.clone(),
+        )?;
 
         let mut index_writer = Self {
             _directory_lock: Some(directory_lock),
@@ -313,11 +442,97 @@ impl<D: Document> IndexWriter<D> {
             stamper,
 
             worker_id: 0,
+
+            write_ahead_log,
+
+            nrt_segments,
+
+            limits: Arc::default(),
+
+            committed_reader: Mutex::new(None),
+            pending_updates: Mutex::new(HashMap::new()),
+            update_key_locks: KeyLocks::default(),
         };
+        index_writer
+            .limits
+            .reset(&index_writer.index, &index_meta.segments)?;
         index_writer.start_workers()?;
+        if !recovered_operations.is_empty() {
+            index_writer.replay(recovered_operations)?;
+        }
         Ok(index_writer)
     }
 
+    /// Replays the operations recovered from the write-ahead log, and commits them.
+    fn replay(&mut self, recovered_operations: RecoveredOperations) -> crate::Result<()> {
+        let RecoveredOperations {
+            delete_all,
+            mut operations,
+        } = recovered_operations;
+        info!(
+            "Replaying {} operations from the write-ahead log",
+            operations.len()
+        );
+        if delete_all {
+            self.segment_updater.remove_all_segments();
+        }
+        operations.sort_by_key(|(opstamp, _)| *opstamp);
+        let next_opstamp = operations
+            .last()
+            .map(|(opstamp, _)| *opstamp + 1)
+            .unwrap_or(0)
+            .max(self.committed_opstamp);
+        self.stamper.revert(next_opstamp);
+
+        let schema = self.index.schema();
+        let mut delete_cursor = self.delete_queue.cursor();
+        let mut adds: Vec<AddBatch<TantivyDocument>> = Vec::new();
+        for (opstamp, operation) in operations {
+            match operation {
+                LoggedOperation::Add(document) => {
+                    adds.push(smallvec![AddOperation { opstamp, document }]);
+                }
+                LoggedOperation::Upsert(document) => {
+                    let key = unique_key_term(&schema, &document)?;
+                    self.delete_queue.push(DeleteOperation {
+                        opstamp,
+                        target: DeleteTarget::Key(key),
+                    });
+                    adds.push(smallvec![AddOperation { opstamp, document }]);
+                }
+                LoggedOperation::Delete(term) => {
+                    // Like `delete_term`, deletes of invalid terms are ignored.
+                    let query = TermQuery::new(term, IndexRecordOption::Basic);
+                    if let Ok(weight) = query.weight(EnableScoring::disabled_from_schema(&schema)) {
+                        self.delete_queue.push(DeleteOperation {
+                            opstamp,
+                            target: DeleteTarget::Query(weight),
+                        });
+                    }
+                }
+                LoggedOperation::NumericUpdate { key, field, value } => {
+                    self.delete_queue.push(DeleteOperation {
+                        opstamp,
+                        target: DeleteTarget::NumericUpdate { key, field, value },
+                    });
+                }
+            }
+        }
+        let mut adds = adds.into_iter().peekable();
+        while let Some(batch) = adds.peek() {
+            delete_cursor.skip_to(batch[0].opstamp);
+            index_documents(
+                self.memory_budget_in_bytes_per_thread,
+                self.index.new_segment(),
+                &mut adds,
+                &self.segment_updater,
+                delete_cursor.clone(),
+            )?;
+        }
+        self.commit()?;
+        Ok(())
+    }
+
     fn drop_sender(&mut self) {
         let (sender, _receiver) = crossbeam_channel::bounded(1);
         self.operation_sender = sender;
@@ -450,6 +665,66 @@ impl<D: Document> IndexWriter<D> {
         self.segment_updater.set_merge_policy(merge_policy);
     }
 
+    /// Returns the settings of the merge scheduler.
+    pub fn merge_scheduler_settings(&self) -> MergeSchedulerSettings {
+        self.segment_updater.merge_scheduler_settings()
+    }
+
+    /// Sets the settings of the merge scheduler.
+    ///
+    /// Running merges are not interrupted, but their writes are throttled according
+    /// to the new settings. The merge thread pool is resized to
+    /// [`MergeSchedulerSettings::max_concurrent_merges`] threads.
+    pub fn set_merge_scheduler_settings(
+        &self,
+        settings: MergeSchedulerSettings,
+    ) -> crate::Result<()> {
+        self.segment_updater.set_merge_scheduler_settings(settings)
+    }
+
+    /// Accessor to the commit deletion policy.
+    pub fn get_commit_deletion_policy(&self) -> Arc<dyn CommitDeletionPolicy> {
+        self.segment_updater.get_commit_deletion_policy()
+    }
+
+    /// Setter for the commit deletion policy.
+    ///
+    /// The policy is applied on the next commit, including to the historical commits
+    /// kept by a previous `IndexWriter`.
+    pub fn set_commit_deletion_policy(
+        &self,
+        commit_deletion_policy: Box<dyn CommitDeletionPolicy>,
+    ) {
+        self.segment_updater
+            .set_commit_deletion_policy(commit_deletion_policy);
+    }
+
+    /// Returns the limits enforced by the index writer.
+    pub fn limits(&self) -> IndexWriterLimits {
+        self.limits.limits()
+    }
+
+    /// Sets the limits enforced by the index writer.
+    ///
+    /// The limits are checked when documents are added. Documents that are already
+    /// in the indexing queue are not affected.
+    pub fn set_limits(&self, limits: IndexWriterLimits) -> crate::Result<()> {
+        self.limits.set_limits(limits);
+        self.limits
+            .refresh_num_bytes_on_disk(&self.index, &self.segment_updater.load_meta().segments)
+    }
+
+    /// Returns the tracker of the usage accounted for by the limits.
+    pub(crate) fn limit_tracker(&self) -> Arc<LimitTracker> {
+        self.limits.clone()
+    }
+
+    /// Resets the usage accounted for by the limits to the one of the last commit.
+    fn reset_limits_usage(&self) -> crate::Result<()> {
+        self.limits
+            .reset(&self.index, &self.segment_updater.load_meta().segments)
+    }
+
     fn start_workers(&mut self) -> crate::Result<()> {
         for _ in 0..self.num_threads {
             self.add_indexing_worker()?;
@@ -499,8 +774,16 @@ impl<D: Document> IndexWriter<D> {
     /// }
     /// ```
     pub fn delete_all_documents(&self) -> crate::Result<Opstamp> {
+        if let Some(write_ahead_log) = &self.write_ahead_log {
+            // The operations logged so far are void.
+            let mut record = WalRecord::default();
+            record.delete_all(self.committed_opstamp)?;
+            write_ahead_log.restart(&record)?;
+        }
+        self.pending_updates.lock().unwrap().clear();
         // Delete segments
         self.segment_updater.remove_all_segments();
+        self.limits.clear_docs();
         // Return new stamp - reverted stamp
         self.stamper.revert(self.committed_opstamp);
         Ok(self.committed_opstamp)
@@ -510,13 +793,57 @@ impl<D: Document> IndexWriter<D> {
     ///
     /// If all segments are empty no new segment will be created.
     ///
+    /// The merge runs with [`MergePriority::High`], ahead of the merges of the merge policy.
+    ///
     /// `segment_ids` is required to be non-empty.
     pub fn merge(&mut self, segment_ids: &[SegmentId]) -> FutureResult<Option<SegmentMeta>> {
-        let merge_operation = self.segment_updater.make_merge_operation(segment_ids);
+        self.merge_with_priority(segment_ids, MergePriority::High)
+    }
+
+    /// Merges a given list of segments, with the given priority in the merge scheduler.
+    ///
+    /// If all segments are empty no new segment will be created.
+    ///
+    /// `segment_ids` is required to be non-empty.
+    pub fn merge_with_priority(
+        &mut self,
+        segment_ids: &[SegmentId],
+        priority: MergePriority,
+    ) -> FutureResult<Option<SegmentMeta>> {
+        let merge_operation = self
+            .segment_updater
+            .make_merge_operation(segment_ids, priority);
         let segment_updater = self.segment_updater.clone();
         segment_updater.start_merge(merge_operation)
     }
 
+    /// Merges the committed segments of the index down to at most `max_segments` segments.
+    ///
+    /// The merges run concurrently, with [`MergePriority::High`]. The returned future
+    /// completes once they are done. The merges that are running when this method is called
+    /// are waited for first, so that their segments are accounted for. The merge policy
+    /// does not start any merge until the merges are planned.
+    ///
+    /// Only the committed segments are merged: the segments added since the last commit
+    /// are left alone.
+    ///
+    /// Returns an error if `max_segments` is 0.
+    pub fn force_merge(&mut self, max_segments: usize) -> FutureResult<()> {
+        self.segment_updater.force_merge(max_segments)
+    }
+
+    /// Rewrites the committed segments whose ratio of deleted documents is at least
+    /// `min_deleted_ratio`, in order to reclaim the space of the deleted documents.
+    ///
+    /// The segments are rewritten concurrently, with [`MergePriority::High`]. The returned
+    /// future completes once they are done. The segments that are being merged are left
+    /// alone, as the merge expunges their deletes already.
+    ///
+    /// Returns an error if `min_deleted_ratio` is not within (0..1].
+    pub fn expunge_deletes(&mut self, min_deleted_ratio: f32) -> FutureResult<()> {
+        self.segment_updater.expunge_deletes(min_deleted_ratio)
+    }
+
     /// Closes the current document channel send.
     /// and replace all the channels by new ones.
     ///
@@ -539,13 +866,21 @@ impl<D: Document> IndexWriter<D> {
     /// After calling rollback, the index is in the same
     /// state as it was after the last commit.
     ///
+    /// The operations recorded in the write-ahead log, if any,
+    /// are discarded as well.
+    ///
     /// The opstamp at the last commit is returned.
     pub fn rollback(&mut self) -> crate::Result<Opstamp> {
         info!("Rolling back to opstamp {}", self.committed_opstamp);
         // marks the segment updater as killed. From now on, all
         // segment updates will be ignored.
         self.segment_updater.kill();
+        if let Some(write_ahead_log) = &self.write_ahead_log {
+            write_ahead_log.discard()?;
+        }
         let document_receiver_res = self.operation_receiver();
+        let limits = self.limits.limits();
+        let merge_scheduler_settings = self.merge_scheduler_settings();
 
         // take the directory lock to create a new index_writer.
         let directory_lock = self
@@ -553,11 +888,13 @@ impl<D: Document> IndexWriter<D> {
             .take()
             .expect("The IndexWriter does not have any lock. This is a bug, please report.");
 
-        let new_index_writer = IndexWriter::new(
+        // Readers obtained from this writer keep on following the new writer.
+        let new_index_writer = IndexWriter::with_nrt_segments(
             &self.index,
             self.num_threads,
             self.memory_budget_in_bytes_per_thread,
             directory_lock,
+            self.nrt_segments.clone(),
         )?;
 
         // the current `self` is dropped right away because of this call.
@@ -565,6 +902,9 @@ impl<D: Document> IndexWriter<D> {
         // This will drop the document queue, and the thread
         // should terminate.
         *self = new_index_writer;
+        self.limits.set_limits(limits);
+        self.reset_limits_usage()?;
+        self.set_merge_scheduler_settings(merge_scheduler_settings)?;
 
         // Drains the document receiver pipeline :
         // Workers don't need to index the pending documents.
@@ -612,6 +952,26 @@ impl<D: Document> IndexWriter<D> {
         // committed segments.
         info!("Preparing commit");
 
+        if let Some(write_ahead_log) = &self.write_ahead_log {
+            // Some operations of this commit could not be logged.
+            write_ahead_log.check_not_failed()?;
+        }
+        self.flush_indexing_workers()?;
+
+        let commit_opstamp = self.stamper.stamp();
+        // The operations of this commit are read from the commit by `update_fields`.
+        self.pending_updates
+            .get_mut()
+            .unwrap()
+            .retain(|_, (opstamp, _)| *opstamp > commit_opstamp);
+        let prepared_commit = PreparedCommit::new(self, commit_opstamp);
+        info!("Prepared commit {}", commit_opstamp);
+        Ok(prepared_commit)
+    }
+
+    /// Makes the indexing workers flush their pending documents into new segments,
+    /// and replaces them by new workers.
+    fn flush_indexing_workers(&mut self) -> crate::Result<()> {
         // this will drop the current document channel
         // and recreate a new one.
         self.recreate_document_channel();
@@ -625,11 +985,54 @@ impl<D: Document> IndexWriter<D> {
             indexing_worker_result?;
             self.add_indexing_worker()?;
         }
+        Ok(())
+    }
 
-        let commit_opstamp = self.stamper.stamp();
-        let prepared_commit = PreparedCommit::new(self, commit_opstamp);
-        info!("Prepared commit {}", commit_opstamp);
-        Ok(prepared_commit)
+    /// Flushes the pending documents into new segments, and makes all of the changes
+    /// made so far searchable by the readers obtained with [`IndexWriter::reader`].
+    ///
+    /// Contrary to [`IndexWriter::commit`], nothing is persisted: the flushed changes are
+    /// not visible to the readers of the index, and they are lost on rollback or crash.
+    ///
+    /// Flushing has a cost, as it cuts segments and applies the pending deletes,
+    /// but it is much cheaper than a commit.
+    ///
+    /// Returns the opstamp of the flush. All of the operations with a smaller opstamp
+    /// are searchable once the readers are reloaded.
+    pub fn flush(&mut self) -> crate::Result<Opstamp> {
+        info!("Flushing");
+        self.flush_indexing_workers()?;
+        let opstamp = self.stamper.stamp();
+        self.segment_updater.schedule_flush(opstamp).wait()?;
+        Ok(opstamp)
+    }
+
+    /// Creates a near-real-time [`IndexReader`], searching the changes made by this
+    /// writer up to its last [`IndexWriter::flush`] or commit.
+    ///
+    /// The reader has to be reloaded to see the changes flushed after its creation.
+    /// It keeps on following this writer after a rollback, but not the writers
+    /// created afterwards.
+    pub fn reader(&self) -> crate::Result<IndexReader> {
+        self.reader_builder().try_into()
+    }
+
+    /// Creates a [`IndexReaderBuilder`] for near-real-time readers.
+    ///
+    /// See [`IndexWriter::reader`].
+    pub fn reader_builder(&self) -> IndexReaderBuilder {
+        self.index
+            .reader_builder()
+            .nrt_segments(self.nrt_segments.clone())
+    }
+
+    /// Takes a snapshot of the last commit.
+    ///
+    /// The files of the commit are protected from garbage collection until the
+    /// snapshot is dropped, so that they can be backed up while indexing goes on.
+    /// See [`IndexSnapshot`].
+    pub fn snapshot(&self) -> crate::Result<IndexSnapshot> {
+        IndexSnapshot::new(self.index.clone(), self.segment_updater.load_meta())
     }
 
     /// Commits all of the pending changes
@@ -649,6 +1052,47 @@ impl<D: Document> IndexWriter<D> {
         self.prepare_commit()?.commit()
     }
 
+    /// Updates the schema of the index.
+    ///
+    /// The new schema can add fields, drop fields, and make existing fields stored or fast.
+    /// Any other change to an existing field is rejected. Fields are matched by name, so the
+    /// [`Field`](crate::schema::Field) handles of the new schema have to be used from then on.
+    ///
+    /// Pending documents are committed before the schema is updated.
+    ///
+    /// Segments written with a previous version of the schema remain readable. Dropped fields
+    /// are hidden right away, and physically removed when the segment gets merged. Fields made
+    /// fast are backfilled from their stored values when the segment gets merged, and fields
+    /// made stored are backfilled from their fast values.
+    ///
+    /// Returns the opstamp of the commit.
+    pub fn update_schema(&mut self, schema: Schema) -> crate::Result<Opstamp> {
+        let current_schema = self.index.schema();
+        let schemas_in_use = self
+            .index
+            .schemas_in_use(&self.index.list_all_segment_metas());
+        validate_schema_update(
+            &current_schema,
+            &schema,
+            schemas_in_use
+                .iter()
+                .map(|versioned_schema| &versioned_schema.schema),
+        )?;
+        for sort_by_field in &self.index.settings().sort_by_fields {
+            if schema.get_field(&sort_by_field.field).is_err() {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "Field {:?} is used to sort the index and cannot be dropped.",
+                    sort_by_field.field
+                )));
+            }
+        }
+        let opstamp = self.commit()?;
+        if schema != current_schema {
+            self.segment_updater.schedule_schema_update(schema).wait()?;
+        }
+        Ok(opstamp)
+    }
+
     pub(crate) fn segment_updater(&self) -> &SegmentUpdater {
         &self.segment_updater
     }
@@ -661,12 +1105,50 @@ impl<D: Document> IndexWriter<D> {
     ///
     /// Like adds, the deletion itself will be visible
     /// only after calling `commit()`.
+    ///
+    /// If the write-ahead log is enabled and the deletion cannot be
+    /// recorded in it, the error is logged and the index writer has to be
+    /// rolled back, like after a failure of [`IndexWriter::add_document`].
+    /// Use [`IndexWriter::try_delete_term`] to get the error instead.
     pub fn delete_term(&self, term: Term) -> Opstamp {
-        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        let query = TermQuery::new(term.clone(), IndexRecordOption::Basic);
         // For backward compatibility, if Term is invalid for the index, do nothing but return an
         // Opstamp
-        self.delete_query(Box::new(query))
-            .unwrap_or_else(|_| self.stamper.stamp())
+        let Ok(weight) = query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))
+        else {
+            return self.stamper.stamp();
+        };
+        let opstamp = self.stamper.stamp();
+        if let Err(err) = self.log_operations(|record, _| record.delete_term(opstamp, &term)) {
+            error!("Failed to log the deletion of {term:?} in the write-ahead log: {err:?}");
+        }
+        self.delete_queue.push(DeleteOperation {
+            opstamp,
+            target: DeleteTarget::Query(weight),
+        });
+        self.record_deleted_key(term, opstamp);
+        opstamp
+    }
+
+    /// Delete all documents containing a given term, like [`IndexWriter::delete_term`].
+    ///
+    /// Returns an `Err` if the write-ahead log is enabled and the deletion
+    /// cannot be recorded in it. The deletion is not applied in that case,
+    /// and the index writer has to be rolled back.
+    pub fn try_delete_term(&self, term: Term) -> crate::Result<Opstamp> {
+        let query = TermQuery::new(term.clone(), IndexRecordOption::Basic);
+        let Ok(weight) = query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))
+        else {
+            return Ok(self.stamper.stamp());
+        };
+        let opstamp = self.stamper.stamp();
+        self.log_operations(|record, _| record.delete_term(opstamp, &term))?;
+        self.delete_queue.push(DeleteOperation {
+            opstamp,
+            target: DeleteTarget::Query(weight),
+        });
+        self.record_deleted_key(term, opstamp);
+        Ok(opstamp)
     }
 
     /// Delete all documents matching a given query.
@@ -678,13 +1160,22 @@ impl<D: Document> IndexWriter<D> {
     ///
     /// Like adds, the deletion itself will be visible
     /// only after calling `commit()`.
+    ///
+    /// Returns an `Err` if the write-ahead log is enabled, as
+    /// queries cannot be recorded in the log.
     #[doc(hidden)]
     pub fn delete_query(&self, query: Box<dyn Query>) -> crate::Result<Opstamp> {
+        if self.write_ahead_log.is_some() {
+            return Err(TantivyError::InvalidArgument(
+                "Deleting documents by query is not supported when the write-ahead log is enabled."
+                    .to_string(),
+            ));
+        }
         let weight = query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))?;
         let opstamp = self.stamper.stamp();
         let delete_operation = DeleteOperation {
             opstamp,
-            target: weight,
+            target: DeleteTarget::Query(weight),
         };
         self.delete_queue.push(delete_operation);
         Ok(opstamp)
@@ -703,14 +1194,128 @@ impl<D: Document> IndexWriter<D> {
 
     /// Adds a document.
     ///
-    /// If the indexing pipeline is full, this call may block.
+    /// If the indexing pipeline is full, this call may block, unless
+    /// [`IndexWriterLimits::max_pending_operations`] is set.
     ///
     /// The opstamp is an increasing `u64` that can
     /// be used by the client to align commits with its own
     /// document queue.
+    ///
+    /// Returns [`TantivyError::LimitExceeded`] if the document exceeds one of the
+    /// [`IndexWriterLimits`].
+    ///
+    /// If the write-ahead log is enabled and the document cannot be recorded in it, an error is
+    /// returned and the index writer rejects any further operation or commit. It then has to be
+    /// rolled back with [`IndexWriter::rollback`], which discards the document.
     pub fn add_document(&self, document: D) -> crate::Result<Opstamp> {
+        let reservation = self.reserve_docs(1)?;
+        let opstamp = self.stamper.stamp();
+        let record = self
+            .prepare_log_record(|record, schema| record.add_document(opstamp, &document, schema))?;
+        self.send_add_documents_batch(smallvec![AddOperation { opstamp, document }])?;
+        reservation.commit();
+        self.append_log_record(record)?;
+        Ok(opstamp)
+    }
+
+    /// Adds a document, without ever blocking.
+    ///
+    /// Contrary to [`IndexWriter::add_document`], if the indexing pipeline is full, returns
+    /// [`TantivyError::LimitExceeded`] right away.
+    pub fn try_add_document(&self, document: D) -> crate::Result<Opstamp> {
+        let reservation = self.reserve_docs(1)?;
+        let opstamp = self.stamper.stamp();
+        let record = self
+            .prepare_log_record(|record, schema| record.add_document(opstamp, &document, schema))?;
+        self.try_send_add_documents_batch(smallvec![AddOperation { opstamp, document }])?;
+        reservation.commit();
+        self.append_log_record(record)?;
+        Ok(opstamp)
+    }
+
+    /// Adds a document, replacing the document with the same unique key, if any.
+    ///
+    /// The schema needs a unique key field (see
+    /// [`SchemaBuilder::set_unique_key_field`](crate::schema::SchemaBuilder::set_unique_key_field))
+    /// and the document must have exactly one value for it.
+    ///
+    /// The previous version of the document is deleted and the new version is added
+    /// under the same opstamp, so that a commit never exposes zero or two versions
+    /// of the document. As for deletes, the previous version is looked up directly
+    /// in the postings of the key.
+    ///
+    /// If the indexing pipeline is full, this call may block.
+    ///
+    /// Until the next commit, the document counts as a new document against
+    /// [`IndexWriterLimits::max_docs`], even if it replaces an existing one.
+    pub fn upsert_document(&self, document: D) -> crate::Result<Opstamp> {
+        let key = unique_key_term(&self.index.schema(), &document)?;
+        let reservation = self.reserve_docs(1)?;
         let opstamp = self.stamper.stamp();
+        let record = self.prepare_log_record(|record, schema| {
+            record.upsert_document(opstamp, &document, schema)
+        })?;
         self.send_add_documents_batch(smallvec![AddOperation { opstamp, document }])?;
+        reservation.commit();
+        // The delete has the opstamp of the new version, so that it only applies to the
+        // previous versions, even though it is pushed after the new version is queued.
+        self.delete_queue.push(DeleteOperation {
+            opstamp,
+            target: DeleteTarget::Key(key.clone()),
+        });
+        self.record_pending_update(key, opstamp, PendingUpdate::Upserted);
+        self.append_log_record(record)?;
+        Ok(opstamp)
+    }
+
+    /// Sets the value of a numeric fast field of the document with the given unique key,
+    /// without reindexing the document.
+    ///
+    /// `field` must be a `u64`, `i64` or `f64` fast field that is neither indexed nor stored,
+    /// as only its fast field values get updated. If the document had several values for
+    /// `field`, they are all replaced by `value`.
+    ///
+    /// Like deletes, the update only affects the documents added before it, and is only
+    /// visible after calling `commit()`. The updated values of a segment are written in a
+    /// separate file, which is folded into the fast fields as the segment gets merged.
+    pub fn update_numeric(
+        &self,
+        key: Term,
+        field: Field,
+        value: impl Into<NumericalValue>,
+    ) -> crate::Result<Opstamp> {
+        let schema = self.index.schema();
+        if schema.unique_key_field() != Some(key.field()) {
+            return Err(TantivyError::InvalidArgument(format!(
+                "{key:?} is not a term of the unique key field."
+            )));
+        }
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() || field_entry.is_indexed() || field_entry.is_stored() {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Field {:?} must be a fast field that is neither indexed nor stored.",
+                field_entry.name()
+            )));
+        }
+        if self
+            .index
+            .settings()
+            .sort_by_fields
+            .iter()
+            .any(|sort_by_field| sort_by_field.field == field_entry.name())
+        {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Field {:?} is used to sort the index.",
+                field_entry.name()
+            )));
+        }
+        let value = to_field_value(field_entry, value.into())?;
+        let opstamp = self.stamper.stamp();
+        self.log_operations(|record, _| record.update_numeric(opstamp, &key, field, value))?;
+        self.delete_queue.push(DeleteOperation {
+            opstamp,
+            target: DeleteTarget::NumericUpdate { key, field, value },
+        });
         Ok(opstamp)
     }
 
@@ -753,71 +1358,398 @@ impl<D: Document> IndexWriter<D> {
         if count == 0 {
             return Ok(self.stamper.stamp());
         }
+        let user_operations: Vec<UserOperation<D>> = user_operations_it.collect();
+        let num_docs = user_operations
+            .iter()
+            .filter(|user_op| !matches!(user_op, UserOperation::Delete(_)))
+            .count() as u64;
+        let reservation = if num_docs > 0 {
+            Some(self.reserve_docs(num_docs)?)
+        } else {
+            None
+        };
         let (batch_opstamp, stamps) = self.get_batch_opstamps(count);
-
+        let user_operations: Vec<(UserOperation<D>, Opstamp)> =
+            user_operations.into_iter().zip(stamps).collect();
+
+        let record = self.prepare_log_record(|record, schema| {
+            for (user_op, opstamp) in &user_operations {
+                match user_op {
+                    UserOperation::Delete(term) => record.delete_term(*opstamp, term)?,
+                    UserOperation::Add(document) => {
+                        record.add_document(*opstamp, document, schema)?
+                    }
+                    UserOperation::Upsert(document) => {
+                        record.upsert_document(*opstamp, document, schema)?
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        // Nothing is pushed to the delete queue or logged before the documents made it to the
+        // indexing queue, so that an invalid operation or a full queue leaves no trace. The
+        // deletes only apply to the documents with a lower opstamp, so that pushing them last
+        // does not change their outcome.
+        let mut deletes = Vec::new();
+        let mut deleted_terms = Vec::new();
+        let mut upserted_keys = Vec::new();
         let mut adds = AddBatch::default();
-
-        for (user_op, opstamp) in user_operations_it.zip(stamps) {
+        for (user_op, opstamp) in user_operations {
             match user_op {
                 UserOperation::Delete(term) => {
+                    deleted_terms.push((term.clone(), opstamp));
                     let query = TermQuery::new(term, IndexRecordOption::Basic);
                     let weight =
                         query.weight(EnableScoring::disabled_from_schema(&self.index.schema()))?;
-                    let delete_operation = DeleteOperation {
+                    deletes.push(DeleteOperation {
                         opstamp,
-                        target: weight,
-                    };
-                    self.delete_queue.push(delete_operation);
+                        target: DeleteTarget::Query(weight),
+                    });
                 }
                 UserOperation::Add(document) => {
                     let add_operation = AddOperation { opstamp, document };
                     adds.push(add_operation);
                 }
+                UserOperation::Upsert(document) => {
+                    let key = unique_key_term(&self.index.schema(), &document)?;
+                    upserted_keys.push((key.clone(), opstamp));
+                    deletes.push(DeleteOperation {
+                        opstamp,
+                        target: DeleteTarget::Key(key),
+                    });
+                    adds.push(AddOperation { opstamp, document });
+                }
             }
         }
-        self.send_add_documents_batch(adds)?;
+        if !adds.is_empty() {
+            self.send_add_documents_batch(adds)?;
+        }
+        if let Some(reservation) = reservation {
+            reservation.commit();
+        }
+        for delete_operation in deletes {
+            self.delete_queue.push(delete_operation);
+        }
+        for (term, opstamp) in deleted_terms {
+            self.record_deleted_key(term, opstamp);
+        }
+        for (key, opstamp) in upserted_keys {
+            self.record_pending_update(key, opstamp, PendingUpdate::Upserted);
+        }
+        self.append_log_record(record)?;
         Ok(batch_opstamp)
     }
 
+    /// Records the deletion of the document with the unique key `term`, if `term` is a term of
+    /// the unique key field.
+    fn record_deleted_key(&self, term: Term, opstamp: Opstamp) {
+        if self.index.schema().unique_key_field() == Some(term.field()) {
+            self.record_pending_update(term, opstamp, PendingUpdate::Deleted);
+        }
+    }
+
+    /// Records the last operation on the document with the unique key `key`, unless a later
+    /// operation on the same document is already recorded.
+    fn record_pending_update(&self, key: Term, opstamp: Opstamp, pending_update: PendingUpdate) {
+        match self.pending_updates.lock().unwrap().entry(key) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().0 <= opstamp {
+                    entry.insert((opstamp, pending_update));
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((opstamp, pending_update));
+            }
+        }
+    }
+
+    /// Appends a record to the write-ahead log, if it is enabled.
+    fn log_operations(
+        &self,
+        record_fn: impl FnOnce(&mut WalRecord, &Schema) -> io::Result<()>,
+    ) -> crate::Result<()> {
+        let record = self.prepare_log_record(record_fn)?;
+        self.append_log_record(record)
+    }
+
+    /// Serializes a record for the write-ahead log, if it is enabled.
+    ///
+    /// The record is appended with [`IndexWriter::append_log_record`] once the operations
+    /// it holds were accepted, so that rejected operations never make it to the log.
+    fn prepare_log_record(
+        &self,
+        record_fn: impl FnOnce(&mut WalRecord, &Schema) -> io::Result<()>,
+    ) -> crate::Result<Option<WalRecord>> {
+        let Some(write_ahead_log) = &self.write_ahead_log else {
+            return Ok(None);
+        };
+        // Once a record could not be appended, no operation is accepted until the writer is
+        // rolled back.
+        write_ahead_log.check_not_failed()?;
+        let mut record = WalRecord::default();
+        record_fn(&mut record, &self.index.schema())?;
+        Ok(Some(record))
+    }
+
+    fn append_log_record(&self, record: Option<WalRecord>) -> crate::Result<()> {
+        match (&self.write_ahead_log, record) {
+            (Some(write_ahead_log), Some(record)) => write_ahead_log.append(&record),
+            _ => Ok(()),
+        }
+    }
+
+    /// Accounts for `num_docs` new documents, checking the limits of the index writer.
+    ///
+    /// The documents are accounted for until the returned reservation is dropped, so that it
+    /// has to be committed once they made it to the indexing queue.
+    fn reserve_docs(&self, num_docs: u64) -> crate::Result<DocsReservation<'_>> {
+        self.limits
+            .add_docs(num_docs, self.operation_sender.len())
+            .map_err(TantivyError::from)
+    }
+
     fn send_add_documents_batch(&self, add_ops: AddBatch<D>) -> crate::Result<()> {
+        if self.limits.max_pending_operations().is_some() {
+            return self.try_send_add_documents_batch(add_ops);
+        }
         if self.index_writer_status.is_alive() && self.operation_sender.send(add_ops).is_ok() {
             Ok(())
         } else {
             Err(error_in_index_worker_thread("An index writer was killed."))
         }
     }
-}
 
-impl<D: Document> Drop for IndexWriter<D> {
-    fn drop(&mut self) {
-        self.segment_updater.kill();
-        self.drop_sender();
-        for work in self.workers_join_handle.drain(..) {
-            let _ = work.join();
+    fn try_send_add_documents_batch(&self, add_ops: AddBatch<D>) -> crate::Result<()> {
+        if !self.index_writer_status.is_alive() {
+            return Err(error_in_index_worker_thread("An index writer was killed."));
+        }
+        match self.operation_sender.try_send(add_ops) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let max_pending_operations = self
+                    .limits
+                    .max_pending_operations()
+                    .unwrap_or(PIPELINE_MAX_SIZE_IN_DOCS);
+                Err(LimitExceeded::MaxPendingOperations(max_pending_operations).into())
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                Err(error_in_index_worker_thread("An index writer was killed."))
+            }
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::{HashMap, HashSet};
-    use std::net::Ipv6Addr;
-
-    use columnar::{Column, MonotonicallyMappableToU128};
-    use itertools::Itertools;
-    use proptest::prop_oneof;
+impl IndexWriter<TantivyDocument> {
+    /// Updates some of the fields of the document with the given unique key.
+    ///
+    /// The current version of the document is read from the doc store of the last
+    /// commit. The values of the fields present in `partial_doc` replace the values
+    /// of the same fields in the current version, and the resulting document is
+    /// reindexed with [`IndexWriter::upsert_document`].
+    ///
+    /// Returns `None` if there is no committed document with the given key, or if the
+    /// document was deleted since the last commit.
+    ///
+    /// Previous calls to `update_fields` are taken into account, even if they are
+    /// not committed yet, and so are the deletes of the key with
+    /// [`IndexWriter::delete_term`] or [`IndexWriter::run`]. A document replaced with
+    /// [`IndexWriter::upsert_document`] since the last commit is read from the last
+    /// commit, and documents deleted by query are not taken into account.
+    /// Since the document is rebuilt from its stored fields, all the fields of the
+    /// schema that are not stored must be part of `partial_doc`, with the exception
+    /// of the unique key field itself.
+    pub fn update_fields(
+        &self,
+        key: Term,
+        partial_doc: TantivyDocument,
+    ) -> crate::Result<Option<Opstamp>> {
+        let schema = self.index.schema();
+        let key_field = key.field();
+        if schema.unique_key_field() != Some(key_field) {
+            return Err(TantivyError::InvalidArgument(format!(
+                "{key:?} is not a term of the unique key field."
+            )));
+        }
+        if partial_doc.get_first(key_field).is_some() {
+            return Err(TantivyError::InvalidArgument(
+                "The unique key of a document cannot be updated.".to_string(),
+            ));
+        }
+        let updated_fields: HashSet<Field> =
+            partial_doc.field_values().map(|(field, _)| field).collect();
+        let lost_fields: Vec<&str> = schema
+            .fields()
+            .filter(|(field, field_entry)| {
+                *field != key_field && !field_entry.is_stored() && !updated_fields.contains(field)
+            })
+            .map(|(_, field_entry)| field_entry.name())
+            .collect();
+        if !lost_fields.is_empty() {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Fields {lost_fields:?} are not stored and need to be part of the partial \
+                 document."
+            )));
+        }
+        let (commit_opstamp, searcher) = self.committed_searcher()?;
+        // The key is locked until the update is sent, so that concurrent updates of the same
+        // document do not overwrite each other.
+        let _key_lock_guard = self.update_key_locks.lock(&key);
+        let pending_update_opt = {
+            let mut pending_updates = self.pending_updates.lock().unwrap();
+            // Operations that made it into a commit are read from the commit.
+            pending_updates.retain(|_, (opstamp, _)| *opstamp > commit_opstamp);
+            pending_updates
+                .get(&key)
+                .map(|(_, pending_update)| pending_update.clone())
+        };
+        let current_doc_opt = match pending_update_opt {
+            Some(PendingUpdate::Updated(pending_doc)) => Some(pending_doc),
+            Some(PendingUpdate::Deleted) => None,
+            Some(PendingUpdate::Upserted) | None => searcher.doc_by_key::<TantivyDocument>(&key)?,
+        };
+        let Some(current_doc) = current_doc_opt else {
+            return Ok(None);
+        };
+        let mut doc = TantivyDocument::new();
+        // Committed documents lack their key if the key field is not stored.
+        if current_doc.get_first(key_field).is_none() {
+            add_key_value(&mut doc, &schema, &key)?;
+        }
+        for (field, value) in current_doc.field_values() {
+            if !updated_fields.contains(&field) {
+                doc.add_field_value(field, value);
+            }
+        }
+        for (field, value) in partial_doc.field_values() {
+            doc.add_field_value(field, value);
+        }
+        let opstamp = self.upsert_document(doc.clone())?;
+        self.record_pending_update(key, opstamp, PendingUpdate::Updated(doc));
+        Ok(Some(opstamp))
+    }
+
+    /// Returns a searcher of the last commit, along with the opstamp of the commit.
+    ///
+    /// The underlying reader is only reloaded when a new commit or merge got saved.
+    fn committed_searcher(&self) -> crate::Result<(Opstamp, Searcher)> {
+        let committed_meta = self.segment_updater.load_meta();
+        let mut committed_reader = self.committed_reader.lock().unwrap();
+        match committed_reader.as_mut() {
+            Some((reader_meta, _)) if Arc::ptr_eq(reader_meta, &committed_meta) => {}
+            Some((reader_meta, reader)) => {
+                reader.reload()?;
+                *reader_meta = committed_meta.clone();
+            }
+            None => {
+                let reader: IndexReader = self
+                    .index
+                    .reader_builder()
+                    .reload_policy(ReloadPolicy::Manual)
+                    .try_into()?;
+                *committed_reader = Some((committed_meta.clone(), reader));
+            }
+        }
+        let searcher = committed_reader
+            .as_ref()
+            .map(|(_, reader)| reader.searcher())
+            .expect("The committed reader was just loaded.");
+        Ok((committed_meta.opstamp, searcher))
+    }
+}
+
+/// Extracts the term of the unique key of a document.
+fn unique_key_term<D: Document>(schema: &Schema, document: &D) -> crate::Result<Term> {
+    let key_field = schema.unique_key_field().ok_or_else(|| {
+        TantivyError::SchemaError("The schema does not define a unique key field.".to_string())
+    })?;
+    let mut values = document
+        .iter_fields_and_values()
+        .filter(|(field, _)| *field == key_field);
+    let (Some((_, value)), None) = (values.next(), values.next()) else {
+        return Err(TantivyError::InvalidArgument(format!(
+            "Document must have exactly one value for the unique key field {:?}.",
+            schema.get_field_name(key_field)
+        )));
+    };
+    let key = match schema.get_field_entry(key_field).field_type() {
+        FieldType::Str(_) => value
+            .as_str()
+            .map(|text| Term::from_field_text(key_field, text)),
+        FieldType::U64(_) => value
+            .as_u64()
+            .map(|val| Term::from_field_u64(key_field, val)),
+        FieldType::I64(_) => value
+            .as_i64()
+            .map(|val| Term::from_field_i64(key_field, val)),
+        FieldType::Bytes(_) => value
+            .as_bytes()
+            .map(|bytes| Term::from_field_bytes(key_field, bytes)),
+        _ => None,
+    };
+    key.ok_or_else(|| {
+        TantivyError::InvalidArgument(format!(
+            "Invalid value for the unique key field {:?}.",
+            schema.get_field_name(key_field)
+        ))
+    })
+}
+
+/// Adds the value of the unique key `key` to `doc`.
+fn add_key_value(doc: &mut TantivyDocument, schema: &Schema, key: &Term) -> crate::Result<()> {
+    let key_field = key.field();
+    let key_value = key.value();
+    match schema.get_field_entry(key_field).field_type() {
+        FieldType::Str(_) => key_value.as_str().map(|text| doc.add_text(key_field, text)),
+        FieldType::U64(_) => key_value.as_u64().map(|val| doc.add_u64(key_field, val)),
+        FieldType::I64(_) => key_value.as_i64().map(|val| doc.add_i64(key_field, val)),
+        FieldType::Bytes(_) => key_value
+            .as_bytes()
+            .map(|bytes| doc.add_bytes(key_field, bytes)),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        TantivyError::SchemaError(format!(
+            "Cannot rebuild the value of the unique key field {:?} from {key:?}.",
+            schema.get_field_name(key_field)
+        ))
+    })
+}
+
+impl<D: Document> Drop for IndexWriter<D> {
+    fn drop(&mut self) {
+        self.segment_updater.kill();
+        self.drop_sender();
+        for work in self.workers_join_handle.drain(..) {
+            let _ = work.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::net::Ipv6Addr;
+
+    use columnar::{Column, MonotonicallyMappableToU128};
+    use itertools::Itertools;
+    use proptest::prop_oneof;
 
     use super::super::operation::UserOperation;
     use crate::collector::{Count, TopDocs};
     use crate::directory::error::LockError;
     use crate::error::*;
+    use crate::index::SegmentId;
     use crate::indexer::index_writer::MEMORY_BUDGET_NUM_BYTES_MIN;
-    use crate::indexer::NoMergePolicy;
+    use crate::indexer::{
+        AddBatch, IndexWriterLimits, KeepLastCommits, LimitExceeded, LogMergePolicy, MergePriority,
+        MergeSchedulerSettings, NoMergePolicy,
+    };
     use crate::query::{QueryParser, TermQuery};
     use crate::schema::{
         self, Facet, FacetOptions, IndexRecordOption, IpAddrOptions, JsonObjectOptions,
-        NumericOptions, Schema, TextFieldIndexing, TextOptions, Value, FAST, INDEXED, STORED,
-        STRING, TEXT,
+        NumericOptions, OwnedValue, Schema, TextFieldIndexing, TextOptions, Value, FAST, INDEXED,
+        STORED, STRING, TEXT,
     };
     use crate::store::DOCSTORE_CACHE_CAPACITY;
     use crate::{
@@ -954,6 +1886,433 @@ mod tests {
         assert_eq!(batch_opstamp2, 1u64);
     }
 
+    #[test]
+    fn test_upsert_document() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING | STORED);
+        let text_field = schema_builder.add_text_field("text", TEXT | STORED);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.upsert_document(doc!(id_field=>"a", text_field=>"a1"))?;
+        index_writer.upsert_document(doc!(id_field=>"b", text_field=>"b1"))?;
+        // Replaced within the same commit.
+        index_writer.upsert_document(doc!(id_field=>"a", text_field=>"a2"))?;
+        index_writer.commit()?;
+        // Replaced across commits, through a batch of operations.
+        index_writer.run(vec![
+            UserOperation::Upsert(doc!(id_field=>"b", text_field=>"b2")),
+            UserOperation::Upsert(doc!(id_field=>"c", text_field=>"c1")),
+        ])?;
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 3);
+        let text_by_key = |key: &str| -> crate::Result<Option<String>> {
+            let doc: Option<TantivyDocument> =
+                searcher.doc_by_key(&Term::from_field_text(id_field, key))?;
+            Ok(doc.map(|doc| {
+                doc.get_first(text_field)
+                    .and_then(|value| value.as_str())
+                    .unwrap()
+                    .to_string()
+            }))
+        };
+        assert_eq!(text_by_key("a")?.as_deref(), Some("a2"));
+        assert_eq!(text_by_key("b")?.as_deref(), Some("b2"));
+        assert_eq!(text_by_key("c")?.as_deref(), Some("c1"));
+        assert_eq!(text_by_key("d")?, None);
+        assert!(searcher
+            .doc_address_by_key(&Term::from_field_text(text_field, "a2"))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_fields() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let title_field = schema_builder.add_text_field("title", TEXT | STORED);
+        let popularity_field = schema_builder.add_u64_field("popularity", STORED | FAST);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(
+            id_field=>1u64,
+            title_field=>"hello happy tax payer",
+            popularity_field=>1u64
+        ))?;
+        index_writer.commit()?;
+
+        let opstamp = index_writer.update_fields(
+            Term::from_field_u64(id_field, 1),
+            doc!(popularity_field=>5u64),
+        )?;
+        assert!(opstamp.is_some());
+        assert_eq!(
+            index_writer.update_fields(
+                Term::from_field_u64(id_field, 2),
+                doc!(popularity_field=>5u64)
+            )?,
+            None
+        );
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let doc: TantivyDocument = searcher
+            .doc_by_key(&Term::from_field_u64(id_field, 1))?
+            .unwrap();
+        assert_eq!(
+            doc.get_first(popularity_field)
+                .and_then(|value| value.as_u64()),
+            Some(5)
+        );
+        assert_eq!(
+            doc.get_first(title_field).and_then(|value| value.as_str()),
+            Some("hello happy tax payer")
+        );
+        let query = TermQuery::new(
+            Term::from_field_text(title_field, "happy"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+
+        assert!(matches!(
+            index_writer.update_fields(Term::from_field_u64(id_field, 1), doc!(id_field=>3u64)),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            index_writer.update_fields(
+                Term::from_field_u64(popularity_field, 1),
+                doc!(popularity_field=>5u64)
+            ),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_fields_twice_before_commit() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let title_field = schema_builder.add_text_field("title", TEXT | STORED);
+        let popularity_field = schema_builder.add_u64_field("popularity", STORED);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(
+            id_field=>1u64,
+            title_field=>"hello",
+            popularity_field=>1u64
+        ))?;
+        index_writer.commit()?;
+
+        let key = Term::from_field_u64(id_field, 1);
+        index_writer.update_fields(key.clone(), doc!(title_field=>"updated"))?;
+        index_writer.update_fields(key.clone(), doc!(popularity_field=>2u64))?;
+        index_writer.commit()?;
+        // The committed searcher gets reloaded after the commit.
+        index_writer.update_fields(key.clone(), doc!(popularity_field=>3u64))?;
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        let doc: TantivyDocument = searcher.doc_by_key(&key)?.unwrap();
+        assert_eq!(
+            doc.get_first(title_field).and_then(|value| value.as_str()),
+            Some("updated")
+        );
+        assert_eq!(
+            doc.get_first(popularity_field)
+                .and_then(|value| value.as_u64()),
+            Some(3)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_fields_after_delete_or_upsert() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let title_field = schema_builder.add_text_field("title", TEXT | STORED);
+        let popularity_field = schema_builder.add_u64_field("popularity", STORED);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(id_field=>1u64, title_field=>"first"))?;
+        index_writer.add_document(doc!(id_field=>2u64, title_field=>"second"))?;
+        index_writer.commit()?;
+
+        // A deleted document must not be brought back by its pending update.
+        let deleted_key = Term::from_field_u64(id_field, 1);
+        index_writer.update_fields(deleted_key.clone(), doc!(popularity_field=>1u64))?;
+        index_writer.delete_term(deleted_key.clone());
+        assert_eq!(
+            index_writer.update_fields(deleted_key.clone(), doc!(popularity_field=>2u64))?,
+            None
+        );
+
+        // A pending update must not win over a newer upsert: the document is read
+        // from the last commit instead.
+        let upserted_key = Term::from_field_u64(id_field, 2);
+        index_writer.update_fields(upserted_key.clone(), doc!(title_field=>"stale"))?;
+        index_writer.upsert_document(doc!(id_field=>2u64, title_field=>"upserted"))?;
+        index_writer.update_fields(upserted_key.clone(), doc!(popularity_field=>3u64))?;
+        index_writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.num_docs(), 1);
+        assert!(searcher
+            .doc_by_key::<TantivyDocument>(&deleted_key)?
+            .is_none());
+        let doc: TantivyDocument = searcher.doc_by_key(&upserted_key)?.unwrap();
+        assert_eq!(
+            doc.get_first(title_field).and_then(|value| value.as_str()),
+            Some("second")
+        );
+        assert_eq!(
+            doc.get_first(popularity_field)
+                .and_then(|value| value.as_u64()),
+            Some(3)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_fields_rebuilds_key_value() {
+        let mut schema_builder = schema::Schema::builder();
+        let str_field = schema_builder.add_text_field("str", STRING);
+        let u64_field = schema_builder.add_u64_field("u64", INDEXED);
+        let i64_field = schema_builder.add_i64_field("i64", INDEXED);
+        let bytes_field = schema_builder.add_bytes_field("bytes", INDEXED);
+        let schema = schema_builder.build();
+        for (key, value) in [
+            (Term::from_field_text(str_field, "a"), OwnedValue::from("a")),
+            (Term::from_field_u64(u64_field, 1), OwnedValue::from(1u64)),
+            (Term::from_field_i64(i64_field, -1), OwnedValue::from(-1i64)),
+            (
+                Term::from_field_bytes(bytes_field, b"b"),
+                OwnedValue::from(b"b".to_vec()),
+            ),
+        ] {
+            let mut doc = TantivyDocument::new();
+            super::add_key_value(&mut doc, &schema, &key).unwrap();
+            let values: Vec<OwnedValue> = doc.get_all(key.field()).map(OwnedValue::from).collect();
+            assert_eq!(values, vec![value]);
+        }
+        // The value of the key does not match the type of its field.
+        let mut doc = TantivyDocument::new();
+        assert!(matches!(
+            super::add_key_value(&mut doc, &schema, &Term::from_field_u64(str_field, 1)),
+            Err(TantivyError::SchemaError(_))
+        ));
+        assert_eq!(doc.field_values().count(), 0);
+    }
+
+    #[test]
+    fn test_update_fields_requires_stored_fields() {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING | STORED);
+        let body_field = schema_builder.add_text_field("body", TEXT);
+        let popularity_field = schema_builder.add_u64_field("popularity", STORED);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        let key = Term::from_field_text(id_field, "a");
+        assert!(matches!(
+            index_writer.update_fields(key.clone(), doc!(popularity_field=>5u64)),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(index_writer
+            .update_fields(key, doc!(popularity_field=>5u64, body_field=>"new body"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_upsert_document_invalid() {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let schema_without_key = schema_builder.build();
+        let index = Index::create_in_ram(schema_without_key);
+        let index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        assert!(matches!(
+            index_writer.upsert_document(doc!(id_field=>1u64)),
+            Err(TantivyError::SchemaError(_))
+        ));
+
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        assert!(matches!(
+            index_writer.upsert_document(doc!(text_field=>"no key")),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            index_writer.upsert_document(doc!(id_field=>1u64, id_field=>2u64)),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        assert!(index_writer.upsert_document(doc!(id_field=>1u64)).is_ok());
+    }
+
+    #[test]
+    fn test_near_real_time_reader() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let nrt_reader = index_writer
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let id_count = |id: u64| {
+            let query =
+                TermQuery::new(Term::from_field_u64(id_field, id), IndexRecordOption::Basic);
+            nrt_reader.searcher().search(&query, &Count).unwrap()
+        };
+
+        index_writer.add_document(doc!(id_field=>1u64))?;
+        index_writer.add_document(doc!(id_field=>2u64))?;
+        index_writer.commit()?;
+        nrt_reader.reload()?;
+        assert_eq!(nrt_reader.searcher().num_docs(), 2);
+
+        // Flushed changes are visible to the near-real-time reader only.
+        index_writer.add_document(doc!(id_field=>3u64))?;
+        index_writer.delete_term(Term::from_field_u64(id_field, 1));
+        index_writer.flush()?;
+        nrt_reader.reload()?;
+        reader.reload()?;
+        assert_eq!(nrt_reader.searcher().num_docs(), 2);
+        assert_eq!(id_count(1), 0);
+        assert_eq!(id_count(3), 1);
+        assert_eq!(reader.searcher().num_docs(), 2);
+
+        // Deletes of flushed documents are applied on the next flush.
+        index_writer.delete_term(Term::from_field_u64(id_field, 3));
+        index_writer.add_document(doc!(id_field=>4u64))?;
+        nrt_reader.reload()?;
+        assert_eq!(id_count(3), 1);
+        index_writer.flush()?;
+        nrt_reader.reload()?;
+        assert_eq!(id_count(3), 0);
+        assert_eq!(id_count(4), 1);
+
+        // Merging flushed segments keeps the flushed deletes.
+        let committed_segment_ids = index.searchable_segment_ids()?;
+        let flushed_segment_ids: Vec<SegmentId> = nrt_reader
+            .searcher()
+            .segment_readers()
+            .iter()
+            .map(|segment_reader| segment_reader.segment_id())
+            .filter(|segment_id| !committed_segment_ids.contains(segment_id))
+            .collect();
+        assert_eq!(flushed_segment_ids.len(), 2);
+        index_writer.merge(&flushed_segment_ids).wait()?;
+        nrt_reader.reload()?;
+        assert_eq!(nrt_reader.searcher().segment_readers().len(), 2);
+        assert_eq!(id_count(3), 0);
+        assert_eq!(id_count(4), 1);
+
+        // A rollback drops the flushed changes.
+        index_writer.rollback()?;
+        nrt_reader.reload()?;
+        assert_eq!(nrt_reader.searcher().num_docs(), 2);
+        assert_eq!(id_count(1), 1);
+
+        index_writer.add_document(doc!(id_field=>5u64))?;
+        index_writer.flush()?;
+        index_writer.commit()?;
+        nrt_reader.reload()?;
+        reader.reload()?;
+        assert_eq!(nrt_reader.searcher().num_docs(), 3);
+        assert_eq!(reader.searcher().num_docs(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_ahead_log_replay() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::builder()
+            .schema(schema_builder.build())
+            .settings(IndexSettings {
+                write_ahead_log: true,
+                ..Default::default()
+            })
+            .create_in_ram()?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let text_count = |text: &str| {
+            let query = TermQuery::new(
+                Term::from_field_text(text_field, text),
+                IndexRecordOption::Basic,
+            );
+            reader.searcher().search(&query, &Count).unwrap()
+        };
+
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(id_field=>1u64, text_field=>"one"))?;
+        index_writer.add_document(doc!(id_field=>2u64, text_field=>"two"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(id_field=>3u64, text_field=>"three"))?;
+        index_writer.upsert_document(doc!(id_field=>1u64, text_field=>"updated"))?;
+        index_writer.try_delete_term(Term::from_field_u64(id_field, 2))?;
+        index_writer.run([UserOperation::Add(doc!(id_field=>4u64, text_field=>"four"))])?;
+        assert!(matches!(
+            index_writer.delete_query(Box::new(TermQuery::new(
+                Term::from_field_u64(id_field, 3),
+                IndexRecordOption::Basic
+            ))),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        // The writer is dropped without committing.
+        drop(index_writer);
+        reader.reload()?;
+        assert_eq!(reader.searcher().num_docs(), 2);
+
+        // The uncommitted operations are replayed and committed by the new writer.
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().num_docs(), 3);
+        assert_eq!(text_count("one"), 0);
+        assert_eq!(text_count("updated"), 1);
+        assert_eq!(text_count("two"), 0);
+        assert_eq!(text_count("three"), 1);
+        assert_eq!(text_count("four"), 1);
+
+        // Rolled back operations are not replayed.
+        index_writer.add_document(doc!(id_field=>5u64, text_field=>"five"))?;
+        index_writer.rollback()?;
+        drop(index_writer);
+        let index_writer: IndexWriter = index.writer_for_tests()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().num_docs(), 3);
+
+        // Operations logged before deleting all documents are void.
+        index_writer.add_document(doc!(id_field=>6u64, text_field=>"six"))?;
+        index_writer.delete_all_documents()?;
+        index_writer.add_document(doc!(id_field=>7u64, text_field=>"seven"))?;
+        drop(index_writer);
+        let _index_writer: IndexWriter = index.writer_for_tests()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().num_docs(), 1);
+        assert_eq!(text_count("seven"), 1);
+        Ok(())
+    }
+
     #[test]
     fn test_lockfile_stops_duplicates() {
         let schema_builder = schema::Schema::builder();
@@ -979,6 +2338,340 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_commit_deletion_policy() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.set_commit_deletion_policy(Box::new(KeepLastCommits::new(3)));
+        for id in 0..4u64 {
+            index_writer.add_document(doc!(id_field=>id))?;
+            index_writer.commit()?;
+        }
+        let commits = index.list_commits()?;
+        assert_eq!(commits.len(), 3);
+        assert!(commits.last().unwrap().is_last());
+        assert!(commits.iter().all(|commit| commit.timestamp().is_some()));
+
+        // The files of the historical commits survive merges.
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.garbage_collect_files().wait()?;
+        let num_docs: Vec<u64> = commits
+            .iter()
+            .map(|commit| Ok(index.open_commit(commit)?.searcher().num_docs()))
+            .collect::<crate::Result<_>>()?;
+        assert_eq!(num_docs, vec![2, 3, 4]);
+
+        // The historical commits are reloaded by a new writer, and released
+        // by the default policy.
+        drop(index_writer);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        assert_eq!(index.list_commits()?.len(), 3);
+        index_writer.add_document(doc!(id_field=>4u64))?;
+        index_writer.commit()?;
+        index_writer.garbage_collect_files().wait()?;
+        let commits = index.list_commits()?;
+        assert_eq!(commits.len(), 1);
+        assert_eq!(index.open_commit(&commits[0])?.searcher().num_docs(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer_limits() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_limits(IndexWriterLimits {
+            max_docs: Some(2),
+            ..Default::default()
+        })?;
+        index_writer.add_document(doc!(id_field=>1u64))?;
+        index_writer.try_add_document(doc!(id_field=>2u64))?;
+        assert!(matches!(
+            index_writer.add_document(doc!(id_field=>3u64)),
+            Err(TantivyError::LimitExceeded(LimitExceeded::MaxDocs(2)))
+        ));
+        assert!(matches!(
+            index_writer.run([UserOperation::Add(doc!(id_field=>3u64))]),
+            Err(TantivyError::LimitExceeded(LimitExceeded::MaxDocs(2)))
+        ));
+        // Deletes are accounted for on commit.
+        index_writer.delete_term(Term::from_field_u64(id_field, 1));
+        index_writer.commit()?;
+        index_writer.add_document(doc!(id_field=>3u64))?;
+        index_writer.rollback()?;
+        assert_eq!(index_writer.limits().max_docs, Some(2));
+        index_writer.add_document(doc!(id_field=>3u64))?;
+        index_writer.commit()?;
+        // The usage is reset by asynchronous commits as well.
+        index_writer.delete_term(Term::from_field_u64(id_field, 2));
+        index_writer.prepare_commit()?.commit_future().wait()?;
+        index_writer.add_document(doc!(id_field=>5u64))?;
+        index_writer.commit()?;
+
+        index_writer.set_limits(IndexWriterLimits {
+            max_pending_operations: Some(0),
+            ..Default::default()
+        })?;
+        assert!(matches!(
+            index_writer.try_add_document(doc!(id_field=>4u64)),
+            Err(TantivyError::LimitExceeded(
+                LimitExceeded::MaxPendingOperations(0)
+            ))
+        ));
+
+        index_writer.set_limits(IndexWriterLimits {
+            max_bytes_on_disk: Some(1),
+            ..Default::default()
+        })?;
+        assert!(matches!(
+            index_writer.add_document(doc!(id_field=>4u64)),
+            Err(TantivyError::LimitExceeded(LimitExceeded::MaxBytesOnDisk(
+                1
+            )))
+        ));
+        index_writer.delete_all_documents()?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(id_field=>4u64))?;
+        index_writer.commit()?;
+        assert_eq!(index.reader()?.searcher().num_docs(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer_limits_rejected_operations() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let stored_field = schema_builder.add_u64_field("stored", STORED);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_limits(IndexWriterLimits {
+            max_docs: Some(3),
+            ..Default::default()
+        })?;
+        // Batches holding an invalid operation are not accounted for.
+        assert!(index_writer
+            .run([
+                UserOperation::Add(doc!(id_field=>1u64)),
+                UserOperation::Delete(Term::from_field_u64(stored_field, 1)),
+            ])
+            .is_err());
+        assert!(index_writer
+            .run([
+                UserOperation::Add(doc!(id_field=>1u64)),
+                UserOperation::Upsert(doc!(stored_field=>1u64)),
+            ])
+            .is_err());
+        assert!(index_writer
+            .upsert_document(doc!(stored_field=>1u64))
+            .is_err());
+        index_writer.add_document(doc!(id_field=>1u64))?;
+        index_writer.add_document(doc!(id_field=>2u64))?;
+        index_writer.commit()?;
+
+        // Upserts count as new documents until the next commit.
+        index_writer.upsert_document(doc!(id_field=>1u64))?;
+        assert!(matches!(
+            index_writer.upsert_document(doc!(id_field=>2u64)),
+            Err(TantivyError::LimitExceeded(LimitExceeded::MaxDocs(3)))
+        ));
+        index_writer.commit()?;
+        index_writer.upsert_document(doc!(id_field=>2u64))?;
+        index_writer.commit()?;
+        assert_eq!(index.reader()?.searcher().num_docs(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_document_with_full_queue() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::builder()
+            .schema(schema_builder.build())
+            .settings(IndexSettings {
+                write_ahead_log: true,
+                ..Default::default()
+            })
+            .create_in_ram()?;
+        let text_count = |text: &str| -> crate::Result<usize> {
+            let query = TermQuery::new(
+                Term::from_field_text(text_field, text),
+                IndexRecordOption::Basic,
+            );
+            index.reader()?.searcher().search(&query, &Count)
+        };
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(id_field=>1u64, text_field=>"first"))?;
+        index_writer.commit()?;
+        index_writer.set_limits(IndexWriterLimits {
+            max_pending_operations: Some(10),
+            ..Default::default()
+        })?;
+
+        // Fills the indexing queue with a batch that no worker consumes.
+        let (full_sender, _full_receiver) = crossbeam_channel::bounded(1);
+        full_sender.send(AddBatch::default()).unwrap();
+        let operation_sender = std::mem::replace(&mut index_writer.operation_sender, full_sender);
+        let upsert = doc!(id_field=>1u64, text_field=>"second");
+        assert!(matches!(
+            index_writer.upsert_document(upsert.clone()),
+            Err(TantivyError::LimitExceeded(
+                LimitExceeded::MaxPendingOperations(10)
+            ))
+        ));
+        assert!(matches!(
+            index_writer.run([UserOperation::Upsert(upsert)]),
+            Err(TantivyError::LimitExceeded(
+                LimitExceeded::MaxPendingOperations(10)
+            ))
+        ));
+        index_writer.operation_sender = operation_sender;
+
+        // The rejected upserts neither deleted the previous version nor made it to the log.
+        index_writer.commit()?;
+        assert_eq!(text_count("first")?, 1);
+        drop(index_writer);
+        let _index_writer: IndexWriter = index.writer_for_tests()?;
+        assert_eq!(text_count("first")?, 1);
+        assert_eq!(text_count("second")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_merge() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for id in 0..5u64 {
+            index_writer.add_document(doc!(id_field=>id))?;
+            index_writer.commit()?;
+        }
+        assert!(matches!(
+            index_writer.force_merge(0).wait(),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        index_writer.force_merge(2).wait()?;
+        assert_eq!(index.searchable_segment_ids()?.len(), 2);
+        index_writer.force_merge(1).wait()?;
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 1);
+        assert_eq!(segment_metas[0].num_docs(), 5);
+        // Nothing to do.
+        index_writer.force_merge(1).wait()?;
+        assert_eq!(index.searchable_segment_ids()?, vec![segment_metas[0].id()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_merge_with_running_policy_merges() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        let mut merge_policy = LogMergePolicy::default();
+        merge_policy.set_min_num_segments(2);
+        index_writer.set_merge_policy(Box::new(merge_policy));
+        // Slow merges are still running as the force merge is requested.
+        index_writer.set_merge_scheduler_settings(MergeSchedulerSettings {
+            max_merge_bytes_per_sec: Some(20_000),
+            ..Default::default()
+        })?;
+        for id in 0..8u64 {
+            index_writer.add_document(doc!(id_field=>id))?;
+            index_writer.commit()?;
+        }
+        index_writer.force_merge(1).wait()?;
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 1);
+        assert_eq!(segment_metas[0].num_docs(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expunge_deletes() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for id in 0..4u64 {
+            index_writer.add_document(doc!(id_field=>id))?;
+        }
+        index_writer.commit()?;
+        for id in 4..8u64 {
+            index_writer.add_document(doc!(id_field=>id))?;
+        }
+        index_writer.commit()?;
+        // 1 deleted document out of 4 in the first segment, 2 out of 4 in the second one.
+        for id in [0u64, 4, 5] {
+            index_writer.delete_term(Term::from_field_u64(id_field, id));
+        }
+        index_writer.commit()?;
+        assert!(matches!(
+            index_writer.expunge_deletes(0.0).wait(),
+            Err(TantivyError::InvalidArgument(_))
+        ));
+        index_writer.expunge_deletes(0.5).wait()?;
+        let mut segment_metas = index.searchable_segment_metas()?;
+        segment_metas.sort_by_key(|segment_meta| segment_meta.max_doc());
+        assert_eq!(
+            segment_metas
+                .iter()
+                .map(|segment_meta| (segment_meta.max_doc(), segment_meta.num_docs()))
+                .collect::<Vec<_>>(),
+            vec![(2, 2), (4, 3)]
+        );
+        index_writer.expunge_deletes(0.1).wait()?;
+        let segment_metas = index.searchable_segment_metas()?;
+        assert!(segment_metas
+            .iter()
+            .all(|segment_meta| !segment_meta.has_deletes()));
+        assert_eq!(index.reader()?.searcher().num_docs(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_scheduler_settings() -> crate::Result<()> {
+        let mut schema_builder = schema::Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let settings = MergeSchedulerSettings {
+            max_concurrent_merges: 1,
+            max_merge_bytes_per_sec: Some(100_000_000),
+        };
+        index_writer.set_merge_scheduler_settings(settings.clone())?;
+        for id in 0..4u64 {
+            index_writer.add_document(doc!(id_field=>id))?;
+            index_writer.commit()?;
+        }
+        let segment_ids = index.searchable_segment_ids()?;
+        let first_merge = index_writer.merge_with_priority(&segment_ids[..2], MergePriority::Low);
+        let second_merge = index_writer.merge(&segment_ids[2..]);
+        let first_segment_meta = first_merge.wait()?.unwrap();
+        let second_segment_meta = second_merge.wait()?.unwrap();
+        for segment_meta in [first_segment_meta, second_segment_meta] {
+            assert_eq!(segment_meta.num_docs(), 2);
+            assert!(segment_meta.num_bytes().unwrap() > 0);
+            assert!(segment_meta.creation_timestamp().is_some());
+        }
+        assert_eq!(index.searchable_segment_ids()?.len(), 2);
+
+        index_writer.rollback()?;
+        assert_eq!(index_writer.merge_scheduler_settings(), settings);
+        Ok(())
+    }
+
     #[test]
     fn test_set_merge_policy() {
         let schema_builder = schema::Schema::builder();
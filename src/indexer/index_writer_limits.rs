@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use common::HasLen;
+use thiserror::Error;
+
+use crate::directory::Directory;
+use crate::index::{Index, SegmentMeta};
+
+/// Limits enforced by an [`IndexWriter`](crate::IndexWriter).
+///
+/// Operations that would exceed one of the limits are rejected with
+/// [`TantivyError::LimitExceeded`](crate::TantivyError::LimitExceeded).
+/// By default, no limit is enforced.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexWriterLimits {
+    /// Maximum number of documents of the index, including the documents that are not
+    /// committed yet.
+    ///
+    /// Deleted documents are only accounted for on the next commit. Likewise, a document
+    /// added with [`IndexWriter::upsert_document`](crate::IndexWriter::upsert_document) or
+    /// [`IndexWriter::update_fields`](crate::IndexWriter::update_fields) counts as a new
+    /// document until the next commit, even if it replaces an existing one.
+    pub max_docs: Option<u64>,
+    /// Maximum number of bytes of the committed segments on disk.
+    ///
+    /// The size of the index is refreshed on every commit, so that the documents
+    /// added since the last commit are not accounted for. The delete and numeric update
+    /// files of the segments are not accounted for either.
+    pub max_bytes_on_disk: Option<u64>,
+    /// Maximum number of batches of documents waiting in the indexing queue.
+    ///
+    /// When set, adding a document returns an error instead of blocking when the queue
+    /// is full, even if the limit exceeds the capacity of the queue.
+    pub max_pending_operations: Option<usize>,
+}
+
+/// The limit of an [`IndexWriter`](crate::IndexWriter) that an operation would exceed.
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The index holds the maximum number of documents.
+    #[error("The index holds the maximum number of documents: {0}.")]
+    MaxDocs(u64),
+    /// The index reached its maximum size on disk.
+    #[error("The index reached its maximum size on disk: {0} bytes.")]
+    MaxBytesOnDisk(u64),
+    /// The indexing queue is full.
+    #[error("The indexing queue is full: {0} batches of documents are pending.")]
+    MaxPendingOperations(usize),
+}
+
+/// Tracks the usage of an index against the limits of its `IndexWriter`.
+#[derive(Default)]
+pub(crate) struct LimitTracker {
+    limits: RwLock<IndexWriterLimits>,
+    // Documents of the last commit, plus the documents added since then.
+    num_docs: AtomicU64,
+    // Size of the segments of the last commit.
+    num_bytes_on_disk: AtomicU64,
+}
+
+impl LimitTracker {
+    pub fn limits(&self) -> IndexWriterLimits {
+        self.limits.read().unwrap().clone()
+    }
+
+    pub fn set_limits(&self, limits: IndexWriterLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    pub fn max_pending_operations(&self) -> Option<usize> {
+        self.limits.read().unwrap().max_pending_operations
+    }
+
+    /// Resets the usage to the one of the given committed segments.
+    pub fn reset(&self, index: &Index, segment_metas: &[SegmentMeta]) -> crate::Result<()> {
+        let num_docs: u64 = segment_metas
+            .iter()
+            .map(|segment_meta| segment_meta.num_docs() as u64)
+            .sum();
+        self.num_docs.store(num_docs, Ordering::SeqCst);
+        self.refresh_num_bytes_on_disk(index, segment_metas)
+    }
+
+    /// Computes the size on disk of the given committed segments, if it is limited.
+    ///
+    /// The size is the one recorded in the segment metas. The files are only read for the
+    /// segments that do not record their size.
+    pub fn refresh_num_bytes_on_disk(
+        &self,
+        index: &Index,
+        segment_metas: &[SegmentMeta],
+    ) -> crate::Result<()> {
+        let mut num_bytes_on_disk = 0u64;
+        if self.limits().max_bytes_on_disk.is_some() {
+            for segment_meta in segment_metas {
+                num_bytes_on_disk += match segment_meta.num_bytes() {
+                    Some(num_bytes) => num_bytes,
+                    None => num_bytes_of_files(index, segment_meta)?,
+                };
+            }
+        }
+        self.num_bytes_on_disk
+            .store(num_bytes_on_disk, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Accounts for the removal of all of the documents.
+    pub fn clear_docs(&self) {
+        self.num_docs.store(0, Ordering::SeqCst);
+    }
+
+    /// Accounts for `num_docs` new documents, given `num_pending_operations` batches of
+    /// documents in the indexing queue.
+    ///
+    /// Nothing is accounted for if a limit would be exceeded. The documents are accounted for
+    /// until the returned reservation is dropped, unless it gets committed.
+    pub fn add_docs(
+        &self,
+        num_docs: u64,
+        num_pending_operations: usize,
+    ) -> Result<DocsReservation<'_>, LimitExceeded> {
+        let limits = self.limits.read().unwrap();
+        if let Some(max_pending_operations) = limits.max_pending_operations {
+            if num_pending_operations >= max_pending_operations {
+                return Err(LimitExceeded::MaxPendingOperations(max_pending_operations));
+            }
+        }
+        if let Some(max_bytes_on_disk) = limits.max_bytes_on_disk {
+            if self.num_bytes_on_disk.load(Ordering::SeqCst) >= max_bytes_on_disk {
+                return Err(LimitExceeded::MaxBytesOnDisk(max_bytes_on_disk));
+            }
+        }
+        let previous_num_docs = self.num_docs.fetch_add(num_docs, Ordering::SeqCst);
+        if let Some(max_docs) = limits.max_docs {
+            if previous_num_docs + num_docs > max_docs {
+                self.num_docs.fetch_sub(num_docs, Ordering::SeqCst);
+                return Err(LimitExceeded::MaxDocs(max_docs));
+            }
+        }
+        Ok(DocsReservation {
+            limit_tracker: self,
+            num_docs,
+        })
+    }
+}
+
+/// Documents accounted for by [`LimitTracker::add_docs`].
+///
+/// Dropping the reservation reverts it, so that the operations failing after the limits were
+/// checked leave no trace. It has to be committed once the documents made it to the indexing
+/// queue.
+#[must_use]
+pub(crate) struct DocsReservation<'a> {
+    limit_tracker: &'a LimitTracker,
+    num_docs: u64,
+}
+
+impl DocsReservation<'_> {
+    /// Keeps the documents accounted for.
+    pub fn commit(mut self) {
+        self.num_docs = 0;
+    }
+}
+
+impl Drop for DocsReservation<'_> {
+    fn drop(&mut self) {
+        if self.num_docs > 0 {
+            self.limit_tracker
+                .num_docs
+                .fetch_sub(self.num_docs, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Reads the size on disk of the files of a segment that does not record it.
+fn num_bytes_of_files(index: &Index, segment_meta: &SegmentMeta) -> crate::Result<u64> {
+    let directory = index.directory();
+    let mut num_bytes = 0u64;
+    for path in segment_meta.list_files() {
+        if directory.exists(&path)? {
+            num_bytes += directory.open_read(&path)?.len() as u64;
+        }
+    }
+    Ok(num_bytes)
+}
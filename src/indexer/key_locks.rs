@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::sync::{Condvar, Mutex};
+
+use crate::schema::Term;
+
+/// Locks on the unique keys of the documents being updated.
+///
+/// Updates of the same document are serialized, while updates of different
+/// documents run concurrently.
+#[derive(Default)]
+pub(crate) struct KeyLocks {
+    locked_keys: Mutex<HashSet<Term>>,
+    unlocked: Condvar,
+}
+
+impl KeyLocks {
+    /// Locks `key`, waiting for it to be unlocked if needed.
+    ///
+    /// The key remains locked until the returned guard is dropped.
+    pub fn lock(&self, key: &Term) -> KeyLockGuard<'_> {
+        let mut locked_keys = self.locked_keys.lock().unwrap();
+        while locked_keys.contains(key) {
+            locked_keys = self.unlocked.wait(locked_keys).unwrap();
+        }
+        locked_keys.insert(key.clone());
+        KeyLockGuard {
+            key_locks: self,
+            key: key.clone(),
+        }
+    }
+}
+
+pub(crate) struct KeyLockGuard<'a> {
+    key_locks: &'a KeyLocks,
+    key: Term,
+}
+
+impl Drop for KeyLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut locked_keys = self.key_locks.locked_keys.lock().unwrap();
+        locked_keys.remove(&self.key);
+        self.key_locks.unlocked.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::KeyLocks;
+    use crate::schema::{Field, Term};
+
+    #[test]
+    fn test_key_locks() {
+        let key_locks = Arc::new(KeyLocks::default());
+        let key = Term::from_field_u64(Field::from_field_id(0), 1);
+        let other_key = Term::from_field_u64(Field::from_field_id(0), 2);
+        let guard = key_locks.lock(&key);
+        // Other keys can be locked while `key` is locked.
+        drop(key_locks.lock(&other_key));
+
+        let locked = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let key_locks = key_locks.clone();
+            let locked = locked.clone();
+            thread::spawn(move || {
+                let _guard = key_locks.lock(&key);
+                locked.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!locked.load(Ordering::SeqCst));
+        drop(guard);
+        handle.join().unwrap();
+        assert!(locked.load(Ordering::SeqCst));
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::HashSet;
 use std::ops::Deref;
 
 use crate::index::SegmentId;
+use crate::indexer::MergePriority;
 use crate::{Inventory, Opstamp, TrackedObject};
 
 #[derive(Default)]
@@ -47,6 +48,7 @@ pub struct MergeOperation {
 pub(crate) struct InnerMergeOperation {
     target_opstamp: Opstamp,
     segment_ids: Vec<SegmentId>,
+    priority: MergePriority,
 }
 
 impl MergeOperation {
@@ -54,10 +56,12 @@ impl MergeOperation {
         inventory: &MergeOperationInventory,
         target_opstamp: Opstamp,
         segment_ids: Vec<SegmentId>,
+        priority: MergePriority,
     ) -> MergeOperation {
         let inner_merge_operation = InnerMergeOperation {
             target_opstamp,
             segment_ids,
+            priority,
         };
         MergeOperation {
             inner: inventory.track(inner_merge_operation),
@@ -74,4 +78,9 @@ impl MergeOperation {
     pub fn segment_ids(&self) -> &[SegmentId] {
         &self.inner.segment_ids[..]
     }
+
+    /// Returns the priority of the merge in the merge scheduler.
+    pub fn priority(&self) -> MergePriority {
+        self.inner.priority
+    }
 }
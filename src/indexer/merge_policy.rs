@@ -1,12 +1,122 @@
 use std::fmt::Debug;
 use std::marker;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::index::{SegmentId, SegmentMeta};
+use crate::indexer::MergePriority;
+
+// Size assumed for the documents of the segments whose size is unknown,
+// when no segment has a known size.
+const DEFAULT_BYTES_PER_DOC: f64 = 1_000.0;
 
 /// Set of segment suggested for a merge.
 #[derive(Debug, Clone)]
 pub struct MergeCandidate(pub Vec<SegmentId>);
 
+/// A merge that is either running or waiting for the merge scheduler to run it.
+#[derive(Debug, Clone)]
+pub struct InFlightMerge {
+    pub(crate) segment_ids: Vec<SegmentId>,
+    pub(crate) num_bytes: u64,
+    pub(crate) priority: MergePriority,
+    pub(crate) is_running: bool,
+}
+
+impl InFlightMerge {
+    /// Returns the segments being merged.
+    pub fn segment_ids(&self) -> &[SegmentId] {
+        &self.segment_ids
+    }
+
+    /// Returns the estimated size in bytes of the segments being merged.
+    pub fn num_bytes(&self) -> u64 {
+        self.num_bytes
+    }
+
+    /// Returns the priority of the merge.
+    pub fn priority(&self) -> MergePriority {
+        self.priority
+    }
+
+    /// Returns true if the merge is running, false if it waits for the scheduler.
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+}
+
+/// State of the index writer handed to the merge policy along with the mergeable segments.
+#[derive(Debug, Clone)]
+pub struct MergePolicyContext {
+    in_flight_merges: Vec<InFlightMerge>,
+    max_concurrent_merges: usize,
+    bytes_per_doc: f64,
+    now: SystemTime,
+}
+
+impl MergePolicyContext {
+    /// Creates a context for the given segments of the index, ignoring the merge scheduler.
+    pub fn for_segments(segment_metas: &[SegmentMeta]) -> MergePolicyContext {
+        MergePolicyContext::new(Vec::new(), usize::MAX, segment_metas)
+    }
+
+    /// `segment_metas` are all of the segments of the index, including the ones in merge.
+    pub(crate) fn new(
+        in_flight_merges: Vec<InFlightMerge>,
+        max_concurrent_merges: usize,
+        segment_metas: &[SegmentMeta],
+    ) -> MergePolicyContext {
+        let (known_bytes, known_docs) = segment_metas
+            .iter()
+            .filter_map(|segment_meta| {
+                Some((segment_meta.num_bytes()?, segment_meta.max_doc() as u64))
+            })
+            .fold((0u64, 0u64), |(bytes, docs), (num_bytes, max_doc)| {
+                (bytes + num_bytes, docs + max_doc)
+            });
+        let bytes_per_doc = if known_docs > 0 {
+            known_bytes as f64 / known_docs as f64
+        } else {
+            DEFAULT_BYTES_PER_DOC
+        };
+        MergePolicyContext {
+            in_flight_merges,
+            max_concurrent_merges,
+            bytes_per_doc,
+            now: SystemTime::now(),
+        }
+    }
+
+    /// Returns the merges that are running or waiting to run.
+    pub fn in_flight_merges(&self) -> &[InFlightMerge] {
+        &self.in_flight_merges
+    }
+
+    /// Returns the maximum number of merges the merge scheduler runs concurrently.
+    pub fn max_concurrent_merges(&self) -> usize {
+        self.max_concurrent_merges
+    }
+
+    /// Returns the size of the segment on disk in bytes.
+    ///
+    /// The size of segments written by versions of tantivy that did not record it is
+    /// estimated from their number of documents.
+    pub fn segment_num_bytes(&self, segment_meta: &SegmentMeta) -> u64 {
+        segment_meta
+            .num_bytes()
+            .unwrap_or_else(|| (segment_meta.max_doc() as f64 * self.bytes_per_doc) as u64)
+    }
+
+    /// Returns the time elapsed since the segment was written, if known.
+    pub fn segment_age(&self, segment_meta: &SegmentMeta) -> Option<Duration> {
+        let creation_time = UNIX_EPOCH + Duration::from_secs(segment_meta.creation_timestamp()?);
+        Some(
+            self.now
+                .duration_since(creation_time)
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
 /// The `MergePolicy` defines which segments should be merged.
 ///
 /// Every time the list of segments changes, the segment updater
@@ -17,6 +127,19 @@ pub trait MergePolicy: marker::Send + marker::Sync + Debug {
     /// This call happens on the segment updater thread, and will block
     /// other segment updates, so all implementations should happen rapidly.
     fn compute_merge_candidates(&self, segments: &[SegmentMeta]) -> Vec<MergeCandidate>;
+
+    /// Given the list of segment metas and the state of the index writer, returns the list
+    /// of merge candidates.
+    ///
+    /// This is the method called by the segment updater. By default, it ignores the context
+    /// and calls [`MergePolicy::compute_merge_candidates`].
+    fn compute_merge_candidates_with_context(
+        &self,
+        segments: &[SegmentMeta],
+        _context: &MergePolicyContext,
+    ) -> Vec<MergeCandidate> {
+        self.compute_merge_candidates(segments)
+    }
 }
 
 /// Never merge segments.
@@ -39,6 +162,7 @@ impl MergePolicy for NoMergePolicy {
 pub mod tests {
 
     use super::*;
+    use crate::index::SegmentMetaInventory;
 
     /// `MergePolicy` useful for test purposes.
     ///
@@ -60,4 +184,26 @@ pub mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_merge_policy_context() {
+        let inventory = SegmentMetaInventory::default();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let sized_segment = inventory
+            .new_segment_meta(SegmentId::generate_random(), 10, 0)
+            .with_num_bytes(1_000)
+            .with_creation_timestamp(now - 60);
+        let unsized_segment = inventory.new_segment_meta(SegmentId::generate_random(), 20, 0);
+        let context =
+            MergePolicyContext::for_segments(&[sized_segment.clone(), unsized_segment.clone()]);
+        assert_eq!(context.segment_num_bytes(&sized_segment), 1_000);
+        // The size is estimated from the size of the documents of the other segments.
+        assert_eq!(context.segment_num_bytes(&unsized_segment), 2_000);
+        assert!(context.segment_age(&sized_segment).unwrap() >= Duration::from_secs(60));
+        assert!(context.segment_age(&unsized_segment).is_none());
+        assert!(context.in_flight_merges().is_empty());
+    }
 }
@@ -0,0 +1,415 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use common::{AntiCallToken, TerminatingWrite};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::indexer::merge_policy::InFlightMerge;
+
+/// Default maximum number of merges running concurrently.
+pub(crate) const NUM_MERGE_THREADS: usize = 4;
+
+/// Priority of a merge.
+///
+/// When more merges are scheduled than the merge scheduler can run concurrently,
+/// the merges with the highest priority run first. Among merges of the same priority,
+/// the smallest merges run first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MergePriority {
+    /// Below the merges of the merge policy.
+    Low,
+    /// The priority of the merges of the merge policy.
+    #[default]
+    Normal,
+    /// Above the merges of the merge policy.
+    High,
+}
+
+/// Settings of the merge scheduler of an [`IndexWriter`](crate::IndexWriter).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeSchedulerSettings {
+    /// Maximum number of merges running concurrently.
+    ///
+    /// The other merges wait until a running merge is over. The merge thread pool has
+    /// one thread per concurrent merge.
+    pub max_concurrent_merges: usize,
+    /// Maximum number of bytes written per second by the merges, all merges combined.
+    pub max_merge_bytes_per_sec: Option<u64>,
+}
+
+impl Default for MergeSchedulerSettings {
+    fn default() -> MergeSchedulerSettings {
+        MergeSchedulerSettings {
+            max_concurrent_merges: NUM_MERGE_THREADS,
+            max_merge_bytes_per_sec: None,
+        }
+    }
+}
+
+type MergeTask = Box<dyn FnOnce(MergeSlot) + Send>;
+
+struct PendingMerge {
+    merge: InFlightMerge,
+    // Order of arrival, to run merges of the same priority and size in order.
+    seq: u64,
+    task: MergeTask,
+}
+
+// Priority, then smallest merges first, then order of arrival.
+type MergeOrder = (MergePriority, Reverse<u64>, Reverse<u64>);
+
+impl PendingMerge {
+    fn key(&self) -> MergeOrder {
+        (
+            self.merge.priority,
+            Reverse(self.merge.num_bytes),
+            Reverse(self.seq),
+        )
+    }
+}
+
+impl PartialEq for PendingMerge {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for PendingMerge {}
+
+impl PartialOrd for PendingMerge {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingMerge {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    running: Vec<(u64, InFlightMerge)>,
+    pending: BinaryHeap<PendingMerge>,
+    next_seq: u64,
+}
+
+/// Runs the merges of the segment updater on the merge threads, following
+/// the [`MergeSchedulerSettings`].
+pub(crate) struct MergeScheduler {
+    settings: RwLock<MergeSchedulerSettings>,
+    state: Mutex<SchedulerState>,
+    io_throttle: Arc<IoThrottle>,
+    // Sized after `MergeSchedulerSettings::max_concurrent_merges`.
+    merge_thread_pool: RwLock<Arc<ThreadPool>>,
+}
+
+/// Held by a running merge. Dropping it lets the next pending merge run.
+pub(crate) struct MergeSlot {
+    scheduler: Arc<MergeScheduler>,
+    seq: u64,
+}
+
+impl Drop for MergeSlot {
+    fn drop(&mut self) {
+        self.scheduler.release(self.seq);
+    }
+}
+
+fn build_merge_thread_pool(num_threads: usize) -> crate::Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .thread_name(|i| format!("merge_thread_{i}"))
+        .num_threads(num_threads)
+        .build()
+        .map_err(|_| {
+            crate::TantivyError::SystemError("Failed to spawn segment merging thread".to_string())
+        })
+}
+
+impl MergeScheduler {
+    pub fn create() -> crate::Result<MergeScheduler> {
+        let settings = MergeSchedulerSettings::default();
+        let merge_thread_pool = build_merge_thread_pool(settings.max_concurrent_merges)?;
+        Ok(MergeScheduler {
+            settings: RwLock::new(settings),
+            state: Mutex::new(SchedulerState::default()),
+            io_throttle: Arc::new(IoThrottle::default()),
+            merge_thread_pool: RwLock::new(Arc::new(merge_thread_pool)),
+        })
+    }
+
+    pub fn settings(&self) -> MergeSchedulerSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Updates the settings, resizing the merge thread pool if needed.
+    ///
+    /// The running merges keep on running on the previous thread pool, which shuts down
+    /// once they are over.
+    pub fn set_settings(self: &Arc<Self>, settings: MergeSchedulerSettings) -> crate::Result<()> {
+        let num_threads = settings.max_concurrent_merges.max(1);
+        if self.merge_thread_pool.read().unwrap().current_num_threads() != num_threads {
+            let merge_thread_pool = build_merge_thread_pool(num_threads)?;
+            *self.merge_thread_pool.write().unwrap() = Arc::new(merge_thread_pool);
+        }
+        self.io_throttle
+            .set_max_bytes_per_sec(settings.max_merge_bytes_per_sec);
+        *self.settings.write().unwrap() = settings;
+        // More merges may be allowed to run.
+        self.dispatch(&mut self.state.lock().unwrap());
+        Ok(())
+    }
+
+    pub fn io_throttle(&self) -> &Arc<IoThrottle> {
+        &self.io_throttle
+    }
+
+    /// Returns the running merges, followed by the pending merges.
+    pub fn in_flight_merges(&self) -> Vec<InFlightMerge> {
+        let state = self.state.lock().unwrap();
+        state
+            .running
+            .iter()
+            .map(|(_, merge)| merge.clone())
+            .chain(state.pending.iter().map(|pending| pending.merge.clone()))
+            .collect()
+    }
+
+    /// Schedules a merge.
+    ///
+    /// `task` is run on a merge thread once the settings allow it. The merge counts as
+    /// running until it drops the `MergeSlot` it is given.
+    pub fn schedule(
+        self: &Arc<Self>,
+        merge: InFlightMerge,
+        task: impl FnOnce(MergeSlot) + Send + 'static,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.pending.push(PendingMerge {
+            merge,
+            seq,
+            task: Box::new(task),
+        });
+        self.dispatch(&mut state);
+    }
+
+    // Starts pending merges as long as the settings allow it.
+    fn dispatch(self: &Arc<Self>, state: &mut SchedulerState) {
+        let max_concurrent_merges = self.settings.read().unwrap().max_concurrent_merges.max(1);
+        let merge_thread_pool = self.merge_thread_pool.read().unwrap().clone();
+        while state.running.len() < max_concurrent_merges {
+            let Some(PendingMerge {
+                mut merge,
+                seq,
+                task,
+            }) = state.pending.pop()
+            else {
+                break;
+            };
+            merge.is_running = true;
+            state.running.push((seq, merge));
+            let slot = MergeSlot {
+                scheduler: self.clone(),
+                seq,
+            };
+            merge_thread_pool.spawn(move || task(slot));
+        }
+    }
+
+    fn release(self: &Arc<Self>, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.running.retain(|(running_seq, _)| *running_seq != seq);
+        self.dispatch(&mut state);
+    }
+}
+
+/// Limits the rate at which the merges write their files.
+#[derive(Default)]
+pub(crate) struct IoThrottle {
+    // 0 means unlimited.
+    max_bytes_per_sec: AtomicU64,
+    // Time at which the next write is allowed.
+    next_write: Mutex<Option<Instant>>,
+}
+
+impl IoThrottle {
+    fn set_max_bytes_per_sec(&self, max_bytes_per_sec: Option<u64>) {
+        self.max_bytes_per_sec
+            .store(max_bytes_per_sec.unwrap_or(0), atomic::Ordering::SeqCst);
+    }
+
+    /// Blocks until writing `num_bytes` bytes does not exceed the rate limit.
+    fn throttle(&self, num_bytes: usize) {
+        let max_bytes_per_sec = self.max_bytes_per_sec.load(atomic::Ordering::SeqCst);
+        if max_bytes_per_sec == 0 || num_bytes == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let write_time = {
+            let mut next_write = self.next_write.lock().unwrap();
+            let write_time = next_write.map_or(now, |next_write| next_write.max(now));
+            *next_write = Some(
+                write_time + Duration::from_secs_f64(num_bytes as f64 / max_bytes_per_sec as f64),
+            );
+            write_time
+        };
+        let delay = write_time.saturating_duration_since(now);
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+/// Writer whose writes are throttled by an [`IoThrottle`].
+pub(crate) struct ThrottledWrite<W> {
+    underlying: W,
+    io_throttle: Arc<IoThrottle>,
+}
+
+impl<W> ThrottledWrite<W> {
+    pub fn wrap(underlying: W, io_throttle: Arc<IoThrottle>) -> ThrottledWrite<W> {
+        ThrottledWrite {
+            underlying,
+            io_throttle,
+        }
+    }
+}
+
+impl<W: Write> Write for ThrottledWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io_throttle.throttle(buf.len());
+        self.underlying.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.underlying.flush()
+    }
+}
+
+impl<W: TerminatingWrite> TerminatingWrite for ThrottledWrite<W> {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.underlying.terminate_ref(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn in_flight_merge(num_bytes: u64, priority: MergePriority) -> InFlightMerge {
+        InFlightMerge {
+            segment_ids: Vec::new(),
+            num_bytes,
+            priority,
+            is_running: false,
+        }
+    }
+
+    #[test]
+    fn test_merge_scheduler_priorities() {
+        let scheduler = Arc::new(MergeScheduler::create().unwrap());
+        scheduler
+            .set_settings(MergeSchedulerSettings {
+                max_concurrent_merges: 1,
+                max_merge_bytes_per_sec: None,
+            })
+            .unwrap();
+        let (blocker_sender, blocker_receiver) = mpsc::channel::<()>();
+        let (order_sender, order_receiver) = mpsc::channel();
+        scheduler.schedule(in_flight_merge(0, MergePriority::Normal), move |_slot| {
+            blocker_receiver.recv().unwrap();
+        });
+        for (id, num_bytes, priority) in [
+            (0, 10, MergePriority::Low),
+            (1, 10, MergePriority::Normal),
+            (2, 1, MergePriority::Normal),
+            (3, 100, MergePriority::High),
+        ] {
+            let order_sender = order_sender.clone();
+            scheduler.schedule(in_flight_merge(num_bytes, priority), move |_slot| {
+                order_sender.send(id).unwrap();
+            });
+        }
+        let in_flight_merges = scheduler.in_flight_merges();
+        assert_eq!(in_flight_merges.len(), 5);
+        assert_eq!(
+            in_flight_merges
+                .iter()
+                .filter(|merge| merge.is_running())
+                .count(),
+            1
+        );
+        blocker_sender.send(()).unwrap();
+        let order: Vec<i32> = order_receiver.iter().take(4).collect();
+        assert_eq!(order, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_merge_scheduler_sizes_thread_pool() {
+        let scheduler = Arc::new(MergeScheduler::create().unwrap());
+        assert_eq!(
+            scheduler
+                .merge_thread_pool
+                .read()
+                .unwrap()
+                .current_num_threads(),
+            NUM_MERGE_THREADS
+        );
+        scheduler
+            .set_settings(MergeSchedulerSettings {
+                max_concurrent_merges: 6,
+                max_merge_bytes_per_sec: None,
+            })
+            .unwrap();
+        assert_eq!(
+            scheduler
+                .merge_thread_pool
+                .read()
+                .unwrap()
+                .current_num_threads(),
+            6
+        );
+        // All of the allowed merges run at the same time.
+        let (started_sender, started_receiver) = mpsc::channel();
+        let (blocker_sender, blocker_receiver) = crossbeam_channel::unbounded::<()>();
+        for _ in 0..6 {
+            let started_sender = started_sender.clone();
+            let blocker_receiver = blocker_receiver.clone();
+            scheduler.schedule(in_flight_merge(0, MergePriority::Normal), move |_slot| {
+                started_sender.send(()).unwrap();
+                let _ = blocker_receiver.recv();
+            });
+        }
+        for _ in 0..6 {
+            started_receiver
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap();
+        }
+        drop(blocker_sender);
+    }
+
+    #[test]
+    fn test_io_throttle() {
+        let io_throttle = Arc::new(IoThrottle::default());
+        io_throttle.set_max_bytes_per_sec(Some(10_000));
+        let mut write = ThrottledWrite::wrap(Vec::new(), io_throttle);
+        let start = Instant::now();
+        for _ in 0..3 {
+            write.write_all(&[0u8; 1_000]).unwrap();
+        }
+        // The first write is not delayed, the next ones wait 100ms each.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(write.underlying.len(), 3_000);
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use columnar::{
     ColumnType, ColumnarReader, MergeRowOrder, RowAddr, ShuffleMergeOrder, StackMergeOrder,
 };
-use common::ReadOnlyBitSet;
+use common::{BitSet, ReadOnlyBitSet};
 use itertools::Itertools;
 use measure_time::debug_time;
 
@@ -12,13 +12,15 @@ use crate::docset::{DocSet, TERMINATED};
 use crate::error::DataCorruption;
 use crate::fastfield::AliveBitSet;
 use crate::fieldnorm::{FieldNormReader, FieldNormReaders, FieldNormsSerializer, FieldNormsWriter};
-use crate::index::{Segment, SegmentComponent, SegmentReader};
+use crate::index::{IndexSettings, Segment, SegmentComponent, SegmentReader};
 use crate::indexer::doc_id_mapping::{MappingType, SegmentDocIdMapping};
+use crate::indexer::schema_update::{rebuild_fast_fields, MigratedStoreReader};
 use crate::indexer::SegmentSerializer;
 use crate::postings::{InvertedIndexSerializer, Postings, SegmentPostings};
-use crate::schema::{value_type_to_column_type, Field, FieldType, Schema};
+use crate::schema::{value_type_to_column_type, Field, FieldType, IndexRecordOption, Schema};
 use crate::store::StoreWriter;
 use crate::termdict::{TermMerger, TermOrdinal};
+use crate::tokenizer::TokenizerManager;
 use crate::{DocAddress, DocId, InvertedIndexReader};
 
 /// Segment's max doc must be `< MAX_DOC_LIMIT`.
@@ -77,7 +79,9 @@ fn estimate_total_num_tokens(readers: &[SegmentReader], field: Field) -> crate::
 }
 
 pub struct IndexMerger {
+    index_settings: IndexSettings,
     schema: Schema,
+    fast_field_tokenizers: TokenizerManager,
     pub(crate) readers: Vec<SegmentReader>,
     max_doc: u32,
 }
@@ -112,7 +116,7 @@ fn convert_to_merge_order(
 ) -> MergeRowOrder {
     match doc_id_mapping.mapping_type() {
         MappingType::Stacked => MergeRowOrder::Stack(StackMergeOrder::stack(columnars)),
-        MappingType::StackedWithDeletes => {
+        MappingType::StackedWithDeletes | MappingType::Shuffled => {
             // RUST/LLVM is amazing. The following conversion is actually a no-op:
             // no allocation, no copy.
             let new_row_id_to_old_row_id: Vec<RowAddr> = doc_id_mapping
@@ -145,9 +149,13 @@ fn extract_fast_field_required_columns(schema: &Schema) -> Vec<(String, ColumnTy
 }
 
 impl IndexMerger {
-    pub fn open(schema: Schema, segments: &[Segment]) -> crate::Result<IndexMerger> {
+    pub fn open(
+        schema: Schema,
+        index_settings: IndexSettings,
+        segments: &[Segment],
+    ) -> crate::Result<IndexMerger> {
         let alive_bitset = segments.iter().map(|_| None).collect_vec();
-        Self::open_with_custom_alive_set(schema, segments, alive_bitset)
+        Self::open_with_custom_alive_set(schema, index_settings, segments, alive_bitset)
     }
 
     // Create merge with a custom delete set.
@@ -164,6 +172,7 @@ impl IndexMerger {
     // segments and partitions them e.g. by a value in a field.
     pub fn open_with_custom_alive_set(
         schema: Schema,
+        index_settings: IndexSettings,
         segments: &[Segment],
         alive_bitset_opt: Vec<Option<AliveBitSet>>,
     ) -> crate::Result<IndexMerger> {
@@ -171,13 +180,16 @@ impl IndexMerger {
         for (segment, new_alive_bitset_opt) in segments.iter().zip(alive_bitset_opt) {
             if segment.meta().num_docs() > 0 {
                 let reader =
-                    SegmentReader::open_with_custom_alive_set(segment, new_alive_bitset_opt)?;
+                    SegmentReader::open_with_schema(segment, schema.clone(), new_alive_bitset_opt)?;
                 readers.push(reader);
             }
         }
+        let fast_field_tokenizers = segments
+            .first()
+            .map(|segment| segment.index().fast_field_tokenizer().clone())
+            .unwrap_or_default();
 
         let max_doc = readers.iter().map(|reader| reader.num_docs()).sum();
-        // sort segments by their natural sort setting
         if max_doc >= MAX_DOC_LIMIT {
             let err_msg = format!(
                 "The segment resulting from this merge would have {max_doc} docs,which exceeds \
@@ -186,7 +198,9 @@ impl IndexMerger {
             return Err(crate::TantivyError::InvalidArgument(err_msg));
         }
         Ok(IndexMerger {
+            index_settings,
             schema,
+            fast_field_tokenizers,
             readers,
             max_doc,
         })
@@ -220,18 +234,14 @@ impl IndexMerger {
     fn write_fast_fields(
         &self,
         fast_field_wrt: &mut WritePtr,
+        columnars: &[&ColumnarReader],
         doc_id_mapping: SegmentDocIdMapping,
     ) -> crate::Result<()> {
         debug_time!("write-fast-fields");
         let required_columns = extract_fast_field_required_columns(&self.schema);
-        let columnars: Vec<&ColumnarReader> = self
-            .readers
-            .iter()
-            .map(|reader| reader.fast_fields().columnar())
-            .collect();
-        let merge_row_order = convert_to_merge_order(&columnars[..], doc_id_mapping);
+        let merge_row_order = convert_to_merge_order(columnars, doc_id_mapping);
         columnar::merge_columnar(
-            &columnars[..],
+            columnars,
             &required_columns,
             merge_row_order,
             fast_field_wrt,
@@ -283,13 +293,64 @@ impl IndexMerger {
         ))
     }
 
+    /// Creates a mapping stacking the documents of `docs`, which holds a subset of the alive
+    /// documents of each segment.
+    fn get_doc_id_from_filtered_data(&self, docs: &[BitSet]) -> SegmentDocIdMapping {
+        let alive_bitsets: Vec<ReadOnlyBitSet> = docs.iter().map(ReadOnlyBitSet::from).collect();
+        let mapping: Vec<DocAddress> = alive_bitsets
+            .iter()
+            .enumerate()
+            .flat_map(|(segment_ord, alive_bitset)| {
+                alive_bitset.iter().map(move |doc_id| DocAddress {
+                    segment_ord: segment_ord as u32,
+                    doc_id,
+                })
+            })
+            .collect();
+        let has_deletes = self
+            .readers
+            .iter()
+            .zip(docs)
+            .any(|(reader, segment_docs)| segment_docs.len() != reader.max_doc() as usize);
+        let mapping_type = if has_deletes {
+            MappingType::StackedWithDeletes
+        } else {
+            MappingType::Stacked
+        };
+        SegmentDocIdMapping::new(
+            mapping,
+            mapping_type,
+            alive_bitsets.into_iter().map(Some).collect(),
+        )
+    }
+
+    /// Maps the doc ids of the segments to the output segment they are written to, and to
+    /// their doc id in it.
+    fn merged_doc_id_map(
+        &self,
+        doc_id_mappings: &[SegmentDocIdMapping],
+    ) -> Vec<Vec<Option<(usize, DocId)>>> {
+        let mut merged_doc_id_map: Vec<Vec<Option<(usize, DocId)>>> = self
+            .readers
+            .iter()
+            .map(|reader| vec![None; reader.max_doc() as usize])
+            .collect();
+        for (output_ord, doc_id_mapping) in doc_id_mappings.iter().enumerate() {
+            for (new_doc_id, old_doc_addr) in doc_id_mapping.iter_old_doc_addrs().enumerate() {
+                let segment_map = &mut merged_doc_id_map[old_doc_addr.segment_ord as usize];
+                segment_map[old_doc_addr.doc_id as usize] = Some((output_ord, new_doc_id as DocId));
+            }
+        }
+        merged_doc_id_map
+    }
+
     fn write_postings_for_field(
         &self,
         indexed_field: Field,
         _field_type: &FieldType,
-        serializer: &mut InvertedIndexSerializer,
-        fieldnorm_reader: Option<FieldNormReader>,
-        doc_id_mapping: &SegmentDocIdMapping,
+        serializers: &mut [&mut InvertedIndexSerializer],
+        fieldnorm_readers: Vec<Option<FieldNormReader>>,
+        doc_id_mappings: &[SegmentDocIdMapping],
     ) -> crate::Result<()> {
         debug_time!("write-postings-for-field");
         let mut positions_buffer: Vec<u32> = Vec::with_capacity(1_000);
@@ -313,20 +374,7 @@ impl IndexMerger {
         let mut merged_terms = TermMerger::new(field_term_streams);
 
         // map from segment doc ids to the resulting merged segment doc id.
-
-        let mut merged_doc_id_map: Vec<Vec<Option<DocId>>> = self
-            .readers
-            .iter()
-            .map(|reader| {
-                let mut segment_local_map = vec![];
-                segment_local_map.resize(reader.max_doc() as usize, None);
-                segment_local_map
-            })
-            .collect();
-        for (new_doc_id, old_doc_addr) in doc_id_mapping.iter_old_doc_addrs().enumerate() {
-            let segment_map = &mut merged_doc_id_map[old_doc_addr.segment_ord as usize];
-            segment_map[old_doc_addr.doc_id as usize] = Some(new_doc_id as DocId);
-        }
+        let merged_doc_id_map = self.merged_doc_id_map(doc_id_mappings);
 
         // Note that the total number of tokens is not exact.
         // It is only used as a parameter in the BM25 formula.
@@ -344,8 +392,26 @@ impl IndexMerger {
         //
         // This stacking applies only when the index is not sorted, in that case the
         // doc_ids are kmerged by their sort property
-        let mut field_serializer =
-            serializer.new_field(indexed_field, total_num_tokens, fieldnorm_reader)?;
+        let mut field_serializers = Vec::with_capacity(serializers.len());
+        for ((serializer, fieldnorm_reader), doc_id_mapping) in serializers
+            .iter_mut()
+            .zip(fieldnorm_readers)
+            .zip(doc_id_mappings)
+        {
+            // When the documents are split across several segments, each of them gets its
+            // share of the tokens.
+            let num_docs = doc_id_mapping.new_doc_id_to_old_doc_addr.len();
+            let num_tokens = if num_docs == self.max_doc as usize {
+                total_num_tokens
+            } else {
+                (total_num_tokens as u128 * num_docs as u128 / self.max_doc as u128) as u64
+            };
+            field_serializers.push(serializer.new_field(
+                indexed_field,
+                num_tokens,
+                fieldnorm_reader,
+            )?);
+        }
 
         let field_entry = self.schema.get_field_entry(indexed_field);
 
@@ -356,27 +422,48 @@ impl IndexMerger {
         );
 
         let mut segment_postings_containing_the_term: Vec<(usize, SegmentPostings)> = vec![];
+        let mut doc_freqs: Vec<u32> = vec![0; field_serializers.len()];
+        let mut doc_id_and_positions: Vec<Vec<(DocId, u32, Vec<u32>)>> =
+            vec![Vec::new(); field_serializers.len()];
 
         while merged_terms.advance() {
             segment_postings_containing_the_term.clear();
+            doc_freqs.fill(0);
             let term_bytes: &[u8] = merged_terms.key();
 
-            let mut total_doc_freq = 0;
-
             // Let's compute the list of non-empty posting lists
             for (segment_ord, term_info) in merged_terms.current_segment_ords_and_term_infos() {
                 let segment_reader = &self.readers[segment_ord];
                 let inverted_index: &InvertedIndexReader = &field_readers[segment_ord];
                 let segment_postings = inverted_index
                     .read_postings_from_terminfo(&term_info, segment_postings_option)?;
-                let alive_bitset_opt = segment_reader.alive_bitset();
-                let doc_freq = if let Some(alive_bitset) = alive_bitset_opt {
-                    segment_postings.doc_freq_given_deletes(alive_bitset)
+                let has_docs = if let [doc_freq] = &mut doc_freqs[..] {
+                    let alive_bitset_opt = segment_reader.alive_bitset();
+                    let segment_doc_freq = if let Some(alive_bitset) = alive_bitset_opt {
+                        segment_postings.doc_freq_given_deletes(alive_bitset)
+                    } else {
+                        segment_postings.doc_freq()
+                    };
+                    *doc_freq += segment_doc_freq;
+                    segment_doc_freq > 0u32
                 } else {
-                    segment_postings.doc_freq()
+                    // The documents are split across several segments: the doc freq of the
+                    // term in each of them is counted on a copy of the postings.
+                    let old_to_new_doc_id = &merged_doc_id_map[segment_ord];
+                    let mut doc_postings = inverted_index
+                        .read_postings_from_terminfo(&term_info, IndexRecordOption::Basic)?;
+                    let mut has_docs = false;
+                    let mut doc = doc_postings.doc();
+                    while doc != TERMINATED {
+                        if let Some((output_ord, _)) = old_to_new_doc_id[doc as usize] {
+                            doc_freqs[output_ord] += 1;
+                            has_docs = true;
+                        }
+                        doc = doc_postings.advance();
+                    }
+                    has_docs
                 };
-                if doc_freq > 0u32 {
-                    total_doc_freq += doc_freq;
+                if has_docs {
                     segment_postings_containing_the_term.push((segment_ord, segment_postings));
                 }
             }
@@ -385,15 +472,12 @@ impl IndexMerger {
             // of all of the segments containing the given term (and that are non-empty)
             //
             // These segments are non-empty and advance has already been called.
-            if total_doc_freq == 0u32 {
+            if segment_postings_containing_the_term.is_empty() {
                 // All docs that used to contain the term have been deleted. The `term` will be
                 // entirely removed.
                 continue;
             }
 
-            // This should never happen as we early exited for total_doc_freq == 0.
-            assert!(!segment_postings_containing_the_term.is_empty());
-
             let has_term_freq = {
                 let has_term_freq = !segment_postings_containing_the_term[0]
                     .1
@@ -425,7 +509,11 @@ impl IndexMerger {
                 has_term_freq
             };
 
-            field_serializer.new_term(term_bytes, total_doc_freq, has_term_freq)?;
+            for (field_serializer, &doc_freq) in field_serializers.iter_mut().zip(&doc_freqs) {
+                if doc_freq > 0u32 {
+                    field_serializer.new_term(term_bytes, doc_freq, has_term_freq)?;
+                }
+            }
 
             // We can now serialize this postings, by pushing each document to the
             // postings serializer.
@@ -437,7 +525,7 @@ impl IndexMerger {
                 let mut doc = segment_postings.doc();
                 while doc != TERMINATED {
                     // deleted doc are skipped as they do not have a `remapped_doc_id`.
-                    if let Some(remapped_doc_id) = old_to_new_doc_id[doc as usize] {
+                    if let Some((output_ord, remapped_doc_id)) = old_to_new_doc_id[doc as usize] {
                         // we make sure to only write the term if
                         // there is at least one document.
                         let term_freq = if has_term_freq {
@@ -451,48 +539,137 @@ impl IndexMerger {
                             0u32
                         };
 
-                        let delta_positions = delta_computer.compute_delta(&positions_buffer);
-                        field_serializer.write_doc(remapped_doc_id, term_freq, delta_positions);
+                        if doc_id_mappings[output_ord].mapping_type() == MappingType::Shuffled {
+                            // Documents of the different segments are interleaved: they
+                            // need to be sorted by their new doc id before being written.
+                            doc_id_and_positions[output_ord].push((
+                                remapped_doc_id,
+                                term_freq,
+                                positions_buffer.to_vec(),
+                            ));
+                        } else {
+                            let delta_positions = delta_computer.compute_delta(&positions_buffer);
+                            field_serializers[output_ord].write_doc(
+                                remapped_doc_id,
+                                term_freq,
+                                delta_positions,
+                            );
+                        }
                     }
 
                     doc = segment_postings.advance();
                 }
             }
-            // closing the term.
-            field_serializer.close_term()?;
+            for ((field_serializer, output_doc_id_and_positions), &doc_freq) in field_serializers
+                .iter_mut()
+                .zip(&mut doc_id_and_positions)
+                .zip(&doc_freqs)
+            {
+                if doc_freq == 0u32 {
+                    continue;
+                }
+                if !output_doc_id_and_positions.is_empty() {
+                    output_doc_id_and_positions.sort_unstable_by_key(|&(doc_id, _, _)| doc_id);
+                    for (doc_id, term_freq, positions) in output_doc_id_and_positions.drain(..) {
+                        let delta_positions = delta_computer.compute_delta(&positions);
+                        field_serializer.write_doc(doc_id, term_freq, delta_positions);
+                    }
+                }
+                // closing the term.
+                field_serializer.close_term()?;
+            }
+        }
+        for field_serializer in field_serializers {
+            field_serializer.close()?;
         }
-        field_serializer.close()?;
         Ok(())
     }
 
     fn write_postings(
         &self,
-        serializer: &mut InvertedIndexSerializer,
-        fieldnorm_readers: FieldNormReaders,
-        doc_id_mapping: &SegmentDocIdMapping,
+        serializers: &mut [&mut InvertedIndexSerializer],
+        fieldnorm_readers: &[FieldNormReaders],
+        doc_id_mappings: &[SegmentDocIdMapping],
     ) -> crate::Result<()> {
         for (field, field_entry) in self.schema.fields() {
-            let fieldnorm_reader = fieldnorm_readers.get_field(field)?;
+            let fieldnorm_readers: Vec<Option<FieldNormReader>> = fieldnorm_readers
+                .iter()
+                .map(|fieldnorm_readers| fieldnorm_readers.get_field(field))
+                .collect::<crate::Result<_>>()?;
             if field_entry.is_indexed() {
                 self.write_postings_for_field(
                     field,
                     field_entry.field_type(),
-                    serializer,
-                    fieldnorm_reader,
-                    doc_id_mapping,
+                    serializers,
+                    fieldnorm_readers,
+                    doc_id_mappings,
                 )?;
             }
         }
         Ok(())
     }
 
-    fn write_storable_fields(&self, store_writer: &mut StoreWriter) -> crate::Result<()> {
+    fn write_storable_fields(
+        &self,
+        store_writers: &mut [&mut StoreWriter],
+        doc_id_mappings: &[SegmentDocIdMapping],
+    ) -> crate::Result<()> {
         debug_time!("write-storable-fields");
         debug!("write-storable-field");
 
-        for reader in &self.readers {
+        if doc_id_mappings
+            .iter()
+            .any(|doc_id_mapping| doc_id_mapping.mapping_type() == MappingType::Shuffled)
+        {
+            let store_readers: Vec<_> = self
+                .readers
+                .iter()
+                .map(|reader| reader.get_store_reader(50))
+                .collect::<Result<_, _>>()?;
+            let migrated_store_readers: Vec<Option<MigratedStoreReader>> = self
+                .readers
+                .iter()
+                .map(|reader| {
+                    if reader.field_mapping().is_some() {
+                        MigratedStoreReader::open(reader, &self.schema, 50).map(Some)
+                    } else {
+                        Ok(None)
+                    }
+                })
+                .collect::<crate::Result<_>>()?;
+            for (store_writer, doc_id_mapping) in store_writers.iter_mut().zip(doc_id_mappings) {
+                for old_doc_addr in doc_id_mapping.iter_old_doc_addrs() {
+                    let segment_ord = old_doc_addr.segment_ord as usize;
+                    if let Some(migrated_store_reader) = &migrated_store_readers[segment_ord] {
+                        let doc = migrated_store_reader.get(old_doc_addr.doc_id)?;
+                        store_writer.store(&doc, &self.schema)?;
+                        continue;
+                    }
+                    let doc_bytes =
+                        store_readers[segment_ord].get_document_bytes(old_doc_addr.doc_id)?;
+                    store_writer.store_bytes(&doc_bytes)?;
+                }
+            }
+            return Ok(());
+        }
+
+        let merged_doc_id_map = self.merged_doc_id_map(doc_id_mappings);
+        for (reader, old_to_new_doc_id) in self.readers.iter().zip(&merged_doc_id_map) {
+            if reader.field_mapping().is_some() {
+                // The segment was written with a previous version of the schema: its documents
+                // need to be serialized again.
+                let migrated_store_reader = MigratedStoreReader::open(reader, &self.schema, 1)?;
+                for doc_id in reader.doc_ids_alive() {
+                    if let Some((output_ord, _)) = old_to_new_doc_id[doc_id as usize] {
+                        let doc = migrated_store_reader.get(doc_id)?;
+                        store_writers[output_ord].store(&doc, &self.schema)?;
+                    }
+                }
+                continue;
+            }
             let store_reader = reader.get_store_reader(1)?;
-            if reader.has_deletes()
+            if let [store_writer] = store_writers {
+                if !(reader.has_deletes()
                     // If there is not enough data in the store, we avoid stacking in order to
                     // avoid creating many small blocks in the doc store. Once we have 5 full blocks,
                     // we start stacking. In the worst case 2/7 of the blocks would be very small.
@@ -507,14 +684,20 @@ impl IndexMerger {
                     //
                     // take 7 in order to not walk over all checkpoints.
                     || store_reader.block_checkpoints().take(7).count() < 6
-                    || store_reader.decompressor() != store_writer.compressor().into()
+                    || store_reader.decompressor() != store_writer.compressor().into())
+                {
+                    store_writer.stack(store_reader)?;
+                    continue;
+                }
+            }
+            for (doc_id, doc_bytes_res) in reader
+                .doc_ids_alive()
+                .zip(store_reader.iter_raw(reader.alive_bitset()))
             {
-                for doc_bytes_res in store_reader.iter_raw(reader.alive_bitset()) {
+                if let Some((output_ord, _)) = old_to_new_doc_id[doc_id as usize] {
                     let doc_bytes = doc_bytes_res?;
-                    store_writer.store_bytes(&doc_bytes)?;
+                    store_writers[output_ord].store_bytes(&doc_bytes)?;
                 }
-            } else {
-                store_writer.stack(store_reader)?;
             }
         }
         Ok(())
@@ -525,31 +708,104 @@ impl IndexMerger {
     ///
     /// # Returns
     /// The number of documents in the resulting segment.
-    pub fn write(&self, mut serializer: SegmentSerializer) -> crate::Result<u32> {
-        let doc_id_mapping = self.get_doc_id_from_concatenated_data()?;
+    pub fn write(&self, serializer: SegmentSerializer) -> crate::Result<u32> {
+        let doc_id_mapping = self
+            .get_doc_id_from_concatenated_data()?
+            .sort_by_fields(&self.readers, &self.index_settings.sort_by_fields)?;
+        self.write_outputs(vec![serializer], vec![doc_id_mapping])?;
+        Ok(self.max_doc)
+    }
+
+    /// Writes the documents of the merged segments to several segments, in a single pass over
+    /// the merged segments.
+    ///
+    /// `docs` holds, for each of the `serializers`, the alive documents of every merged
+    /// segment that are written to it. A document is written to at most one segment.
+    ///
+    /// # Returns
+    /// The number of documents in each of the resulting segments.
+    pub(crate) fn write_split(
+        &self,
+        serializers: Vec<SegmentSerializer>,
+        docs: &[Vec<BitSet>],
+    ) -> crate::Result<Vec<u32>> {
+        let doc_id_mappings: Vec<SegmentDocIdMapping> = docs
+            .iter()
+            .map(|output_docs| {
+                self.get_doc_id_from_filtered_data(output_docs)
+                    .sort_by_fields(&self.readers, &self.index_settings.sort_by_fields)
+            })
+            .collect::<crate::Result<_>>()?;
+        let num_docs: Vec<u32> = doc_id_mappings
+            .iter()
+            .map(|doc_id_mapping| doc_id_mapping.new_doc_id_to_old_doc_addr.len() as u32)
+            .collect();
+        self.write_outputs(serializers, doc_id_mappings)?;
+        Ok(num_docs)
+    }
+
+    fn write_outputs(
+        &self,
+        mut serializers: Vec<SegmentSerializer>,
+        doc_id_mappings: Vec<SegmentDocIdMapping>,
+    ) -> crate::Result<()> {
         debug!("write-fieldnorms");
-        if let Some(fieldnorms_serializer) = serializer.extract_fieldnorms_serializer() {
-            self.write_fieldnorms(fieldnorms_serializer, &doc_id_mapping)?;
+        let mut fieldnorm_readers = Vec::with_capacity(serializers.len());
+        for (serializer, doc_id_mapping) in serializers.iter_mut().zip(&doc_id_mappings) {
+            if let Some(fieldnorms_serializer) = serializer.extract_fieldnorms_serializer() {
+                self.write_fieldnorms(fieldnorms_serializer, doc_id_mapping)?;
+            }
+            let fieldnorm_data = serializer
+                .segment()
+                .open_read(SegmentComponent::FieldNorms)?;
+            fieldnorm_readers.push(FieldNormReaders::open(fieldnorm_data)?);
         }
         debug!("write-postings");
-        let fieldnorm_data = serializer
-            .segment()
-            .open_read(SegmentComponent::FieldNorms)?;
-        let fieldnorm_readers = FieldNormReaders::open(fieldnorm_data)?;
+        let mut postings_serializers: Vec<&mut InvertedIndexSerializer> = serializers
+            .iter_mut()
+            .map(SegmentSerializer::get_postings_serializer)
+            .collect();
         self.write_postings(
-            serializer.get_postings_serializer(),
-            fieldnorm_readers,
-            &doc_id_mapping,
+            &mut postings_serializers,
+            &fieldnorm_readers,
+            &doc_id_mappings,
         )?;
 
         debug!("write-storagefields");
-        self.write_storable_fields(serializer.get_store_writer())?;
+        let mut store_writers: Vec<&mut StoreWriter> = serializers
+            .iter_mut()
+            .map(SegmentSerializer::get_store_writer)
+            .collect();
+        self.write_storable_fields(&mut store_writers, &doc_id_mappings)?;
         debug!("write-fastfields");
-        self.write_fast_fields(serializer.get_fast_field_write(), doc_id_mapping)?;
+        let rebuilt_columnars: Vec<Option<ColumnarReader>> = self
+            .readers
+            .iter()
+            .map(|reader| rebuild_fast_fields(reader, &self.schema, &self.fast_field_tokenizers))
+            .collect::<crate::Result<_>>()?;
+        let columnars: Vec<&ColumnarReader> = self
+            .readers
+            .iter()
+            .zip(&rebuilt_columnars)
+            .map(|(reader, rebuilt_columnar_opt)| {
+                rebuilt_columnar_opt
+                    .as_ref()
+                    .unwrap_or_else(|| reader.fast_fields().columnar())
+            })
+            .collect();
+        for (serializer, doc_id_mapping) in serializers.iter_mut().zip(doc_id_mappings) {
+            self.write_fast_fields(
+                serializer.get_fast_field_write(),
+                &columnars,
+                doc_id_mapping,
+            )?;
+        }
 
         debug!("close-serializer");
-        serializer.close()?;
-        Ok(self.max_doc)
+        for serializer in serializers {
+            serializer.close()?;
+        }
+        Ok(())
     }
 }
 
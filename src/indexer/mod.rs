@@ -4,21 +4,28 @@
 //! `IndexWriter` is the main entry point for that, which created from
 //! [`Index::writer`](crate::Index::writer).
 
+mod commit_deletion_policy;
 pub(crate) mod delete_queue;
 pub(crate) mod path_to_unordered_id;
 
 pub(crate) mod doc_id_mapping;
 mod doc_opstamp_mapping;
 mod flat_map_with_buffer;
+pub(crate) mod index_splitter;
 pub(crate) mod index_writer;
+pub(crate) mod index_writer_limits;
 pub(crate) mod index_writer_status;
+mod key_locks;
 mod log_merge_policy;
 mod merge_index_test;
 mod merge_operation;
 pub(crate) mod merge_policy;
+pub(crate) mod merge_scheduler;
 pub(crate) mod merger;
+pub(crate) mod numeric_updates;
 pub(crate) mod operation;
 pub(crate) mod prepared_commit;
+pub(crate) mod schema_update;
 mod segment_entry;
 mod segment_manager;
 mod segment_register;
@@ -27,14 +34,24 @@ pub(crate) mod segment_updater;
 pub(crate) mod segment_writer;
 pub(crate) mod single_segment_index_writer;
 mod stamper;
+mod tiered_merge_policy;
+pub(crate) mod write_ahead_log;
 
 use crossbeam_channel as channel;
 use smallvec::SmallVec;
 
+pub use self::commit_deletion_policy::{
+    CommitDeletionPolicy, KeepCommitsForDuration, KeepLastCommit, KeepLastCommits,
+};
+pub use self::index_splitter::{IndexSplitter, SplitKey};
 pub use self::index_writer::IndexWriter;
+pub use self::index_writer_limits::{IndexWriterLimits, LimitExceeded};
 pub use self::log_merge_policy::LogMergePolicy;
 pub use self::merge_operation::MergeOperation;
-pub use self::merge_policy::{MergeCandidate, MergePolicy, NoMergePolicy};
+pub use self::merge_policy::{
+    InFlightMerge, MergeCandidate, MergePolicy, MergePolicyContext, NoMergePolicy,
+};
+pub use self::merge_scheduler::{MergePriority, MergeSchedulerSettings};
 use self::operation::AddOperation;
 pub use self::operation::UserOperation;
 pub use self::prepared_commit::PreparedCommit;
@@ -43,6 +60,7 @@ pub(crate) use self::segment_serializer::SegmentSerializer;
 pub use self::segment_updater::{merge_filtered_segments, merge_indices};
 pub use self::segment_writer::SegmentWriter;
 pub use self::single_segment_index_writer::SingleSegmentIndexWriter;
+pub use self::tiered_merge_policy::TieredMergePolicy;
 
 /// Alias for the default merge policy, which is the `LogMergePolicy`.
 pub type DefaultMergePolicy = LogMergePolicy;
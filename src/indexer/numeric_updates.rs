@@ -0,0 +1,463 @@
+//! In-place updates of numeric fast fields.
+//!
+//! See [`IndexWriter::update_numeric`](crate::IndexWriter::update_numeric).
+//!
+//! The updates of a segment are stored in a `.upd` file, versioned by opstamp like the `.del`
+//! file. It is a columnar holding, for each updated field, a column with the values of the
+//! updated documents only. Like the `.del` file, each `.upd` file contains all of the updates of
+//! the segment so far, so that its size only depends on the number of updated documents.
+//!
+//! When the segment is opened, the updated values are applied on top of the columns of the
+//! `.fast` file (see [`apply_numeric_updates`]). The resulting columns are shared by all of the
+//! readers of the index through the [`NumericUpdatesCache`], so that reloading a reader does not
+//! rebuild them. The updates get folded into the regular fast fields as the segment is merged.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::io;
+use std::sync::{Arc, Mutex, Weak};
+
+use columnar::{
+    Column, ColumnType, ColumnarReader, ColumnarWriter, DynamicColumn, MonotonicallyMappableToU64,
+    NumericalValue, RowId,
+};
+
+use crate::directory::TerminatingWrite;
+use crate::index::{Segment, SegmentComponent, SegmentId, SegmentReader};
+use crate::schema::{Field, FieldEntry, FieldType};
+use crate::{DocId, Opstamp, TantivyError};
+
+/// Converts `value` to the type of the numeric fast field `field_entry`.
+///
+/// Returns an error if the field cannot be updated, or if the value does not fit in its type.
+pub(crate) fn to_field_value(
+    field_entry: &FieldEntry,
+    value: NumericalValue,
+) -> crate::Result<NumericalValue> {
+    let converted_value = match (field_entry.field_type(), value) {
+        (FieldType::U64(_), NumericalValue::U64(val)) => Some(NumericalValue::U64(val)),
+        (FieldType::U64(_), NumericalValue::I64(val)) => {
+            u64::try_from(val).ok().map(NumericalValue::U64)
+        }
+        (FieldType::I64(_), NumericalValue::I64(val)) => Some(NumericalValue::I64(val)),
+        (FieldType::I64(_), NumericalValue::U64(val)) => {
+            i64::try_from(val).ok().map(NumericalValue::I64)
+        }
+        (FieldType::F64(_), NumericalValue::F64(val)) => Some(NumericalValue::F64(val)),
+        (FieldType::F64(_), NumericalValue::U64(val)) => Some(NumericalValue::F64(val as f64)),
+        (FieldType::F64(_), NumericalValue::I64(val)) => Some(NumericalValue::F64(val as f64)),
+        (FieldType::U64(_) | FieldType::I64(_), NumericalValue::F64(_)) => None,
+        _ => {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Field {:?} is not a numeric field.",
+                field_entry.name()
+            )))
+        }
+    };
+    converted_value.ok_or_else(|| {
+        TantivyError::InvalidArgument(format!(
+            "{value:?} is not a valid value for the field {:?}.",
+            field_entry.name()
+        ))
+    })
+}
+
+/// Numeric updates of a segment, collected while applying the delete queue.
+#[derive(Default)]
+pub(crate) struct NumericUpdates {
+    updates: HashMap<Field, BTreeMap<DocId, NumericalValue>>,
+}
+
+impl NumericUpdates {
+    /// Sets the value of `field` for the document `doc`.
+    ///
+    /// Later updates of the same document override the previous ones.
+    pub fn record(&mut self, field: Field, doc: DocId, value: NumericalValue) {
+        self.updates.entry(field).or_default().insert(doc, value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Writes the numeric updates file of `segment`, and returns the segment with its
+    /// updated meta.
+    ///
+    /// The file contains the updated values of the documents updated so far, including the ones
+    /// of the previous numeric updates file of the segment.
+    pub fn write(
+        &self,
+        segment_reader: &SegmentReader,
+        segment: Segment,
+        opstamp: Opstamp,
+    ) -> crate::Result<Segment> {
+        let schema = segment_reader.schema();
+        let mut updates: BTreeMap<Field, BTreeMap<DocId, NumericalValue>> = BTreeMap::new();
+        if segment.meta().numeric_updates_opstamp().is_some() {
+            let previous_updates =
+                ColumnarReader::open(segment.open_read(SegmentComponent::NumericUpdates)?)?;
+            for (column_name, column_handle) in previous_updates.list_columns()? {
+                // The field may have been dropped since.
+                let Ok(field) = schema.get_field(&column_name) else {
+                    continue;
+                };
+                updates
+                    .entry(field)
+                    .or_default()
+                    .extend(read_updated_values(&column_handle.open()?));
+            }
+        }
+        for (field, field_updates) in &self.updates {
+            updates.entry(*field).or_default().extend(field_updates);
+        }
+        let mut columnar_writer = ColumnarWriter::default();
+        for (field, field_updates) in &updates {
+            let field_entry = schema.get_field_entry(*field);
+            let column_name = field_entry.name();
+            let column_type = match field_entry.field_type() {
+                FieldType::U64(_) => ColumnType::U64,
+                FieldType::I64(_) => ColumnType::I64,
+                _ => ColumnType::F64,
+            };
+            columnar_writer.record_column_type(column_name, column_type, false);
+            for (doc, value) in field_updates {
+                columnar_writer.record_numerical(*doc, column_name, *value);
+            }
+        }
+        let mut segment = segment.with_numeric_updates_opstamp(opstamp);
+        let mut numeric_updates_file = segment.open_write(SegmentComponent::NumericUpdates)?;
+        columnar_writer.serialize(segment_reader.max_doc(), &mut numeric_updates_file)?;
+        numeric_updates_file.terminate()?;
+        Ok(segment)
+    }
+}
+
+/// Returns the `(doc, value)` pairs of a column of a numeric updates file.
+fn read_updated_values(column: &DynamicColumn) -> Vec<(DocId, NumericalValue)> {
+    fn read_column<T: MonotonicallyMappableToU64 + PartialOrd + Debug>(
+        column: &Column<T>,
+        to_value: impl Fn(T) -> NumericalValue,
+    ) -> Vec<(DocId, NumericalValue)> {
+        let num_vals = column.values.num_vals();
+        let mut docs: Vec<RowId> = (0..num_vals).collect();
+        column.index.select_batch_in_place(0, &mut docs);
+        docs.into_iter()
+            .zip(column.values.iter())
+            .map(|(doc, value)| (doc, to_value(value)))
+            .collect()
+    }
+    match column {
+        DynamicColumn::U64(column) => read_column(column, NumericalValue::U64),
+        DynamicColumn::I64(column) => read_column(column, NumericalValue::I64),
+        DynamicColumn::F64(column) => read_column(column, NumericalValue::F64),
+        _ => Vec::new(),
+    }
+}
+
+/// Applies the numeric updates file `numeric_updates` on top of the `fast_fields` columnar.
+///
+/// Returns an in-memory columnar holding the full columns of the updated fields: the values
+/// of the updated documents replace all of their values in `fast_fields`.
+pub(crate) fn apply_numeric_updates(
+    fast_fields: &ColumnarReader,
+    numeric_updates: &ColumnarReader,
+) -> io::Result<ColumnarReader> {
+    let num_docs = fast_fields.num_rows();
+    let mut columnar_writer = ColumnarWriter::default();
+    for (column_name, updates_column_handle) in numeric_updates.list_columns()? {
+        let column_type = updates_column_handle.column_type();
+        columnar_writer.record_column_type(&column_name, column_type, false);
+        let mut updated_values = read_updated_values(&updates_column_handle.open()?)
+            .into_iter()
+            .peekable();
+        let column_opt: Option<DynamicColumn> = fast_fields
+            .read_columns(&column_name)?
+            .into_iter()
+            .find(|column_handle| column_handle.column_type() == column_type)
+            .map(|column_handle| column_handle.open())
+            .transpose()?;
+        for doc in 0..num_docs {
+            if let Some((_, value)) = updated_values.next_if(|(updated_doc, _)| *updated_doc == doc)
+            {
+                columnar_writer.record_numerical(doc, &column_name, value);
+                continue;
+            }
+            match &column_opt {
+                Some(DynamicColumn::U64(column)) => {
+                    for val in column.values_for_doc(doc) {
+                        columnar_writer.record_numerical(doc, &column_name, val);
+                    }
+                }
+                Some(DynamicColumn::I64(column)) => {
+                    for val in column.values_for_doc(doc) {
+                        columnar_writer.record_numerical(doc, &column_name, val);
+                    }
+                }
+                Some(DynamicColumn::F64(column)) => {
+                    for val in column.values_for_doc(doc) {
+                        columnar_writer.record_numerical(doc, &column_name, val);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut columnar_data = Vec::new();
+    columnar_writer.serialize(num_docs, &mut columnar_data)?;
+    ColumnarReader::open(columnar_data)
+}
+
+/// Fast field columns rewritten by numeric updates, shared by the readers of an index.
+///
+/// The columns only depend on the segment and on the opstamp of its numeric updates. They are
+/// kept for as long as a reader of the segment holds on to them.
+#[derive(Clone, Default)]
+pub(crate) struct NumericUpdatesCache {
+    columnars: Arc<Mutex<CachedColumnars>>,
+}
+
+type CachedColumnars = HashMap<(SegmentId, Opstamp), Weak<ColumnarReader>>;
+
+impl NumericUpdatesCache {
+    /// Returns the columns of `segment_id` rewritten by the numeric updates of `opstamp`,
+    /// computing them with `apply` if no reader holds them.
+    pub fn get_or_apply(
+        &self,
+        segment_id: SegmentId,
+        opstamp: Opstamp,
+        apply: impl FnOnce() -> crate::Result<ColumnarReader>,
+    ) -> crate::Result<Arc<ColumnarReader>> {
+        let key = (segment_id, opstamp);
+        let cached_columnar = self
+            .columnars
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(Weak::upgrade);
+        if let Some(columnar) = cached_columnar {
+            return Ok(columnar);
+        }
+        // The columns are computed without holding the lock, so that the segments can be
+        // opened concurrently.
+        let columnar = Arc::new(apply()?);
+        let mut columnars = self.columnars.lock().unwrap();
+        columnars.retain(|_, columnar| columnar.strong_count() > 0);
+        if let Some(concurrent_columnar) = columnars.get(&key).and_then(Weak::upgrade) {
+            return Ok(concurrent_columnar);
+        }
+        columnars.insert(key, Arc::downgrade(&columnar));
+        Ok(columnar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use columnar::{ColumnarReader, NumericalValue};
+
+    use crate::index::{SegmentComponent, SegmentId};
+    use crate::indexer::NoMergePolicy;
+    use crate::schema::{Schema, Term, FAST, INDEXED, STORED};
+    use crate::{Index, IndexWriter, Searcher, TantivyError};
+
+    fn value_by_key(searcher: &Searcher, key: u64, field_name: &str) -> Vec<u64> {
+        let id_field = searcher.schema().get_field("id").unwrap();
+        let doc_address = searcher
+            .doc_address_by_key(&Term::from_field_u64(id_field, key))
+            .unwrap()
+            .unwrap();
+        let column = searcher
+            .segment_reader(doc_address.segment_ord)
+            .fast_fields()
+            .u64(field_name)
+            .unwrap();
+        column.values_for_doc(doc_address.doc_id).collect()
+    }
+
+    #[test]
+    fn test_update_numeric() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let score_field = schema_builder.add_u64_field("score", FAST);
+        let clicks_field = schema_builder.add_u64_field("clicks", FAST);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(id_field=>1u64, score_field=>1u64, clicks_field=>1u64))?;
+        index_writer.add_document(doc!(id_field=>2u64, score_field=>2u64, clicks_field=>2u64))?;
+        index_writer.commit()?;
+
+        // Updates of committed and uncommitted documents.
+        index_writer.add_document(doc!(id_field=>3u64, clicks_field=>3u64))?;
+        index_writer.update_numeric(Term::from_field_u64(id_field, 1), score_field, 10u64)?;
+        index_writer.update_numeric(Term::from_field_u64(id_field, 3), score_field, 30u64)?;
+        // Documents added after the update are not affected.
+        index_writer.update_numeric(Term::from_field_u64(id_field, 4), score_field, 40u64)?;
+        index_writer.add_document(doc!(id_field=>4u64, score_field=>4u64))?;
+        index_writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        assert_eq!(value_by_key(&searcher, 1, "score"), vec![10]);
+        assert_eq!(value_by_key(&searcher, 1, "clicks"), vec![1]);
+        assert_eq!(value_by_key(&searcher, 2, "score"), vec![2]);
+        assert_eq!(value_by_key(&searcher, 3, "score"), vec![30]);
+        assert_eq!(value_by_key(&searcher, 3, "clicks"), vec![3]);
+        assert_eq!(value_by_key(&searcher, 4, "score"), vec![4]);
+
+        // Previous updates of the segment are kept.
+        index_writer.update_numeric(Term::from_field_u64(id_field, 1), clicks_field, 11u64)?;
+        index_writer.commit()?;
+        reader.reload()?;
+        let searcher = reader.searcher();
+        assert_eq!(value_by_key(&searcher, 1, "score"), vec![10]);
+        assert_eq!(value_by_key(&searcher, 1, "clicks"), vec![11]);
+
+        // Updates are folded into the fast fields on merge.
+        let segment_ids: Vec<SegmentId> = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        reader.reload()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        assert!(searcher.segment_readers()[0]
+            .fast_fields()
+            .numeric_updates()
+            .is_none());
+        assert_eq!(value_by_key(&searcher, 1, "score"), vec![10]);
+        assert_eq!(value_by_key(&searcher, 1, "clicks"), vec![11]);
+        assert_eq!(value_by_key(&searcher, 2, "score"), vec![2]);
+        assert_eq!(value_by_key(&searcher, 3, "score"), vec![30]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_numeric_only_writes_updated_values() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let score_field = schema_builder.add_u64_field("score", FAST);
+        let clicks_field = schema_builder.add_u64_field("clicks", FAST);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for id in 0..100u64 {
+            index_writer.add_document(doc!(id_field=>id, score_field=>id, clicks_field=>id))?;
+        }
+        index_writer.commit()?;
+        index_writer.update_numeric(Term::from_field_u64(id_field, 10), score_field, 1000u64)?;
+        index_writer.commit()?;
+        index_writer.update_numeric(Term::from_field_u64(id_field, 20), score_field, 2000u64)?;
+        index_writer.update_numeric(Term::from_field_u64(id_field, 30), clicks_field, 3000u64)?;
+        index_writer.commit()?;
+
+        let segment_metas = index.searchable_segment_metas()?;
+        assert_eq!(segment_metas.len(), 1);
+        let segment = index.segment(segment_metas[0].clone());
+        let numeric_updates =
+            ColumnarReader::open(segment.open_read(SegmentComponent::NumericUpdates)?)?;
+        let num_vals_per_column: Vec<(String, u32)> = numeric_updates
+            .list_columns()?
+            .into_iter()
+            .map(|(column_name, column_handle)| {
+                let column = column_handle.open_u64_lenient().unwrap().unwrap();
+                (column_name, column.values.num_vals())
+            })
+            .collect();
+        assert_eq!(
+            num_vals_per_column,
+            vec![("clicks".to_string(), 1), ("score".to_string(), 2)]
+        );
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(value_by_key(&searcher, 10, "score"), vec![1000]);
+        assert_eq!(value_by_key(&searcher, 20, "score"), vec![2000]);
+        assert_eq!(value_by_key(&searcher, 30, "score"), vec![30]);
+        assert_eq!(value_by_key(&searcher, 30, "clicks"), vec![3000]);
+        assert_eq!(value_by_key(&searcher, 40, "clicks"), vec![40]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_updates_are_shared_across_reloads() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let score_field = schema_builder.add_u64_field("score", FAST);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for id in 0..100u64 {
+            index_writer.add_document(doc!(id_field=>id, score_field=>id))?;
+        }
+        index_writer.commit()?;
+        index_writer.update_numeric(Term::from_field_u64(id_field, 10), score_field, 1000u64)?;
+        index_writer.commit()?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        let numeric_updates = |searcher: &Searcher| {
+            searcher.segment_readers()[0]
+                .fast_fields()
+                .numeric_updates()
+                .map(|numeric_updates| numeric_updates as *const ColumnarReader)
+        };
+        assert!(numeric_updates(&searcher).is_some());
+
+        // The segment is reopened, but its numeric updates are not applied again.
+        index_writer.add_document(doc!(id_field=>100u64, score_field=>100u64))?;
+        index_writer.commit()?;
+        reader.reload()?;
+        let reloaded_searcher = reader.searcher();
+        assert_eq!(reloaded_searcher.segment_readers().len(), 2);
+        assert_eq!(
+            numeric_updates(&reloaded_searcher),
+            numeric_updates(&searcher)
+        );
+
+        // New updates get applied.
+        index_writer.update_numeric(Term::from_field_u64(id_field, 20), score_field, 2000u64)?;
+        index_writer.commit()?;
+        reader.reload()?;
+        let updated_searcher = reader.searcher();
+        assert_ne!(
+            numeric_updates(&updated_searcher),
+            numeric_updates(&searcher)
+        );
+        assert_eq!(value_by_key(&updated_searcher, 10, "score"), vec![1000]);
+        assert_eq!(value_by_key(&updated_searcher, 20, "score"), vec![2000]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_numeric_invalid() {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let score_field = schema_builder.add_u64_field("score", FAST);
+        let indexed_score_field = schema_builder.add_u64_field("indexed_score", INDEXED | FAST);
+        let stored_score_field = schema_builder.add_u64_field("stored_score", STORED | FAST);
+        let rank_field = schema_builder.add_f64_field("rank", FAST);
+        schema_builder.set_unique_key_field(id_field);
+        let index = Index::create_in_ram(schema_builder.build());
+        let index_writer: IndexWriter = index.writer_for_tests().unwrap();
+        let key = Term::from_field_u64(id_field, 1);
+        for (key, field, value) in [
+            (
+                Term::from_field_u64(score_field, 1),
+                score_field,
+                NumericalValue::U64(1),
+            ),
+            (key.clone(), indexed_score_field, NumericalValue::U64(1)),
+            (key.clone(), stored_score_field, NumericalValue::U64(1)),
+            (key.clone(), score_field, NumericalValue::I64(-1)),
+            (key.clone(), score_field, NumericalValue::F64(1.5)),
+        ] {
+            assert!(matches!(
+                index_writer.update_numeric(key, field, value),
+                Err(TantivyError::InvalidArgument(_))
+            ));
+        }
+        assert!(index_writer
+            .update_numeric(key.clone(), score_field, 1i64)
+            .is_ok());
+        assert!(index_writer.update_numeric(key, rank_field, 1u64).is_ok());
+    }
+}
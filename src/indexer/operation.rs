@@ -1,12 +1,33 @@
+use columnar::NumericalValue;
+
 use crate::query::Weight;
 use crate::schema::document::Document;
-use crate::schema::{TantivyDocument, Term};
+use crate::schema::{Field, TantivyDocument, Term};
 use crate::Opstamp;
 
+/// Documents targeted by a delete operation.
+pub enum DeleteTarget {
+    /// Documents matching a query.
+    Query(Box<dyn Weight>),
+    /// Documents with a given value of the unique key field.
+    ///
+    /// The documents are looked up directly in the postings of the key.
+    Key(Term),
+    /// Not an actual delete: sets the value of a numeric fast field of the documents
+    /// with a given value of the unique key field.
+    ///
+    /// Updates go through the delete queue to be applied to the documents added before them.
+    NumericUpdate {
+        key: Term,
+        field: Field,
+        value: NumericalValue,
+    },
+}
+
 /// Timestamped Delete operation.
 pub struct DeleteOperation {
     pub opstamp: Opstamp,
-    pub target: Box<dyn Weight>,
+    pub target: DeleteTarget,
 }
 
 /// Timestamped Add operation.
@@ -23,4 +44,9 @@ pub enum UserOperation<D: Document = TantivyDocument> {
     Add(D),
     /// Delete operation
     Delete(Term),
+    /// Upsert operation
+    ///
+    /// Replaces the document with the same unique key, if any.
+    /// See [`IndexWriter::upsert_document`](crate::IndexWriter::upsert_document).
+    Upsert(D),
 }
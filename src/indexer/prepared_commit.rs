@@ -46,8 +46,9 @@ impl<'a, D: Document> PreparedCommit<'a, D> {
     /// At this point deletes have not been flushed yet.
     pub fn commit_future(self) -> FutureResult<Opstamp> {
         info!("committing {}", self.opstamp);
+        let limits = self.index_writer.limit_tracker();
         self.index_writer
             .segment_updater()
-            .schedule_commit(self.opstamp, self.payload)
+            .schedule_commit(self.opstamp, self.payload, limits)
     }
 }
@@ -0,0 +1,387 @@
+//! Helpers used by the merger to rewrite the segments written with a previous version of
+//! the schema.
+//!
+//! See [`IndexWriter::update_schema`](crate::IndexWriter::update_schema). The fast fields of
+//! segments with numeric updates get rebuilt the same way, see
+//! [`IndexWriter::update_numeric`](crate::IndexWriter::update_numeric).
+
+use columnar::{ColumnarReader, DynamicColumn};
+use common::json_path_writer::JSON_PATH_SEGMENT_SEP_STR;
+
+use crate::error::DataCorruption;
+use crate::fastfield::FastFieldsWriter;
+use crate::index::SegmentReader;
+use crate::schema::{Facet, Field, OwnedValue, Schema, TantivyDocument, Type};
+use crate::store::StoreReader;
+use crate::tokenizer::TokenizerManager;
+use crate::DocId;
+
+/// Returns true if the column `column_name` belongs to a fast field of `schema`.
+pub(crate) fn is_fast_column(schema: &Schema, column_name: &str) -> bool {
+    let field_name = column_name
+        .split(JSON_PATH_SEGMENT_SEP_STR)
+        .next()
+        .unwrap_or(column_name);
+    schema
+        .get_field(field_name)
+        .map(|field| schema.get_field_entry(field).is_fast())
+        .unwrap_or(false)
+}
+
+/// Rebuilds the fast fields of a segment written with a previous version of the schema, or
+/// with numeric updates.
+///
+/// The columns of the fields that have been dropped are removed, and the fields that have been
+/// made fast are populated from their stored values. The columns rewritten by numeric updates
+/// replace the original ones.
+///
+/// Returns `None` if the fast fields of the segment can be merged as is.
+pub(crate) fn rebuild_fast_fields(
+    reader: &SegmentReader,
+    schema: &Schema,
+    fast_field_tokenizers: &TokenizerManager,
+) -> crate::Result<Option<ColumnarReader>> {
+    let has_numeric_updates = reader.fast_fields().numeric_updates().is_some();
+    let backfilled_fields: Vec<Field> = match reader.field_mapping() {
+        Some(field_mapping) => reader
+            .segment_schema()
+            .fields()
+            .filter(|(_, old_field_entry)| {
+                !old_field_entry.is_fast() && old_field_entry.is_stored()
+            })
+            .filter_map(|(old_field, _)| field_mapping.map(old_field))
+            .filter(|&field| schema.get_field_entry(field).is_fast())
+            .collect(),
+        None if has_numeric_updates => Vec::new(),
+        None => return Ok(None),
+    };
+    let columnar = reader.fast_fields().columnar();
+    let columns = reader.fast_fields().list_columns()?;
+    if backfilled_fields.is_empty()
+        && !has_numeric_updates
+        && columns
+            .iter()
+            .all(|(column_name, _)| is_fast_column(schema, column_name))
+    {
+        return Ok(None);
+    }
+    let mut fast_fields_writer =
+        FastFieldsWriter::from_schema_and_tokenizer_manager(schema, fast_field_tokenizers.clone())?;
+    for (column_name, column_handle) in &columns {
+        if is_fast_column(schema, column_name) {
+            fast_fields_writer.record_column(
+                column_name,
+                &column_handle.open()?,
+                columnar.num_rows(),
+            )?;
+        }
+    }
+    let store_reader = reader.get_store_reader(1)?;
+    for doc_id in 0..reader.max_doc() {
+        let mut doc = TantivyDocument::default();
+        if !backfilled_fields.is_empty() {
+            let stored_doc: TantivyDocument = store_reader.get(doc_id)?;
+            for (field, value) in stored_doc.field_values() {
+                if backfilled_fields.contains(&field) {
+                    doc.add_field_value(field, value);
+                }
+            }
+        }
+        fast_fields_writer.add_document(&doc)?;
+    }
+    let mut fast_fields_data = Vec::new();
+    fast_fields_writer.serialize(&mut fast_fields_data)?;
+    Ok(Some(ColumnarReader::open(fast_fields_data)?))
+}
+
+/// Reads the stored documents of a segment written with a previous version of the schema.
+///
+/// Fields that have been dropped are removed, and fields that have been made stored are
+/// populated from their fast field values. As fast field values are normalized by the fast
+/// field tokenizer, text fields get backfilled with their normalized value.
+pub(crate) struct MigratedStoreReader {
+    store_reader: StoreReader,
+    backfilled_columns: Vec<(Field, DynamicColumn, bool)>,
+}
+
+impl MigratedStoreReader {
+    /// Opens the stored documents of `reader`, exposed through `schema`.
+    pub fn open(
+        reader: &SegmentReader,
+        schema: &Schema,
+        cache_num_blocks: usize,
+    ) -> crate::Result<MigratedStoreReader> {
+        let store_reader = reader.get_store_reader(cache_num_blocks)?;
+        let mut backfilled_columns = Vec::new();
+        if let Some(field_mapping) = reader.field_mapping() {
+            for (old_field, old_field_entry) in reader.segment_schema().fields() {
+                let Some(field) = field_mapping.map(old_field) else {
+                    continue;
+                };
+                let field_entry = schema.get_field_entry(field);
+                let value_type = field_entry.field_type().value_type();
+                if old_field_entry.is_stored()
+                    || !old_field_entry.is_fast()
+                    || !field_entry.is_stored()
+                    || value_type == Type::Json
+                {
+                    continue;
+                }
+                let column_handles = reader
+                    .fast_fields()
+                    .dynamic_column_handles(field_entry.name())?;
+                for column_handle in column_handles {
+                    backfilled_columns.push((
+                        field,
+                        column_handle.open()?,
+                        value_type == Type::Facet,
+                    ));
+                }
+            }
+        }
+        Ok(MigratedStoreReader {
+            store_reader,
+            backfilled_columns,
+        })
+    }
+
+    /// Returns the stored document `doc_id`.
+    pub fn get(&self, doc_id: DocId) -> crate::Result<TantivyDocument> {
+        let mut doc: TantivyDocument = self.store_reader.get(doc_id)?;
+        let mut buffer = Vec::new();
+        for (field, column, is_facet) in &self.backfilled_columns {
+            let values: Vec<OwnedValue> = match column {
+                DynamicColumn::Bool(column) => column
+                    .values_for_doc(doc_id)
+                    .map(OwnedValue::Bool)
+                    .collect(),
+                DynamicColumn::I64(column) => {
+                    column.values_for_doc(doc_id).map(OwnedValue::I64).collect()
+                }
+                DynamicColumn::U64(column) => {
+                    column.values_for_doc(doc_id).map(OwnedValue::U64).collect()
+                }
+                DynamicColumn::F64(column) => {
+                    column.values_for_doc(doc_id).map(OwnedValue::F64).collect()
+                }
+                DynamicColumn::IpAddr(column) => column
+                    .values_for_doc(doc_id)
+                    .map(OwnedValue::IpAddr)
+                    .collect(),
+                DynamicColumn::DateTime(column) => column
+                    .values_for_doc(doc_id)
+                    .map(OwnedValue::Date)
+                    .collect(),
+                DynamicColumn::Bytes(column) => {
+                    let mut values = Vec::new();
+                    for term_ord in column.term_ords(doc_id) {
+                        buffer.clear();
+                        column.ord_to_bytes(term_ord, &mut buffer)?;
+                        values.push(OwnedValue::Bytes(buffer.clone()));
+                    }
+                    values
+                }
+                DynamicColumn::Str(column) => {
+                    let mut values = Vec::new();
+                    for term_ord in column.term_ords(doc_id) {
+                        buffer.clear();
+                        column.ord_to_bytes(term_ord, &mut buffer)?;
+                        let value = if *is_facet {
+                            OwnedValue::Facet(Facet::from_encoded(buffer.clone()).map_err(
+                                |_| DataCorruption::comment_only("Facet is not valid utf-8."),
+                            )?)
+                        } else {
+                            OwnedValue::Str(String::from_utf8_lossy(&buffer).into_owned())
+                        };
+                        values.push(value);
+                    }
+                    values
+                }
+            };
+            for value in &values {
+                doc.add_field_value(*field, value);
+            }
+        }
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collector::{Count, TopDocs};
+    use crate::directory::RamDirectory;
+    use crate::indexer::NoMergePolicy;
+    use crate::query::{AllQuery, TermQuery};
+    use crate::schema::{
+        IndexRecordOption, Schema, TantivyDocument, Value, FAST, INDEXED, STORED, STRING, TEXT,
+    };
+    use crate::{Index, IndexWriter, Order, Term};
+
+    fn initial_schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_u64_field("rank", INDEXED | STORED);
+        schema_builder.add_u64_field("score", FAST);
+        schema_builder.add_text_field("tag", STRING | STORED);
+        schema_builder.build()
+    }
+
+    fn updated_schema() -> Schema {
+        // `tag` is dropped, `rank` is made fast, `score` is made stored, and `body` is added.
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_text_field("body", TEXT | STORED);
+        schema_builder.add_u64_field("rank", INDEXED | STORED | FAST);
+        schema_builder.add_u64_field("score", FAST | STORED);
+        schema_builder.build()
+    }
+
+    fn create_index_with_two_segments(directory: RamDirectory) -> crate::Result<Index> {
+        let schema = initial_schema();
+        let index = Index::create(directory, schema.clone(), Default::default())?;
+        let title = schema.get_field("title").unwrap();
+        let rank = schema.get_field("rank").unwrap();
+        let score = schema.get_field("score").unwrap();
+        let tag = schema.get_field("tag").unwrap();
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        for i in 0..2u64 {
+            index_writer.add_document(doc!(
+                title => format!("first {i}"),
+                rank => i,
+                score => 10 + i,
+                tag => "red",
+            ))?;
+            index_writer.commit()?;
+        }
+        Ok(index)
+    }
+
+    #[test]
+    fn test_update_schema_add_and_drop_fields() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let index = create_index_with_two_segments(directory.clone())?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let schema = updated_schema();
+        index_writer.update_schema(schema.clone())?;
+        assert_eq!(index.schema_version(), 1);
+        assert_eq!(index.schema(), schema);
+
+        let title = schema.get_field("title").unwrap();
+        let body = schema.get_field("body").unwrap();
+        index_writer.add_document(doc!(title => "second", body => "hello"))?;
+        index_writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.schema(), &schema);
+        assert_eq!(searcher.segment_readers().len(), 3);
+        let first_query = TermQuery::new(
+            Term::from_field_text(title, "first"),
+            IndexRecordOption::WithFreqs,
+        );
+        assert_eq!(searcher.search(&first_query, &Count)?, 2);
+        let hello_query = TermQuery::new(
+            Term::from_field_text(body, "hello"),
+            IndexRecordOption::WithFreqs,
+        );
+        assert_eq!(searcher.search(&hello_query, &Count)?, 1);
+        // The dropped field is hidden from the stored documents.
+        let top_docs = searcher.search(&first_query, &TopDocs::with_limit(2))?;
+        for (_, doc_address) in top_docs {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            assert_eq!(doc.field_values().count(), 2);
+            assert!(doc
+                .get_first(title)
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .starts_with("first"));
+        }
+
+        // Segments written with the previous schema can still be read after reopening the index.
+        let reopened_index = Index::open(directory)?;
+        assert_eq!(reopened_index.schema_version(), 1);
+        let reopened_searcher = reopened_index.reader()?.searcher();
+        assert_eq!(reopened_searcher.search(&first_query, &Count)?, 2);
+        assert_eq!(reopened_searcher.search(&hello_query, &Count)?, 1);
+
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        let metas = index.load_metas()?;
+        assert_eq!(metas.segments.len(), 1);
+        assert_eq!(metas.segments[0].schema_version(), 1);
+        assert!(metas.previous_schemas.is_empty());
+        reader.reload()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 3);
+        assert_eq!(searcher.search(&first_query, &Count)?, 2);
+        assert_eq!(searcher.search(&hello_query, &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_schema_backfill_on_merge() -> crate::Result<()> {
+        let index = create_index_with_two_segments(RamDirectory::create())?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        let schema = updated_schema();
+        index_writer.update_schema(schema.clone())?;
+        let rank = schema.get_field("rank").unwrap();
+        let score = schema.get_field("score").unwrap();
+
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+
+        let searcher = index.reader()?.searcher();
+        let segment_reader = searcher.segment_reader(0);
+        assert!(segment_reader
+            .fast_fields()
+            .columnar()
+            .read_columns("tag")?
+            .is_empty());
+        let rank_column = segment_reader.fast_fields().u64("rank")?;
+        let top_docs = searcher.search(
+            &AllQuery,
+            &TopDocs::with_limit(2).order_by_fast_field::<u64>("rank", Order::Asc),
+        )?;
+        assert_eq!(top_docs.len(), 2);
+        for (expected_rank, (rank_value, doc_address)) in top_docs.into_iter().enumerate() {
+            assert_eq!(rank_value, expected_rank as u64);
+            assert_eq!(rank_column.first(doc_address.doc_id), Some(rank_value));
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            assert_eq!(doc.get_first(rank).unwrap().as_u64(), Some(rank_value));
+            assert_eq!(
+                doc.get_first(score).unwrap().as_u64(),
+                Some(10 + rank_value)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_schema_invalid() -> crate::Result<()> {
+        let index = create_index_with_two_segments(RamDirectory::create())?;
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", STRING);
+        assert!(index_writer.update_schema(schema_builder.build()).is_err());
+        assert_eq!(index.schema_version(), 0);
+
+        // Dropping `tag`, and adding it back while some segments still hold its data.
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_u64_field("rank", INDEXED | STORED);
+        schema_builder.add_u64_field("score", FAST);
+        let dropped_schema = schema_builder.build();
+        index_writer.update_schema(dropped_schema)?;
+        assert!(index_writer.update_schema(initial_schema()).is_err());
+        assert_eq!(index.schema_version(), 1);
+        Ok(())
+    }
+}
@@ -145,36 +145,35 @@ impl SegmentManager {
 
     /// Marks a list of segments as in merge.
     ///
+    /// Returns the segment entries, together with whether they are committed or not.
     /// Returns an error if some segments are missing, or if
     /// the `segment_ids` are not either all committed or all
     /// uncommitted.
-    pub fn start_merge(&self, segment_ids: &[SegmentId]) -> crate::Result<Vec<SegmentEntry>> {
+    pub fn start_merge(
+        &self,
+        segment_ids: &[SegmentId],
+    ) -> crate::Result<(Vec<SegmentEntry>, SegmentsStatus)> {
         let registers_lock = self.read();
-        let mut segment_entries = vec![];
-        if registers_lock.uncommitted.contains_all(segment_ids) {
-            for segment_id in segment_ids {
-                let segment_entry = registers_lock.uncommitted.get(segment_id).expect(
-                    "Segment id not found {}. Should never happen because of the contains all \
-                     if-block.",
-                );
-                segment_entries.push(segment_entry);
-            }
-        } else if registers_lock.committed.contains_all(segment_ids) {
-            for segment_id in segment_ids {
-                let segment_entry = registers_lock.committed.get(segment_id).expect(
-                    "Segment id not found {}. Should never happen because of the contains all \
-                     if-block.",
-                );
-                segment_entries.push(segment_entry);
-            }
-        } else {
+        let segments_status = registers_lock.segments_status(segment_ids).ok_or_else(|| {
             let error_msg = "Merge operation sent for segments that are not all uncommitted or \
                              committed."
                 .to_string();
-            return Err(TantivyError::InvalidArgument(error_msg));
-        }
-
-        Ok(segment_entries)
+            TantivyError::InvalidArgument(error_msg)
+        })?;
+        let register = match segments_status {
+            SegmentsStatus::Uncommitted => &registers_lock.uncommitted,
+            SegmentsStatus::Committed => &registers_lock.committed,
+        };
+        let segment_entries = segment_ids
+            .iter()
+            .map(|segment_id| {
+                register.get(segment_id).expect(
+                    "Segment id not found. Should never happen because of the segments status \
+                     check.",
+                )
+            })
+            .collect();
+        Ok((segment_entries, segments_status))
     }
 
     pub fn add_segment(&self, segment_entry: SegmentEntry) {
@@ -125,20 +125,20 @@ mod tests {
         let segment_id_merged = SegmentId::generate_random();
 
         {
-            let segment_meta = inventory.new_segment_meta(segment_id_a, 0u32);
+            let segment_meta = inventory.new_segment_meta(segment_id_a, 0u32, 0);
             let segment_entry = SegmentEntry::new(segment_meta, delete_queue.cursor(), None);
             segment_register.add_segment_entry(segment_entry);
         }
         assert_eq!(segment_ids(&segment_register), vec![segment_id_a]);
         {
-            let segment_meta = inventory.new_segment_meta(segment_id_b, 0u32);
+            let segment_meta = inventory.new_segment_meta(segment_id_b, 0u32, 0);
             let segment_entry = SegmentEntry::new(segment_meta, delete_queue.cursor(), None);
             segment_register.add_segment_entry(segment_entry);
         }
         segment_register.remove_segment(&segment_id_a);
         segment_register.remove_segment(&segment_id_b);
         {
-            let segment_meta_merged = inventory.new_segment_meta(segment_id_merged, 0u32);
+            let segment_meta_merged = inventory.new_segment_meta(segment_id_merged, 0u32, 0);
             let segment_entry = SegmentEntry::new(segment_meta_merged, delete_queue.cursor(), None);
             segment_register.add_segment_entry(segment_entry);
         }
@@ -18,10 +18,22 @@ pub struct SegmentSerializer {
 
 impl SegmentSerializer {
     /// Creates a new `SegmentSerializer`.
-    pub fn for_segment(mut segment: Segment) -> crate::Result<SegmentSerializer> {
+    ///
+    /// When writing a new segment of a sorted index, the documents are stored in the
+    /// `TempStore` first, and written to the `Store` in sort order when the segment is
+    /// finalized. Merges already receive their documents in sort order.
+    pub fn for_segment(
+        mut segment: Segment,
+        is_in_merge: bool,
+    ) -> crate::Result<SegmentSerializer> {
         let settings = segment.index().settings().clone();
         let store_writer = {
-            let store_write = segment.open_write(SegmentComponent::Store)?;
+            let store_component = if !settings.sort_by_fields.is_empty() && !is_in_merge {
+                SegmentComponent::TempStore
+            } else {
+                SegmentComponent::Store
+            };
+            let store_write = segment.open_write(store_component)?;
             StoreWriter::new(
                 store_write,
                 settings.docstore_compression,
@@ -54,6 +66,10 @@ impl SegmentSerializer {
         &self.segment
     }
 
+    pub fn segment_mut(&mut self) -> &mut Segment {
+        &mut self.segment
+    }
+
     /// Accessor to the `PostingsSerializer`.
     pub fn get_postings_serializer(&mut self) -> &mut InvertedIndexSerializer {
         &mut self.postings_serializer
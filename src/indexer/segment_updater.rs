@@ -4,29 +4,38 @@ use std::io::Write;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use super::segment_manager::SegmentManager;
 use crate::core::META_FILEPATH;
+use crate::directory::error::DeleteError;
 use crate::directory::{Directory, DirectoryClone, GarbageCollectionResult};
 use crate::fastfield::AliveBitSet;
-use crate::index::{Index, IndexMeta, IndexSettings, Segment, SegmentId, SegmentMeta};
+use crate::index::{
+    commit_path, Index, IndexCommit, IndexMeta, IndexSettings, Segment, SegmentId, SegmentMeta,
+};
 use crate::indexer::delete_queue::DeleteCursor;
 use crate::indexer::index_writer::advance_deletes;
+use crate::indexer::index_writer_limits::LimitTracker;
 use crate::indexer::merge_operation::MergeOperationInventory;
+use crate::indexer::merge_policy::MergePolicyContext;
+use crate::indexer::merge_scheduler::{IoThrottle, MergeScheduler};
 use crate::indexer::merger::IndexMerger;
 use crate::indexer::segment_manager::SegmentsStatus;
 use crate::indexer::stamper::Stamper;
+use crate::indexer::write_ahead_log::{write_ahead_log_path, WriteAheadLog};
 use crate::indexer::{
-    DefaultMergePolicy, MergeCandidate, MergeOperation, MergePolicy, SegmentEntry,
+    CommitDeletionPolicy, DefaultMergePolicy, InFlightMerge, KeepLastCommit, MergeCandidate,
+    MergeOperation, MergePolicy, MergePriority, MergeSchedulerSettings, SegmentEntry,
     SegmentSerializer,
 };
+use crate::reader::NrtSegments;
+use crate::schema::Schema;
 use crate::{FutureResult, Opstamp};
 
-const NUM_MERGE_THREADS: usize = 4;
-
 /// Save the index meta file.
 /// This operation is atomic:
 /// Either
@@ -83,12 +92,29 @@ fn garbage_collect_files(
         .garbage_collect(move || segment_updater.list_files())
 }
 
+/// Returns the opstamp of the last operation that `delete_cursor` has to consume
+/// to catch up with `target_delete_cursor`, if it is behind.
+fn catch_up_opstamp(
+    delete_cursor: &DeleteCursor,
+    target_delete_cursor: &DeleteCursor,
+) -> Option<Opstamp> {
+    let mut delete_cursor = delete_cursor.clone();
+    let mut opstamp = None;
+    while delete_cursor.is_behind(target_delete_cursor) {
+        opstamp = delete_cursor.get().map(|operation| operation.opstamp);
+        delete_cursor.advance();
+    }
+    opstamp
+}
+
 /// Merges a list of segments the list of segment givens in the `segment_entries`.
 /// This function happens in the calling thread and is computationally expensive.
 fn merge(
     index: &Index,
     mut segment_entries: Vec<SegmentEntry>,
+    segments_status: SegmentsStatus,
     target_opstamp: Opstamp,
+    io_throttle: &Arc<IoThrottle>,
 ) -> crate::Result<Option<SegmentEntry>> {
     let num_docs = segment_entries
         .iter()
@@ -98,16 +124,48 @@ fn merge(
         return Ok(None);
     }
 
-    // first we need to apply deletes to our segment.
-    let merged_segment = index.new_segment();
-
-    // First we apply all of the delete to the merged segment, up to the target opstamp.
-    for segment_entry in &mut segment_entries {
-        let segment = index.segment(segment_entry.meta().clone());
-        advance_deletes(segment, segment_entry, target_opstamp)?;
-    }
+    // The merged segment is written with the current version of the schema.
+    let (schema_version, schema) = index.versioned_schema();
 
-    let delete_cursor = segment_entries[0].delete_cursor().clone();
+    // first we need to apply deletes to our segment.
+    let merged_segment = index
+        .segment(index.new_segment_meta_with_schema_version(
+            SegmentId::generate_random(),
+            0,
+            schema_version,
+        ))
+        .with_io_throttle(io_throttle.clone());
+
+    let delete_cursor = match segments_status {
+        SegmentsStatus::Uncommitted => {
+            // Uncommitted segments may have been created at different points of the delete
+            // queue. The merged segment takes the most advanced delete cursor, and the deletes
+            // that the other segments are lagging behind are applied to them.
+            let mut delete_cursor = segment_entries[0].delete_cursor().clone();
+            for segment_entry in &mut segment_entries[1..] {
+                if delete_cursor.is_behind(segment_entry.delete_cursor()) {
+                    delete_cursor = segment_entry.delete_cursor().clone();
+                }
+            }
+            for segment_entry in &mut segment_entries {
+                let segment = index.segment(segment_entry.meta().clone());
+                let segment_target_opstamp =
+                    catch_up_opstamp(segment_entry.delete_cursor(), &delete_cursor)
+                        .map_or(target_opstamp, |opstamp| opstamp.max(target_opstamp));
+                advance_deletes(segment, segment_entry, segment_target_opstamp)?;
+            }
+            delete_cursor
+        }
+        SegmentsStatus::Committed => {
+            // The deletes of committed segments are persisted by the merge: they must not
+            // go beyond the target opstamp, as the following deletes may be rolled back.
+            for segment_entry in &mut segment_entries {
+                let segment = index.segment(segment_entry.meta().clone());
+                advance_deletes(segment, segment_entry, target_opstamp)?;
+            }
+            segment_entries[0].delete_cursor().clone()
+        }
+    };
 
     let segments: Vec<Segment> = segment_entries
         .iter()
@@ -115,19 +173,67 @@ fn merge(
         .collect();
 
     // An IndexMerger is like a "view" of our merged segments.
-    let merger: IndexMerger = IndexMerger::open(index.schema(), &segments[..])?;
+    let merger: IndexMerger = IndexMerger::open(schema, index.settings().clone(), &segments[..])?;
 
     // ... we just serialize this index merger in our new segment to merge the segments.
-    let segment_serializer = SegmentSerializer::for_segment(merged_segment.clone())?;
+    let segment_serializer = SegmentSerializer::for_segment(merged_segment.clone(), true)?;
 
     let num_docs = merger.write(segment_serializer)?;
 
-    let merged_segment_id = merged_segment.id();
-
-    let segment_meta = index.new_segment_meta(merged_segment_id, num_docs);
+    let segment_meta = merged_segment
+        .with_max_doc(num_docs)
+        .with_file_stats()?
+        .meta()
+        .clone();
     Ok(Some(SegmentEntry::new(segment_meta, delete_cursor, None)))
 }
 
+/// Groups the segments into at most `max_segments` groups of balanced byte sizes.
+///
+/// Only the groups of more than one segment are returned: they are the merges reducing
+/// the segments to `max_segments` segments.
+fn plan_force_merge(
+    segments: &[SegmentMeta],
+    max_segments: usize,
+    context: &MergePolicyContext,
+) -> Vec<Vec<SegmentId>> {
+    if segments.len() <= max_segments {
+        return Vec::new();
+    }
+    let mut size_sorted_segments: Vec<(u64, SegmentId)> = segments
+        .iter()
+        .map(|segment| (context.segment_num_bytes(segment), segment.id()))
+        .collect();
+    size_sorted_segments.sort_by_key(|(num_bytes, _)| std::cmp::Reverse(*num_bytes));
+    // Each segment goes to the smallest group so far.
+    let mut groups: Vec<(u64, Vec<SegmentId>)> = vec![(0, Vec::new()); max_segments];
+    for (num_bytes, segment_id) in size_sorted_segments {
+        let (group_num_bytes, group) = groups
+            .iter_mut()
+            .min_by_key(|(group_num_bytes, _)| *group_num_bytes)
+            .expect("max_segments is required to be non-zero");
+        *group_num_bytes += num_bytes;
+        group.push(segment_id);
+    }
+    groups
+        .into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// Returns the segments whose ratio of deleted documents is at least `min_deleted_ratio`.
+fn plan_expunge_deletes(segments: &[SegmentMeta], min_deleted_ratio: f32) -> Vec<Vec<SegmentId>> {
+    segments
+        .iter()
+        .filter(|segment| {
+            segment.has_deletes()
+                && segment.num_deleted_docs() as f32 / segment.max_doc() as f32 >= min_deleted_ratio
+        })
+        .map(|segment| vec![segment.id()])
+        .collect()
+}
+
 /// Advanced: Merges a list of segments from different indices in a new index.
 ///
 /// Returns `TantivyError` if the indices list is empty or their
@@ -219,12 +325,20 @@ pub fn merge_filtered_segments<T: Into<Box<dyn Directory>>>(
     )?;
     let merged_segment = merged_index.new_segment();
     let merged_segment_id = merged_segment.id();
-    let merger: IndexMerger =
-        IndexMerger::open_with_custom_alive_set(merged_index.schema(), segments, filter_doc_ids)?;
-    let segment_serializer = SegmentSerializer::for_segment(merged_segment)?;
+    let merger: IndexMerger = IndexMerger::open_with_custom_alive_set(
+        merged_index.schema(),
+        merged_index.settings().clone(),
+        segments,
+        filter_doc_ids,
+    )?;
+    let segment_serializer = SegmentSerializer::for_segment(merged_segment, true)?;
     let num_docs = merger.write(segment_serializer)?;
 
-    let segment_meta = merged_index.new_segment_meta(merged_segment_id, num_docs);
+    let segment_meta = merged_index
+        .segment(merged_index.new_segment_meta(merged_segment_id, num_docs))
+        .with_file_stats()?
+        .meta()
+        .clone();
 
     let stats = format!(
         "Segments Merge: [{}]",
@@ -241,8 +355,11 @@ pub fn merge_filtered_segments<T: Into<Box<dyn Directory>>>(
         index_settings: target_settings, // index_settings of all segments should be the same
         segments: vec![segment_meta],
         schema: target_schema,
+        schema_version: 0,
+        previous_schemas: Vec::new(),
         opstamp: 0u64,
         payload: Some(stats),
+        commit_timestamp: None,
     };
 
     // save the meta.json
@@ -260,14 +377,52 @@ pub(crate) struct InnerSegmentUpdater {
     // the unique active `SegmentUpdater`.
     active_index_meta: RwLock<Arc<IndexMeta>>,
     pool: ThreadPool,
-    merge_thread_pool: ThreadPool,
+    merge_scheduler: Arc<MergeScheduler>,
 
     index: Index,
     segment_manager: SegmentManager,
     merge_policy: RwLock<Arc<dyn MergePolicy>>,
+    commit_deletion_policy: RwLock<Arc<dyn CommitDeletionPolicy>>,
+    // The commits preceding the last one that are kept by the commit deletion policy.
+    historical_commits: RwLock<Vec<IndexCommit>>,
     killed: AtomicBool,
     stamper: Stamper,
     merge_operations: MergeOperationInventory,
+    // Force merges waiting for the running merges to end. The merges of the merge policy
+    // do not start as long as a force merge is pending.
+    pending_force_merges: Mutex<Vec<PendingForceMerge>>,
+    write_ahead_log: Option<Arc<WriteAheadLog>>,
+    nrt_segments: NrtSegments,
+}
+
+/// A force merge waiting for the running merges to end before planning its merges.
+struct PendingForceMerge {
+    max_segments: usize,
+    result_sender: oneshot::Sender<crate::Result<()>>,
+}
+
+/// Sends the outcome of a group of merges once all of them are over, that is once
+/// the last reference to it is dropped.
+struct MergeGroupResult {
+    result: Mutex<crate::Result<()>>,
+    result_sender: Option<oneshot::Sender<crate::Result<()>>>,
+}
+
+impl MergeGroupResult {
+    fn record(&self, merge_result: crate::Result<Option<SegmentMeta>>) {
+        if let Err(merge_error) = merge_result {
+            *self.result.lock().unwrap() = Err(merge_error);
+        }
+    }
+}
+
+impl Drop for MergeGroupResult {
+    fn drop(&mut self) {
+        if let Some(result_sender) = self.result_sender.take() {
+            let result = std::mem::replace(self.result.get_mut().unwrap(), Ok(()));
+            let _ = result_sender.send(result);
+        }
+    }
 }
 
 impl SegmentUpdater {
@@ -275,6 +430,8 @@ impl SegmentUpdater {
         index: Index,
         stamper: Stamper,
         delete_cursor: &DeleteCursor,
+        write_ahead_log: Option<Arc<WriteAheadLog>>,
+        nrt_segments: NrtSegments,
     ) -> crate::Result<SegmentUpdater> {
         let segments = index.searchable_segment_metas()?;
         let segment_manager = SegmentManager::from_segments(segments, delete_cursor);
@@ -287,26 +444,25 @@ impl SegmentUpdater {
                     "Failed to spawn segment updater thread".to_string(),
                 )
             })?;
-        let merge_thread_pool = ThreadPoolBuilder::new()
-            .thread_name(|i| format!("merge_thread_{i}"))
-            .num_threads(NUM_MERGE_THREADS)
-            .build()
-            .map_err(|_| {
-                crate::TantivyError::SystemError(
-                    "Failed to spawn segment merging thread".to_string(),
-                )
-            })?;
+        let merge_scheduler = Arc::new(MergeScheduler::create()?);
         let index_meta = index.load_metas()?;
+        let historical_commits = index.list_historical_commits()?;
+        nrt_segments.publish(index_meta.opstamp, index_meta.segments.clone());
         Ok(SegmentUpdater(Arc::new(InnerSegmentUpdater {
             active_index_meta: RwLock::new(Arc::new(index_meta)),
             pool,
-            merge_thread_pool,
+            merge_scheduler,
             index,
             segment_manager,
             merge_policy: RwLock::new(Arc::new(DefaultMergePolicy::default())),
+            commit_deletion_policy: RwLock::new(Arc::new(KeepLastCommit)),
+            historical_commits: RwLock::new(historical_commits),
             killed: AtomicBool::new(false),
             stamper,
             merge_operations: Default::default(),
+            pending_force_merges: Mutex::new(Vec::new()),
+            write_ahead_log,
+            nrt_segments,
         })))
     }
 
@@ -319,6 +475,28 @@ impl SegmentUpdater {
         *self.merge_policy.write().unwrap() = arc_merge_policy;
     }
 
+    pub fn merge_scheduler_settings(&self) -> MergeSchedulerSettings {
+        self.merge_scheduler.settings()
+    }
+
+    pub fn set_merge_scheduler_settings(
+        &self,
+        settings: MergeSchedulerSettings,
+    ) -> crate::Result<()> {
+        self.merge_scheduler.set_settings(settings)
+    }
+
+    pub fn get_commit_deletion_policy(&self) -> Arc<dyn CommitDeletionPolicy> {
+        self.commit_deletion_policy.read().unwrap().clone()
+    }
+
+    pub fn set_commit_deletion_policy(
+        &self,
+        commit_deletion_policy: Box<dyn CommitDeletionPolicy>,
+    ) {
+        *self.commit_deletion_policy.write().unwrap() = Arc::from(commit_deletion_policy);
+    }
+
     fn schedule_task<T: 'static + Send, F: FnOnce() -> crate::Result<T> + 'static + Send>(
         &self,
         task: F,
@@ -395,13 +573,33 @@ impl SegmentUpdater {
             //
             // Segment 1 from disk 1, Segment 1 from disk 2, etc.
             commited_segment_metas.sort_by_key(|segment_meta| -(segment_meta.max_doc() as i32));
+            let (schema_version, schema) = index.versioned_schema();
+            let previous_schemas = index.schemas_in_use(&commited_segment_metas);
+            // The metas are also saved after a merge or a schema update,
+            // in which case they still describe the same commit.
+            let previous_index_meta = self.load_meta();
+            let is_new_commit = previous_index_meta.opstamp != opstamp;
+            let commit_timestamp = if is_new_commit {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|duration| duration.as_secs())
+            } else {
+                previous_index_meta.commit_timestamp
+            };
             let index_meta = IndexMeta {
                 index_settings: index.settings().clone(),
                 segments: commited_segment_metas,
-                schema: index.schema(),
+                schema,
+                schema_version,
+                previous_schemas,
                 opstamp,
                 payload: commit_message,
+                commit_timestamp,
             };
+            if is_new_commit {
+                self.apply_commit_deletion_policy(&previous_index_meta, &index_meta)?;
+            }
             // TODO add context to the error.
             save_metas(&index_meta, directory.box_clone().borrow_mut())?;
             self.store_meta(&index_meta);
@@ -409,6 +607,49 @@ impl SegmentUpdater {
         Ok(())
     }
 
+    /// Asks the commit deletion policy which of the historical commits to keep, now that
+    /// `last_index_meta` supersedes `previous_index_meta` as the last commit.
+    ///
+    /// The metas of the previous commit are saved if it is kept, and the metas of the
+    /// commits that are not kept are deleted. Their other files are garbage collected.
+    fn apply_commit_deletion_policy(
+        &self,
+        previous_index_meta: &IndexMeta,
+        last_index_meta: &IndexMeta,
+    ) -> crate::Result<()> {
+        let directory = self.index.directory();
+        let mut historical_commits = self.historical_commits.write().unwrap();
+        historical_commits.push(IndexCommit::new(
+            commit_path(previous_index_meta.opstamp),
+            previous_index_meta.clone(),
+        ));
+        let last_commit = IndexCommit::new(META_FILEPATH.to_path_buf(), last_index_meta.clone());
+        let commits_to_keep: HashSet<Opstamp> = self
+            .get_commit_deletion_policy()
+            .commits_to_keep(&historical_commits, &last_commit)
+            .into_iter()
+            .collect();
+        let (kept_commits, deleted_commits): (Vec<IndexCommit>, Vec<IndexCommit>) =
+            std::mem::take(&mut *historical_commits)
+                .into_iter()
+                .partition(|commit| commits_to_keep.contains(&commit.opstamp()));
+        if commits_to_keep.contains(&previous_index_meta.opstamp) {
+            let mut buffer = serde_json::to_vec_pretty(previous_index_meta)?;
+            writeln!(&mut buffer)?;
+            directory.atomic_write(&commit_path(previous_index_meta.opstamp), &buffer[..])?;
+        }
+        for commit in deleted_commits {
+            if let Err(DeleteError::IoError { io_error, filepath }) =
+                directory.delete(commit.path())
+            {
+                // The file is left to the garbage collection.
+                warn!("Failed to delete {filepath:?}: {io_error:?}");
+            }
+        }
+        *historical_commits = kept_commits;
+        Ok(())
+    }
+
     pub fn schedule_garbage_collect(&self) -> FutureResult<GarbageCollectionResult> {
         let self_clone = self.clone();
         self.schedule_task(move || garbage_collect_files(self_clone))
@@ -426,44 +667,109 @@ impl SegmentUpdater {
             .flat_map(|segment_meta| segment_meta.list_files())
             .collect();
         files.insert(META_FILEPATH.to_path_buf());
+        for commit in self.historical_commits.read().unwrap().iter() {
+            files.insert(commit.path().to_path_buf());
+        }
+        if self.write_ahead_log.is_some() {
+            files.insert(write_ahead_log_path(self.load_meta().opstamp));
+        }
         files
     }
 
+    /// Commits the segments with the deletes up to `opstamp` applied.
+    ///
+    /// Once the commit is saved, the usage of `limits` is reset to the one of the new commit.
     pub(crate) fn schedule_commit(
         &self,
         opstamp: Opstamp,
         payload: Option<String>,
+        limits: Arc<LimitTracker>,
     ) -> FutureResult<Opstamp> {
         let segment_updater: SegmentUpdater = self.clone();
         self.schedule_task(move || {
             let segment_entries = segment_updater.purge_deletes(opstamp)?;
             segment_updater.segment_manager.commit(segment_entries);
             segment_updater.save_metas(opstamp, payload)?;
+            limits.reset(
+                &segment_updater.index,
+                &segment_updater.load_meta().segments,
+            )?;
+            if let Some(write_ahead_log) = &segment_updater.write_ahead_log {
+                if segment_updater.is_alive() {
+                    write_ahead_log.rotate(opstamp)?;
+                }
+            }
+            segment_updater.nrt_segments.publish(
+                opstamp,
+                segment_updater.segment_manager.committed_segment_metas(),
+            );
             let _ = garbage_collect_files(segment_updater.clone());
             segment_updater.consider_merge_options();
             Ok(opstamp)
         })
     }
 
+    /// Publishes the committed and uncommitted segments for near-real-time search, with the
+    /// deletes up to `opstamp` applied.
+    ///
+    /// Contrary to a commit, the segment entries are left untouched: the deletes are applied
+    /// to copies of their metas, so that they do not get persisted before the next commit.
+    pub(crate) fn schedule_flush(&self, opstamp: Opstamp) -> FutureResult<()> {
+        let segment_updater: SegmentUpdater = self.clone();
+        self.schedule_task(move || {
+            if segment_updater.is_alive() {
+                let segment_entries = segment_updater.purge_deletes(opstamp)?;
+                let segment_metas = segment_entries
+                    .iter()
+                    .map(|segment_entry| segment_entry.meta().clone())
+                    .collect();
+                segment_updater.nrt_segments.publish(opstamp, segment_metas);
+            }
+            Ok(())
+        })
+    }
+
+    /// Switches the index to a new version of its schema, and saves the metas.
+    ///
+    /// The schema is expected to have been validated beforehand.
+    pub(crate) fn schedule_schema_update(&self, schema: Schema) -> FutureResult<()> {
+        let segment_updater: SegmentUpdater = self.clone();
+        self.schedule_task(move || {
+            let index = &segment_updater.index;
+            index.set_schema(index.schema_version() + 1, schema);
+            let index_meta = segment_updater.load_meta();
+            segment_updater.save_metas(index_meta.opstamp, index_meta.payload.clone())
+        })
+    }
+
     fn store_meta(&self, index_meta: &IndexMeta) {
         *self.active_index_meta.write().unwrap() = Arc::new(index_meta.clone());
     }
 
-    fn load_meta(&self) -> Arc<IndexMeta> {
+    pub(crate) fn load_meta(&self) -> Arc<IndexMeta> {
         self.active_index_meta.read().unwrap().clone()
     }
 
-    pub(crate) fn make_merge_operation(&self, segment_ids: &[SegmentId]) -> MergeOperation {
+    pub(crate) fn make_merge_operation(
+        &self,
+        segment_ids: &[SegmentId],
+        priority: MergePriority,
+    ) -> MergeOperation {
         let commit_opstamp = self.load_meta().opstamp;
-        MergeOperation::new(&self.merge_operations, commit_opstamp, segment_ids.to_vec())
+        MergeOperation::new(
+            &self.merge_operations,
+            commit_opstamp,
+            segment_ids.to_vec(),
+            priority,
+        )
     }
 
-    // Starts a merge operation. This function will block until the merge operation is effectively
-    // started. Note that it does not wait for the merge to terminate.
+    // Schedules a merge operation. This function will block until the merge operation is
+    // handed to the merge scheduler. Note that it does not wait for the merge to terminate.
     // The calling thread should not be block for a long time, as this only involve waiting for the
     // `SegmentUpdater` queue which in turns only contains lightweight operations.
     //
-    // The merge itself happens on a different thread.
+    // The merge itself happens on a merge thread, once the merge scheduler lets it run.
     //
     // When successful, this function returns a `Future` for a `Result<SegmentMeta>` that represents
     // the actual outcome of the merge operation.
@@ -479,6 +785,20 @@ impl SegmentUpdater {
         &self,
         merge_operation: MergeOperation,
     ) -> FutureResult<Option<SegmentMeta>> {
+        let (scheduled_result, merging_future_send) =
+            FutureResult::create("Merge operation failed.");
+        self.start_merge_with_callback(merge_operation, move |merge_result| {
+            let _send_result = merging_future_send.send(merge_result);
+        });
+        scheduled_result
+    }
+
+    /// Same as `start_merge`, except that the outcome of the merge is handed to `on_merge_end`.
+    fn start_merge_with_callback(
+        &self,
+        merge_operation: MergeOperation,
+        on_merge_end: impl FnOnce(crate::Result<Option<SegmentMeta>>) + Send + 'static,
+    ) {
         assert!(
             !merge_operation.segment_ids().is_empty(),
             "Segment_ids cannot be empty."
@@ -489,50 +809,79 @@ impl SegmentUpdater {
             .segment_manager
             .start_merge(merge_operation.segment_ids())
         {
-            Ok(segment_entries) => segment_entries,
+            Ok((segment_entries, _)) => segment_entries,
             Err(err) => {
                 warn!(
                     "Starting the merge failed for the following reason. This is not fatal. {}",
                     err
                 );
-                return err.into();
+                on_merge_end(Err(err));
+                return;
             }
         };
+        let context = self.merge_policy_context();
+        let in_flight_merge = InFlightMerge {
+            segment_ids: merge_operation.segment_ids().to_vec(),
+            num_bytes: segment_entries
+                .iter()
+                .map(|segment_entry| context.segment_num_bytes(segment_entry.meta()))
+                .sum(),
+            priority: merge_operation.priority(),
+            is_running: false,
+        };
 
-        info!("Starting merge  - {:?}", merge_operation.segment_ids());
-
-        let (scheduled_result, merging_future_send) =
-            FutureResult::create("Merge operation failed.");
-
-        self.merge_thread_pool.spawn(move || {
-            // The fact that `merge_operation` is moved here is important.
-            // Its lifetime is used to track how many merging thread are currently running,
-            // as well as which segment is currently in merge and therefore should not be
-            // candidate for another merge.
-            match merge(
-                &segment_updater.index,
-                segment_entries,
-                merge_operation.target_opstamp(),
-            ) {
-                Ok(after_merge_segment_entry) => {
-                    let res = segment_updater.end_merge(merge_operation, after_merge_segment_entry);
-                    let _send_result = merging_future_send.send(res);
+        info!("Scheduling merge  - {:?}", merge_operation.segment_ids());
+
+        self.merge_scheduler
+            .schedule(in_flight_merge, move |merge_slot| {
+                // The fact that `merge_operation` is moved here is important.
+                // Its lifetime is used to track how many merging thread are currently running,
+                // as well as which segment is currently in merge and therefore should not be
+                // candidate for another merge.
+                if !segment_updater.is_alive() {
+                    on_merge_end(Err(crate::TantivyError::SystemError(
+                        "Segment updater killed".to_string(),
+                    )));
+                    return;
                 }
-                Err(merge_error) => {
-                    warn!(
-                        "Merge of {:?} was cancelled: {:?}",
-                        merge_operation.segment_ids().to_vec(),
-                        merge_error
-                    );
-                    if cfg!(test) {
-                        panic!("{merge_error:?}");
+                // The segments may have been updated while the merge was waiting to run.
+                let merge_result = segment_updater
+                    .segment_manager
+                    .start_merge(merge_operation.segment_ids())
+                    .and_then(|(segment_entries, segments_status)| {
+                        info!("Starting merge  - {:?}", merge_operation.segment_ids());
+                        merge(
+                            &segment_updater.index,
+                            segment_entries,
+                            segments_status,
+                            merge_operation.target_opstamp(),
+                            segment_updater.merge_scheduler.io_throttle(),
+                        )
+                    });
+                // The files are written: the next merge can start.
+                drop(merge_slot);
+                match merge_result {
+                    Ok(after_merge_segment_entry) => {
+                        let res =
+                            segment_updater.end_merge(merge_operation, after_merge_segment_entry);
+                        on_merge_end(res);
+                    }
+                    Err(merge_error) => {
+                        warn!(
+                            "Merge of {:?} was cancelled: {:?}",
+                            merge_operation.segment_ids().to_vec(),
+                            merge_error
+                        );
+                        if cfg!(test) {
+                            panic!("{merge_error:?}");
+                        }
+                        drop(merge_operation);
+                        on_merge_end(Err(merge_error));
                     }
-                    let _send_result = merging_future_send.send(Err(merge_error));
                 }
-            }
-        });
-
-        scheduled_result
+                // A force merge may be waiting for this merge to be over.
+                segment_updater.schedule_pending_force_merges();
+            });
     }
 
     pub(crate) fn get_mergeable_segments(&self) -> (Vec<SegmentMeta>, Vec<SegmentMeta>) {
@@ -541,7 +890,27 @@ impl SegmentUpdater {
             .get_mergeable_segments(&merge_segment_ids)
     }
 
+    /// Returns the context handed to the merge policy.
+    fn merge_policy_context(&self) -> MergePolicyContext {
+        let segment_metas: Vec<SegmentMeta> = self
+            .segment_manager
+            .segment_entries()
+            .iter()
+            .map(|segment_entry| segment_entry.meta().clone())
+            .collect();
+        MergePolicyContext::new(
+            self.merge_scheduler.in_flight_merges(),
+            self.merge_scheduler.settings().max_concurrent_merges,
+            &segment_metas,
+        )
+    }
+
     fn consider_merge_options(&self) {
+        self.start_pending_force_merges();
+        if !self.pending_force_merges.lock().unwrap().is_empty() {
+            // The merges of the merge policy wait for the pending force merges to start.
+            return;
+        }
         let (committed_segments, uncommitted_segments) = self.get_mergeable_segments();
 
         // Committed segments cannot be merged with uncommitted_segments.
@@ -549,27 +918,27 @@ impl SegmentUpdater {
         let merge_policy = self.get_merge_policy();
 
         let current_opstamp = self.stamper.stamp();
-        let mut merge_candidates: Vec<MergeOperation> = merge_policy
-            .compute_merge_candidates(&uncommitted_segments)
-            .into_iter()
-            .map(|merge_candidate| {
-                MergeOperation::new(&self.merge_operations, current_opstamp, merge_candidate.0)
-            })
-            .collect();
-
         let commit_opstamp = self.load_meta().opstamp;
-        let committed_merge_candidates = merge_policy
-            .compute_merge_candidates(&committed_segments)
-            .into_iter()
-            .map(|merge_candidate: MergeCandidate| {
-                MergeOperation::new(&self.merge_operations, commit_opstamp, merge_candidate.0)
-            });
-        merge_candidates.extend(committed_merge_candidates);
-
-        for merge_operation in merge_candidates {
-            // If a merge cannot be started this is not a fatal error.
-            // We do log a warning in `start_merge`.
-            drop(self.start_merge(merge_operation));
+        for (segments, target_opstamp) in [
+            (uncommitted_segments, current_opstamp),
+            (committed_segments, commit_opstamp),
+        ] {
+            // The context is computed after the previous merges are scheduled,
+            // so that they are accounted for.
+            let context = self.merge_policy_context();
+            let merge_candidates: Vec<MergeCandidate> =
+                merge_policy.compute_merge_candidates_with_context(&segments, &context);
+            for merge_candidate in merge_candidates {
+                let merge_operation = MergeOperation::new(
+                    &self.merge_operations,
+                    target_opstamp,
+                    merge_candidate.0,
+                    MergePriority::Normal,
+                );
+                // If a merge cannot be started this is not a fatal error.
+                // We do log a warning in `start_merge`.
+                drop(self.start_merge(merge_operation));
+            }
         }
     }
 
@@ -622,6 +991,7 @@ impl SegmentUpdater {
                     }
                 }
                 let previous_metas = segment_updater.load_meta();
+                let nrt_segment_entry = after_merge_segment_entry.clone();
                 let segments_status = segment_updater
                     .segment_manager
                     .end_merge(merge_operation.segment_ids(), after_merge_segment_entry)?;
@@ -630,6 +1000,8 @@ impl SegmentUpdater {
                     segment_updater
                         .save_metas(previous_metas.opstamp, previous_metas.payload.clone())?;
                 }
+                segment_updater
+                    .end_merge_nrt_segments(merge_operation.segment_ids(), nrt_segment_entry)?;
 
                 segment_updater.consider_merge_options();
             } // we drop all possible handle to a now useless `SegmentMeta`.
@@ -641,6 +1013,35 @@ impl SegmentUpdater {
         Ok(after_merge_segment_meta)
     }
 
+    /// Replaces the merged segments by the segment resulting from the merge in the
+    /// segments published for near-real-time search.
+    fn end_merge_nrt_segments(
+        &self,
+        merged_segment_ids: &[SegmentId],
+        after_merge_segment_entry: Option<SegmentEntry>,
+    ) -> crate::Result<()> {
+        let nrt_segments = self.nrt_segments.get();
+        let (nrt_opstamp, nrt_segment_metas) = nrt_segments.as_ref();
+        let mut segment_metas: Vec<SegmentMeta> = nrt_segment_metas
+            .iter()
+            .filter(|segment_meta| !merged_segment_ids.contains(&segment_meta.id()))
+            .cloned()
+            .collect();
+        if segment_metas.len() == nrt_segment_metas.len() {
+            // The merged segments are not published yet.
+            return Ok(());
+        }
+        if let Some(mut segment_entry) = after_merge_segment_entry {
+            // The deletes following the merge are applied to a copy of the entry,
+            // as they may not be committed yet.
+            let segment = self.index.segment(segment_entry.meta().clone());
+            advance_deletes(segment, &mut segment_entry, *nrt_opstamp)?;
+            segment_metas.push(segment_entry.meta().clone());
+        }
+        self.nrt_segments.publish(*nrt_opstamp, segment_metas);
+        Ok(())
+    }
+
     /// Wait for current merging threads.
     ///
     /// Upon termination of the current merging threads,
@@ -660,20 +1061,156 @@ impl SegmentUpdater {
         self.merge_operations.wait_until_empty();
         Ok(())
     }
+
+    /// Merges the committed segments down to at most `max_segments` segments.
+    ///
+    /// The merges are planned once the running merges are over, so that all of the committed
+    /// segments are accounted for. In the meantime, the merge policy does not start any merge.
+    pub(crate) fn force_merge(&self, max_segments: usize) -> FutureResult<()> {
+        if max_segments == 0 {
+            return crate::TantivyError::InvalidArgument(
+                "Cannot force merge to 0 segments.".to_string(),
+            )
+            .into();
+        }
+        let (scheduled_result, result_sender) = FutureResult::create("Force merge failed.");
+        let segment_updater = self.clone();
+        drop(self.schedule_task(move || {
+            segment_updater
+                .pending_force_merges
+                .lock()
+                .unwrap()
+                .push(PendingForceMerge {
+                    max_segments,
+                    result_sender,
+                });
+            segment_updater.start_pending_force_merges();
+            Ok(())
+        }));
+        scheduled_result
+    }
+
+    /// Plans and starts the pending force merges, one after the other, as long as no merge
+    /// is running.
+    ///
+    /// This runs on the segment updater thread, like the merges of the merge policy, so that
+    /// no other merge starts between the planning of the merges and their start.
+    fn start_pending_force_merges(&self) {
+        let mut pending_force_merges = self.pending_force_merges.lock().unwrap();
+        while self.merge_operations.len() == 0 && !pending_force_merges.is_empty() {
+            let PendingForceMerge {
+                max_segments,
+                result_sender,
+            } = pending_force_merges.remove(0);
+            let (committed_segments, _) = self.get_mergeable_segments();
+            let context = self.merge_policy_context();
+            let plan = plan_force_merge(&committed_segments, max_segments, &context);
+            self.start_planned_merges(plan, result_sender);
+        }
+    }
+
+    /// Schedules the start of the pending force merges, if any.
+    fn schedule_pending_force_merges(&self) {
+        if self.pending_force_merges.lock().unwrap().is_empty() {
+            return;
+        }
+        let segment_updater = self.clone();
+        drop(self.schedule_task(move || {
+            segment_updater.start_pending_force_merges();
+            Ok(())
+        }));
+    }
+
+    /// Rewrites the committed segments whose ratio of deleted documents is at least
+    /// `min_deleted_ratio`.
+    ///
+    /// The segments that are being merged already are left alone.
+    pub(crate) fn expunge_deletes(&self, min_deleted_ratio: f32) -> FutureResult<()> {
+        if !(min_deleted_ratio > 0f32 && min_deleted_ratio <= 1f32) {
+            return crate::TantivyError::InvalidArgument(format!(
+                "The ratio of deleted documents must be within (0..1], got {min_deleted_ratio}."
+            ))
+            .into();
+        }
+        let (scheduled_result, result_sender) = FutureResult::create("Expunging deletes failed.");
+        let segment_updater = self.clone();
+        drop(self.schedule_task(move || {
+            let (committed_segments, _) = segment_updater.get_mergeable_segments();
+            let plan = plan_expunge_deletes(&committed_segments, min_deleted_ratio);
+            segment_updater.start_planned_merges(plan, result_sender);
+            Ok(())
+        }));
+        scheduled_result
+    }
+
+    /// Starts the given merges with a high priority. Their outcome is sent to `result_sender`
+    /// once all of them are over.
+    ///
+    /// The merges run concurrently, following the merge scheduler settings.
+    fn start_planned_merges(
+        &self,
+        plan: Vec<Vec<SegmentId>>,
+        result_sender: oneshot::Sender<crate::Result<()>>,
+    ) {
+        let merge_group_result = Arc::new(MergeGroupResult {
+            result: Mutex::new(Ok(())),
+            result_sender: Some(result_sender),
+        });
+        for segment_ids in plan {
+            let merge_operation = self.make_merge_operation(&segment_ids, MergePriority::High);
+            let merge_group_result = merge_group_result.clone();
+            self.start_merge_with_callback(merge_operation, move |merge_result| {
+                merge_group_result.record(merge_result)
+            });
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::merge_indices;
-    use crate::collector::TopDocs;
+    use std::sync::Arc;
+
+    use super::{merge, merge_indices, plan_force_merge};
+    use crate::collector::{Count, TopDocs};
     use crate::directory::RamDirectory;
     use crate::fastfield::AliveBitSet;
+    use crate::index::{SegmentId, SegmentMeta, SegmentMetaInventory};
+    use crate::indexer::delete_queue::DeleteQueue;
     use crate::indexer::merge_policy::tests::MergeWheneverPossible;
+    use crate::indexer::merge_scheduler::IoThrottle;
     use crate::indexer::merger::IndexMerger;
+    use crate::indexer::operation::{DeleteOperation, DeleteTarget};
+    use crate::indexer::segment_manager::SegmentsStatus;
     use crate::indexer::segment_updater::merge_filtered_segments;
-    use crate::query::QueryParser;
+    use crate::indexer::{MergePolicyContext, NoMergePolicy, SegmentEntry};
+    use crate::query::{QueryParser, TermQuery};
     use crate::schema::*;
-    use crate::{Directory, DocAddress, Index, Segment};
+    use crate::{Directory, DocAddress, Index, IndexWriter, Segment};
+
+    #[test]
+    fn test_plan_force_merge() {
+        let inventory = SegmentMetaInventory::default();
+        let segments: Vec<SegmentMeta> = [100u64, 1, 1, 1, 50]
+            .into_iter()
+            .map(|num_bytes| {
+                inventory
+                    .new_segment_meta(SegmentId::generate_random(), 1, 0)
+                    .with_num_bytes(num_bytes)
+            })
+            .collect();
+        let context = MergePolicyContext::for_segments(&segments);
+        assert!(plan_force_merge(&segments, 5, &context).is_empty());
+        // The largest segment is left alone.
+        let merges = plan_force_merge(&segments, 2, &context);
+        assert_eq!(merges.len(), 1);
+        let mut merged_ids = merges[0].clone();
+        merged_ids.sort();
+        let mut expected_ids: Vec<SegmentId> =
+            segments[1..].iter().map(|segment| segment.id()).collect();
+        expected_ids.sort();
+        assert_eq!(merged_ids, expected_ids);
+        assert_eq!(plan_force_merge(&segments, 1, &context)[0].len(), 5);
+    }
 
     #[test]
     fn test_delete_during_merge() -> crate::Result<()> {
@@ -716,6 +1253,78 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_rollback_after_merge_of_committed_segments() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+
+        let mut index_writer = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(text_field=>"a"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text_field=>"b"))?;
+        index_writer.commit()?;
+
+        // The delete is not committed: the merge must not persist it.
+        index_writer.delete_term(Term::from_field_text(text_field, "a"));
+        let segment_ids = index.searchable_segment_ids()?;
+        assert_eq!(segment_ids.len(), 2);
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.rollback()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        assert_eq!(searcher.segment_readers().len(), 1);
+        assert_eq!(searcher.num_docs(), 2);
+        let query = TermQuery::new(
+            Term::from_field_text(text_field, "a"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(searcher.search(&query, &Count)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_committed_segments_ignores_lagging_deletes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(NoMergePolicy));
+        index_writer.add_document(doc!(text_field=>"a"))?;
+        index_writer.add_document(doc!(text_field=>"b"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text_field=>"a"))?;
+        let target_opstamp = index_writer.commit()?;
+
+        // The first segment lags behind a delete that is not committed.
+        let delete_queue = DeleteQueue::new();
+        let lagging_delete_cursor = delete_queue.cursor();
+        delete_queue.push(DeleteOperation {
+            opstamp: target_opstamp + 1,
+            target: DeleteTarget::Key(Term::from_field_text(text_field, "a")),
+        });
+        let mut delete_cursor = lagging_delete_cursor.clone();
+        delete_cursor.advance();
+        let segment_metas = index.searchable_segment_metas()?;
+        let segment_entries = vec![
+            SegmentEntry::new(segment_metas[0].clone(), lagging_delete_cursor, None),
+            SegmentEntry::new(segment_metas[1].clone(), delete_cursor, None),
+        ];
+        let merged_segment_entry = merge(
+            &index,
+            segment_entries,
+            SegmentsStatus::Committed,
+            target_opstamp,
+            &Arc::new(IoThrottle::default()),
+        )?
+        .unwrap();
+        assert_eq!(merged_segment_entry.meta().num_docs(), 3);
+        Ok(())
+    }
+
     #[test]
     fn delete_all_docs_min() -> crate::Result<()> {
         let mut schema_builder = Schema::builder();
@@ -1062,6 +1671,7 @@ mod tests {
             )?;
             let merger: IndexMerger = IndexMerger::open_with_custom_alive_set(
                 merged_index.schema(),
+                merged_index.settings().clone(),
                 &segments[..],
                 filter_segments,
             )?;
@@ -1077,6 +1687,7 @@ mod tests {
                 Index::create(RamDirectory::default(), target_schema, target_settings)?;
             let merger: IndexMerger = IndexMerger::open_with_custom_alive_set(
                 merged_index.schema(),
+                merged_index.settings().clone(),
                 &segments[..],
                 filter_segments,
             )?;
@@ -1,12 +1,14 @@
-use columnar::MonotonicallyMappableToU64;
+use columnar::{MergeRowOrder, MonotonicallyMappableToU64, RowAddr, ShuffleMergeOrder};
 use common::JsonPathWriter;
 use itertools::Itertools;
 use tokenizer_api::BoxTokenStream;
 
 use super::operation::AddOperation;
-use crate::fastfield::FastFieldsWriter;
+use crate::directory::FileSlice;
+use crate::fastfield::{FastFieldReaders, FastFieldsWriter};
 use crate::fieldnorm::{FieldNormReaders, FieldNormsWriter};
 use crate::index::{Segment, SegmentComponent};
+use crate::indexer::doc_id_mapping::DocIdMapping;
 use crate::indexer::segment_serializer::SegmentSerializer;
 use crate::json_utils::{index_json_value, IndexingPositionsPerPath};
 use crate::postings::{
@@ -15,9 +17,14 @@ use crate::postings::{
 };
 use crate::schema::document::{Document, Value};
 use crate::schema::{FieldEntry, FieldType, Schema, Term, DATE_TIME_PRECISION_INDEXED};
+use crate::store::{StoreReader, StoreWriter};
 use crate::tokenizer::{FacetTokenizer, PreTokenizedStream, TextAnalyzer, Tokenizer};
 use crate::{DocId, Opstamp, TantivyError};
 
+/// Number of decompressed blocks of the temp docstore kept in cache while the documents
+/// are copied in sort order, which does not follow the order of the blocks.
+const TEMP_STORE_CACHE_NUM_BLOCKS: usize = 100;
+
 /// Computes the initial size of the hash table.
 ///
 /// Returns the recommended initial table size as a power of 2.
@@ -70,11 +77,11 @@ impl SegmentWriter {
     /// - segment: The segment being written
     /// - schema
     pub fn for_segment(memory_budget_in_bytes: usize, segment: Segment) -> crate::Result<Self> {
-        let schema = segment.schema();
+        let schema = segment.segment_schema();
         let tokenizer_manager = segment.index().tokenizers().clone();
         let tokenizer_manager_fast_field = segment.index().fast_field_tokenizer().clone();
         let table_size = compute_initial_table_size(memory_budget_in_bytes)?;
-        let segment_serializer = SegmentSerializer::for_segment(segment)?;
+        let segment_serializer = SegmentSerializer::for_segment(segment, false)?;
         let per_field_postings_writers = PerFieldPostingsWriter::for_schema(&schema);
         let per_field_text_analyzers = schema
             .fields()
@@ -121,16 +128,27 @@ impl SegmentWriter {
     ///
     /// Finalize consumes the `SegmentWriter`, so that it cannot
     /// be used afterwards.
+    ///
+    /// If the index is sorted, the documents are written in sort order, and the returned
+    /// opstamps are ordered by the new doc ids.
     pub fn finalize(mut self) -> crate::Result<Vec<u64>> {
         self.fieldnorms_writer.fill_up_to_max_doc(self.max_doc);
-        remap_and_write(
+        let doc_id_map = remap_and_write(
             self.schema,
             &self.per_field_postings_writers,
             self.ctx,
             self.fast_field_writers,
             &self.fieldnorms_writer,
+            self.max_doc,
             self.segment_serializer,
         )?;
+        if let Some(doc_id_map) = doc_id_map {
+            let doc_opstamps = doc_id_map
+                .iter_old_doc_ids()
+                .map(|old_doc_id| self.doc_opstamps[old_doc_id as usize])
+                .collect();
+            return Ok(doc_opstamps);
+        }
         Ok(self.doc_opstamps)
     }
 
@@ -384,18 +402,58 @@ impl SegmentWriter {
 /// Writes a view of a segment by pushing information
 /// to the `SegmentSerializer`.
 ///
-/// `doc_id_map` is used to map to the new doc_id order.
+/// If the index is sorted, the doc id mapping is computed from the fast fields, and used to
+/// write the documents in the new doc_id order. The mapping is then returned.
 fn remap_and_write(
     schema: Schema,
     per_field_postings_writers: &PerFieldPostingsWriter,
     ctx: IndexingContext,
     fast_field_writers: FastFieldsWriter,
     fieldnorms_writer: &FieldNormsWriter,
+    max_doc: DocId,
     mut serializer: SegmentSerializer,
-) -> crate::Result<()> {
+) -> crate::Result<Option<DocIdMapping>> {
     debug!("remap-and-write");
+    let sort_by_fields = serializer
+        .segment()
+        .index()
+        .settings()
+        .sort_by_fields
+        .clone();
+    let doc_id_map = if sort_by_fields.is_empty() {
+        debug!("fastfield-serialize");
+        fast_field_writers.serialize(serializer.get_fast_field_write())?;
+        None
+    } else {
+        // The sort keys are read from the fast fields, so we serialize them in memory first,
+        // and then write them in the new doc_id order.
+        debug!("fastfield-serialize-sorted");
+        let mut fast_fields_data = Vec::new();
+        fast_field_writers.serialize(&mut fast_fields_data)?;
+        let fast_field_readers =
+            FastFieldReaders::open(FileSlice::from(fast_fields_data), schema.clone())?;
+        let doc_id_map =
+            DocIdMapping::sort_by_fields(&fast_field_readers, max_doc, &sort_by_fields)?;
+        let merge_row_order = MergeRowOrder::Shuffled(ShuffleMergeOrder {
+            new_row_id_to_old_row_id: doc_id_map
+                .iter_old_doc_ids()
+                .map(|row_id| RowAddr {
+                    segment_ord: 0u32,
+                    row_id,
+                })
+                .collect(),
+            alive_bitsets: vec![None],
+        });
+        columnar::merge_columnar(
+            &[fast_field_readers.columnar()],
+            &[],
+            merge_row_order,
+            serializer.get_fast_field_write(),
+        )?;
+        Some(doc_id_map)
+    };
     if let Some(fieldnorms_serializer) = serializer.extract_fieldnorms_serializer() {
-        fieldnorms_writer.serialize(fieldnorms_serializer)?;
+        fieldnorms_writer.serialize_with_doc_id_map(fieldnorms_serializer, doc_id_map.as_ref())?;
     }
     let fieldnorm_data = serializer
         .segment()
@@ -406,15 +464,42 @@ fn remap_and_write(
         schema,
         per_field_postings_writers,
         fieldnorm_readers,
+        doc_id_map.as_ref(),
         serializer.get_postings_serializer(),
     )?;
-    debug!("fastfield-serialize");
-    fast_field_writers.serialize(serializer.get_fast_field_write())?;
+
+    if let Some(doc_id_map) = doc_id_map.as_ref() {
+        debug!("resort-docstore");
+        // The documents have been stored in the temp docstore in insertion order:
+        // we close it, and copy the documents to the docstore in the new doc_id order.
+        let store_write = serializer
+            .segment_mut()
+            .open_write(SegmentComponent::Store)?;
+        let settings = serializer.segment().index().settings().clone();
+        let store_writer = StoreWriter::new(
+            store_write,
+            settings.docstore_compression,
+            settings.docstore_blocksize,
+            settings.docstore_compress_dedicated_thread,
+        )?;
+        let temp_store_writer = std::mem::replace(&mut serializer.store_writer, store_writer);
+        temp_store_writer.close()?;
+        let temp_store_reader = StoreReader::open(
+            serializer
+                .segment()
+                .open_read(SegmentComponent::TempStore)?,
+            TEMP_STORE_CACHE_NUM_BLOCKS,
+        )?;
+        for old_doc_id in doc_id_map.iter_old_doc_ids() {
+            let doc_bytes = temp_store_reader.get_document_bytes(old_doc_id)?;
+            serializer.get_store_writer().store_bytes(&doc_bytes)?;
+        }
+    }
 
     debug!("serializer-close");
     serializer.close()?;
 
-    Ok(())
+    Ok(doc_id_map)
 }
 
 #[cfg(test)]
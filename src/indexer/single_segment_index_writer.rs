@@ -40,14 +40,21 @@ impl<D: Document> SingleSegmentIndexWriter<D> {
     pub fn finalize(self) -> crate::Result<Index> {
         let max_doc = self.segment_writer.max_doc();
         self.segment_writer.finalize()?;
-        let segment: Segment = self.segment.with_max_doc(max_doc);
+        let segment: Segment = self.segment.with_max_doc(max_doc).with_file_stats()?;
+        segment.meta().untrack_temp_docstore();
         let index = segment.index();
+        let (schema_version, schema) = index.versioned_schema();
+        let segments = vec![segment.meta().clone()];
+        let previous_schemas = index.schemas_in_use(&segments);
         let index_meta = IndexMeta {
             index_settings: index.settings().clone(),
-            segments: vec![segment.meta().clone()],
-            schema: index.schema(),
+            segments,
+            schema,
+            schema_version,
+            previous_schemas,
             opstamp: 0,
             payload: None,
+            commit_timestamp: None,
         };
         save_metas(&index_meta, index.directory())?;
         index.directory().sync_directory()?;
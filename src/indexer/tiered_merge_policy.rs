@@ -0,0 +1,534 @@
+use std::collections::HashSet;
+
+use super::merge_policy::{MergeCandidate, MergePolicy, MergePolicyContext};
+use crate::index::{SegmentId, SegmentMeta};
+
+const DEFAULT_MAX_MERGED_SEGMENT_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+const DEFAULT_FLOOR_SEGMENT_BYTES: u64 = 2 * 1024 * 1024;
+const DEFAULT_SEGMENTS_PER_TIER: f64 = 10.0;
+const DEFAULT_MAX_MERGE_AT_ONCE: usize = 10;
+const DEFAULT_DEL_DOCS_RATIO_ALLOWED: f32 = 0.33f32;
+const DEFAULT_MAX_CONCURRENT_MERGES: usize = 4;
+
+/// `TieredMergePolicy` merges segments of similar sizes on disk.
+///
+/// Segments are grouped in tiers of exponentially growing byte sizes. Each tier
+/// may hold up to `segments_per_tier` segments: above this budget, the policy picks
+/// the merges with the best score. A merge scores better when its segments have
+/// similar sizes, when it is small, and when it reclaims many deleted documents.
+///
+/// Merges never produce segments larger than `max_merged_segment_bytes`. Segments that
+/// are already larger than half of this size are left alone, unless their ratio of deleted
+/// documents exceeds `del_docs_ratio_allowed`, in which case they get rewritten to
+/// expunge their deletes.
+///
+/// The sizes are read from the [`MergePolicyContext::segment_num_bytes`] of the segments.
+#[derive(Debug, Clone)]
+pub struct TieredMergePolicy {
+    max_merged_segment_bytes: u64,
+    floor_segment_bytes: u64,
+    segments_per_tier: f64,
+    max_merge_at_once: usize,
+    del_docs_ratio_allowed: f32,
+    max_concurrent_merges: usize,
+}
+
+impl TieredMergePolicy {
+    /// Set the maximum size in bytes of the segments produced by a merge.
+    ///
+    /// Segments larger than half of this size are only merged to expunge their deletes.
+    pub fn set_max_merged_segment_bytes(&mut self, max_merged_segment_bytes: u64) {
+        self.max_merged_segment_bytes = max_merged_segment_bytes;
+    }
+
+    /// Set the size in bytes under which all segments are considered to have the same size.
+    ///
+    /// This prevents tiny segments from forming many small tiers.
+    pub fn set_floor_segment_bytes(&mut self, floor_segment_bytes: u64) {
+        self.floor_segment_bytes = floor_segment_bytes;
+    }
+
+    /// Set the number of segments allowed in a tier before merges are triggered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if segments_per_tier is smaller than 2.
+    pub fn set_segments_per_tier(&mut self, segments_per_tier: f64) {
+        assert!(segments_per_tier >= 2.0);
+        self.segments_per_tier = segments_per_tier;
+    }
+
+    /// Set the maximum number of segments merged together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if max_merge_at_once is smaller than 2.
+    pub fn set_max_merge_at_once(&mut self, max_merge_at_once: usize) {
+        assert!(max_merge_at_once >= 2);
+        self.max_merge_at_once = max_merge_at_once;
+    }
+
+    /// Set the ratio of deleted documents in a segment to tolerate.
+    ///
+    /// Segments exceeding it are merged regardless of their size, possibly on their own.
+    ///
+    /// # Panics
+    ///
+    /// Panics if del_docs_ratio_allowed is not within (0..1].
+    pub fn set_del_docs_ratio_allowed(&mut self, del_docs_ratio_allowed: f32) {
+        assert!(del_docs_ratio_allowed <= 1.0f32);
+        assert!(del_docs_ratio_allowed > 0f32);
+        self.del_docs_ratio_allowed = del_docs_ratio_allowed;
+    }
+
+    /// Set the maximum number of merges in flight, that is running or waiting to run.
+    ///
+    /// The merges in flight are read from the [`MergePolicyContext`], so that the limit holds
+    /// across the calls to the policy: no merge is proposed while the merge scheduler holds
+    /// that many merges, including the merges that were not proposed by this policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if max_concurrent_merges is 0.
+    pub fn set_max_concurrent_merges(&mut self, max_concurrent_merges: usize) {
+        assert!(max_concurrent_merges > 0);
+        self.max_concurrent_merges = max_concurrent_merges;
+    }
+
+    fn floor_size(&self, num_bytes: f64) -> f64 {
+        num_bytes.max(self.floor_segment_bytes as f64)
+    }
+
+    /// Returns the number of segments the index may hold given the sizes of its segments.
+    fn allowed_segment_count(&self, segments: &[&SizedSegment]) -> f64 {
+        let mut bytes_left: f64 = segments.iter().map(|segment| segment.live_bytes).sum();
+        let min_segment_bytes = segments
+            .iter()
+            .map(|segment| segment.live_bytes)
+            .fold(f64::MAX, f64::min);
+        let mut tier_bytes = self.floor_size(min_segment_bytes);
+        let mut allowed_segment_count = 0.0;
+        loop {
+            let tier_segment_count = bytes_left / tier_bytes;
+            if tier_segment_count < self.segments_per_tier {
+                allowed_segment_count += tier_segment_count.ceil();
+                break;
+            }
+            allowed_segment_count += self.segments_per_tier;
+            bytes_left -= self.segments_per_tier * tier_bytes;
+            tier_bytes *= self.max_merge_at_once as f64;
+        }
+        allowed_segment_count.max(self.segments_per_tier)
+    }
+
+    /// Scores a merge: the lower, the better.
+    fn score(&self, candidate: &[&SizedSegment], hit_too_large: bool) -> f64 {
+        let total_bytes: f64 = candidate.iter().map(|segment| segment.bytes).sum();
+        let total_live_bytes: f64 = candidate.iter().map(|segment| segment.live_bytes).sum();
+        let skew = if hit_too_large {
+            // The merge would produce a segment of the maximum size: its skew does not
+            // matter, as it cannot be merged any further anyway.
+            1.0 / self.max_merge_at_once as f64
+        } else {
+            let total_floored_bytes: f64 = candidate
+                .iter()
+                .map(|segment| self.floor_size(segment.live_bytes))
+                .sum();
+            self.floor_size(candidate[0].live_bytes) / total_floored_bytes
+        };
+        // Gently favor smaller merges.
+        let size_score = total_live_bytes.max(1.0).powf(0.05);
+        // Strongly favor merges reclaiming deletes.
+        let live_ratio = if total_bytes > 0.0 {
+            total_live_bytes / total_bytes
+        } else {
+            1.0
+        };
+        skew * size_score * live_ratio * live_ratio
+    }
+
+    /// Picks the best merge among the eligible segments, sorted by decreasing live size.
+    fn find_best_merge<'a>(&self, eligible: &[&'a SizedSegment]) -> Option<Vec<&'a SizedSegment>> {
+        let max_merged_segment_bytes = self.max_merged_segment_bytes as f64;
+        let mut best: Option<(f64, Vec<&SizedSegment>)> = None;
+        for start in 0..eligible.len() {
+            let mut candidate: Vec<&SizedSegment> = Vec::new();
+            let mut candidate_bytes = 0.0;
+            let mut hit_too_large = false;
+            for &segment in &eligible[start..] {
+                if candidate.len() >= self.max_merge_at_once {
+                    break;
+                }
+                if !candidate.is_empty()
+                    && candidate_bytes + segment.live_bytes > max_merged_segment_bytes
+                {
+                    hit_too_large = true;
+                    // Smaller segments may still fit in the merge.
+                    continue;
+                }
+                candidate_bytes += segment.live_bytes;
+                candidate.push(segment);
+            }
+            let reclaims_deletes =
+                candidate.len() == 1 && candidate[0].deletes_ratio > self.del_docs_ratio_allowed;
+            if candidate.len() < 2 && !reclaims_deletes {
+                continue;
+            }
+            let score = self.score(&candidate, hit_too_large);
+            if best
+                .as_ref()
+                .map(|(best_score, _)| score < *best_score)
+                .unwrap_or(true)
+            {
+                best = Some((score, candidate));
+            }
+        }
+        best.map(|(_, candidate)| candidate)
+    }
+}
+
+/// A segment along with its estimated size on disk.
+struct SizedSegment {
+    id: SegmentId,
+    bytes: f64,
+    // Size of the alive documents.
+    live_bytes: f64,
+    deletes_ratio: f32,
+}
+
+fn sized_segments(segments: &[SegmentMeta], context: &MergePolicyContext) -> Vec<SizedSegment> {
+    segments
+        .iter()
+        .map(|segment| {
+            let bytes = context.segment_num_bytes(segment) as f64;
+            let deletes_ratio = if segment.max_doc() == 0 {
+                0f32
+            } else {
+                segment.num_deleted_docs() as f32 / segment.max_doc() as f32
+            };
+            SizedSegment {
+                id: segment.id(),
+                bytes,
+                live_bytes: bytes * (1.0 - deletes_ratio as f64),
+                deletes_ratio,
+            }
+        })
+        .collect()
+}
+
+impl MergePolicy for TieredMergePolicy {
+    fn compute_merge_candidates(&self, segments: &[SegmentMeta]) -> Vec<MergeCandidate> {
+        self.compute_merge_candidates_with_context(
+            segments,
+            &MergePolicyContext::for_segments(segments),
+        )
+    }
+
+    fn compute_merge_candidates_with_context(
+        &self,
+        segments: &[SegmentMeta],
+        context: &MergePolicyContext,
+    ) -> Vec<MergeCandidate> {
+        let max_merges = self
+            .max_concurrent_merges
+            .min(context.max_concurrent_merges())
+            .saturating_sub(context.in_flight_merges().len());
+        let sized_segments = sized_segments(segments, context);
+        let max_segment_bytes = self.max_merged_segment_bytes as f64 / 2.0;
+        let mut eligible: Vec<&SizedSegment> = sized_segments
+            .iter()
+            .filter(|segment| {
+                segment.live_bytes <= max_segment_bytes
+                    || segment.deletes_ratio > self.del_docs_ratio_allowed
+            })
+            .collect();
+        eligible.sort_by(|left, right| right.live_bytes.total_cmp(&left.live_bytes));
+
+        let mut merge_candidates = Vec::new();
+        while merge_candidates.len() < max_merges && !eligible.is_empty() {
+            let has_too_many_segments =
+                eligible.len() as f64 > self.allowed_segment_count(&eligible);
+            let has_too_many_deletes = eligible
+                .iter()
+                .any(|segment| segment.deletes_ratio > self.del_docs_ratio_allowed);
+            if !has_too_many_segments && !has_too_many_deletes {
+                break;
+            }
+            let Some(best_merge) = self.find_best_merge(&eligible) else {
+                break;
+            };
+            let merged_ids: HashSet<SegmentId> =
+                best_merge.iter().map(|segment| segment.id).collect();
+            if !has_too_many_segments
+                && best_merge
+                    .iter()
+                    .all(|segment| segment.deletes_ratio <= self.del_docs_ratio_allowed)
+            {
+                // The index only needs merges reclaiming deletes.
+                eligible.retain(|segment| segment.deletes_ratio > self.del_docs_ratio_allowed);
+                continue;
+            }
+            eligible.retain(|segment| !merged_ids.contains(&segment.id));
+            merge_candidates.push(MergeCandidate(
+                best_merge.iter().map(|segment| segment.id).collect(),
+            ));
+        }
+        merge_candidates
+    }
+}
+
+impl Default for TieredMergePolicy {
+    fn default() -> TieredMergePolicy {
+        TieredMergePolicy {
+            max_merged_segment_bytes: DEFAULT_MAX_MERGED_SEGMENT_BYTES,
+            floor_segment_bytes: DEFAULT_FLOOR_SEGMENT_BYTES,
+            segments_per_tier: DEFAULT_SEGMENTS_PER_TIER,
+            max_merge_at_once: DEFAULT_MAX_MERGE_AT_ONCE,
+            del_docs_ratio_allowed: DEFAULT_DEL_DOCS_RATIO_ALLOWED,
+            max_concurrent_merges: DEFAULT_MAX_CONCURRENT_MERGES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::sync::Arc;
+
+    use once_cell::sync::Lazy;
+
+    use super::*;
+    use crate::index::SegmentMetaInventory;
+    use crate::indexer::{InFlightMerge, MergePriority, MergeSchedulerSettings};
+    use crate::schema::{Schema, INDEXED};
+    use crate::{Index, IndexWriter};
+
+    static INVENTORY: Lazy<SegmentMetaInventory> = Lazy::new(SegmentMetaInventory::default);
+
+    const MB: u64 = 1024 * 1024;
+
+    fn create_segment_meta(num_docs: u32, num_bytes: u64) -> SegmentMeta {
+        INVENTORY
+            .new_segment_meta(SegmentId::generate_random(), num_docs, 0)
+            .with_num_bytes(num_bytes)
+    }
+
+    fn test_merge_policy() -> TieredMergePolicy {
+        let mut tiered_merge_policy = TieredMergePolicy::default();
+        tiered_merge_policy.set_segments_per_tier(3.0);
+        tiered_merge_policy.set_max_merge_at_once(3);
+        tiered_merge_policy.set_floor_segment_bytes(MB);
+        tiered_merge_policy.set_max_merged_segment_bytes(100 * MB);
+        tiered_merge_policy
+    }
+
+    fn merged_sizes(segments: &[SegmentMeta], candidates: &[MergeCandidate]) -> Vec<Vec<u64>> {
+        candidates
+            .iter()
+            .map(|candidate| {
+                let mut sizes: Vec<u64> = candidate
+                    .0
+                    .iter()
+                    .map(|segment_id| {
+                        segments
+                            .iter()
+                            .find(|segment| segment.id() == *segment_id)
+                            .and_then(SegmentMeta::num_bytes)
+                            .unwrap()
+                    })
+                    .collect();
+                sizes.sort();
+                sizes
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_empty() {
+        assert!(test_merge_policy().compute_merge_candidates(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_within_budget() {
+        let segments = vec![
+            create_segment_meta(10, MB),
+            create_segment_meta(10, MB),
+            create_segment_meta(10, MB),
+        ];
+        assert!(test_merge_policy()
+            .compute_merge_candidates(&segments)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_merges_similar_sizes() {
+        let mut segments = vec![create_segment_meta(100, 20 * MB)];
+        segments.extend((0..10).map(|_| create_segment_meta(10, MB)));
+        let candidates = test_merge_policy().compute_merge_candidates(&segments);
+        assert_eq!(merged_sizes(&segments, &candidates), vec![vec![MB, MB, MB]]);
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_max_merged_segment_bytes() {
+        let mut segments = vec![create_segment_meta(100, 60 * MB)];
+        segments.extend((0..5).map(|_| create_segment_meta(100, 40 * MB)));
+        let candidates = test_merge_policy().compute_merge_candidates(&segments);
+        // The segment larger than half of the maximum size is left alone, and
+        // the merge of the other segments is capped to the maximum size.
+        assert_eq!(
+            merged_sizes(&segments, &candidates),
+            vec![vec![40 * MB, 40 * MB]]
+        );
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_reclaims_deletes() {
+        let segment_with_deletes = |num_bytes: u64| {
+            INVENTORY
+                .new_segment_meta(SegmentId::generate_random(), 100, 0)
+                .with_delete_meta(50, 0)
+                .with_num_bytes(num_bytes)
+        };
+        // A large segment with many deletes gets rewritten on its own.
+        let segments = vec![segment_with_deletes(80 * MB)];
+        let candidates = test_merge_policy().compute_merge_candidates(&segments);
+        assert_eq!(merged_sizes(&segments, &candidates), vec![vec![80 * MB]]);
+
+        // Merges reclaiming deletes are preferred.
+        let mut segments: Vec<SegmentMeta> =
+            (0..3).map(|_| create_segment_meta(100, 10 * MB)).collect();
+        segments.push(segment_with_deletes(10 * MB));
+        let candidates = test_merge_policy().compute_merge_candidates(&segments);
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates[0].0.contains(&segments[3].id()));
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_max_concurrent_merges() {
+        let segments: Vec<SegmentMeta> = (0..30).map(|_| create_segment_meta(10, MB)).collect();
+        let mut merge_policy = test_merge_policy();
+        assert_eq!(merge_policy.compute_merge_candidates(&segments).len(), 4);
+        merge_policy.set_max_concurrent_merges(2);
+        assert_eq!(merge_policy.compute_merge_candidates(&segments).len(), 2);
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_in_flight_merges() {
+        let segments: Vec<SegmentMeta> = (0..30).map(|_| create_segment_meta(10, MB)).collect();
+        let in_flight_merge = InFlightMerge {
+            segment_ids: Vec::new(),
+            num_bytes: 3 * MB,
+            priority: MergePriority::Normal,
+            is_running: true,
+        };
+        let merge_policy = test_merge_policy();
+        let context = MergePolicyContext::new(vec![in_flight_merge.clone()], 4, &segments);
+        assert_eq!(
+            merge_policy
+                .compute_merge_candidates_with_context(&segments, &context)
+                .len(),
+            3
+        );
+        let context = MergePolicyContext::new(vec![in_flight_merge; 2], 2, &segments);
+        assert!(merge_policy
+            .compute_merge_candidates_with_context(&segments, &context)
+            .is_empty());
+    }
+
+    /// Records the largest number of merges in flight once the candidates it computes
+    /// are scheduled.
+    #[derive(Debug)]
+    struct RecordingMergePolicy {
+        merge_policy: TieredMergePolicy,
+        max_in_flight_merges: Arc<AtomicUsize>,
+    }
+
+    impl MergePolicy for RecordingMergePolicy {
+        fn compute_merge_candidates(&self, segments: &[SegmentMeta]) -> Vec<MergeCandidate> {
+            self.merge_policy.compute_merge_candidates(segments)
+        }
+
+        fn compute_merge_candidates_with_context(
+            &self,
+            segments: &[SegmentMeta],
+            context: &MergePolicyContext,
+        ) -> Vec<MergeCandidate> {
+            let merge_candidates = self
+                .merge_policy
+                .compute_merge_candidates_with_context(segments, context);
+            self.max_in_flight_merges.fetch_max(
+                context.in_flight_merges().len() + merge_candidates.len(),
+                AtomicOrdering::SeqCst,
+            );
+            merge_candidates
+        }
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_max_concurrent_merges_across_calls() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let int_field = schema_builder.add_u64_field("intval", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut tiered_merge_policy = TieredMergePolicy::default();
+        tiered_merge_policy.set_segments_per_tier(2.0);
+        tiered_merge_policy.set_max_merge_at_once(2);
+        tiered_merge_policy.set_max_concurrent_merges(1);
+        let max_in_flight_merges = Arc::new(AtomicUsize::new(0));
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(RecordingMergePolicy {
+            merge_policy: tiered_merge_policy,
+            max_in_flight_merges: max_in_flight_merges.clone(),
+        }));
+        // Slow merges stay in flight while the next commits call the merge policy.
+        index_writer.set_merge_scheduler_settings(MergeSchedulerSettings {
+            max_merge_bytes_per_sec: Some(10_000),
+            ..Default::default()
+        })?;
+        for val in 0..6u64 {
+            index_writer.add_document(doc!(int_field=>val))?;
+            index_writer.commit()?;
+        }
+        index_writer.wait_merging_threads()?;
+        assert_eq!(max_in_flight_merges.load(AtomicOrdering::SeqCst), 1);
+        assert!(index.searchable_segment_metas()?.len() < 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_unknown_sizes() {
+        // The size of the segments is estimated from the size of the documents of the
+        // segments whose size is known.
+        let mut segments: Vec<SegmentMeta> = (0..7)
+            .map(|_| INVENTORY.new_segment_meta(SegmentId::generate_random(), 10, 0))
+            .collect();
+        segments.push(create_segment_meta(10, MB));
+        let candidates = test_merge_policy().compute_merge_candidates(&segments);
+        assert!(!candidates.is_empty());
+        assert_eq!(candidates[0].0.len(), 3);
+    }
+
+    #[test]
+    fn test_tiered_merge_policy_records_segment_sizes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let int_field = schema_builder.add_u64_field("intval", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut tiered_merge_policy = TieredMergePolicy::default();
+        tiered_merge_policy.set_segments_per_tier(2.0);
+        tiered_merge_policy.set_max_merge_at_once(2);
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.set_merge_policy(Box::new(tiered_merge_policy));
+        for val in 0..4u64 {
+            index_writer.add_document(doc!(int_field=>val))?;
+            index_writer.commit()?;
+        }
+        index_writer.wait_merging_threads()?;
+        let segment_metas = index.searchable_segment_metas()?;
+        assert!(segment_metas.len() <= 2);
+        assert!(segment_metas
+            .iter()
+            .all(|segment_meta| segment_meta.num_bytes().unwrap() > 0));
+        let reader = index.reader()?;
+        assert_eq!(reader.searcher().num_docs(), 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,540 @@
+//! Write-ahead log of the `IndexWriter`.
+//!
+//! When [`IndexSettings::write_ahead_log`](crate::IndexSettings::write_ahead_log) is enabled,
+//! every operation of the `IndexWriter` is appended to the log, together with its opstamp,
+//! before the call returns. Documents are handed to the indexing pipeline before they are
+//! logged, so that the documents rejected by the pipeline never make it to the log, while
+//! deletes and numeric updates are logged before they are applied. Either way, an operation is
+//! logged before the next commit. The log is replaced by an empty one at each commit, and
+//! replayed when a new `IndexWriter` is opened, so that the operations which were not
+//! committed before a crash are recovered.
+//!
+//! If a record cannot be appended, the log is marked as failed: the operation it holds may
+//! already be applied, but could not be recovered. The `IndexWriter` then rejects every
+//! operation and commit until it is rolled back.
+//!
+//! The log of the commit with opstamp `N` is the file `wal.N.log`. Since a new log is only
+//! used once the `meta.json` of its commit is saved, a crash in the middle of a commit
+//! leaves either the previous meta and its complete log, or the new meta.
+//!
+//! The log is a sequence of records, each of them holding the operations of one call to the
+//! `IndexWriter`. A record is framed by the length and the crc32 of its payload. Reading
+//! the log stops at the first truncated or corrupted record, which can only be the last
+//! record written before a crash.
+//!
+//! Records are flushed to the operating system as they are appended. They survive a crash
+//! of the process, but are only guaranteed to survive a power loss after a commit.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use columnar::{NumericalType, NumericalValue};
+use common::{BinarySerializable, VInt};
+
+use crate::directory::error::{DeleteError, OpenReadError};
+use crate::directory::{Directory, WritePtr};
+use crate::error::DataCorruption;
+use crate::schema::document::{
+    BinaryDocumentDeserializer, BinaryDocumentSerializer, Document, DocumentDeserialize,
+};
+use crate::schema::{Field, Schema, TantivyDocument, Term};
+use crate::{Opstamp, TantivyError};
+
+const ADD_CODE: u8 = 0;
+const UPSERT_CODE: u8 = 1;
+const DELETE_CODE: u8 = 2;
+const NUMERIC_UPDATE_CODE: u8 = 3;
+const DELETE_ALL_CODE: u8 = 4;
+
+/// Size of the header of a record: the length and the crc32 of its payload.
+const RECORD_HEADER_LEN: usize = 8;
+
+/// Returns the path of the write-ahead log following the commit with the given opstamp.
+pub(crate) fn write_ahead_log_path(opstamp: Opstamp) -> PathBuf {
+    PathBuf::from(format!("wal.{opstamp}.log"))
+}
+
+/// An operation read from the write-ahead log.
+#[derive(Debug)]
+pub(crate) enum LoggedOperation {
+    Add(TantivyDocument),
+    Upsert(TantivyDocument),
+    Delete(Term),
+    NumericUpdate {
+        key: Term,
+        field: Field,
+        value: NumericalValue,
+    },
+}
+
+/// The operations recovered from the write-ahead log.
+#[derive(Debug, Default)]
+pub(crate) struct RecoveredOperations {
+    /// True if all documents were deleted before the recovered operations.
+    pub delete_all: bool,
+    pub operations: Vec<(Opstamp, LoggedOperation)>,
+}
+
+impl RecoveredOperations {
+    pub fn is_empty(&self) -> bool {
+        !self.delete_all && self.operations.is_empty()
+    }
+}
+
+/// A group of operations, appended atomically to the write-ahead log.
+#[derive(Default)]
+pub(crate) struct WalRecord {
+    buffer: Vec<u8>,
+}
+
+impl WalRecord {
+    pub fn add_document<D: Document>(
+        &mut self,
+        opstamp: Opstamp,
+        document: &D,
+        schema: &Schema,
+    ) -> io::Result<()> {
+        self.push_document(opstamp, ADD_CODE, document, schema)
+    }
+
+    pub fn upsert_document<D: Document>(
+        &mut self,
+        opstamp: Opstamp,
+        document: &D,
+        schema: &Schema,
+    ) -> io::Result<()> {
+        self.push_document(opstamp, UPSERT_CODE, document, schema)
+    }
+
+    pub fn delete_term(&mut self, opstamp: Opstamp, term: &Term) -> io::Result<()> {
+        self.push_header(opstamp, DELETE_CODE)?;
+        push_term(&mut self.buffer, term)
+    }
+
+    pub fn update_numeric(
+        &mut self,
+        opstamp: Opstamp,
+        key: &Term,
+        field: Field,
+        value: NumericalValue,
+    ) -> io::Result<()> {
+        self.push_header(opstamp, NUMERIC_UPDATE_CODE)?;
+        push_term(&mut self.buffer, key)?;
+        field.serialize(&mut self.buffer)?;
+        value
+            .numerical_type()
+            .to_code()
+            .serialize(&mut self.buffer)?;
+        let raw_value = match value {
+            NumericalValue::I64(val) => val as u64,
+            NumericalValue::U64(val) => val,
+            NumericalValue::F64(val) => val.to_bits(),
+        };
+        raw_value.serialize(&mut self.buffer)
+    }
+
+    pub fn delete_all(&mut self, opstamp: Opstamp) -> io::Result<()> {
+        self.push_header(opstamp, DELETE_ALL_CODE)
+    }
+
+    fn push_header(&mut self, opstamp: Opstamp, code: u8) -> io::Result<()> {
+        opstamp.serialize(&mut self.buffer)?;
+        code.serialize(&mut self.buffer)
+    }
+
+    fn push_document<D: Document>(
+        &mut self,
+        opstamp: Opstamp,
+        code: u8,
+        document: &D,
+        schema: &Schema,
+    ) -> io::Result<()> {
+        self.push_header(opstamp, code)?;
+        let mut doc_bytes = Vec::new();
+        BinaryDocumentSerializer::new(&mut doc_bytes, schema)
+            .serialize_doc_with_all_fields(document)?;
+        VInt(doc_bytes.len() as u64).serialize(&mut self.buffer)?;
+        self.buffer.extend_from_slice(&doc_bytes);
+        Ok(())
+    }
+}
+
+fn push_term(buffer: &mut Vec<u8>, term: &Term) -> io::Result<()> {
+    let term_bytes = term.serialized_term();
+    VInt(term_bytes.len() as u64).serialize(buffer)?;
+    buffer.extend_from_slice(term_bytes);
+    Ok(())
+}
+
+fn read_bytes<'a>(payload: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = VInt::deserialize(payload)?.val() as usize;
+    if payload.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Truncated operation.",
+        ));
+    }
+    let (bytes, rest) = payload.split_at(len);
+    *payload = rest;
+    Ok(bytes)
+}
+
+fn read_term(payload: &mut &[u8]) -> io::Result<Term> {
+    let term_bytes = read_bytes(payload)?;
+    if term_bytes.len() < 5 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid term."));
+    }
+    Ok(Term::wrap(term_bytes.to_vec()))
+}
+
+fn read_document(payload: &mut &[u8]) -> crate::Result<TantivyDocument> {
+    let mut doc_bytes = read_bytes(payload)?;
+    let deserializer = BinaryDocumentDeserializer::from_reader(&mut doc_bytes)?;
+    Ok(TantivyDocument::deserialize(deserializer)?)
+}
+
+/// Returns the payloads of the valid records of the log.
+fn read_records(mut bytes: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    while bytes.len() >= RECORD_HEADER_LEN {
+        let mut header = &bytes[..RECORD_HEADER_LEN];
+        let (Ok(len), Ok(checksum)) =
+            (u32::deserialize(&mut header), u32::deserialize(&mut header))
+        else {
+            break;
+        };
+        let record_end = RECORD_HEADER_LEN + len as usize;
+        if bytes.len() < record_end {
+            break;
+        }
+        let payload = &bytes[RECORD_HEADER_LEN..record_end];
+        if crc32fast::hash(payload) != checksum {
+            break;
+        }
+        records.push(payload);
+        bytes = &bytes[record_end..];
+    }
+    records
+}
+
+fn decode_operations(mut payload: &[u8], recovered: &mut RecoveredOperations) -> crate::Result<()> {
+    while !payload.is_empty() {
+        let opstamp = Opstamp::deserialize(&mut payload)?;
+        let code = u8::deserialize(&mut payload)?;
+        let operation = match code {
+            ADD_CODE => LoggedOperation::Add(read_document(&mut payload)?),
+            UPSERT_CODE => LoggedOperation::Upsert(read_document(&mut payload)?),
+            DELETE_CODE => LoggedOperation::Delete(read_term(&mut payload)?),
+            NUMERIC_UPDATE_CODE => {
+                let key = read_term(&mut payload)?;
+                let field = Field::deserialize(&mut payload)?;
+                let numerical_type = NumericalType::try_from_code(u8::deserialize(&mut payload)?)
+                    .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Invalid numerical type.")
+                })?;
+                let raw_value = u64::deserialize(&mut payload)?;
+                let value = match numerical_type {
+                    NumericalType::I64 => NumericalValue::I64(raw_value as i64),
+                    NumericalType::U64 => NumericalValue::U64(raw_value),
+                    NumericalType::F64 => NumericalValue::F64(f64::from_bits(raw_value)),
+                };
+                LoggedOperation::NumericUpdate { key, field, value }
+            }
+            DELETE_ALL_CODE => {
+                // The operations logged before deleting all documents are void.
+                recovered.delete_all = true;
+                recovered.operations.clear();
+                continue;
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown operation code {code}."),
+                )
+                .into());
+            }
+        };
+        recovered.operations.push((opstamp, operation));
+    }
+    Ok(())
+}
+
+struct WalState {
+    path: PathBuf,
+    writer: Option<WritePtr>,
+    // Set when a record could not be appended.
+    failed: bool,
+}
+
+/// The write-ahead log of an `IndexWriter`.
+pub(crate) struct WriteAheadLog {
+    directory: Box<dyn Directory>,
+    state: Mutex<WalState>,
+}
+
+impl WriteAheadLog {
+    /// Opens the write-ahead log following the commit with opstamp `committed_opstamp`,
+    /// and returns the operations it contains.
+    ///
+    /// If some operations are recovered, the log is kept as is until the next commit, and
+    /// nothing can be appended to it in the meantime.
+    pub fn open(
+        directory: Box<dyn Directory>,
+        committed_opstamp: Opstamp,
+    ) -> crate::Result<(WriteAheadLog, RecoveredOperations)> {
+        let path = write_ahead_log_path(committed_opstamp);
+        let bytes = match directory.atomic_read(&path) {
+            Ok(bytes) => bytes,
+            Err(OpenReadError::FileDoesNotExist(_)) => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut recovered = RecoveredOperations::default();
+        for payload in read_records(&bytes) {
+            decode_operations(payload, &mut recovered).map_err(|err| {
+                TantivyError::DataCorruption(DataCorruption::new(
+                    path.clone(),
+                    format!("Invalid write-ahead log record: {err}"),
+                ))
+            })?;
+        }
+        let writer = if recovered.is_empty() {
+            delete_if_exists(directory.as_ref(), &path)?;
+            let mut writer = create_log(directory.as_ref(), &path)?;
+            if !is_flushed_log_readable(directory.as_ref(), &path, &mut writer)? {
+                drop(writer);
+                delete_if_exists(directory.as_ref(), &path)?;
+                return Err(TantivyError::InvalidArgument(
+                    "The write-ahead log requires a directory where the content of a flushed file \
+                     is readable before its writer is terminated."
+                        .to_string(),
+                ));
+            }
+            Some(writer)
+        } else {
+            None
+        };
+        let write_ahead_log = WriteAheadLog {
+            directory,
+            state: Mutex::new(WalState {
+                path,
+                writer,
+                failed: false,
+            }),
+        };
+        Ok((write_ahead_log, recovered))
+    }
+
+    /// Appends a record to the log, and flushes it.
+    pub fn append(&self, record: &WalRecord) -> crate::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.failed {
+            return Err(failed_log_error());
+        }
+        let writer = state.writer.as_mut().ok_or_else(|| {
+            TantivyError::InvalidArgument(
+                "The write-ahead log cannot be appended to before the recovered operations are \
+                 committed."
+                    .to_string(),
+            )
+        })?;
+        if let Err(io_error) = write_record(writer, record) {
+            state.failed = true;
+            return Err(io_error.into());
+        }
+        Ok(())
+    }
+
+    /// Returns an error if a record could not be appended to the log.
+    pub fn check_not_failed(&self) -> crate::Result<()> {
+        if self.state.lock().unwrap().failed {
+            return Err(failed_log_error());
+        }
+        Ok(())
+    }
+
+    /// Replaces the log by an empty log following the commit with opstamp `commit_opstamp`.
+    ///
+    /// Must be called once the metas of the commit are saved.
+    pub fn rotate(&self, commit_opstamp: Opstamp) -> crate::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let new_path = write_ahead_log_path(commit_opstamp);
+        if new_path == state.path && state.writer.is_some() {
+            return Ok(());
+        }
+        delete_if_exists(self.directory.as_ref(), &new_path)?;
+        let new_writer = create_log(self.directory.as_ref(), &new_path)?;
+        let old_path = std::mem::replace(&mut state.path, new_path);
+        // The former writer has to be dropped before its file gets deleted.
+        state.writer = Some(new_writer);
+        if old_path != state.path {
+            // If the deletion fails, the file is removed by the next garbage collection.
+            let _ = self.directory.delete(&old_path);
+        }
+        Ok(())
+    }
+
+    /// Replaces the content of the log by a single record.
+    pub fn restart(&self, record: &WalRecord) -> crate::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.failed {
+            return Err(failed_log_error());
+        }
+        state.writer = None;
+        delete_if_exists(self.directory.as_ref(), &state.path)?;
+        let mut writer = create_log(self.directory.as_ref(), &state.path)?;
+        write_record(&mut writer, record)?;
+        state.writer = Some(writer);
+        Ok(())
+    }
+
+    /// Deletes the log, discarding the operations it contains.
+    pub fn discard(&self) -> crate::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.writer = None;
+        delete_if_exists(self.directory.as_ref(), &state.path)
+    }
+}
+
+fn failed_log_error() -> TantivyError {
+    TantivyError::SystemError(
+        "An operation could not be recorded in the write-ahead log. The index writer has to be \
+         rolled back."
+            .to_string(),
+    )
+}
+
+fn write_record(writer: &mut WritePtr, record: &WalRecord) -> io::Result<()> {
+    crate::fail_point!("WriteAheadLog::write_record", |_| {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "simulated write failure",
+        ))
+    });
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    let mut header_writer = &mut header[..];
+    (record.buffer.len() as u32).serialize(&mut header_writer)?;
+    crc32fast::hash(&record.buffer).serialize(&mut header_writer)?;
+    writer.write_all(&header)?;
+    writer.write_all(&record.buffer)?;
+    writer.flush()
+}
+
+fn create_log(directory: &dyn Directory, path: &Path) -> crate::Result<WritePtr> {
+    let mut writer = directory.open_write(path)?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// Appends an empty record to the freshly created log at `path`, and returns true if it can
+/// be read back before the log is terminated, as the records are read after a crash while
+/// they were only flushed.
+fn is_flushed_log_readable(
+    directory: &dyn Directory,
+    path: &Path,
+    writer: &mut WritePtr,
+) -> io::Result<bool> {
+    write_record(writer, &WalRecord::default())?;
+    Ok(matches!(directory.atomic_read(path), Ok(bytes) if read_records(&bytes).len() == 1))
+}
+
+fn delete_if_exists(directory: &dyn Directory, path: &Path) -> crate::Result<()> {
+    match directory.delete(path) {
+        Ok(()) | Err(DeleteError::FileDoesNotExist(_)) => Ok(()),
+        Err(DeleteError::IoError { io_error, .. }) => Err(TantivyError::IoError(io_error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directory::RamDirectory;
+    use crate::schema::{Schema, FAST, INDEXED, TEXT};
+
+    #[test]
+    fn test_write_ahead_log_recovers_valid_records() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let score_field = schema_builder.add_f64_field("score", FAST);
+        let schema = schema_builder.build();
+        let directory = RamDirectory::create();
+        let (write_ahead_log, recovered) = WriteAheadLog::open(Box::new(directory.clone()), 3)?;
+        assert!(recovered.is_empty());
+
+        let mut record = WalRecord::default();
+        record.add_document(3, &doc!(id_field=>1u64, text_field=>"hello"), &schema)?;
+        record.delete_term(4, &Term::from_field_u64(id_field, 2))?;
+        write_ahead_log.append(&record)?;
+        let mut record = WalRecord::default();
+        record.update_numeric(
+            5,
+            &Term::from_field_u64(id_field, 1),
+            score_field,
+            NumericalValue::F64(1.5),
+        )?;
+        write_ahead_log.append(&record)?;
+        drop(write_ahead_log);
+
+        // A truncated record is ignored.
+        let path = write_ahead_log_path(3);
+        let mut bytes = directory.atomic_read(&path)?;
+        bytes.extend_from_slice(&[10, 0, 0, 0, 1, 2]);
+        directory.atomic_write(&path, &bytes)?;
+
+        let (write_ahead_log, recovered) = WriteAheadLog::open(Box::new(directory.clone()), 3)?;
+        assert!(!recovered.delete_all);
+        assert_eq!(recovered.operations.len(), 3);
+        assert!(matches!(
+            &recovered.operations[0],
+            (3, LoggedOperation::Add(doc)) if doc.get_first(text_field).is_some()
+        ));
+        assert!(matches!(
+            &recovered.operations[1],
+            (4, LoggedOperation::Delete(term)) if *term == Term::from_field_u64(id_field, 2)
+        ));
+        assert!(matches!(
+            &recovered.operations[2],
+            (5, LoggedOperation::NumericUpdate { field, value: NumericalValue::F64(val), .. })
+                if *field == score_field && *val == 1.5
+        ));
+        // Nothing can be appended until the recovered operations are committed.
+        assert!(write_ahead_log.append(&WalRecord::default()).is_err());
+
+        write_ahead_log.rotate(6)?;
+        assert!(!directory.exists(&path)?);
+        let (_, recovered) = WriteAheadLog::open(Box::new(directory.clone()), 6)?;
+        assert!(recovered.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_ahead_log_restart() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let schema = schema_builder.build();
+        let directory = RamDirectory::create();
+        let (write_ahead_log, _) = WriteAheadLog::open(Box::new(directory.clone()), 0)?;
+        let mut record = WalRecord::default();
+        record.add_document(0, &doc!(id_field=>1u64), &schema)?;
+        write_ahead_log.append(&record)?;
+        let mut record = WalRecord::default();
+        record.delete_all(0)?;
+        write_ahead_log.restart(&record)?;
+        let mut record = WalRecord::default();
+        record.upsert_document(0, &doc!(id_field=>2u64), &schema)?;
+        write_ahead_log.append(&record)?;
+        drop(write_ahead_log);
+
+        let (write_ahead_log, recovered) = WriteAheadLog::open(Box::new(directory.clone()), 0)?;
+        assert!(recovered.delete_all);
+        assert_eq!(recovered.operations.len(), 1);
+        assert!(matches!(
+            &recovered.operations[0],
+            (0, LoggedOperation::Upsert(_))
+        ));
+        write_ahead_log.discard()?;
+        assert!(!directory.exists(&write_ahead_log_path(0))?);
+        Ok(())
+    }
+}
@@ -222,8 +222,8 @@ pub use crate::core::{Executor, Searcher, SearcherGeneration};
 pub use crate::directory::Directory;
 #[allow(deprecated)] // Remove with index sorting
 pub use crate::index::{
-    Index, IndexBuilder, IndexMeta, IndexSettings, InvertedIndexReader, Order, Segment,
-    SegmentMeta, SegmentReader,
+    Index, IndexBuilder, IndexCommit, IndexMeta, IndexSettings, IndexSnapshot, IndexSortByField,
+    InvertedIndexReader, Order, Segment, SegmentMeta, SegmentReader, VersionedSchema,
 };
 pub use crate::indexer::{IndexWriter, SingleSegmentIndexWriter};
 pub use crate::schema::{Document, TantivyDocument, Term};
@@ -282,7 +282,9 @@ pub fn version_string() -> &'static str {
 /// Defines tantivy's merging strategy
 pub mod merge_policy {
     pub use crate::indexer::{
-        DefaultMergePolicy, LogMergePolicy, MergeCandidate, MergePolicy, NoMergePolicy,
+        DefaultMergePolicy, InFlightMerge, LogMergePolicy, MergeCandidate, MergePolicy,
+        MergePolicyContext, MergePriority, MergeSchedulerSettings, NoMergePolicy,
+        TieredMergePolicy,
     };
 }
 
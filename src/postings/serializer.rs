@@ -61,7 +61,7 @@ impl InvertedIndexSerializer {
             terms_write: CompositeWrite::wrap(segment.open_write(Terms)?),
             postings_write: CompositeWrite::wrap(segment.open_write(Postings)?),
             positions_write: CompositeWrite::wrap(segment.open_write(Positions)?),
-            schema: segment.schema(),
+            schema: segment.segment_schema(),
         };
         Ok(inv_index_serializer)
     }
@@ -107,6 +107,32 @@ where
             ))
         }
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        Box::pin(async move {
+            let inverted_index = reader.inverted_index(self.field)?;
+            let term_dict = inverted_index.terms();
+            // The automaton can only be run on the term dictionary once it is available.
+            term_dict.warm_up_dictionary().await?;
+            let (first_term_info, last_term_info) = {
+                let mut term_stream = self.automaton_stream(term_dict)?;
+                if !term_stream.advance() {
+                    return Ok(());
+                }
+                let first_term_info = term_stream.value().clone();
+                let mut last_term_info = first_term_info.clone();
+                while term_stream.advance() {
+                    last_term_info = term_stream.value().clone();
+                }
+                (first_term_info, last_term_info)
+            };
+            inverted_index
+                .warm_postings_between(&first_term_info, &last_term_info, false)
+                .await?;
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1,3 +1,6 @@
+use std::borrow::Borrow;
+use std::sync::Arc;
+
 use common::{BitSet, TinySet};
 
 use crate::docset::{DocSet, TERMINATED};
@@ -10,28 +13,25 @@ use crate::DocId;
 /// Skipping is relatively fast here as we can directly point to the
 /// right tiny bitset bucket.
 ///
+/// The bitset can either be owned, or shared through an `Arc<BitSet>`
+/// (as done by the [`QueryCache`](crate::query::QueryCache)).
+///
 /// TODO: Consider implementing a `BitTreeSet` in order to advance faster
 /// when the bitset is sparse
-pub struct BitSetDocSet {
-    docs: BitSet,
+pub struct BitSetDocSet<TBitSet: Borrow<BitSet> = BitSet> {
+    docs: TBitSet,
     cursor_bucket: u32, //< index associated with the current tiny bitset
     cursor_tinybitset: TinySet,
     doc: u32,
 }
 
-impl BitSetDocSet {
-    fn go_to_bucket(&mut self, bucket_addr: u32) {
-        self.cursor_bucket = bucket_addr;
-        self.cursor_tinybitset = self.docs.tinyset(bucket_addr);
-    }
-}
-
-impl From<BitSet> for BitSetDocSet {
-    fn from(docs: BitSet) -> BitSetDocSet {
-        let first_tiny_bitset = if docs.max_value() == 0 {
+impl<TBitSet: Borrow<BitSet> + Send> BitSetDocSet<TBitSet> {
+    fn new(docs: TBitSet) -> BitSetDocSet<TBitSet> {
+        let bitset: &BitSet = docs.borrow();
+        let first_tiny_bitset = if bitset.max_value() == 0 {
             TinySet::empty()
         } else {
-            docs.tinyset(0)
+            bitset.tinyset(0)
         };
         let mut docset = BitSetDocSet {
             docs,
@@ -42,16 +42,37 @@ impl From<BitSet> for BitSetDocSet {
         docset.advance();
         docset
     }
+
+    fn go_to_bucket(&mut self, bucket_addr: u32) {
+        self.cursor_bucket = bucket_addr;
+        self.cursor_tinybitset = self.docs.borrow().tinyset(bucket_addr);
+    }
 }
 
-impl DocSet for BitSetDocSet {
+impl From<BitSet> for BitSetDocSet {
+    fn from(docs: BitSet) -> BitSetDocSet {
+        BitSetDocSet::new(docs)
+    }
+}
+
+impl From<Arc<BitSet>> for BitSetDocSet<Arc<BitSet>> {
+    fn from(docs: Arc<BitSet>) -> BitSetDocSet<Arc<BitSet>> {
+        BitSetDocSet::new(docs)
+    }
+}
+
+impl<TBitSet: Borrow<BitSet> + Send> DocSet for BitSetDocSet<TBitSet> {
     #[inline]
     fn advance(&mut self) -> DocId {
         if let Some(lower) = self.cursor_tinybitset.pop_lowest() {
             self.doc = (self.cursor_bucket * 64u32) | lower;
             return self.doc;
         }
-        if let Some(cursor_bucket) = self.docs.first_non_empty_bucket(self.cursor_bucket + 1) {
+        if let Some(cursor_bucket) = self
+            .docs
+            .borrow()
+            .first_non_empty_bucket(self.cursor_bucket + 1)
+        {
             self.go_to_bucket(cursor_bucket);
             let lower = self.cursor_tinybitset.pop_lowest().unwrap();
             self.doc = (cursor_bucket * 64u32) | lower;
@@ -63,7 +84,7 @@ impl DocSet for BitSetDocSet {
     }
 
     fn seek(&mut self, target: DocId) -> DocId {
-        if target >= self.docs.max_value() {
+        if target >= self.docs.borrow().max_value() {
             self.doc = TERMINATED;
             return TERMINATED;
         }
@@ -89,7 +110,7 @@ impl DocSet for BitSetDocSet {
 
     /// Returns the number of values set in the underlying bitset.
     fn size_hint(&self) -> u32 {
-        self.docs.len() as u32
+        self.docs.borrow().len() as u32
     }
 }
 
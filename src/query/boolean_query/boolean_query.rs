@@ -1,5 +1,9 @@
 use super::boolean_weight::BooleanWeight;
-use crate::query::{EnableScoring, Occur, Query, SumWithCoordsCombiner, TermQuery, Weight};
+use crate::query::query_cache::maybe_cache_clause;
+use crate::query::{
+    profile, EnableScoring, Occur, Query, QueryFingerprint, SumWithCoordsCombiner, TermQuery,
+    Weight,
+};
 use crate::schema::{IndexRecordOption, Term};
 
 /// The boolean query returns a set of documents
@@ -163,7 +167,16 @@ impl Query for BooleanQuery {
         let sub_weights = self
             .subqueries
             .iter()
-            .map(|(occur, subquery)| Ok((*occur, subquery.weight(enable_scoring)?)))
+            .map(|(occur, subquery)| {
+                let weight = profile::weight(subquery.as_ref(), enable_scoring)?;
+                // Clauses that do not contribute to the score may be served by the query cache.
+                let weight = if *occur == Occur::MustNot || !enable_scoring.is_scoring_enabled() {
+                    maybe_cache_clause(subquery.as_ref(), weight, &enable_scoring)
+                } else {
+                    weight
+                };
+                Ok((*occur, weight))
+            })
             .collect::<crate::Result<_>>()?;
         Ok(Box::new(BooleanWeight::with_minimum_number_should_match(
             sub_weights,
@@ -178,6 +191,22 @@ impl Query for BooleanQuery {
             subquery.query_terms(visitor);
         }
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("boolean");
+        fingerprint.push_u64(self.minimum_number_should_match as u64);
+        fingerprint.push_u64(self.subqueries.len() as u64);
+        for (occur, subquery) in &self.subqueries {
+            let occur_code = match occur {
+                Occur::Should => 0,
+                Occur::Must => 1,
+                Occur::MustNot => 2,
+            };
+            fingerprint.push_u64(occur_code);
+            fingerprint.push_fingerprint(&subquery.fingerprint()?);
+        }
+        Some(fingerprint)
+    }
 }
 
 impl BooleanQuery {
@@ -353,6 +353,12 @@ impl<TScoreCombiner: ScoreCombiner + Sync> Weight for BooleanWeight<TScoreCombin
         }
         Ok(())
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        let weights = self.weights.iter().map(|(_, weight)| weight.as_ref());
+        Box::pin(crate::query::weight::warmup_weights(weights, reader))
+    }
 }
 
 fn is_positive_occur(occur: Occur) -> bool {
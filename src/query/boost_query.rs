@@ -2,7 +2,7 @@ use std::fmt;
 
 use crate::docset::COLLECT_BLOCK_BUFFER_LEN;
 use crate::fastfield::AliveBitSet;
-use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{profile, EnableScoring, Explanation, Query, QueryFingerprint, Scorer, Weight};
 use crate::{DocId, DocSet, Score, SegmentReader, Term};
 
 /// `BoostQuery` is a wrapper over a query used to boost its score.
@@ -39,7 +39,7 @@ impl fmt::Debug for BoostQuery {
 
 impl Query for BoostQuery {
     fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
-        let weight_without_boost = self.query.weight(enable_scoring)?;
+        let weight_without_boost = profile::weight(self.query.as_ref(), enable_scoring)?;
         let boosted_weight = if enable_scoring.is_scoring_enabled() {
             Box::new(BoostWeight::new(weight_without_boost, self.boost))
         } else {
@@ -51,6 +51,10 @@ impl Query for BoostQuery {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         self.query.query_terms(visitor)
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        self.query.fingerprint()
+    }
 }
 
 /// Weight associated to the BoostQuery.
@@ -83,6 +87,11 @@ impl Weight for BoostWeight {
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
         self.weight.count(reader)
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        self.weight.warmup(reader)
+    }
 }
 
 pub(crate) struct BoostScorer<S: Scorer> {
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use crate::docset::COLLECT_BLOCK_BUFFER_LEN;
-use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{profile, EnableScoring, Explanation, Query, QueryFingerprint, Scorer, Weight};
 use crate::{DocId, DocSet, Score, SegmentReader, TantivyError, Term};
 
 /// `ConstScoreQuery` is a wrapper over a query to provide a constant score.
@@ -38,7 +38,7 @@ impl fmt::Debug for ConstScoreQuery {
 
 impl Query for ConstScoreQuery {
     fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
-        let inner_weight = self.query.weight(enable_scoring)?;
+        let inner_weight = profile::weight(self.query.as_ref(), enable_scoring)?;
         Ok(if enable_scoring.is_scoring_enabled() {
             Box::new(ConstWeight::new(inner_weight, self.score))
         } else {
@@ -49,6 +49,10 @@ impl Query for ConstScoreQuery {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         self.query.query_terms(visitor);
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        self.query.fingerprint()
+    }
 }
 
 struct ConstWeight {
@@ -84,6 +88,11 @@ impl Weight for ConstWeight {
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
         self.weight.count(reader)
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        self.weight.warmup(reader)
+    }
 }
 
 /// Wraps a `DocSet` and simply returns a constant `Scorer`.
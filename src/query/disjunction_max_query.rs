@@ -1,4 +1,7 @@
-use crate::query::{BooleanWeight, DisjunctionMaxCombiner, EnableScoring, Occur, Query, Weight};
+use crate::query::{
+    profile, BooleanWeight, DisjunctionMaxCombiner, EnableScoring, Occur, Query, QueryFingerprint,
+    Weight,
+};
 use crate::{Score, Term};
 
 /// The disjunction max query returns documents matching one or more wrapped queries,
@@ -94,7 +97,12 @@ impl Query for DisjunctionMaxQuery {
         let disjuncts = self
             .disjuncts
             .iter()
-            .map(|disjunct| Ok((Occur::Should, disjunct.weight(enable_scoring)?)))
+            .map(|disjunct| {
+                Ok((
+                    Occur::Should,
+                    profile::weight(disjunct.as_ref(), enable_scoring)?,
+                ))
+            })
             .collect::<crate::Result<_>>()?;
         let tie_breaker = self.tie_breaker;
         Ok(Box::new(BooleanWeight::new(
@@ -109,6 +117,15 @@ impl Query for DisjunctionMaxQuery {
             disjunct.query_terms(visitor);
         }
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("disjunction_max");
+        fingerprint.push_u64(self.disjuncts.len() as u64);
+        for disjunct in &self.disjuncts {
+            fingerprint.push_fingerprint(&disjunct.fingerprint()?);
+        }
+        Some(fingerprint)
+    }
 }
 
 impl DisjunctionMaxQuery {
@@ -6,7 +6,7 @@ use super::{ConstScorer, EmptyScorer};
 use crate::docset::{DocSet, TERMINATED};
 use crate::index::SegmentReader;
 use crate::query::explanation::does_not_match;
-use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{EnableScoring, Explanation, Query, QueryFingerprint, Scorer, Weight};
 use crate::{DocId, Score, TantivyError};
 
 /// Query that matches all documents with a non-null value in the specified field.
@@ -45,6 +45,12 @@ impl Query for ExistsQuery {
             field_name: self.field_name.clone(),
         }))
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("exists");
+        fingerprint.push_bytes(self.field_name.as_bytes());
+        Some(fingerprint)
+    }
 }
 
 /// Weight associated with the `ExistsQuery` query.
@@ -82,6 +88,14 @@ impl Weight for ExistsWeight {
         }
         Ok(Explanation::new("ExistsQuery", 1.0))
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        Box::pin(crate::query::weight::warmup_fast_field(
+            reader,
+            &self.field_name,
+        ))
+    }
 }
 
 pub(crate) struct ExistsDocSet {
@@ -2,7 +2,7 @@ use levenshtein_automata::{Distance, LevenshteinAutomatonBuilder, DFA};
 use once_cell::sync::OnceCell;
 use tantivy_fst::Automaton;
 
-use crate::query::{AutomatonWeight, EnableScoring, Query, Weight};
+use crate::query::{AutomatonWeight, EnableScoring, Query, QueryFingerprint, Weight};
 use crate::schema::{Term, Type};
 use crate::TantivyError::InvalidArgument;
 
@@ -178,6 +178,15 @@ impl Query for FuzzyTermQuery {
     fn weight(&self, _enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
         Ok(Box::new(self.specialized_weight()?))
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("fuzzy_term");
+        fingerprint.push_term(&self.term);
+        fingerprint.push_u64(self.distance as u64);
+        fingerprint.push_u64(self.transposition_cost_one as u64);
+        fingerprint.push_u64(self.prefix as u64);
+        Some(fingerprint)
+    }
 }
 
 #[cfg(test)]
@@ -16,7 +16,9 @@ mod intersection;
 mod more_like_this;
 mod phrase_prefix_query;
 mod phrase_query;
+pub(crate) mod profile;
 mod query;
+mod query_cache;
 mod query_parser;
 mod range_query;
 mod regex_query;
@@ -52,7 +54,13 @@ pub use self::intersection::{intersect_scorers, Intersection};
 pub use self::more_like_this::{MoreLikeThisQuery, MoreLikeThisQueryBuilder};
 pub use self::phrase_prefix_query::PhrasePrefixQuery;
 pub use self::phrase_query::PhraseQuery;
+pub use self::profile::{
+    LookupProfile, QueryProfile, QueryProfileNode, SegmentCollectionProfile, SegmentScorerProfile,
+};
 pub use self::query::{EnableScoring, Query, QueryClone};
+pub use self::query_cache::{
+    CachedQuery, QueryCache, QueryCacheStats, QueryCachingPolicy, QueryFingerprint,
+};
 pub use self::query_parser::{QueryParser, QueryParserError};
 pub use self::range_query::{FastFieldRangeWeight, IPFastFieldRangeWeight, RangeQuery};
 pub use self::regex_query::RegexQuery;
@@ -66,6 +74,8 @@ pub use self::term_query::TermQuery;
 pub use self::union::Union;
 #[cfg(test)]
 pub use self::vec_docset::VecDocSet;
+#[cfg(feature = "quickwit")]
+pub use self::weight::WarmupFuture;
 pub use self::weight::Weight;
 
 #[cfg(test)]
@@ -2,7 +2,7 @@ use std::ops::Bound;
 
 use super::{prefix_end, PhrasePrefixWeight};
 use crate::query::bm25::Bm25Weight;
-use crate::query::{EnableScoring, Query, RangeQuery, Weight};
+use crate::query::{EnableScoring, Query, QueryFingerprint, RangeQuery, Weight};
 use crate::schema::{Field, IndexRecordOption, Term};
 
 const DEFAULT_MAX_EXPANSIONS: u32 = 50;
@@ -164,4 +164,17 @@ impl Query for PhrasePrefixQuery {
             visitor(term, true);
         }
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("phrase_prefix");
+        fingerprint.push_u64(self.max_expansions as u64);
+        fingerprint.push_u64(self.phrase_terms.len() as u64);
+        for (offset, term) in &self.phrase_terms {
+            fingerprint.push_u64(*offset as u64);
+            fingerprint.push_term(term);
+        }
+        fingerprint.push_u64(self.prefix.0 as u64);
+        fingerprint.push_term(&self.prefix.1);
+        Some(fingerprint)
+    }
 }
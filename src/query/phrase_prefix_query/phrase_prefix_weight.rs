@@ -153,6 +153,37 @@ impl Weight for PhrasePrefixWeight {
         }
         Ok(explanation)
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        use std::ops::Bound;
+        Box::pin(async move {
+            let field = self.prefix.1.field();
+            let inverted_index = reader.inverted_index(field)?;
+            let prefix_bytes = self.prefix.1.serialized_value_bytes();
+            let prefix_end_opt = prefix_end(prefix_bytes);
+            let upper_bound = match &prefix_end_opt {
+                Some(end) => Bound::Excluded(&end[..]),
+                None => Bound::Unbounded,
+            };
+            let warm_prefix = inverted_index.warm_postings_range_bytes(
+                Bound::Included(prefix_bytes),
+                upper_bound,
+                Some(self.max_expansions as u64),
+                true,
+            );
+            let warm_phrase_terms = futures_util::future::try_join_all(
+                self.phrase_terms
+                    .iter()
+                    .map(|(_, term)| inverted_index.warm_postings(term, true)),
+            );
+            futures_util::future::try_join(warm_prefix, warm_phrase_terms).await?;
+            if self.similarity_weight_opt.is_some() {
+                crate::query::weight::warmup_fieldnorms(reader, field).await?;
+            }
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,6 @@
 use super::PhraseWeight;
 use crate::query::bm25::Bm25Weight;
-use crate::query::{EnableScoring, Query, Weight};
+use crate::query::{EnableScoring, Query, QueryFingerprint, Weight};
 use crate::schema::{Field, IndexRecordOption, Term};
 
 /// `PhraseQuery` matches a specific sequence of words.
@@ -146,4 +146,15 @@ impl Query for PhraseQuery {
             visitor(term, true);
         }
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("phrase");
+        fingerprint.push_u64(self.slop as u64);
+        fingerprint.push_u64(self.phrase_terms.len() as u64);
+        for (offset, term) in &self.phrase_terms {
+            fingerprint.push_u64(*offset as u64);
+            fingerprint.push_term(term);
+        }
+        Some(fingerprint)
+    }
 }
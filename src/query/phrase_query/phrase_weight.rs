@@ -113,6 +113,24 @@ impl Weight for PhraseWeight {
         }
         Ok(explanation)
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        Box::pin(async move {
+            let field = self.phrase_terms[0].1.field();
+            let inverted_index = reader.inverted_index(field)?;
+            futures_util::future::try_join_all(
+                self.phrase_terms
+                    .iter()
+                    .map(|(_, term)| inverted_index.warm_postings(term, true)),
+            )
+            .await?;
+            if self.similarity_weight_opt.is_some() {
+                crate::query::weight::warmup_fieldnorms(reader, field).await?;
+            }
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
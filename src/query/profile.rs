@@ -0,0 +1,549 @@
+//! Query profiling.
+//!
+//! [`Searcher::search_with_profile`](crate::Searcher::search_with_profile) runs a search while
+//! recording where time is spent, and returns a [`QueryProfile`].
+//!
+//! While a profiled search is running, a profiler is registered in a thread local. Composite
+//! queries build the weight of their sub-queries through [`weight`], so that every
+//! [`Weight`] gets wrapped into a profiling weight, and the tree of the profile mirrors the
+//! structure of the query.
+//!
+//! Profiling is not free: scorers are wrapped and every call to `advance`, `seek` and `score`
+//! is timed. Wrapping scorers also disables some optimizations (e.g. block-WAND for unions of
+//! term queries), so timings should be compared with each other, not with unprofiled searches.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::docset::DocSet;
+use crate::index::SegmentId;
+use crate::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::{DocId, Score, SegmentReader};
+
+thread_local! {
+    static ACTIVE_PROFILER: RefCell<Option<Arc<Profiler>>> = const { RefCell::new(None) };
+}
+
+/// Profile of a search, as returned by
+/// [`Searcher::search_with_profile`](crate::Searcher::search_with_profile).
+///
+/// All durations are expressed in nanoseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryProfile {
+    /// Profile of the query tree.
+    pub query: QueryProfileNode,
+    /// Time spent collecting each segment, including the time spent in the scorers.
+    pub collection: Vec<SegmentCollectionProfile>,
+    /// Time spent merging the segment fruits.
+    pub merge_fruits_nanos: u64,
+    /// Term dictionary lookups, for statistics and scorer creation.
+    pub term_dictionary: LookupProfile,
+    /// Document store fetches performed while searching.
+    pub doc_store: LookupProfile,
+}
+
+impl QueryProfile {
+    /// Serializes the profile to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Profile serialization should never fail")
+    }
+
+    /// Serializes the profile to pretty JSON.
+    pub fn to_pretty_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Profile serialization should never fail")
+    }
+}
+
+/// Profile of a query and of its sub-queries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryProfileNode {
+    /// `Debug` representation of the query.
+    pub query: String,
+    /// Time spent building the weight, including the weights of sub-queries.
+    pub weight_nanos: u64,
+    /// Timings of the scorers of this query, for each segment.
+    pub segments: Vec<SegmentScorerProfile>,
+    /// Profile of the sub-queries.
+    pub children: Vec<QueryProfileNode>,
+}
+
+/// Timings of the scorer of a query on a given segment.
+///
+/// When a query is evaluated several times on a segment, the timings are summed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentScorerProfile {
+    /// Segment the scorer was created for.
+    pub segment_id: String,
+    /// Time spent creating the scorer, including the scorers of sub-queries.
+    pub create_scorer_nanos: u64,
+    /// Number of calls to `advance`.
+    pub advance_count: u64,
+    /// Time spent in `advance`.
+    pub advance_nanos: u64,
+    /// Number of calls to `seek`.
+    pub seek_count: u64,
+    /// Time spent in `seek`.
+    pub seek_nanos: u64,
+    /// Number of calls to `score`.
+    pub score_count: u64,
+    /// Time spent in `score`.
+    pub score_nanos: u64,
+}
+
+impl SegmentScorerProfile {
+    fn merge(&mut self, other: &SegmentScorerProfile) {
+        self.create_scorer_nanos += other.create_scorer_nanos;
+        self.advance_count += other.advance_count;
+        self.advance_nanos += other.advance_nanos;
+        self.seek_count += other.seek_count;
+        self.seek_nanos += other.seek_nanos;
+        self.score_count += other.score_count;
+        self.score_nanos += other.score_nanos;
+    }
+}
+
+/// Time spent collecting a segment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentCollectionProfile {
+    /// Ordinal of the segment in the searcher.
+    pub segment_ord: u32,
+    /// Id of the segment.
+    pub segment_id: String,
+    /// Time spent in `Collector::collect_segment`.
+    pub collect_nanos: u64,
+}
+
+/// Number of lookups of a given kind and the time spent performing them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LookupProfile {
+    /// Number of lookups.
+    pub count: u64,
+    /// Time spent in lookups.
+    pub nanos: u64,
+}
+
+#[derive(Default)]
+struct LookupRecorder {
+    count: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl LookupRecorder {
+    fn record(&self, start: Instant) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(elapsed_nanos(start), Ordering::Relaxed);
+    }
+
+    fn profile(&self) -> LookupProfile {
+        LookupProfile {
+            count: self.count.load(Ordering::Relaxed),
+            nanos: self.nanos.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct ProfileNodeRecorder {
+    query: String,
+    weight_nanos: AtomicU64,
+    segments: Mutex<BTreeMap<SegmentId, SegmentScorerProfile>>,
+    children: Mutex<Vec<Arc<ProfileNodeRecorder>>>,
+}
+
+impl ProfileNodeRecorder {
+    fn record_segment(&self, segment_id: SegmentId, segment_profile: &SegmentScorerProfile) {
+        self.segments
+            .lock()
+            .unwrap()
+            .entry(segment_id)
+            .or_insert_with(|| SegmentScorerProfile {
+                segment_id: segment_id.uuid_string(),
+                ..Default::default()
+            })
+            .merge(segment_profile);
+    }
+
+    fn profile(&self) -> QueryProfileNode {
+        QueryProfileNode {
+            query: self.query.clone(),
+            weight_nanos: self.weight_nanos.load(Ordering::Relaxed),
+            segments: self.segments.lock().unwrap().values().cloned().collect(),
+            children: self
+                .children
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|child| child.profile())
+                .collect(),
+        }
+    }
+}
+
+/// Records the profile of a search.
+#[derive(Default)]
+pub(crate) struct Profiler {
+    // Nodes whose weight is being built. Weights are built on a single thread.
+    stack: Mutex<Vec<Arc<ProfileNodeRecorder>>>,
+    root: Mutex<Option<Arc<ProfileNodeRecorder>>>,
+    collection: Mutex<Vec<SegmentCollectionProfile>>,
+    merge_fruits_nanos: AtomicU64,
+    term_dictionary: LookupRecorder,
+    doc_store: LookupRecorder,
+}
+
+impl Profiler {
+    /// Runs `f` with this profiler registered as the active profiler of the current thread.
+    pub(crate) fn install<R>(self: &Arc<Self>, f: impl FnOnce() -> R) -> R {
+        struct RestoreGuard(Option<Arc<Profiler>>);
+        impl Drop for RestoreGuard {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                ACTIVE_PROFILER.with(|active| *active.borrow_mut() = previous);
+            }
+        }
+        let previous = ACTIVE_PROFILER.with(|active| active.borrow_mut().replace(self.clone()));
+        let _guard = RestoreGuard(previous);
+        f()
+    }
+
+    pub(crate) fn record_collection(
+        &self,
+        segment_ord: u32,
+        segment_id: SegmentId,
+        start: Instant,
+    ) {
+        self.collection
+            .lock()
+            .unwrap()
+            .push(SegmentCollectionProfile {
+                segment_ord,
+                segment_id: segment_id.uuid_string(),
+                collect_nanos: elapsed_nanos(start),
+            });
+    }
+
+    pub(crate) fn record_merge_fruits(&self, start: Instant) {
+        self.merge_fruits_nanos
+            .store(elapsed_nanos(start), Ordering::Relaxed);
+    }
+
+    pub(crate) fn profile(&self) -> QueryProfile {
+        let query = self
+            .root
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|root| root.profile())
+            .expect("The weight of the profiled query should have been built");
+        let mut collection = self.collection.lock().unwrap().clone();
+        collection.sort_by_key(|segment_collection| segment_collection.segment_ord);
+        QueryProfile {
+            query,
+            collection,
+            merge_fruits_nanos: self.merge_fruits_nanos.load(Ordering::Relaxed),
+            term_dictionary: self.term_dictionary.profile(),
+            doc_store: self.doc_store.profile(),
+        }
+    }
+}
+
+fn active_profiler() -> Option<Arc<Profiler>> {
+    ACTIVE_PROFILER.with(|active| active.borrow().clone())
+}
+
+fn elapsed_nanos(start: Instant) -> u64 {
+    start.elapsed().as_nanos() as u64
+}
+
+/// Times a term dictionary lookup, if a profiled search is running on the current thread.
+pub(crate) fn time_term_dictionary_lookup<R>(f: impl FnOnce() -> R) -> R {
+    time_lookup(|profiler| &profiler.term_dictionary, f)
+}
+
+/// Times a doc store fetch, if a profiled search is running on the current thread.
+pub(crate) fn time_doc_store_fetch<R>(f: impl FnOnce() -> R) -> R {
+    time_lookup(|profiler| &profiler.doc_store, f)
+}
+
+fn time_lookup<R>(recorder: impl Fn(&Profiler) -> &LookupRecorder, f: impl FnOnce() -> R) -> R {
+    let Some(profiler) = active_profiler() else {
+        return f();
+    };
+    let start = Instant::now();
+    let result = f();
+    recorder(&profiler).record(start);
+    result
+}
+
+/// Builds the weight of `query`.
+///
+/// Composite queries should build the weights of their sub-queries through this function
+/// rather than calling [`Query::weight`] directly, so that the sub-queries appear in
+/// query profiles.
+pub(crate) fn weight(
+    query: &dyn Query,
+    enable_scoring: EnableScoring<'_>,
+) -> crate::Result<Box<dyn Weight>> {
+    let Some(profiler) = active_profiler() else {
+        return query.weight(enable_scoring);
+    };
+    let node = Arc::new(ProfileNodeRecorder {
+        query: format!("{query:?}"),
+        weight_nanos: AtomicU64::default(),
+        segments: Mutex::default(),
+        children: Mutex::default(),
+    });
+    {
+        let mut stack = profiler.stack.lock().unwrap();
+        if let Some(parent) = stack.last() {
+            parent.children.lock().unwrap().push(node.clone());
+        } else {
+            *profiler.root.lock().unwrap() = Some(node.clone());
+        }
+        stack.push(node.clone());
+    }
+    let start = Instant::now();
+    let weight_res = query.weight(enable_scoring);
+    node.weight_nanos
+        .store(elapsed_nanos(start), Ordering::Relaxed);
+    profiler.stack.lock().unwrap().pop();
+    Ok(Box::new(ProfiledWeight {
+        weight: weight_res?,
+        node,
+    }))
+}
+
+/// Weight recording the timings of its scorers.
+///
+/// It only overrides `scorer`, so that all of the `for_each` variants go through the
+/// profiled scorer.
+struct ProfiledWeight {
+    weight: Box<dyn Weight>,
+    node: Arc<ProfileNodeRecorder>,
+}
+
+impl Weight for ProfiledWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        let start = Instant::now();
+        let scorer = self.weight.scorer(reader, boost)?;
+        let profile = SegmentScorerProfile {
+            create_scorer_nanos: elapsed_nanos(start),
+            ..Default::default()
+        };
+        Ok(Box::new(ProfiledScorer {
+            scorer,
+            node: self.node.clone(),
+            segment_id: reader.segment_id(),
+            profile,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        self.weight.explain(reader, doc)
+    }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        self.weight.warmup(reader)
+    }
+}
+
+/// Scorer timing the calls to its underlying scorer.
+///
+/// The timings are reported to the profile node when the scorer is dropped.
+struct ProfiledScorer {
+    scorer: Box<dyn Scorer>,
+    node: Arc<ProfileNodeRecorder>,
+    segment_id: SegmentId,
+    profile: SegmentScorerProfile,
+}
+
+impl DocSet for ProfiledScorer {
+    fn advance(&mut self) -> DocId {
+        let start = Instant::now();
+        let doc = self.scorer.advance();
+        self.profile.advance_count += 1;
+        self.profile.advance_nanos += elapsed_nanos(start);
+        doc
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        let start = Instant::now();
+        let doc = self.scorer.seek(target);
+        self.profile.seek_count += 1;
+        self.profile.seek_nanos += elapsed_nanos(start);
+        doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.scorer.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.scorer.size_hint()
+    }
+}
+
+impl Scorer for ProfiledScorer {
+    fn score(&mut self) -> Score {
+        let start = Instant::now();
+        let score = self.scorer.score();
+        self.profile.score_count += 1;
+        self.profile.score_nanos += elapsed_nanos(start);
+        score
+    }
+}
+
+impl Drop for ProfiledScorer {
+    fn drop(&mut self) {
+        self.node.record_segment(self.segment_id, &self.profile);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collector::{Count, TopDocs};
+    use crate::query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery};
+    use crate::schema::{IndexRecordOption, Schema, Value, STORED, TEXT};
+    use crate::{Index, IndexWriter, TantivyDocument, Term};
+
+    fn test_index() -> crate::Result<(Index, crate::schema::Field)> {
+        let mut schema_builder = Schema::builder();
+        let text = schema_builder.add_text_field("text", TEXT | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(text => "hello happy world"))?;
+        index_writer.add_document(doc!(text => "happy days"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(text => "hello again"))?;
+        index_writer.commit()?;
+        Ok((index, text))
+    }
+
+    fn term_query(text: crate::schema::Field, word: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            Term::from_field_text(text, word),
+            IndexRecordOption::WithFreqs,
+        ))
+    }
+
+    #[test]
+    fn test_profile_mirrors_query_tree() -> crate::Result<()> {
+        let (index, text) = test_index()?;
+        let searcher = index.reader()?.searcher();
+        let query = BooleanQuery::new(vec![
+            (Occur::Should, term_query(text, "hello")),
+            (
+                Occur::Should,
+                Box::new(BoostQuery::new(term_query(text, "happy"), 2.0)),
+            ),
+        ]);
+        let (top_docs, profile) = searcher.search_with_profile(&query, &TopDocs::with_limit(10))?;
+        assert_eq!(top_docs, searcher.search(&query, &TopDocs::with_limit(10))?);
+        assert!(profile.query.query.starts_with("BooleanQuery"));
+        assert_eq!(profile.query.children.len(), 2);
+        assert!(profile.query.children[0].query.starts_with("TermQuery"));
+        assert!(profile.query.children[1].query.starts_with("Boost"));
+        assert_eq!(profile.query.children[1].children.len(), 1);
+        assert!(profile.query.children[1].children[0].children.is_empty());
+        assert_eq!(profile.query.segments.len(), 2);
+        let hello_segments = &profile.query.children[0].segments;
+        assert_eq!(hello_segments.len(), 2);
+        let num_hello_scored: u64 = hello_segments
+            .iter()
+            .map(|segment| segment.score_count)
+            .sum();
+        assert_eq!(num_hello_scored, 2);
+        assert_eq!(profile.collection.len(), 2);
+        assert_eq!(profile.collection[0].segment_ord, 0);
+        // Statistics for the two terms are fetched on each segment.
+        assert!(profile.term_dictionary.count >= 4);
+        assert_eq!(profile.doc_store.count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_json() -> crate::Result<()> {
+        let (index, text) = test_index()?;
+        let searcher = index.reader()?.searcher();
+        let query = term_query(text, "hello");
+        let (count, profile) = searcher.search_with_profile(query.as_ref(), &Count)?;
+        assert_eq!(count, 2);
+        let json = profile.to_json();
+        let json_value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(json_value["query"]["query"]
+            .as_str()
+            .unwrap()
+            .starts_with("TermQuery"));
+        assert_eq!(json_value["collection"].as_array().unwrap().len(), 2);
+        let deserialized: super::QueryProfile = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.query.segments, profile.query.segments);
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_records_doc_store_fetches() -> crate::Result<()> {
+        use crate::collector::{Collector, SegmentCollector};
+        use crate::store::StoreReader;
+        use crate::{DocId, Score, SegmentOrdinal, SegmentReader};
+
+        // Collector fetching the stored documents while collecting.
+        struct StoredTextCollector;
+        struct StoredTextSegmentCollector(StoreReader, Vec<String>);
+
+        impl Collector for StoredTextCollector {
+            type Fruit = Vec<String>;
+            type Child = StoredTextSegmentCollector;
+
+            fn for_segment(
+                &self,
+                _segment_ord: SegmentOrdinal,
+                segment_reader: &SegmentReader,
+            ) -> crate::Result<Self::Child> {
+                Ok(StoredTextSegmentCollector(
+                    segment_reader.get_store_reader(1)?,
+                    Vec::new(),
+                ))
+            }
+
+            fn requires_scoring(&self) -> bool {
+                false
+            }
+
+            fn merge_fruits(&self, fruits: Vec<Vec<String>>) -> crate::Result<Vec<String>> {
+                let mut texts: Vec<String> = fruits.into_iter().flatten().collect();
+                texts.sort();
+                Ok(texts)
+            }
+        }
+
+        impl SegmentCollector for StoredTextSegmentCollector {
+            type Fruit = Vec<String>;
+
+            fn collect(&mut self, doc: DocId, _score: Score) {
+                let doc: TantivyDocument = self.0.get(doc).unwrap();
+                let (_field, value) = doc.field_values().next().unwrap();
+                let text = value.as_str().unwrap();
+                self.1.push(text.to_string());
+            }
+
+            fn harvest(self) -> Vec<String> {
+                self.1
+            }
+        }
+
+        let (index, text) = test_index()?;
+        let searcher = index.reader()?.searcher();
+        let query = term_query(text, "hello");
+        let (texts, profile) =
+            searcher.search_with_profile(query.as_ref(), &StoredTextCollector)?;
+        assert_eq!(texts, vec!["hello again", "hello happy world"]);
+        assert_eq!(profile.doc_store.count, 2);
+        Ok(())
+    }
+}
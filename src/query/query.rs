@@ -5,7 +5,7 @@ use downcast_rs::impl_downcast;
 use super::bm25::Bm25StatisticsProvider;
 use super::Weight;
 use crate::core::searcher::Searcher;
-use crate::query::Explanation;
+use crate::query::{Explanation, QueryFingerprint};
 use crate::schema::Schema;
 use crate::{DocAddress, Term};
 
@@ -160,6 +160,16 @@ pub trait Query: QueryClone + Send + Sync + downcast_rs::Downcast + fmt::Debug {
     /// Note that there can be multiple instances of any given term
     /// in a query and deduplication must be handled by the visitor.
     fn query_terms<'a>(&'a self, _visitor: &mut dyn FnMut(&'a Term, bool)) {}
+
+    /// Returns a fingerprint identifying the set of documents matched by the query, or `None`
+    /// if the query cannot be identified.
+    ///
+    /// Queries matching different sets of documents must have different fingerprints.
+    /// Only queries with a fingerprint get cached by the
+    /// [`QueryCache`](crate::query::QueryCache).
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        None
+    }
 }
 
 /// Implements `box_clone`.
@@ -188,6 +198,10 @@ impl Query for Box<dyn Query> {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         self.as_ref().query_terms(visitor);
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        self.as_ref().fingerprint()
+    }
 }
 
 impl QueryClone for Box<dyn Query> {
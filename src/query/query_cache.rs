@@ -0,0 +1,926 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::BitSet;
+use lru::LruCache;
+use rustc_hash::FxHashMap;
+
+use crate::index::SegmentId;
+use crate::query::{
+    profile, AllQuery, BitSetDocSet, BooleanQuery, ConstScorer, EmptyQuery, EnableScoring,
+    Explanation, FuzzyTermQuery, PhrasePrefixQuery, PhraseQuery, Query, RangeQuery, RegexQuery,
+    Scorer, TermSetQuery, Weight,
+};
+use crate::{DocId, Opstamp, Score, SegmentReader, TantivyError, Term};
+
+/// Number of recent usages tracked by the [`QueryCachingPolicy`].
+const USAGE_HISTORY_LEN: usize = 256;
+
+/// Approximate memory overhead of a cache entry, on top of its bitset and key.
+const ENTRY_OVERHEAD_NUM_BYTES: usize = 64;
+
+/// Heuristic deciding which clauses of a [`BooleanQuery`] get cached.
+///
+/// Only non-scoring clauses are ever considered: `MustNot` clauses, and all clauses when
+/// scoring is disabled. Among those, a clause is cached once it has been seen often enough among
+/// the last 256 clauses evaluated. Queries that are expensive to evaluate (range, regex, fuzzy,
+/// phrase, set and nested boolean queries) are cached earlier than cheap ones
+/// (e.g. a single `TermQuery`), and match-all/match-none queries are never cached.
+///
+/// Segments with fewer than `min_segment_num_docs` documents are never cached either, as they
+/// are cheap to evaluate and likely to be merged away soon.
+#[derive(Clone, Debug)]
+pub struct QueryCachingPolicy {
+    /// Number of usages after which an expensive query gets cached.
+    pub min_frequency_costly: usize,
+    /// Number of usages after which any other query gets cached.
+    pub min_frequency: usize,
+    /// Segments with less documents than this are never cached.
+    pub min_segment_num_docs: u32,
+}
+
+impl Default for QueryCachingPolicy {
+    fn default() -> Self {
+        QueryCachingPolicy {
+            min_frequency_costly: 2,
+            min_frequency: 5,
+            min_segment_num_docs: 10_000,
+        }
+    }
+}
+
+impl QueryCachingPolicy {
+    fn is_costly(query: &dyn Query) -> bool {
+        query.is::<RangeQuery>()
+            || query.is::<RegexQuery>()
+            || query.is::<FuzzyTermQuery>()
+            || query.is::<PhraseQuery>()
+            || query.is::<PhrasePrefixQuery>()
+            || query.is::<TermSetQuery>()
+            || query.is::<BooleanQuery>()
+    }
+
+    fn is_trivial(query: &dyn Query) -> bool {
+        query.is::<AllQuery>() || query.is::<EmptyQuery>() || query.is::<CachedQuery>()
+    }
+
+    fn min_frequency_for(&self, query: &dyn Query) -> usize {
+        if Self::is_costly(query) {
+            self.min_frequency_costly
+        } else {
+            self.min_frequency
+        }
+    }
+}
+
+/// Statistics about a [`QueryCache`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    /// The number of `(query, segment)` entries in the cache.
+    pub num_entries: usize,
+    /// The approximate amount of memory used by the cache, in bytes.
+    pub memory_usage: usize,
+    /// The number of cache hits.
+    pub cache_hits: usize,
+    /// The number of cache misses.
+    pub cache_misses: usize,
+}
+
+/// Identifies the set of documents matched by a [`Query`], for the [`QueryCache`].
+///
+/// A fingerprint is an unambiguous encoding of the parts of a query that decide which
+/// documents it matches: the kind of the query, followed by its parameters. Each parameter is
+/// length-prefixed, so that different queries can never end up with the same fingerprint.
+///
+/// See [`Query::fingerprint()`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QueryFingerprint(Vec<u8>);
+
+impl QueryFingerprint {
+    /// Starts the fingerprint of a query.
+    ///
+    /// `kind` must identify the type of the query, and be unique among the queries.
+    pub fn new(kind: &str) -> QueryFingerprint {
+        let mut fingerprint = QueryFingerprint(Vec::new());
+        fingerprint.push_bytes(kind.as_bytes());
+        fingerprint
+    }
+
+    /// Appends some bytes to the fingerprint.
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.push_u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    /// Appends an integer to the fingerprint.
+    pub fn push_u64(&mut self, val: u64) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Appends a term, including its field and its type, to the fingerprint.
+    pub fn push_term(&mut self, term: &Term) {
+        self.push_bytes(term.serialized_term());
+    }
+
+    /// Appends the fingerprint of a subquery to the fingerprint.
+    pub fn push_fingerprint(&mut self, fingerprint: &QueryFingerprint) {
+        self.push_bytes(&fingerprint.0);
+    }
+
+    fn num_bytes(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Fingerprints refer to fields by their id, which a schema update may renumber, so the version
+/// of the schema of the searcher is part of the key. Numeric updates change the documents
+/// matched by the queries on fast fields, so the numeric updates opstamp of the segment is part
+/// of the key as well.
+type CacheKey = (SegmentId, u32, Option<Opstamp>, QueryFingerprint);
+
+struct CacheEntries {
+    lru: LruCache<CacheKey, Arc<BitSet>>,
+    memory_usage: usize,
+}
+
+#[derive(Default)]
+struct UsageHistory {
+    recent: VecDeque<QueryFingerprint>,
+    counts: FxHashMap<QueryFingerprint, usize>,
+}
+
+impl UsageHistory {
+    /// Records a usage of `key`, and returns the number of usages of `key` within the history.
+    fn record(&mut self, key: &QueryFingerprint) -> usize {
+        if self.recent.len() == USAGE_HISTORY_LEN {
+            if let Some(evicted) = self.recent.pop_front() {
+                if let Some(count) = self.counts.get_mut(&evicted) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.counts.remove(&evicted);
+                    }
+                }
+            }
+        }
+        self.recent.push_back(key.clone());
+        let count = self.counts.entry(key.clone()).or_default();
+        *count += 1;
+        *count
+    }
+}
+
+struct InnerQueryCache {
+    memory_budget: usize,
+    policy: QueryCachingPolicy,
+    entries: Mutex<CacheEntries>,
+    usage_history: Mutex<UsageHistory>,
+    cache_hits: AtomicUsize,
+    cache_misses: AtomicUsize,
+}
+
+/// Per-segment cache of the documents matching a query.
+///
+/// Entries are keyed by `(SegmentId, schema version, numeric updates opstamp, query)` and hold
+/// the matching documents as a [`BitSet`]. Deleted documents are not taken into account, so that
+/// entries remain valid as long as the segment is alive and does not get a new schema or new
+/// numeric updates. When an [`IndexReader`](crate::IndexReader) reloads, the entries of the
+/// segments it does not read anymore, or reads with a newer schema or newer numeric updates, are
+/// evicted. A cache can therefore be shared by the readers of several indexes.
+///
+/// Queries are identified by their [`QueryFingerprint`]. Queries without a fingerprint, such as
+/// custom queries that do not implement [`Query::fingerprint()`], are never cached.
+///
+/// The cache is bounded by a memory budget, evicting the least recently used entries first.
+///
+/// `QueryCache` is cheap to clone: clones share the same entries.
+#[derive(Clone)]
+pub struct QueryCache {
+    inner: Arc<InnerQueryCache>,
+}
+
+impl fmt::Debug for QueryCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryCache")
+            .field("memory_budget", &self.inner.memory_budget)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl QueryCache {
+    /// Creates a new query cache with the given memory budget (in bytes) and the default
+    /// [`QueryCachingPolicy`].
+    pub fn new(memory_budget: usize) -> QueryCache {
+        QueryCache::with_policy(memory_budget, QueryCachingPolicy::default())
+    }
+
+    /// Creates a new query cache with the given memory budget (in bytes) and caching policy.
+    pub fn with_policy(memory_budget: usize, policy: QueryCachingPolicy) -> QueryCache {
+        QueryCache {
+            inner: Arc::new(InnerQueryCache {
+                memory_budget,
+                policy,
+                entries: Mutex::new(CacheEntries {
+                    lru: LruCache::unbounded(),
+                    memory_usage: 0,
+                }),
+                usage_history: Mutex::default(),
+                cache_hits: AtomicUsize::default(),
+                cache_misses: AtomicUsize::default(),
+            }),
+        }
+    }
+
+    /// Returns the caching policy.
+    pub fn policy(&self) -> &QueryCachingPolicy {
+        &self.inner.policy
+    }
+
+    /// Returns the memory budget of the cache, in bytes.
+    pub fn memory_budget(&self) -> usize {
+        self.inner.memory_budget
+    }
+
+    /// Returns statistics about the cache.
+    pub fn stats(&self) -> QueryCacheStats {
+        let entries = self.inner.entries.lock().unwrap();
+        QueryCacheStats {
+            num_entries: entries.lru.len(),
+            memory_usage: entries.memory_usage,
+            cache_hits: self.inner.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.inner.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes all of the entries of the cache.
+    pub fn clear(&self) {
+        let mut entries = self.inner.entries.lock().unwrap();
+        entries.lru.clear();
+        entries.memory_usage = 0;
+    }
+
+    /// Evicts the entries of the segments in `segments`, identified by their segment id, schema
+    /// version and numeric updates opstamp.
+    pub(crate) fn evict_segments(&self, segments: &HashSet<(SegmentId, u32, Option<Opstamp>)>) {
+        if segments.is_empty() {
+            return;
+        }
+        let mut entries = self.inner.entries.lock().unwrap();
+        let keys_to_remove: Vec<CacheKey> = entries
+            .lru
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|(segment_id, schema_version, numeric_updates_opstamp, _)| {
+                segments.contains(&(*segment_id, *schema_version, *numeric_updates_opstamp))
+            })
+            .cloned()
+            .collect();
+        for key in keys_to_remove {
+            if let Some(bitset) = entries.lru.pop(&key) {
+                entries.memory_usage -= entry_num_bytes(&key, &bitset);
+            }
+        }
+    }
+
+    /// Records a usage of the clause `query`, with the given fingerprint, and returns true if the
+    /// policy decides it should be cached.
+    pub(crate) fn should_cache(&self, query: &dyn Query, fingerprint: &QueryFingerprint) -> bool {
+        if QueryCachingPolicy::is_trivial(query) {
+            return false;
+        }
+        let count = self.inner.usage_history.lock().unwrap().record(fingerprint);
+        count >= self.inner.policy.min_frequency_for(query)
+    }
+
+    fn get_or_compute(
+        &self,
+        segment_reader: &SegmentReader,
+        schema_version: u32,
+        fingerprint: &QueryFingerprint,
+        weight: &dyn Weight,
+    ) -> crate::Result<Arc<BitSet>> {
+        let key: CacheKey = (
+            segment_reader.segment_id(),
+            schema_version,
+            segment_reader.numeric_updates_opstamp(),
+            fingerprint.clone(),
+        );
+        if let Some(bitset) = self.inner.entries.lock().unwrap().lru.get(&key) {
+            self.inner.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(bitset.clone());
+        }
+        self.inner.cache_misses.fetch_add(1, Ordering::Relaxed);
+        // The bitset is computed without holding the lock, so that concurrent searches
+        // are not blocked. Two searches may end up computing the same entry.
+        let mut bitset = BitSet::with_max_value(segment_reader.max_doc());
+        weight.for_each_no_score(segment_reader, &mut |docs| {
+            for &doc in docs {
+                bitset.insert(doc);
+            }
+        })?;
+        let bitset = Arc::new(bitset);
+        self.insert(key, bitset.clone());
+        Ok(bitset)
+    }
+
+    fn insert(&self, key: CacheKey, bitset: Arc<BitSet>) {
+        let num_bytes = entry_num_bytes(&key, &bitset);
+        if num_bytes > self.inner.memory_budget {
+            return;
+        }
+        let mut entries = self.inner.entries.lock().unwrap();
+        if let Some(previous) = entries.lru.put(key.clone(), bitset) {
+            entries.memory_usage -= entry_num_bytes(&key, &previous);
+        }
+        entries.memory_usage += num_bytes;
+        while entries.memory_usage > self.inner.memory_budget {
+            let Some((evicted_key, evicted)) = entries.lru.pop_lru() else {
+                break;
+            };
+            entries.memory_usage -= entry_num_bytes(&evicted_key, &evicted);
+        }
+    }
+}
+
+fn entry_num_bytes((_, _, _, fingerprint): &CacheKey, bitset: &BitSet) -> usize {
+    let bitset_num_bytes = (bitset.max_value() as usize + 63) / 64 * 8;
+    bitset_num_bytes + fingerprint.num_bytes() + ENTRY_OVERHEAD_NUM_BYTES
+}
+
+/// `CachedQuery` wraps a query and caches the set of documents it matches, for each segment,
+/// in the [`QueryCache`] of the [`IndexReader`](crate::IndexReader).
+///
+/// Since only the set of matching documents is cached, the scores of the inner query are
+/// lost: all of the matching documents get a constant score of `1.0`, just like with a
+/// [`ConstScoreQuery`](crate::query::ConstScoreQuery). `CachedQuery` is meant to be used for
+/// filters.
+///
+/// Wrapped queries are always cached, regardless of the [`QueryCachingPolicy`].
+/// If the index reader has no query cache, or if the wrapped query has no
+/// [fingerprint](Query::fingerprint), the query is simply evaluated.
+///
+/// ```rust
+/// use tantivy::collector::Count;
+/// use tantivy::query::{CachedQuery, QueryCache, TermQuery};
+/// use tantivy::schema::{IndexRecordOption, Schema, STRING};
+/// use tantivy::{doc, Index, IndexWriter, Term};
+///
+/// # fn main() -> tantivy::Result<()> {
+/// let mut schema_builder = Schema::builder();
+/// let status = schema_builder.add_text_field("status", STRING);
+/// let index = Index::create_in_ram(schema_builder.build());
+/// let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 20_000_000)?;
+/// index_writer.add_document(doc!(status => "active"))?;
+/// index_writer.add_document(doc!(status => "deleted"))?;
+/// index_writer.commit()?;
+///
+/// let reader = index
+///     .reader_builder()
+///     .query_cache(QueryCache::new(10_000_000))
+///     .try_into()?;
+/// let searcher = reader.searcher();
+/// let active = CachedQuery::new(Box::new(TermQuery::new(
+///     Term::from_field_text(status, "active"),
+///     IndexRecordOption::Basic,
+/// )));
+/// assert_eq!(searcher.search(&active, &Count)?, 1);
+/// assert_eq!(searcher.search(&active, &Count)?, 1);
+/// assert_eq!(searcher.query_cache().unwrap().stats().cache_hits, 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachedQuery {
+    query: Box<dyn Query>,
+}
+
+impl CachedQuery {
+    /// Creates a new `CachedQuery` wrapping `query`.
+    pub fn new(query: Box<dyn Query>) -> CachedQuery {
+        CachedQuery { query }
+    }
+
+    /// Returns the wrapped query.
+    pub fn query(&self) -> &dyn Query {
+        self.query.as_ref()
+    }
+}
+
+impl Clone for CachedQuery {
+    fn clone(&self) -> Self {
+        CachedQuery {
+            query: self.query.box_clone(),
+        }
+    }
+}
+
+impl Query for CachedQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+        let inner_weight = profile::weight(
+            self.query.as_ref(),
+            EnableScoring::Disabled {
+                schema: enable_scoring.schema(),
+                searcher_opt: enable_scoring.searcher(),
+            },
+        )?;
+        let Some((searcher, cache)) = enable_scoring.searcher().and_then(|searcher| {
+            searcher
+                .query_cache()
+                .map(|query_cache| (searcher, query_cache))
+        }) else {
+            return Ok(inner_weight);
+        };
+        let Some(fingerprint) = self.query.fingerprint() else {
+            return Ok(inner_weight);
+        };
+        Ok(Box::new(CachingWeight::new(
+            inner_weight,
+            cache.clone(),
+            searcher.schema_version(),
+            fingerprint,
+            0,
+        )))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor);
+    }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        self.query.fingerprint()
+    }
+}
+
+/// Wraps the given clause weight into a [`CachingWeight`] if the searcher has a
+/// [`QueryCache`] and its policy decides the clause is worth caching.
+pub(crate) fn maybe_cache_clause(
+    query: &dyn Query,
+    weight: Box<dyn Weight>,
+    enable_scoring: &EnableScoring<'_>,
+) -> Box<dyn Weight> {
+    let Some((searcher, cache)) = enable_scoring.searcher().and_then(|searcher| {
+        searcher
+            .query_cache()
+            .map(|query_cache| (searcher, query_cache))
+    }) else {
+        return weight;
+    };
+    let Some(fingerprint) = query.fingerprint() else {
+        return weight;
+    };
+    if !cache.should_cache(query, &fingerprint) {
+        return weight;
+    }
+    let min_segment_num_docs = cache.policy().min_segment_num_docs;
+    Box::new(CachingWeight::new(
+        weight,
+        cache.clone(),
+        searcher.schema_version(),
+        fingerprint,
+        min_segment_num_docs,
+    ))
+}
+
+/// Weight serving the documents of its inner weight from a [`QueryCache`].
+///
+/// All of the documents get a score equal to the boost.
+struct CachingWeight {
+    weight: Box<dyn Weight>,
+    cache: QueryCache,
+    // Version of the schema of the searcher the weight was created for.
+    schema_version: u32,
+    fingerprint: QueryFingerprint,
+    min_segment_num_docs: u32,
+}
+
+impl CachingWeight {
+    fn new(
+        weight: Box<dyn Weight>,
+        cache: QueryCache,
+        schema_version: u32,
+        fingerprint: QueryFingerprint,
+        min_segment_num_docs: u32,
+    ) -> CachingWeight {
+        CachingWeight {
+            weight,
+            cache,
+            schema_version,
+            fingerprint,
+            min_segment_num_docs,
+        }
+    }
+}
+
+impl Weight for CachingWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> crate::Result<Box<dyn Scorer>> {
+        if reader.max_doc() < self.min_segment_num_docs {
+            return self.weight.scorer(reader, boost);
+        }
+        let bitset = self.cache.get_or_compute(
+            reader,
+            self.schema_version,
+            &self.fingerprint,
+            self.weight.as_ref(),
+        )?;
+        Ok(Box::new(ConstScorer::new(
+            BitSetDocSet::from(bitset),
+            boost,
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> crate::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+        Ok(Explanation::new("CachedQuery", scorer.score()))
+    }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        self.weight.warmup(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedQuery, QueryCache, QueryCachingPolicy};
+    use crate::collector::{Count, TopDocs};
+    use crate::query::{BooleanQuery, EnableScoring, Occur, Query, RangeQuery, TermQuery, Weight};
+    use crate::schema::{IndexRecordOption, Schema, FAST, INDEXED, STRING};
+    use crate::{Index, IndexReader, IndexWriter, ReloadPolicy, Term};
+
+    fn no_threshold_policy() -> QueryCachingPolicy {
+        QueryCachingPolicy {
+            min_frequency_costly: 1,
+            min_frequency: 1,
+            min_segment_num_docs: 0,
+        }
+    }
+
+    fn reader_with_cache(index: &Index, cache: QueryCache) -> crate::Result<IndexReader> {
+        index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .query_cache(cache)
+            .try_into()
+    }
+
+    #[test]
+    fn test_cached_query_hits_and_misses() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let tenant = schema_builder.add_u64_field("tenant", INDEXED | FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..10u64 {
+            index_writer.add_document(doc!(tenant => i % 3))?;
+        }
+        index_writer.commit()?;
+        index_writer.add_document(doc!(tenant => 1u64))?;
+        index_writer.commit()?;
+        let reader = reader_with_cache(&index, QueryCache::new(1_000_000))?;
+        let searcher = reader.searcher();
+        let query = CachedQuery::new(Box::new(TermQuery::new(
+            Term::from_field_u64(tenant, 1),
+            IndexRecordOption::Basic,
+        )));
+        assert_eq!(searcher.search(&query, &Count)?, 4);
+        let stats = searcher.query_cache().unwrap().stats();
+        assert_eq!(stats.cache_misses, 2);
+        assert_eq!(stats.cache_hits, 0);
+        assert_eq!(stats.num_entries, 2);
+        assert_eq!(searcher.search(&query, &Count)?, 4);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+        assert_eq!(top_docs.len(), 4);
+        assert!(top_docs.iter().all(|(score, _)| *score == 1.0));
+        let stats = searcher.query_cache().unwrap().stats();
+        assert_eq!(stats.cache_misses, 2);
+        assert_eq!(stats.cache_hits, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_deletes_are_applied() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let status = schema_builder.add_text_field("status", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.add_document(doc!(status => "inactive"))?;
+        index_writer.commit()?;
+        let reader = reader_with_cache(&index, QueryCache::new(1_000_000))?;
+        let query = CachedQuery::new(Box::new(TermQuery::new(
+            Term::from_field_text(status, "active"),
+            IndexRecordOption::Basic,
+        )));
+        assert_eq!(reader.searcher().search(&query, &Count)?, 2);
+        index_writer.delete_term(Term::from_field_text(status, "inactive"));
+        index_writer.commit()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().search(&query, &Count)?, 2);
+        index_writer.delete_term(Term::from_field_text(status, "active"));
+        index_writer.commit()?;
+        reader.reload()?;
+        assert_eq!(reader.searcher().search(&query, &Count)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_numeric_updates_are_applied() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_u64_field("id", INDEXED);
+        let score = schema_builder.add_u64_field("score", FAST);
+        schema_builder.set_unique_key_field(id);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..4u64 {
+            index_writer.add_document(doc!(id => i, score => i))?;
+        }
+        index_writer.commit()?;
+        let cache = QueryCache::new(1_000_000);
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let query = CachedQuery::new(Box::new(RangeQuery::new_u64_bounds(
+            "score".to_string(),
+            std::ops::Bound::Included(2),
+            std::ops::Bound::Unbounded,
+        )));
+        assert_eq!(reader.searcher().search(&query, &Count)?, 2);
+        assert_eq!(cache.stats().num_entries, 1);
+        index_writer.update_numeric(Term::from_field_u64(id, 0), score, 10u64)?;
+        index_writer.commit()?;
+        reader.reload()?;
+        // The entry of the segment before the update got evicted.
+        assert_eq!(cache.stats().num_entries, 0);
+        assert_eq!(reader.searcher().search(&query, &Count)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_schema_update_renumbering_fields() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let first = schema_builder.add_text_field("first", STRING);
+        let second = schema_builder.add_text_field("second", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(first => "active"))?;
+        index_writer.add_document(doc!(second => "active"))?;
+        index_writer.add_document(doc!(second => "active"))?;
+        index_writer.commit()?;
+        let cache = QueryCache::new(1_000_000);
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let cached_term_query = |field| {
+            CachedQuery::new(Box::new(TermQuery::new(
+                Term::from_field_text(field, "active"),
+                IndexRecordOption::Basic,
+            )))
+        };
+        assert_eq!(
+            reader
+                .searcher()
+                .search(&cached_term_query(first), &Count)?,
+            1
+        );
+        assert_eq!(cache.stats().num_entries, 1);
+
+        // Dropping `first` gives its field id to `second`, so that the queries on both fields
+        // get the same fingerprint.
+        let mut schema_builder = Schema::builder();
+        let new_second = schema_builder.add_text_field("second", STRING);
+        assert_eq!(new_second, first);
+        index_writer.update_schema(schema_builder.build())?;
+        reader.reload()?;
+        // The segment is unchanged, but its entry was computed with the previous schema.
+        assert_eq!(cache.stats().num_entries, 0);
+        let searcher = reader.searcher();
+        assert_eq!(searcher.search(&cached_term_query(new_second), &Count)?, 2);
+        assert_eq!(searcher.search(&cached_term_query(new_second), &Count)?, 2);
+        assert_eq!(cache.stats().cache_hits, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_evicts_dead_segments() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let status = schema_builder.add_text_field("status", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.commit()?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.commit()?;
+        let cache = QueryCache::new(1_000_000);
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let query = CachedQuery::new(Box::new(TermQuery::new(
+            Term::from_field_text(status, "active"),
+            IndexRecordOption::Basic,
+        )));
+        assert_eq!(reader.searcher().search(&query, &Count)?, 2);
+        assert_eq!(cache.stats().num_entries, 2);
+        let segment_ids = index.searchable_segment_ids()?;
+        index_writer.merge(&segment_ids).wait()?;
+        index_writer.wait_merging_threads()?;
+        reader.reload()?;
+        assert_eq!(cache.stats().num_entries, 0);
+        assert_eq!(cache.stats().memory_usage, 0);
+        assert_eq!(reader.searcher().search(&query, &Count)?, 2);
+        assert_eq!(cache.stats().num_entries, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_memory_budget() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let val = schema_builder.add_u64_field("val", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..1_000u64 {
+            index_writer.add_document(doc!(val => i % 10))?;
+        }
+        index_writer.commit()?;
+        // Each entry takes a few hundred bytes.
+        let cache = QueryCache::new(500);
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let searcher = reader.searcher();
+        for i in 0..10u64 {
+            let query = CachedQuery::new(Box::new(TermQuery::new(
+                Term::from_field_u64(val, i),
+                IndexRecordOption::Basic,
+            )));
+            assert_eq!(searcher.search(&query, &Count)?, 100);
+        }
+        let stats = cache.stats();
+        assert!(stats.memory_usage <= 500);
+        assert!(stats.num_entries >= 1);
+        assert!(stats.num_entries < 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_boolean_query_caches_filter_clauses() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let status = schema_builder.add_text_field("status", STRING);
+        let tenant = schema_builder.add_u64_field("tenant", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        for i in 0..20u64 {
+            let status_val = if i % 2 == 0 { "active" } else { "inactive" };
+            index_writer.add_document(doc!(status => status_val, tenant => i % 4))?;
+        }
+        index_writer.commit()?;
+        let cache = QueryCache::with_policy(
+            1_000_000,
+            QueryCachingPolicy {
+                min_frequency: 2,
+                ..no_threshold_policy()
+            },
+        );
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let searcher = reader.searcher();
+        let status_query: Box<dyn Query> = Box::new(TermQuery::new(
+            Term::from_field_text(status, "active"),
+            IndexRecordOption::Basic,
+        ));
+        let tenant_query: Box<dyn Query> = Box::new(RangeQuery::new_u64_bounds(
+            "tenant".to_string(),
+            std::ops::Bound::Included(2),
+            std::ops::Bound::Unbounded,
+        ));
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, status_query),
+            (Occur::MustNot, tenant_query),
+        ]);
+        // The range query is costly and cached right away, the term query on the second usage.
+        assert_eq!(searcher.search(&query, &Count)?, 5);
+        assert_eq!(cache.stats().num_entries, 1);
+        assert_eq!(searcher.search(&query, &Count)?, 5);
+        assert_eq!(cache.stats().num_entries, 2);
+        assert_eq!(searcher.search(&query, &Count)?, 5);
+        assert_eq!(cache.stats().cache_hits, 3);
+        // With scoring enabled, only the `MustNot` clause is cached.
+        cache.clear();
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+        assert_eq!(top_docs.len(), 5);
+        assert_eq!(cache.stats().num_entries, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_small_segments_are_not_cached() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let status = schema_builder.add_text_field("status", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.commit()?;
+        let cache = QueryCache::new(1_000_000);
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let query = BooleanQuery::new(vec![
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(status, "active"),
+                    IndexRecordOption::Basic,
+                )) as Box<dyn Query>,
+            ),
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(status, "inactive"),
+                    IndexRecordOption::Basic,
+                )) as Box<dyn Query>,
+            ),
+        ]);
+        for _ in 0..10 {
+            assert_eq!(reader.searcher().search(&query, &Count)?, 1);
+        }
+        assert_eq!(cache.stats().num_entries, 0);
+        Ok(())
+    }
+
+    /// A query whose `Debug` representation does not tell what it matches.
+    #[derive(Clone)]
+    struct OpaqueQuery(TermQuery);
+
+    impl std::fmt::Debug for OpaqueQuery {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "OpaqueQuery")
+        }
+    }
+
+    impl Query for OpaqueQuery {
+        fn weight(&self, enable_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
+            self.0.weight(enable_scoring)
+        }
+    }
+
+    #[test]
+    fn test_query_cache_queries_without_fingerprint_are_not_cached() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let status = schema_builder.add_text_field("status", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer: IndexWriter = index.writer_for_tests()?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.add_document(doc!(status => "active"))?;
+        index_writer.add_document(doc!(status => "inactive"))?;
+        index_writer.commit()?;
+        let cache = QueryCache::new(1_000_000);
+        let reader = reader_with_cache(&index, cache.clone())?;
+        let opaque_query = |text: &str| {
+            CachedQuery::new(Box::new(OpaqueQuery(TermQuery::new(
+                Term::from_field_text(status, text),
+                IndexRecordOption::Basic,
+            ))))
+        };
+        // Both queries have the same `Debug` representation.
+        assert_eq!(
+            reader.searcher().search(&opaque_query("active"), &Count)?,
+            2
+        );
+        assert_eq!(
+            reader
+                .searcher()
+                .search(&opaque_query("inactive"), &Count)?,
+            1
+        );
+        assert_eq!(cache.stats().num_entries, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_cache_shared_between_indexes() -> crate::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let status = schema_builder.add_text_field("status", STRING);
+        let schema = schema_builder.build();
+        let cache = QueryCache::new(1_000_000);
+        let query = CachedQuery::new(Box::new(TermQuery::new(
+            Term::from_field_text(status, "active"),
+            IndexRecordOption::Basic,
+        )));
+        let mut index_writers = Vec::new();
+        let mut readers = Vec::new();
+        for _ in 0..2 {
+            let index = Index::create_in_ram(schema.clone());
+            let mut index_writer: IndexWriter = index.writer_for_tests()?;
+            index_writer.add_document(doc!(status => "active"))?;
+            index_writer.commit()?;
+            let reader = reader_with_cache(&index, cache.clone())?;
+            assert_eq!(reader.searcher().search(&query, &Count)?, 1);
+            index_writers.push(index_writer);
+            readers.push(reader);
+        }
+        assert_eq!(cache.stats().num_entries, 2);
+        // Reloading the reader of the first index does not evict the entries of the second one.
+        index_writers[0].add_document(doc!(status => "active"))?;
+        index_writers[0].commit()?;
+        readers[0].reload()?;
+        assert_eq!(cache.stats().num_entries, 2);
+        assert_eq!(readers[1].searcher().search(&query, &Count)?, 1);
+        assert_eq!(cache.stats().cache_hits, 1);
+        Ok(())
+    }
+}
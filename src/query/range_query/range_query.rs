@@ -12,7 +12,9 @@ use crate::index::SegmentReader;
 use crate::query::explanation::does_not_match;
 use crate::query::range_query::range_query_ip_fastfield::IPFastFieldRangeWeight;
 use crate::query::range_query::{is_type_valid_for_fastfield_range_query, map_bound_res};
-use crate::query::{BitSetDocSet, ConstScorer, EnableScoring, Explanation, Query, Scorer, Weight};
+use crate::query::{
+    BitSetDocSet, ConstScorer, EnableScoring, Explanation, Query, QueryFingerprint, Scorer, Weight,
+};
 use crate::schema::{Field, IndexRecordOption, Term, Type};
 use crate::termdict::{TermDictionary, TermStreamer};
 use crate::{DateTime, DocId, Score};
@@ -387,6 +389,33 @@ impl Query for RangeQuery {
             }))
         }
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("range");
+        fingerprint.push_bytes(self.field.as_bytes());
+        fingerprint.push_u64(self.value_type.to_code() as u64);
+        for bound in [&self.lower_bound, &self.upper_bound] {
+            match bound {
+                Bound::Included(val) => {
+                    fingerprint.push_u64(0);
+                    fingerprint.push_bytes(val);
+                }
+                Bound::Excluded(val) => {
+                    fingerprint.push_u64(1);
+                    fingerprint.push_bytes(val);
+                }
+                Bound::Unbounded => fingerprint.push_u64(2),
+            }
+        }
+        match self.limit {
+            Some(limit) => {
+                fingerprint.push_u64(1);
+                fingerprint.push_u64(limit);
+            }
+            None => fingerprint.push_u64(0),
+        }
+        Some(fingerprint)
+    }
 }
 
 pub struct RangeWeight {
@@ -459,6 +488,29 @@ impl Weight for RangeWeight {
         }
         Ok(Explanation::new("RangeQuery", 1.0))
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        fn as_slice(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+            match bound {
+                Bound::Included(bytes) => Bound::Included(&bytes[..]),
+                Bound::Excluded(bytes) => Bound::Excluded(&bytes[..]),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+        Box::pin(async move {
+            let inverted_index = reader.inverted_index(reader.schema().get_field(&self.field)?)?;
+            inverted_index
+                .warm_postings_range_bytes(
+                    as_slice(&self.lower_bound),
+                    as_slice(&self.upper_bound),
+                    self.limit,
+                    false,
+                )
+                .await?;
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
@@ -56,6 +56,11 @@ impl Weight for IPFastFieldRangeWeight {
         let explanation = Explanation::new("Const", scorer.score());
         Ok(explanation)
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        Box::pin(crate::query::weight::warmup_fast_field(reader, &self.field))
+    }
 }
 
 fn bound_to_value_range(
@@ -102,6 +102,11 @@ impl Weight for FastFieldRangeWeight {
 
         Ok(explanation)
     }
+
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        Box::pin(crate::query::weight::warmup_fast_field(reader, &self.field))
+    }
 }
 
 // Returns None, if the range cannot be converted to a inclusive range (which equals to a empty
@@ -1,10 +1,11 @@
 use std::clone::Clone;
+use std::fmt::{self, Write as _};
 use std::sync::Arc;
 
 use tantivy_fst::Regex;
 
 use crate::error::TantivyError;
-use crate::query::{AutomatonWeight, EnableScoring, Query, Weight};
+use crate::query::{AutomatonWeight, EnableScoring, Query, QueryFingerprint, Weight};
 use crate::schema::Field;
 
 /// A Regex Query matches all of the documents
@@ -57,6 +58,8 @@ use crate::schema::Field;
 pub struct RegexQuery {
     regex: Arc<Regex>,
     field: Field,
+    // Identifies the pattern of `regex` in the query fingerprint.
+    pattern_key: String,
 }
 
 impl RegexQuery {
@@ -69,9 +72,12 @@ impl RegexQuery {
 
     /// Creates a new RegexQuery from a fully built Regex
     pub fn from_regex<T: Into<Arc<Regex>>>(regex: T, field: Field) -> Self {
+        let regex = regex.into();
+        let pattern_key = regex_pattern_key(&regex);
         RegexQuery {
-            regex: regex.into(),
+            regex,
             field,
+            pattern_key,
         }
     }
 
@@ -84,6 +90,42 @@ impl Query for RegexQuery {
     fn weight(&self, _enabled_scoring: EnableScoring<'_>) -> crate::Result<Box<dyn Weight>> {
         Ok(Box::new(self.specialized_weight()))
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("regex");
+        fingerprint.push_u64(self.field.field_id() as u64);
+        fingerprint.push_bytes(self.pattern_key.as_bytes());
+        Some(fingerprint)
+    }
+}
+
+/// Returns the first line of the `Debug` representation of a `Regex`, which holds
+/// its escaped pattern.
+///
+/// `Regex` does not expose its pattern, and the rest of its `Debug` representation
+/// is a dump of the whole DFA, so formatting stops at the first line break.
+fn regex_pattern_key(regex: &Regex) -> String {
+    struct FirstLine(String);
+
+    impl fmt::Write for FirstLine {
+        fn write_str(&mut self, text: &str) -> fmt::Result {
+            match text.split_once('\n') {
+                Some((line_end, _)) => {
+                    self.0.push_str(line_end);
+                    Err(fmt::Error)
+                }
+                None => {
+                    self.0.push_str(text);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    let mut first_line = FirstLine(String::new());
+    // The error only signals that formatting stopped at the first line break.
+    let _ = write!(first_line, "{regex:?}");
+    first_line.0
 }
 
 #[cfg(test)]
@@ -94,6 +136,7 @@ mod test {
 
     use super::RegexQuery;
     use crate::collector::TopDocs;
+    use crate::query::Query;
     use crate::schema::{Field, Schema, TEXT};
     use crate::{assert_nearly_equals, Index, IndexReader, IndexWriter};
 
@@ -188,4 +231,16 @@ mod test {
             res => panic!("unexpected result: {res:?}"),
         }
     }
+
+    #[test]
+    pub fn test_fingerprint() -> crate::Result<()> {
+        let (_reader, field) = build_test_index()?;
+        let query = RegexQuery::from_pattern("jap[\n]+", field)?;
+        assert_eq!(query.pattern_key, r#"Regex("jap[\n]+")"#);
+        let same_query = RegexQuery::from_regex(Regex::new("jap[\n]+").unwrap(), field);
+        assert_eq!(query.fingerprint(), same_query.fingerprint());
+        let other_query = RegexQuery::from_pattern("jap[\n]", field)?;
+        assert_ne!(query.fingerprint(), other_query.fingerprint());
+        Ok(())
+    }
 }
@@ -4,7 +4,9 @@ use tantivy_fst::raw::CompiledAddr;
 use tantivy_fst::{Automaton, Map};
 
 use crate::query::score_combiner::DoNothingCombiner;
-use crate::query::{AutomatonWeight, BooleanWeight, EnableScoring, Occur, Query, Weight};
+use crate::query::{
+    AutomatonWeight, BooleanWeight, EnableScoring, Occur, Query, QueryFingerprint, Weight,
+};
 use crate::schema::{Field, Schema};
 use crate::Term;
 
@@ -80,6 +82,20 @@ impl Query for TermSetQuery {
             }
         }
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("term_set");
+        let mut fields: Vec<&Field> = self.terms_map.keys().collect();
+        fields.sort();
+        for field in fields {
+            let terms = &self.terms_map[field];
+            fingerprint.push_u64(terms.len() as u64);
+            for term in terms {
+                fingerprint.push_term(term);
+            }
+        }
+        Some(fingerprint)
+    }
 }
 
 struct SetDfaWrapper(Map<Vec<u8>>);
@@ -2,7 +2,7 @@ use std::fmt;
 
 use super::term_weight::TermWeight;
 use crate::query::bm25::Bm25Weight;
-use crate::query::{EnableScoring, Explanation, Query, Weight};
+use crate::query::{EnableScoring, Explanation, Query, QueryFingerprint, Weight};
 use crate::schema::IndexRecordOption;
 use crate::Term;
 
@@ -127,6 +127,12 @@ impl Query for TermQuery {
     fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
         visitor(&self.term, false);
     }
+
+    fn fingerprint(&self) -> Option<QueryFingerprint> {
+        let mut fingerprint = QueryFingerprint::new("term");
+        fingerprint.push_term(&self.term);
+        Some(fingerprint)
+    }
 }
 
 #[cfg(test)]
@@ -33,6 +33,22 @@ impl Weight for TermWeight {
         Ok(explanation)
     }
 
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, reader: &'a SegmentReader) -> crate::query::WarmupFuture<'a> {
+        Box::pin(async move {
+            let field = self.term.field();
+            let inverted_index = reader.inverted_index(field)?;
+            let with_positions = self.index_record_option.has_positions();
+            inverted_index
+                .warm_postings(&self.term, with_positions)
+                .await?;
+            if self.scoring_enabled {
+                crate::query::weight::warmup_fieldnorms(reader, field).await?;
+            }
+            Ok(())
+        })
+    }
+
     fn count(&self, reader: &SegmentReader) -> crate::Result<u32> {
         if let Some(alive_bitset) = reader.alive_bitset() {
             Ok(self.scorer(reader, 1.0)?.count(alive_bitset))
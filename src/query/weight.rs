@@ -4,6 +4,55 @@ use crate::index::SegmentReader;
 use crate::query::Explanation;
 use crate::{DocId, DocSet, Score, TERMINATED};
 
+/// Future returned by [`Weight::warmup`].
+#[cfg(feature = "quickwit")]
+pub type WarmupFuture<'a> = futures_util::future::BoxFuture<'a, crate::Result<()>>;
+
+/// Warms up all of the given weights on `reader`, concurrently.
+#[cfg(feature = "quickwit")]
+pub(crate) async fn warmup_weights<'a>(
+    weights: impl Iterator<Item = &'a dyn Weight>,
+    reader: &'a SegmentReader,
+) -> crate::Result<()> {
+    futures_util::future::try_join_all(weights.map(|weight| weight.warmup(reader))).await?;
+    Ok(())
+}
+
+/// Prefetches the fieldnorms of `field`, required to compute BM25 scores.
+#[cfg(feature = "quickwit")]
+pub(crate) async fn warmup_fieldnorms(
+    reader: &SegmentReader,
+    field: crate::schema::Field,
+) -> crate::Result<()> {
+    if let Some(fieldnorm_file) = reader
+        .fieldnorms_readers()
+        .get_inner_file()
+        .open_read(field)
+    {
+        fieldnorm_file.read_bytes_async().await?;
+    }
+    Ok(())
+}
+
+/// Prefetches the fast field columns associated with `field_name`.
+#[cfg(feature = "quickwit")]
+pub(crate) async fn warmup_fast_field(
+    reader: &SegmentReader,
+    field_name: &str,
+) -> crate::Result<()> {
+    let column_handles = reader
+        .fast_fields()
+        .list_dynamic_column_handles(field_name)
+        .await?;
+    futures_util::future::try_join_all(
+        column_handles
+            .iter()
+            .map(|column_handle| column_handle.file_slice().read_bytes_async()),
+    )
+    .await?;
+    Ok(())
+}
+
 /// Iterates through all of the documents and scores matched by the DocSet
 /// `DocSet`.
 pub(crate) fn for_each_scorer<TScorer: Scorer + ?Sized>(
@@ -130,4 +179,17 @@ pub trait Weight: Send + Sync + 'static {
         for_each_pruning_scorer(scorer.as_mut(), threshold, callback);
         Ok(())
     }
+
+    /// Asynchronously prefetches the data required to score the documents of `reader`:
+    /// term dictionary blocks, postings, fieldnorms or fast field columns.
+    ///
+    /// This does not return any data: it makes sure the bytes are available in the
+    /// [`Directory`](crate::Directory) caches, so that the synchronous `scorer(..)` does
+    /// not block on I/O. It is only useful with a `Directory` caching asynchronous reads.
+    ///
+    /// The default implementation does not prefetch anything.
+    #[cfg(feature = "quickwit")]
+    fn warmup<'a>(&'a self, _reader: &'a SegmentReader) -> WarmupFuture<'a> {
+        Box::pin(futures_util::future::ready(Ok(())))
+    }
 }
@@ -1,7 +1,8 @@
 mod warming;
 
+use std::collections::HashSet;
 use std::sync::atomic::AtomicU64;
-use std::sync::{atomic, Arc, Weak};
+use std::sync::{atomic, Arc, RwLock, Weak};
 
 use arc_swap::ArcSwap;
 pub use warming::Warmer;
@@ -9,8 +10,11 @@ pub use warming::Warmer;
 use self::warming::WarmingState;
 use crate::core::searcher::{SearcherGeneration, SearcherInner};
 use crate::directory::{Directory, WatchCallback, WatchHandle, META_LOCK};
+use crate::index::{Segment, SegmentMeta};
+use crate::query::QueryCache;
+use crate::schema::Schema;
 use crate::store::DOCSTORE_CACHE_CAPACITY;
-use crate::{Index, Inventory, Searcher, SegmentReader, TrackedObject};
+use crate::{Index, Inventory, Opstamp, Searcher, SegmentReader, TrackedObject};
 
 /// Defines when a new version of the index should be reloaded.
 ///
@@ -37,6 +41,7 @@ pub enum ReloadPolicy {
 /// - [`Warmer`] implementations
 /// - number of warming threads, for parallelizing warming work
 /// - The cache size of the underlying doc store readers.
+/// - A [`QueryCache`] shared by the searchers of the reader.
 #[derive(Clone)]
 pub struct IndexReaderBuilder {
     reload_policy: ReloadPolicy,
@@ -44,6 +49,8 @@ pub struct IndexReaderBuilder {
     warmers: Vec<Weak<dyn Warmer>>,
     num_warming_threads: usize,
     doc_store_cache_num_blocks: usize,
+    query_cache: Option<QueryCache>,
+    nrt_segments: Option<NrtSegments>,
 }
 
 impl IndexReaderBuilder {
@@ -55,6 +62,8 @@ impl IndexReaderBuilder {
             warmers: Vec::new(),
             num_warming_threads: 1,
             doc_store_cache_num_blocks: DOCSTORE_CACHE_CAPACITY,
+            query_cache: None,
+            nrt_segments: None,
         }
     }
 
@@ -72,6 +81,8 @@ impl IndexReaderBuilder {
         )?;
         let inner_reader = InnerIndexReader::new(
             self.doc_store_cache_num_blocks,
+            self.query_cache,
+            self.nrt_segments,
             self.index,
             warming_state,
             searcher_generation_inventory,
@@ -114,6 +125,14 @@ impl IndexReaderBuilder {
         self
     }
 
+    /// Makes the reader search the segments published by an `IndexWriter`, instead of
+    /// the segments of the last commit.
+    #[must_use]
+    pub(crate) fn nrt_segments(mut self, nrt_segments: NrtSegments) -> IndexReaderBuilder {
+        self.nrt_segments = Some(nrt_segments);
+        self
+    }
+
     /// Sets the cache size of the doc store readers.
     ///
     /// The doc store readers cache by default DOCSTORE_CACHE_CAPACITY(100) decompressed blocks.
@@ -126,6 +145,23 @@ impl IndexReaderBuilder {
         self
     }
 
+    /// Sets the [`QueryCache`] used by the searchers of this reader.
+    ///
+    /// By default, no query cache is used. Entries of segments that are
+    /// removed from the index are evicted whenever the reader reloads.
+    #[must_use]
+    pub fn query_cache(mut self, query_cache: QueryCache) -> IndexReaderBuilder {
+        self.query_cache = Some(query_cache);
+        self
+    }
+
+    /// Sets a [`QueryCache`] with the given memory budget, in bytes, and the default
+    /// [`QueryCachingPolicy`](crate::query::QueryCachingPolicy).
+    #[must_use]
+    pub fn query_cache_memory_budget(self, memory_budget: usize) -> IndexReaderBuilder {
+        self.query_cache(QueryCache::new(memory_budget))
+    }
+
     /// Set the [`Warmer`]s that are invoked when reloading searchable segments.
     #[must_use]
     pub fn warmers(mut self, warmers: Vec<Weak<dyn Warmer>>) -> IndexReaderBuilder {
@@ -152,8 +188,32 @@ impl TryInto<IndexReader> for IndexReaderBuilder {
     }
 }
 
+/// The segments published by an `IndexWriter` for near-real-time search.
+///
+/// They include the segments which are flushed but not committed yet, with the deletes
+/// up to the opstamp of the flush applied.
+///
+/// They are also used to pin a reader on the segments of a historical commit.
+#[derive(Clone, Default)]
+pub(crate) struct NrtSegments(Arc<RwLock<Arc<PublishedSegments>>>);
+
+type PublishedSegments = (Opstamp, Vec<SegmentMeta>);
+
+impl NrtSegments {
+    pub fn publish(&self, opstamp: Opstamp, segment_metas: Vec<SegmentMeta>) {
+        *self.0.write().unwrap() = Arc::new((opstamp, segment_metas));
+    }
+
+    /// Returns the opstamp up to which the deletes are applied, and the segment metas.
+    pub fn get(&self) -> Arc<PublishedSegments> {
+        self.0.read().unwrap().clone()
+    }
+}
+
 struct InnerIndexReader {
     doc_store_cache_num_blocks: usize,
+    query_cache: Option<QueryCache>,
+    nrt_segments: Option<NrtSegments>,
     index: Index,
     warming_state: WarmingState,
     searcher: arc_swap::ArcSwap<SearcherInner>,
@@ -164,6 +224,8 @@ struct InnerIndexReader {
 impl InnerIndexReader {
     fn new(
         doc_store_cache_num_blocks: usize,
+        query_cache: Option<QueryCache>,
+        nrt_segments: Option<NrtSegments>,
         index: Index,
         warming_state: WarmingState,
         // The searcher_generation_inventory is not used as source, but as target to track the
@@ -174,13 +236,17 @@ impl InnerIndexReader {
 
         let searcher = Self::create_searcher(
             &index,
+            nrt_segments.as_ref(),
             doc_store_cache_num_blocks,
+            query_cache.as_ref(),
             &warming_state,
             &searcher_generation_counter,
             &searcher_generation_inventory,
         )?;
         Ok(InnerIndexReader {
             doc_store_cache_num_blocks,
+            query_cache,
+            nrt_segments,
             index,
             warming_state,
             searcher: ArcSwap::from(searcher),
@@ -188,19 +254,37 @@ impl InnerIndexReader {
             searcher_generation_inventory,
         })
     }
-    /// Opens the freshest segments [`SegmentReader`].
+    /// Opens the freshest segments [`SegmentReader`], along with the schema they are read with
+    /// and its version.
+    ///
+    /// The segments are the ones published in `nrt_segments` if any, and the segments of the
+    /// last commit otherwise.
     ///
     /// This function acquires a lock to prevent GC from removing files
     /// as we are opening our index.
-    fn open_segment_readers(index: &Index) -> crate::Result<Vec<SegmentReader>> {
+    fn open_segment_readers(
+        index: &Index,
+        nrt_segments: Option<&NrtSegments>,
+    ) -> crate::Result<(u32, Schema, Vec<SegmentReader>)> {
         // Prevents segment files from getting deleted while we are in the process of opening them
         let _meta_lock = index.directory().acquire_lock(&META_LOCK)?;
-        let searchable_segments = index.searchable_segments()?;
+        let searchable_segments: Vec<Segment> = match nrt_segments {
+            Some(nrt_segments) => nrt_segments
+                .get()
+                .1
+                .iter()
+                .map(|segment_meta| index.segment(segment_meta.clone()))
+                .collect(),
+            None => index.searchable_segments()?,
+        };
+        // The schema is read after the metas, so that it is at least as recent as the schema
+        // of any of the searchable segments.
+        let (schema_version, schema) = index.versioned_schema();
         let segment_readers = searchable_segments
             .iter()
-            .map(SegmentReader::open)
-            .collect::<crate::Result<_>>()?;
-        Ok(segment_readers)
+            .map(|segment| SegmentReader::open_with_schema(segment, schema.clone(), None))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok((schema_version, schema, segment_readers))
     }
 
     fn track_segment_readers_in_inventory(
@@ -216,25 +300,29 @@ impl InnerIndexReader {
 
     fn create_searcher(
         index: &Index,
+        nrt_segments: Option<&NrtSegments>,
         doc_store_cache_num_blocks: usize,
+        query_cache: Option<&QueryCache>,
         warming_state: &WarmingState,
         searcher_generation_counter: &Arc<AtomicU64>,
         searcher_generation_inventory: &Inventory<SearcherGeneration>,
     ) -> crate::Result<Arc<SearcherInner>> {
-        let segment_readers = Self::open_segment_readers(index)?;
+        let (schema_version, schema, segment_readers) =
+            Self::open_segment_readers(index, nrt_segments)?;
         let searcher_generation = Self::track_segment_readers_in_inventory(
             &segment_readers,
             searcher_generation_counter,
             searcher_generation_inventory,
         );
 
-        let schema = index.schema();
         let searcher = Arc::new(SearcherInner::new(
             schema,
+            schema_version,
             index.clone(),
             segment_readers,
             searcher_generation,
             doc_store_cache_num_blocks,
+            query_cache.cloned(),
         )?);
 
         warming_state.warm_new_searcher_generation(&searcher.clone().into())?;
@@ -244,12 +332,37 @@ impl InnerIndexReader {
     fn reload(&self) -> crate::Result<()> {
         let searcher = Self::create_searcher(
             &self.index,
+            self.nrt_segments.as_ref(),
             self.doc_store_cache_num_blocks,
+            self.query_cache.as_ref(),
             &self.warming_state,
             &self.searcher_generation_counter,
             &self.searcher_generation_inventory,
         )?;
 
+        // Only the segments this reader stopped reading, or reads with a newer schema or newer
+        // numeric updates, are evicted: the cache may be shared with the readers of other indexes.
+        if let Some(query_cache) = &self.query_cache {
+            let segment_versions = |searcher: Searcher| -> HashSet<_> {
+                searcher
+                    .segment_readers()
+                    .iter()
+                    .map(|segment_reader| {
+                        (
+                            segment_reader.segment_id(),
+                            searcher.schema_version(),
+                            segment_reader.numeric_updates_opstamp(),
+                        )
+                    })
+                    .collect()
+            };
+            let alive_segments = segment_versions(searcher.clone().into());
+            let removed_segments: HashSet<_> = segment_versions(self.searcher())
+                .difference(&alive_segments)
+                .copied()
+                .collect();
+            query_cache.evict_segments(&removed_segments);
+        }
         self.searcher.store(searcher);
 
         Ok(())
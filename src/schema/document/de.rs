@@ -21,6 +21,7 @@ use common::{u64_to_f64, BinarySerializable, DateTime, VInt};
 use super::se::BinaryObjectSerializer;
 use super::{OwnedValue, Value};
 use crate::schema::document::type_codes;
+use crate::schema::field_mapping::FieldMapping;
 use crate::schema::{Facet, Field};
 use crate::tokenizer::PreTokenizedString;
 
@@ -292,6 +293,7 @@ pub struct BinaryDocumentDeserializer<'de, R> {
     length: usize,
     position: usize,
     reader: &'de mut R,
+    field_mapping: Option<&'de FieldMapping>,
 }
 
 impl<'de, R> BinaryDocumentDeserializer<'de, R>
@@ -305,9 +307,18 @@ where R: Read
             length: length.val() as usize,
             position: 0,
             reader,
+            field_mapping: None,
         })
     }
 
+    /// Remaps the fields of the document with `field_mapping`.
+    ///
+    /// Fields that are not mapped are skipped.
+    pub(crate) fn with_field_mapping(mut self, field_mapping: Option<&'de FieldMapping>) -> Self {
+        self.field_mapping = field_mapping;
+        self
+    }
+
     /// Returns true if the deserializer has deserialized all the entries
     /// within the document.
     fn is_complete(&self) -> bool {
@@ -324,18 +335,25 @@ where R: Read
     }
 
     fn next_field<V: ValueDeserialize>(&mut self) -> Result<Option<(Field, V)>, DeserializeError> {
-        if self.is_complete() {
-            return Ok(None);
-        }
+        loop {
+            if self.is_complete() {
+                return Ok(None);
+            }
 
-        let field = Field::deserialize(self.reader).map_err(DeserializeError::from)?;
+            let field = Field::deserialize(self.reader).map_err(DeserializeError::from)?;
 
-        let deserializer = BinaryValueDeserializer::from_reader(self.reader)?;
-        let value = V::deserialize(deserializer)?;
+            let deserializer = BinaryValueDeserializer::from_reader(self.reader)?;
+            let value = V::deserialize(deserializer)?;
 
-        self.position += 1;
+            self.position += 1;
 
-        Ok(Some((field, value)))
+            let Some(field_mapping) = self.field_mapping else {
+                return Ok(Some((field, value)));
+            };
+            if let Some(field) = field_mapping.map(field) {
+                return Ok(Some((field, value)));
+            }
+        }
     }
 }
 
@@ -27,10 +27,25 @@ where W: Write
     /// to the writer.
     #[inline]
     pub(crate) fn serialize_doc<D>(&mut self, doc: &D) -> io::Result<()>
+    where D: Document {
+        self.serialize_doc_fields(doc, false)
+    }
+
+    /// Serializes all of the fields of a given document, including the ones which are not
+    /// stored, and write the output to the writer.
+    ///
+    /// Pre-tokenized values are kept as is, so that the document can be indexed again
+    /// after deserialization.
+    pub(crate) fn serialize_doc_with_all_fields<D>(&mut self, doc: &D) -> io::Result<()>
+    where D: Document {
+        self.serialize_doc_fields(doc, true)
+    }
+
+    fn serialize_doc_fields<D>(&mut self, doc: &D, all_fields: bool) -> io::Result<()>
     where D: Document {
         let stored_field_values = || {
             doc.iter_fields_and_values()
-                .filter(|(field, _)| self.schema.get_field_entry(*field).is_stored())
+                .filter(|(field, _)| all_fields || self.schema.get_field_entry(*field).is_stored())
         };
         let num_field_values = stored_field_values().count();
         let mut actual_length = 0;
@@ -41,7 +56,9 @@ where W: Write
 
             let mut serializer = BinaryValueSerializer::new(self.writer);
             match value_access.as_value() {
-                ReferenceValue::Leaf(ReferenceValueLeaf::PreTokStr(pre_tokenized_text)) => {
+                ReferenceValue::Leaf(ReferenceValueLeaf::PreTokStr(pre_tokenized_text))
+                    if !all_fields =>
+                {
                     serializer.serialize_value(ReferenceValue::Leaf::<&'_ OwnedValue>(
                         ReferenceValueLeaf::Str(&pre_tokenized_text.text),
                     ))?;
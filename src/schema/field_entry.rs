@@ -19,6 +19,12 @@ pub struct FieldEntry {
     name: String,
     #[serde(flatten)]
     field_type: FieldType,
+    #[serde(default, skip_serializing_if = "is_false")]
+    unique: bool,
+}
+
+fn is_false(val: &bool) -> bool {
+    !val
 }
 
 impl FieldEntry {
@@ -28,6 +34,7 @@ impl FieldEntry {
         FieldEntry {
             name: field_name,
             field_type,
+            unique: false,
         }
     }
 
@@ -116,6 +123,32 @@ impl FieldEntry {
         }
     }
 
+    /// Returns true if the field is the unique key field of the schema.
+    ///
+    /// See [`SchemaBuilder::set_unique_key_field`](crate::schema::SchemaBuilder::set_unique_key_field).
+    pub fn is_unique_key(&self) -> bool {
+        self.unique
+    }
+
+    /// Returns true if the field can be used as a unique key.
+    ///
+    /// Its values need to be indexed as a single term: the field needs to be an indexed `u64`,
+    /// `i64` or bytes field, or a text field indexed with the `raw` tokenizer.
+    pub(crate) fn can_be_unique_key(&self) -> bool {
+        match &self.field_type {
+            FieldType::U64(_) | FieldType::I64(_) | FieldType::Bytes(_) => self.is_indexed(),
+            FieldType::Str(text_options) => text_options
+                .get_indexing_options()
+                .map(|indexing_options| indexing_options.tokenizer() == "raw")
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn set_unique_key(&mut self) {
+        self.unique = true;
+    }
+
     /// Returns true if the field is stored
     #[inline]
     pub fn is_stored(&self) -> bool {
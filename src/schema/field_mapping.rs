@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use crate::schema::{Field, FieldEntry, Schema};
+use crate::TantivyError;
+
+/// Maps the fields of the schema a segment was written with, to the fields of
+/// a more recent version of the schema.
+///
+/// Fields are matched by name. Fields that have been dropped, are mapped to `None`.
+#[derive(Clone, Debug)]
+pub(crate) struct FieldMapping {
+    new_fields: Arc<[Option<Field>]>,
+}
+
+impl FieldMapping {
+    /// Returns the mapping from the fields of `old_schema` to the fields of `new_schema`,
+    /// or `None` if the two schemas are identical.
+    pub fn between(old_schema: &Schema, new_schema: &Schema) -> Option<FieldMapping> {
+        if old_schema == new_schema {
+            return None;
+        }
+        let new_fields = old_schema
+            .fields()
+            .map(|(_, old_field_entry)| {
+                let new_field = new_schema.get_field(old_field_entry.name()).ok()?;
+                let new_field_entry = new_schema.get_field_entry(new_field);
+                if is_same_field_data(old_field_entry, new_field_entry) {
+                    Some(new_field)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Some(FieldMapping { new_fields })
+    }
+
+    /// Returns the field of the new schema associated with `old_field`.
+    pub fn map(&self, old_field: Field) -> Option<Field> {
+        self.new_fields
+            .get(old_field.field_id() as usize)
+            .copied()
+            .flatten()
+    }
+}
+
+/// Returns the serialized options of a field entry, without the `stored` and `fast` flags.
+fn options_except_stored_and_fast(field_entry: &FieldEntry) -> serde_json::Value {
+    let mut field_entry_json =
+        serde_json::to_value(field_entry).expect("field entries are always serializable");
+    if let Some(options) = field_entry_json
+        .get_mut("options")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        options.remove("stored");
+        options.remove("fast");
+    }
+    field_entry_json
+}
+
+/// Returns true if the data indexed for `old_field_entry` can be read as `new_field_entry`.
+///
+/// Both entries need to have the same name, type and indexing options. They may only
+/// differ by being stored or fast.
+fn is_same_field_data(old_field_entry: &FieldEntry, new_field_entry: &FieldEntry) -> bool {
+    old_field_entry == new_field_entry
+        || options_except_stored_and_fast(old_field_entry)
+            == options_except_stored_and_fast(new_field_entry)
+}
+
+/// Checks that the schema of an index can be updated from `old_schema` to `new_schema`.
+///
+/// Fields can be added or dropped, and existing fields can be made stored or fast. Any other
+/// change is rejected.
+///
+/// `schemas_in_use` are the previous versions of the schema still used by some segments.
+/// Adding a field that bears the name of a field of one of these schemas is rejected, as the
+/// data of the old field would resurface.
+pub(crate) fn validate_schema_update<'a>(
+    old_schema: &Schema,
+    new_schema: &Schema,
+    schemas_in_use: impl Iterator<Item = &'a Schema>,
+) -> crate::Result<()> {
+    for (_, new_field_entry) in new_schema.fields() {
+        let field_name = new_field_entry.name();
+        let Ok(old_field) = old_schema.get_field(field_name) else {
+            continue;
+        };
+        let old_field_entry = old_schema.get_field_entry(old_field);
+        if !is_same_field_data(old_field_entry, new_field_entry) {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {field_name:?} cannot be changed: only the stored and fast options of an \
+                 existing field can be updated."
+            )));
+        }
+        if (old_field_entry.is_stored() && !new_field_entry.is_stored())
+            || (old_field_entry.is_fast() && !new_field_entry.is_fast())
+        {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {field_name:?} cannot be made non-stored or non-fast."
+            )));
+        }
+    }
+    for schema_in_use in schemas_in_use {
+        for (_, field_entry) in schema_in_use.fields() {
+            let field_name = field_entry.name();
+            if old_schema.get_field(field_name).is_err() && new_schema.get_field(field_name).is_ok()
+            {
+                return Err(TantivyError::SchemaError(format!(
+                    "Field {field_name:?} was dropped, but some segments still contain its data. \
+                     Merge these segments before adding it again."
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_schema_update, FieldMapping};
+    use crate::schema::{Field, Schema, FAST, INDEXED, STORED, STRING, TEXT};
+
+    #[test]
+    fn test_field_mapping() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        schema_builder.add_u64_field("count", INDEXED);
+        schema_builder.add_text_field("dropped", STRING);
+        let old_schema = schema_builder.build();
+        assert!(FieldMapping::between(&old_schema, &old_schema).is_none());
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("count", INDEXED | FAST);
+        schema_builder.add_text_field("title", TEXT | STORED);
+        schema_builder.add_text_field("new", STRING);
+        let new_schema = schema_builder.build();
+        let field_mapping = FieldMapping::between(&old_schema, &new_schema).unwrap();
+        assert_eq!(
+            field_mapping.map(Field::from_field_id(0)),
+            Some(Field::from_field_id(1))
+        );
+        assert_eq!(
+            field_mapping.map(Field::from_field_id(1)),
+            Some(Field::from_field_id(0))
+        );
+        assert_eq!(field_mapping.map(Field::from_field_id(2)), None);
+        assert!(validate_schema_update(&old_schema, &new_schema, std::iter::empty()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_schema_update_errors() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("count", INDEXED | STORED);
+        let old_schema = schema_builder.build();
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_i64_field("count", INDEXED | STORED);
+        let type_change = schema_builder.build();
+        assert!(validate_schema_update(&old_schema, &type_change, std::iter::empty()).is_err());
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("count", INDEXED);
+        let unstored = schema_builder.build();
+        assert!(validate_schema_update(&old_schema, &unstored, std::iter::empty()).is_err());
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        let dropped_schema = schema_builder.build();
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        schema_builder.add_u64_field("count", INDEXED | STORED);
+        let re_added = schema_builder.build();
+        assert!(
+            validate_schema_update(&dropped_schema, &re_added, std::iter::once(&old_schema))
+                .is_err()
+        );
+        assert!(validate_schema_update(&dropped_schema, &re_added, std::iter::empty()).is_ok());
+    }
+}
@@ -113,6 +113,7 @@ mod schema;
 pub(crate) mod term;
 
 mod field_entry;
+pub(crate) mod field_mapping;
 mod field_type;
 
 mod bytes_options;
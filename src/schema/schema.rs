@@ -205,6 +205,32 @@ impl SchemaBuilder {
         field
     }
 
+    /// Sets the unique key field of the schema.
+    ///
+    /// Documents can then be replaced with
+    /// [`IndexWriter::upsert_document`](crate::IndexWriter::upsert_document), and looked up with
+    /// [`Searcher::doc_by_key`](crate::Searcher::doc_by_key).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the field is not an indexed `u64`, `i64` or bytes field, or a text field
+    /// indexed with the `raw` tokenizer, or if a unique key field has already been set.
+    pub fn set_unique_key_field(&mut self, field: Field) {
+        assert!(
+            self.fields
+                .iter()
+                .all(|field_entry| !field_entry.is_unique_key()),
+            "The unique key field of the schema has already been set"
+        );
+        let field_entry = &mut self.fields[field.field_id() as usize];
+        assert!(
+            field_entry.can_be_unique_key(),
+            "Field {:?} cannot be used as a unique key",
+            field_entry.name()
+        );
+        field_entry.set_unique_key();
+    }
+
     /// Finalize the creation of a `Schema`
     /// This will consume your `SchemaBuilder`
     pub fn build(self) -> Schema {
@@ -282,6 +308,15 @@ impl Schema {
         &self.0.fields[field.field_id() as usize]
     }
 
+    /// Returns the unique key field of the schema, if any.
+    ///
+    /// See [`SchemaBuilder::set_unique_key_field`].
+    pub fn unique_key_field(&self) -> Option<Field> {
+        self.fields()
+            .find(|(_, field_entry)| field_entry.is_unique_key())
+            .map(|(field, _)| field)
+    }
+
     /// Return the field name for a given `Field`.
     pub fn get_field_name(&self, field: Field) -> &str {
         self.get_field_entry(field).name()
@@ -960,4 +995,41 @@ mod tests {
         assert_eq!(schema.find_field("thiswouldbeareallylongfieldname"), None);
         assert_eq!(schema.find_field("baz.bar.foo"), None);
     }
+
+    #[test]
+    fn test_unique_key_field() {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING);
+        schema_builder.add_text_field("body", TEXT);
+        schema_builder.set_unique_key_field(id_field);
+        let schema = schema_builder.build();
+        assert_eq!(schema.unique_key_field(), Some(id_field));
+
+        let schema_json = serde_json::to_string(&schema).unwrap();
+        assert_eq!(schema_json.matches(r#""unique":true"#).count(), 1);
+        let schema_deser: Schema = serde_json::from_str(&schema_json).unwrap();
+        assert_eq!(schema_deser.unique_key_field(), Some(id_field));
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("id", STRING);
+        assert_eq!(schema_builder.build().unique_key_field(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be used as a unique key")]
+    fn test_unique_key_field_tokenized() {
+        let mut schema_builder = Schema::builder();
+        let body_field = schema_builder.add_text_field("body", TEXT);
+        schema_builder.set_unique_key_field(body_field);
+    }
+
+    #[test]
+    #[should_panic(expected = "already been set")]
+    fn test_unique_key_field_twice() {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED);
+        let other_id_field = schema_builder.add_i64_field("other_id", INDEXED);
+        schema_builder.set_unique_key_field(id_field);
+        schema_builder.set_unique_key_field(other_id_field);
+    }
 }
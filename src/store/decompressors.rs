@@ -31,13 +31,19 @@ impl From<Compressor> for Decompressor {
 
 impl Decompressor {
     pub(crate) fn from_id(id: u8) -> Decompressor {
+        Decompressor::try_from_id(id).unwrap_or_else(|| panic!("unknown compressor id {id:?}"))
+    }
+
+    /// Returns the decompressor with the id `id`, or `None` if it is unknown or its
+    /// feature is disabled.
+    pub(crate) fn try_from_id(id: u8) -> Option<Decompressor> {
         match id {
-            0 => Decompressor::None,
+            0 => Some(Decompressor::None),
             #[cfg(feature = "lz4-compression")]
-            1 => Decompressor::Lz4,
+            1 => Some(Decompressor::Lz4),
             #[cfg(feature = "zstd-compression")]
-            4 => Decompressor::Zstd,
-            _ => panic!("unknown compressor id {id:?}"),
+            4 => Some(Decompressor::Zstd),
+            _ => None,
         }
     }
 
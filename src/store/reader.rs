@@ -14,7 +14,9 @@ use super::Decompressor;
 use crate::directory::FileSlice;
 use crate::error::DataCorruption;
 use crate::fastfield::AliveBitSet;
+use crate::query::profile;
 use crate::schema::document::{BinaryDocumentDeserializer, DocumentDeserialize};
+use crate::schema::field_mapping::FieldMapping;
 use crate::space_usage::StoreSpaceUsage;
 use crate::store::index::Checkpoint;
 use crate::DocId;
@@ -32,6 +34,7 @@ pub struct StoreReader {
     skip_index: Arc<SkipIndex>,
     space_usage: StoreSpaceUsage,
     cache: BlockCache,
+    field_mapping: Option<FieldMapping>,
 }
 
 /// The cache for decompressed blocks.
@@ -137,10 +140,19 @@ impl StoreReader {
                 cache_misses: Default::default(),
             },
             skip_index: Arc::new(skip_index),
+            field_mapping: None,
             space_usage,
         })
     }
 
+    /// Remaps the fields of the documents returned by the reader.
+    ///
+    /// This is used to read documents of segments written with a previous version of the schema.
+    pub(crate) fn with_field_mapping(mut self, field_mapping: Option<FieldMapping>) -> StoreReader {
+        self.field_mapping = field_mapping;
+        self
+    }
+
     pub(crate) fn block_checkpoints(&self) -> impl Iterator<Item = Checkpoint> + '_ {
         self.skip_index.checkpoints()
     }
@@ -204,7 +216,8 @@ impl StoreReader {
         let mut doc_bytes = self.get_document_bytes(doc_id)?;
 
         let deserializer = BinaryDocumentDeserializer::from_reader(&mut doc_bytes)
-            .map_err(crate::TantivyError::from)?;
+            .map_err(crate::TantivyError::from)?
+            .with_field_mapping(self.field_mapping.as_ref());
         D::deserialize(deserializer).map_err(crate::TantivyError::from)
     }
 
@@ -215,9 +228,11 @@ impl StoreReader {
     /// so accessing docs from the same compressed block should be faster.
     /// For that reason a store reader should be kept and reused.
     pub fn get_document_bytes(&self, doc_id: DocId) -> crate::Result<OwnedBytes> {
-        let checkpoint = self.block_checkpoint(doc_id)?;
-        let block = self.read_block(&checkpoint)?;
-        Self::get_document_bytes_from_block(block, doc_id, &checkpoint)
+        profile::time_doc_store_fetch(|| {
+            let checkpoint = self.block_checkpoint(doc_id)?;
+            let block = self.read_block(&checkpoint)?;
+            Self::get_document_bytes_from_block(block, doc_id, &checkpoint)
+        })
     }
 
     /// Advanced API.
@@ -245,7 +260,8 @@ impl StoreReader {
             let mut doc_bytes = doc_bytes_res?;
 
             let deserializer = BinaryDocumentDeserializer::from_reader(&mut doc_bytes)
-                .map_err(crate::TantivyError::from)?;
+                .map_err(crate::TantivyError::from)?
+                .with_field_mapping(self.field_mapping.as_ref());
             D::deserialize(deserializer).map_err(crate::TantivyError::from)
         })
     }
@@ -392,7 +408,8 @@ impl StoreReader {
         let mut doc_bytes = self.get_document_bytes_async(doc_id, executor).await?;
 
         let deserializer = BinaryDocumentDeserializer::from_reader(&mut doc_bytes)
-            .map_err(crate::TantivyError::from)?;
+            .map_err(crate::TantivyError::from)?
+            .with_field_mapping(self.field_mapping.as_ref());
         D::deserialize(deserializer).map_err(crate::TantivyError::from)
     }
 }
@@ -2,7 +2,7 @@ use std::path::Path;
 
 use tantivy::directory::{Directory, ManagedDirectory, RamDirectory, TerminatingWrite};
 use tantivy::schema::{Schema, TEXT};
-use tantivy::{doc, Index, IndexWriter, Term};
+use tantivy::{doc, Index, IndexSettings, IndexWriter, Term};
 
 #[test]
 fn test_failpoints_managed_directory_gc_if_delete_fails() {
@@ -122,3 +122,40 @@ fn test_fail_on_commit_segment() -> tantivy::Result<()> {
     assert!(index_writer.commit().is_err());
     Ok(())
 }
+
+#[test]
+fn test_write_ahead_log_append_fails() -> tantivy::Result<()> {
+    let _fail_scenario_guard = fail::FailScenario::setup();
+    let mut schema_builder = Schema::builder();
+    let text_field = schema_builder.add_text_field("text", TEXT);
+    let index = Index::builder()
+        .schema(schema_builder.build())
+        .settings(IndexSettings {
+            write_ahead_log: true,
+            ..Default::default()
+        })
+        .create_in_ram()?;
+    let mut index_writer: IndexWriter = index.writer_with_num_threads(1, 15_000_000)?;
+    index_writer.add_document(doc!(text_field => "a"))?;
+    index_writer.commit()?;
+
+    fail::cfg("WriteAheadLog::write_record", "1*return").unwrap();
+    // The document is queued, but it cannot be logged.
+    assert!(index_writer.add_document(doc!(text_field => "b")).is_err());
+    // The writer rejects everything until it is rolled back.
+    assert!(index_writer.add_document(doc!(text_field => "c")).is_err());
+    assert!(index_writer.commit().is_err());
+    index_writer.rollback()?;
+
+    // Retrying does not index the document twice.
+    index_writer.add_document(doc!(text_field => "b"))?;
+    index_writer.commit()?;
+    let num_docs_containing = |s: &str| {
+        let term = Term::from_field_text(text_field, s);
+        index.reader()?.searcher().doc_freq(&term)
+    };
+    assert_eq!(num_docs_containing("a")?, 1);
+    assert_eq!(num_docs_containing("b")?, 1);
+    assert_eq!(num_docs_containing("c")?, 0);
+    Ok(())
+}